@@ -1,4 +1,5 @@
 #TODO implement a better engine example
+import os
 import sys
 
 if __name__ == "__main__":
@@ -7,4 +8,9 @@ if __name__ == "__main__":
     
     if sys.argv[1] == '-search_user' and sys.argv[2] =='user123':
         print("test output")
+        #leave a file for the run's artifacts
+        output_dir = os.environ.get("SHERLOCK_OUTPUT_DIR")
+        if output_dir:
+            with open(os.path.join(output_dir, "avatar.txt"), "w") as avatar:
+                avatar.write("user123's avatar")
         sys.exit(0)
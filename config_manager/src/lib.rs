@@ -1,8 +1,22 @@
 //TODO: change the way invalid initiation is handled
 #![allow(unused)] //TODO: remove
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::{fs, io::Read};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+mod migrations;
+mod profiles;
+mod storage;
+pub use migrations::CURRENT_VERSION;
+pub use profiles::PROFILE_ENV_VAR;
+pub use storage::{
+    ArtifactSettings, RemoteStorage, RetentionSettings, SecretRef, StrorageType, SyncSettings,
+    TlsOptions,
+};
 
 static CONFIG_LOCATIONS: [&str; 4] = [
     "./mock_files/sherlock.toml", //TODO: remove. for debugging purposes only
@@ -13,47 +27,477 @@ static CONFIG_LOCATIONS: [&str; 4] = [
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ConfigManager {
-    engines_location: String,
+    /// The schema version of the config file, see the `migrations` module.
+    #[serde(default)]
+    version: u32,
+    /// The engines roots ordered by precedence, an engine from an earlier root shadows
+    /// engines with the same name in later roots.
+    engines_locations: Vec<String>,
+    /// How engines are searched for inside the engines roots.
+    #[serde(default)]
+    discovery: Discovery,
     storage: StrorageType,
+    /// The remote storage server the local storage is synced with, if any.
+    sync: Option<SyncSettings>,
+    /// How the files produced by engine runs are kept.
+    #[serde(default)]
+    artifacts: ArtifactSettings,
+    /// How long the engine runs are kept.
+    #[serde(default)]
+    retention: RetentionSettings,
+    /// Where the logs are written.
+    #[serde(default)]
+    logs: LogSettings,
+    /// The proxy that engines should use, i.e: `socks5://127.0.0.1:9050`.
+    proxy: Option<String>,
+    /// The vault of secrets, i.e: `~/.sherlock/vault`, `None` when there's none.
+    vault: Option<String>,
+    /// Per engine overrides of the engines vendor settings, by engine name.
+    #[serde(default)]
+    engines: HashMap<String, EngineConfig>,
     //TODO: add struct for holding sensitive information like credentials and cryptographic keys.
+    /// The active profile, `None` when only the base section is used.
+    #[serde(skip)]
+    profile: Option<String>,
+    /// The names of the profiles defined in the config file.
+    #[serde(skip)]
+    profiles: Vec<String>,
+    /// The config file the instance was loaded from.
+    #[serde(skip)]
+    source: Option<PathBuf>,
 }
 
 impl ConfigManager {
+    /// ## Description
+    /// Initiates ConfigManager from the first config file found in the default locations.
+    ///
+    /// The active profile is taken from the `SHERLOCK_PROFILE` environment variable.
     pub fn init() -> Result<ConfigManager, String> {
+        ConfigManager::init_with_profile(None)
+    }
+
+    /// ## Description
+    /// Initiates ConfigManager like `init`, with an explicitly selected profile.
+    ///
+    /// Falls back to the `SHERLOCK_PROFILE` environment variable when `profile` is `None`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let configs = ConfigManager::init_with_profile(Some("acme"))?;
+    /// ```
+    pub fn init_with_profile(profile: Option<&str>) -> Result<ConfigManager, String> {
         //pick the the first config file that exists from the different options
         let config_file = CONFIG_LOCATIONS
             .iter()
-            .find(|file| Path::new(file).exists());
+            .map(|file| expand_home(file))
+            .find(|file| file.exists());
 
         //read the file and create an instance out of it
         match config_file {
-            Some(path) => {
-                match fs::read_to_string(path) {
-                    Ok(content) => {
-                        //try to deserilize the toml file into a ConfigManager instance
-                        match toml::from_str(&content) {
-                            Ok(instance) => Ok(instance),
-                            Err(error) => Err(error.to_string()),
-                        }
-                    }
-                    Err(error) => Err(error.to_string()),
-                }
-            }
+            Some(path) => ConfigManager::from_file_with_profile(path, profile),
             None => Err("can't find config file".to_owned()),
         }
     }
 
-    pub fn get_engines_location(&self) -> &str {
-        return &self.engines_location;
+    /// ## Description
+    /// Creates an instance from a config file.
+    ///
+    /// Config files written by older versions are upgraded in memory only,
+    /// use `ConfigManager::upgrade_file` to rewrite them on disk.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ConfigManager, String> {
+        ConfigManager::from_file_with_profile(path, None)
+    }
+
+    /// ## Description
+    /// Creates an instance from a config file with an explicitly selected profile.
+    pub fn from_file_with_profile<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> Result<ConfigManager, String> {
+        ConfigManager::load(path.as_ref(), profiles::select_profile(profile))
+    }
+
+    /// ## Description
+    /// Creates an instance from the content of a config file, upgrading it to the current version.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use config_manager::ConfigManager;
+    /// let configs = ConfigManager::from_toml(r#"
+    ///     version = 3
+    ///     engines_locations = ["./engines", "/opt/sherlock/engines"]
+    ///     storage = { sqlite = { path = "./sherlock.db" } }
+    /// "#).unwrap();
+    /// assert_eq!(configs.get_version(), config_manager::CURRENT_VERSION);
+    /// ```
+    pub fn from_toml(content: &str) -> Result<ConfigManager, String> {
+        ConfigManager::from_toml_with_profile(content, None)
+    }
+
+    /// ## Description
+    /// Creates an instance from the content of a config file with an explicitly selected profile.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use config_manager::ConfigManager;
+    /// let configs = ConfigManager::from_toml_with_profile(r#"
+    ///     version = 3
+    ///     engines_locations = ["./engines"]
+    ///     storage = { sqlite = { path = "./sherlock.db" } }
+    ///     [profiles.acme]
+    ///     proxy = "socks5://127.0.0.1:9050"
+    /// "#, Some("acme")).unwrap();
+    /// assert_eq!(configs.get_profile(), Some("acme"));
+    /// assert_eq!(configs.get_proxy(), Some("socks5://127.0.0.1:9050"));
+    /// ```
+    pub fn from_toml_with_profile(
+        content: &str,
+        profile: Option<&str>,
+    ) -> Result<ConfigManager, String> {
+        ConfigManager::parse(content, profiles::select_profile(profile))
+    }
+
+    /// ## Description
+    /// Reloads the config file with another profile, `None` selects the base section.
+    ///
+    /// The current instance is left untouched so a failed switch can be ignored safely.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// configs = configs.switch_profile(Some("acme"))?;
+    /// ```
+    pub fn switch_profile(&self, profile: Option<&str>) -> Result<ConfigManager, String> {
+        match &self.source {
+            Some(path) => ConfigManager::load(path, profile.map(ToOwned::to_owned)),
+            None => Err("the config wasn't loaded from a file".to_owned()),
+        }
+    }
+
+    // read a config file and keep its location for reloading
+    fn load(path: &Path, profile: Option<String>) -> Result<ConfigManager, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let mut configs = ConfigManager::parse(&content, profile)?;
+                configs.source = Some(path.to_path_buf());
+                Ok(configs)
+            }
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    // parse, migrate and apply a profile to the content of a config file
+    fn parse(content: &str, profile: Option<String>) -> Result<ConfigManager, String> {
+        //parse into a generic document first so older layouts can be migrated
+        let document: toml::Value = toml::from_str(content).map_err(|error| error.to_string())?;
+        let (document, _) = migrations::migrate(document)?;
+        let mut document = match document {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("migrate returns tables only"),
+        };
+
+        //merge the selected profile onto the base section
+        let profiles = profiles::take_profiles(&mut document)?;
+        if let Some(name) = &profile {
+            match profiles.get(name).and_then(toml::Value::as_table) {
+                Some(overrides) => profiles::apply_profile(&mut document, overrides),
+                None => return Err(format!("unknown profile: {}", name)),
+            }
+        }
+
+        //try to deserilize the document into a ConfigManager instance
+        let mut configs: ConfigManager = toml::Value::Table(document)
+            .try_into()
+            .map_err(|error| error.to_string())?;
+        configs.storage.validate()?;
+        if let Some(sync) = &configs.sync {
+            sync.validate(&configs.storage)?;
+        }
+        configs.profile = profile;
+        configs.profiles = profiles.keys().cloned().collect();
+        Ok(configs)
+    }
+
+    /// ## Description
+    /// Upgrades a config file on disk to the current version.
+    ///
+    /// The original file is kept next to it as `<file>.v<old version>.bak`, or
+    /// `<file>.v<old version>.<n>.bak` when that backup exists already. The upgraded file is
+    /// written next to the config and renamed over it, a crash leaves either the old or the new
+    /// config. Returns `false` when the file is up to date already and was left untouched.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// if ConfigManager::upgrade_file("./sherlock.toml")? {
+    ///     println!("sherlock.toml was upgraded");
+    /// }
+    /// ```
+    pub fn upgrade_file<P: AsRef<Path>>(path: P) -> Result<bool, String> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let document: toml::Value = toml::from_str(&content).map_err(|error| error.to_string())?;
+        let (document, from_version) = migrations::migrate(document)?;
+
+        if from_version == CURRENT_VERSION {
+            return Ok(false);
+        }
+
+        //make sure the upgraded document is valid before touching the disk
+        let _: ConfigManager = document
+            .clone()
+            .try_into()
+            .map_err(|error| error.to_string())?;
+        let upgraded = toml::to_string(&document).map_err(|error| error.to_string())?;
+
+        //keep a backup of the original file, an older backup is never overwritten
+        keep_backup(path, &content, from_version)?;
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let replaced = fs::File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(upgraded.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, path));
+        if let Err(error) = replaced {
+            let _ = fs::remove_file(&temporary);
+            return Err(error.to_string());
+        }
+        Ok(true)
+    }
+
+    /// Gets the schema version of the loaded config.
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    /// ## Description
+    /// Gets the engines roots ordered by precedence, with `~` expanded to the home directory.
+    pub fn get_engines_locations(&self) -> Vec<PathBuf> {
+        self.engines_locations
+            .iter()
+            .map(|location| expand_home(location))
+            .collect()
+    }
+
+    /// Gets the engines discovery settings.
+    pub fn get_discovery(&self) -> &Discovery {
+        &self.discovery
+    }
+
+    /// Gets the storage settings.
+    pub fn get_storage(&self) -> &StrorageType {
+        &self.storage
+    }
+
+    /// Gets the sync settings, `None` when the storage isn't synced.
+    pub fn get_sync(&self) -> Option<&SyncSettings> {
+        self.sync.as_ref()
+    }
+
+    /// Gets the settings of the files produced by engine runs.
+    pub fn get_artifacts(&self) -> &ArtifactSettings {
+        &self.artifacts
+    }
+
+    /// Gets the retention settings of the engine runs.
+    pub fn get_retention(&self) -> &RetentionSettings {
+        &self.retention
+    }
+
+    /// Gets the settings of the log file.
+    pub fn get_logs(&self) -> &LogSettings {
+        &self.logs
+    }
+
+    /// Gets the proxy that engines should use, if any.
+    pub fn get_proxy(&self) -> Option<&str> {
+        self.proxy.as_deref()
+    }
+
+    /// Gets the vault of secrets with `~` expanded to the home directory, if there's one.
+    pub fn get_vault(&self) -> Option<PathBuf> {
+        self.vault.as_deref().map(expand_home)
+    }
+
+    /// Gets the overrides of all the configured engines, by engine name.
+    pub fn get_engines_configs(&self) -> &HashMap<String, EngineConfig> {
+        &self.engines
+    }
+
+    /// Gets the overrides of an engine, if it has a `[engines.<name>]` section.
+    pub fn get_engine_config(&self, engine: &str) -> Option<&EngineConfig> {
+        self.engines.get(engine)
+    }
+
+    /// Gets the name of the active profile, `None` when only the base section is used.
+    pub fn get_profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Gets the names of the profiles defined in the config file, sorted.
+    pub fn list_profiles(&self) -> &[String] {
+        &self.profiles
+    }
+
+    /// Gets the config file the instance was loaded from, `None` when it was parsed from text.
+    pub fn get_source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+/// ## Description
+/// Settings for searching engines inside the engines roots.
+/// ## Example
+/// **sherlock.toml:**
+/// ```toml
+/// [discovery]
+/// recursive = true
+/// max_depth = 3
+/// ignore = [".git", "*.disabled"]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct Discovery {
+    /// Search nested directories instead of the roots' direct children only.
+    #[serde(default)]
+    pub recursive: bool,
+    /// Limits how deep a recursive search goes below a root.
+    pub max_depth: Option<usize>,
+    /// Glob patterns of directory names to skip.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+/// ## Description
+/// Settings for the logs: the latest ones are kept in memory for the UI, and written to the log
+/// file if there's one.
+/// ## Example
+/// **sherlock.toml:**
+/// ```toml
+/// [logs]
+/// file = "~/.sherlock/sherlock.log"
+/// max_size = 10485760 # rotated once it's over 10 MiB
+/// keep = 3            # sherlock.log.1 to sherlock.log.3
+/// lines = 1000        # the latest logs the UI can read
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LogSettings {
+    /// The log file, `~` is expanded to the home directory.
+    pub file: Option<String>,
+    /// The size in bytes the log file is rotated at.
+    #[serde(default = "LogSettings::default_max_size")]
+    pub max_size: u64,
+    /// The number of rotated log files that are kept.
+    #[serde(default = "LogSettings::default_keep")]
+    pub keep: usize,
+    /// The number of latest logs kept in memory for the UI.
+    #[serde(default = "LogSettings::default_lines")]
+    pub lines: usize,
+}
+
+impl LogSettings {
+    fn default_max_size() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_keep() -> usize {
+        3
+    }
+
+    fn default_lines() -> usize {
+        1000
+    }
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            file: None,
+            max_size: LogSettings::default_max_size(),
+            keep: LogSettings::default_keep(),
+            lines: LogSettings::default_lines(),
+        }
+    }
+}
+
+/// ## Description
+/// User overrides for an engine's settings, the engine's own settings are used for missing keys.
+/// ## Example
+/// **sherlock.toml:**
+/// ```toml
+/// [engines.facebook]
+/// enabled = true
+/// timeout = 60
+/// prefix = "python3.11"
+/// working_dir = "~/investigations"
+/// concurrency = 2
+/// proxy = "socks5://127.0.0.1:9050"
+/// [engines.facebook.env]
+/// FB_LOCALE = "en_US"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct EngineConfig {
+    /// Disabled engines are listed but can't be executed.
+    pub enabled: Option<bool>,
+    /// The engine's timeout in seconds.
+    pub timeout: Option<u64>,
+    /// Replaces the engine's prefix.
+    pub prefix: Option<String>,
+    /// Extra environment variables for the engine.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The engine's working directory, `~` is expanded to the home directory.
+    pub working_dir: Option<String>,
+    /// The maximum number of the engine's processes that can run at the same time.
+    pub concurrency: Option<usize>,
+    /// The engine's proxy, overrides the global `proxy`.
+    pub proxy: Option<String>,
+}
+
+/// ## Description
+/// Expands a leading `~` in a path to the user's home directory.
+/// ## Example
+/// **Basic usage:**
+/// ```ignore
+/// let engines = expand_home("~/.sherlock/engines");
+/// ```
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-pub enum StrorageType {
-    #[serde(rename = "remote")]
-    Remote,
-    #[serde(rename = "local")]
-    Local { path: String, encrypted: bool },
+// write the content of a config that's upgraded from `version` to a backup next to it,
+// `<file>.v<version>.bak` or the first `<file>.v<version>.<n>.bak` that doesn't exist
+fn keep_backup(path: &Path, content: &str, version: u32) -> Result<PathBuf, String> {
+    for n in 0.. {
+        let mut backup = path.as_os_str().to_owned();
+        match n {
+            0 => backup.push(format!(".v{}.bak", version)),
+            n => backup.push(format!(".v{}.{}.bak", version, n)),
+        }
+        let backup = PathBuf::from(backup);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(mut file) => {
+                return file
+                    .write_all(content.as_bytes())
+                    .and_then(|_| file.sync_all())
+                    .map(|_| backup)
+                    .map_err(|error| error.to_string())
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.to_string()),
+        }
+    }
+    unreachable!("a backup name is always free")
 }
 
 #[cfg(test)]
@@ -67,4 +511,115 @@ mod tests {
         let manager: ConfigManager = toml::from_str(&content).unwrap();
         println!("{:?}", manager);
     }
+
+    #[test]
+    fn log_settings() {
+        let configs = ConfigManager::from_toml(
+            r#"
+            version = 3
+            engines_locations = ["./engines"]
+            storage = { sqlite = { path = "./sherlock.db" } }
+            [logs]
+            file = "./sherlock.log"
+            lines = 50
+            "#,
+        )
+        .unwrap();
+        assert_eq!(configs.get_logs().file.as_deref(), Some("./sherlock.log"));
+        assert_eq!(configs.get_logs().lines, 50);
+        //the settings that aren't given keep their default
+        assert_eq!(configs.get_logs().keep, 3);
+    }
+
+    #[test]
+    fn switch_profiles() {
+        let configs =
+            ConfigManager::from_file_with_profile("./mock_files/sherlock.toml", Some("acme"))
+                .unwrap();
+        assert_eq!(configs.get_profile(), Some("acme"));
+        assert_eq!(configs.list_profiles(), ["acme", "tor"]);
+        assert_eq!(configs.get_engines_locations().len(), 2);
+        assert!(configs.get_discovery().recursive);
+        assert_eq!(configs.get_discovery().ignore, [".git"]); // inherited from the base section
+
+        //switch back to the base section
+        let configs = configs.switch_profile(None).unwrap();
+        assert_eq!(configs.get_profile(), None);
+        assert_eq!(configs.get_engines_locations().len(), 1);
+        assert!(!configs.get_discovery().recursive);
+
+        //unknown profile
+        assert!(configs.switch_profile(Some("unknown")).is_err());
+    }
+
+    #[test]
+    fn engines_overrides() {
+        let configs = ConfigManager::from_toml_with_profile(
+            r#"
+            version = 3
+            engines_locations = ["./engines"]
+            storage = { sqlite = { path = "./sherlock.db" } }
+            [engines.google]
+            timeout = 30
+            [engines.google.env]
+            LANG = "en"
+            [profiles.offline.engines.google]
+            enabled = false
+            "#,
+            Some("offline"),
+        )
+        .unwrap();
+
+        let google = configs.get_engine_config("google").unwrap();
+        assert_eq!(google.enabled, Some(false));
+        assert_eq!(google.timeout, Some(30)); // inherited from the base section
+        assert_eq!(google.env["LANG"], "en");
+        assert!(configs.get_engine_config("bing").is_none());
+    }
+
+    #[test]
+    fn expand_home_dir() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(expand_home("~/engines"), home.join("engines"));
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~user/engines"), PathBuf::from("~user/engines"));
+        assert_eq!(expand_home("./engines"), PathBuf::from("./engines"));
+    }
+
+    #[test]
+    fn upgrade_file_keeps_backup() {
+        let dir =
+            std::env::temp_dir().join(format!("sherlock_upgrade_file_test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sherlock.toml");
+        fs::copy("./mock_files/migrations/v0_to_v1/before.toml", &path).unwrap();
+
+        //first call upgrades the file and keeps the original
+        assert!(ConfigManager::upgrade_file(&path).unwrap());
+        let backup = fs::read_to_string(dir.join("sherlock.toml.v0.bak")).unwrap();
+        assert_eq!(
+            backup,
+            fs::read_to_string("./mock_files/migrations/v0_to_v1/before.toml").unwrap()
+        );
+        let upgraded = ConfigManager::from_file(&path).unwrap();
+        assert_eq!(upgraded.get_version(), CURRENT_VERSION);
+
+        //second call has nothing to do
+        assert!(!ConfigManager::upgrade_file(&path).unwrap());
+
+        //upgrading the original again keeps the first backup
+        fs::copy("./mock_files/migrations/v0_to_v1/before.toml", &path).unwrap();
+        fs::write(dir.join("sherlock.toml.v0.bak"), "kept").unwrap();
+        assert!(ConfigManager::upgrade_file(&path).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.join("sherlock.toml.v0.bak")).unwrap(),
+            "kept"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("sherlock.toml.v0.1.bak")).unwrap(),
+            fs::read_to_string("./mock_files/migrations/v0_to_v1/before.toml").unwrap()
+        );
+        assert!(!dir.join("sherlock.toml.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
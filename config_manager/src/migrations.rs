@@ -0,0 +1,201 @@
+/*
+Config Migrations:
+Upgrades `sherlock.toml` documents written by older versions of the framework
+to the current schema, one version at a time.
+
+To change the schema: bump `CURRENT_VERSION`, append a `vN_to_vN+1` function to `MIGRATIONS`
+and add a `before.toml`/`after.toml` pair under `mock_files/migrations/vN_to_vN+1/`.
+Migrations that move or rename keys must update the `[profiles.<name>]` tables as well.
+ */
+use toml::value::Table;
+use toml::Value;
+
+/// The config schema version written and understood by this build.
+pub const CURRENT_VERSION: u32 = 3;
+
+/// A single schema upgrade that edits the document in place.
+type Migration = fn(&mut Table) -> Result<(), String>;
+
+/// The migrations chain, `MIGRATIONS[n]` upgrades a document from version `n` to `n + 1`.
+static MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// ## Description
+/// Reads the schema version of a config document.
+///
+/// Documents without a `version` key predate versioning and are treated as version 0.
+pub fn version_of(document: &Table) -> Result<u32, String> {
+    match document.get("version") {
+        None => Ok(0),
+        Some(Value::Integer(version)) => {
+            u32::try_from(*version).map_err(|_| format!("invalid config version: {}", version))
+        }
+        Some(other) => Err(format!("invalid config version: {}", other)),
+    }
+}
+
+/// ## Description
+/// Upgrades a config document to `CURRENT_VERSION` in memory.
+///
+/// Returns the upgraded document and the version it was upgraded from.
+/// Fails when the document was written by a newer version of the framework.
+/// ## Example
+/// **Basic usage:**
+/// ```ignore
+/// let document: toml::Value = toml::from_str(&content)?;
+/// let (document, from_version) = migrations::migrate(document)?;
+/// ```
+pub fn migrate(document: Value) -> Result<(Value, u32), String> {
+    let mut table = match document {
+        Value::Table(table) => table,
+        _ => return Err("config must be a toml table".to_owned()),
+    };
+
+    let from_version = version_of(&table)?;
+    if from_version > CURRENT_VERSION {
+        return Err(format!(
+            "config version {} is newer than the supported version {}",
+            from_version, CURRENT_VERSION
+        ));
+    }
+
+    //apply every migration between the document's version and the current one
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize) {
+        migration(&mut table)
+            .map_err(|error| format!("migrating config from version {}: {}", version, error))?;
+        table.insert("version".into(), Value::Integer(version as i64 + 1));
+    }
+
+    Ok((Value::Table(table), from_version))
+}
+
+// ------------------------------------------ Migrations ------------------------------------------
+
+// apply a migration to the base section and to every profile
+fn with_profiles(document: &mut Table, migration: Migration) -> Result<(), String> {
+    migration(document)?;
+    if let Some(Value::Table(profiles)) = document.get_mut("profiles") {
+        for (name, profile) in profiles.iter_mut() {
+            if let Value::Table(profile) = profile {
+                migration(profile).map_err(|error| format!("profile `{}`: {}", name, error))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// v0 configs predate versioning and have the same layout as v1, only the `version` key is added.
+fn v0_to_v1(_document: &mut Table) -> Result<(), String> {
+    Ok(())
+}
+
+// v2 replaced the single `engines_location` with the `engines_locations` list of roots.
+fn v1_to_v2(document: &mut Table) -> Result<(), String> {
+    match document.remove("engines_location") {
+        Some(Value::String(location)) => {
+            document.insert(
+                "engines_locations".into(),
+                Value::Array(vec![Value::String(location)]),
+            );
+            Ok(())
+        }
+        Some(_) => Err("`engines_location` must be a string".to_owned()),
+        None => Err("missing `engines_location`".to_owned()),
+    }
+}
+
+// v3 gave the storage variants settings, `storage = "local"` was already a table. The old remote
+// storage had no endpoint in the config, there's no url to carry over into `[storage.remote]`.
+fn v2_to_v3(document: &mut Table) -> Result<(), String> {
+    with_profiles(document, |section| match section.get("storage") {
+        Some(Value::String(storage)) if storage == "remote" => Err(
+            "`storage = \"remote\"` has no server url, replace it with a `[storage.remote]` \
+             table with the server's `url`"
+                .to_owned(),
+        ),
+        Some(Value::String(storage)) => Err(format!("unknown storage `{}`", storage)),
+        _ => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // runs a single migration over its fixture and compares it with the expected document
+    fn check_fixture(from_version: u32) {
+        let dir = format!(
+            "./mock_files/migrations/v{}_to_v{}",
+            from_version,
+            from_version + 1
+        );
+        let before: Value =
+            toml::from_str(&fs::read_to_string(format!("{}/before.toml", dir)).unwrap()).unwrap();
+        let after: Value =
+            toml::from_str(&fs::read_to_string(format!("{}/after.toml", dir)).unwrap()).unwrap();
+
+        let mut table = before.as_table().unwrap().clone();
+        assert_eq!(version_of(&table).unwrap(), from_version);
+        MIGRATIONS[from_version as usize](&mut table).unwrap();
+        table.insert("version".into(), Value::Integer(from_version as i64 + 1));
+        assert_eq!(Value::Table(table), after);
+    }
+
+    #[test]
+    fn every_migration_has_fixtures() {
+        for version in 0..CURRENT_VERSION {
+            check_fixture(version);
+        }
+    }
+
+    #[test]
+    fn migrate_to_current() {
+        let content = fs::read_to_string("./mock_files/migrations/v0_to_v1/before.toml").unwrap();
+        let (document, from_version) = migrate(toml::from_str(&content).unwrap()).unwrap();
+        assert_eq!(from_version, 0);
+        assert_eq!(
+            version_of(document.as_table().unwrap()).unwrap(),
+            CURRENT_VERSION
+        );
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let document: Value =
+            toml::from_str(&format!("version = {}", CURRENT_VERSION + 1)).unwrap();
+        assert!(migrate(document).is_err());
+    }
+
+    #[test]
+    fn reject_remote_storage_without_url() {
+        let mut table = toml::from_str::<Value>(
+            r#"
+            version = 2
+            engines_locations = ["./engines"]
+            [storage.local]
+            path = "./sherlock.db"
+            encrypted = false
+            [profiles.field]
+            storage = "remote"
+            "#,
+        )
+        .unwrap()
+        .as_table()
+        .unwrap()
+        .clone();
+        let error = v2_to_v3(&mut table).unwrap_err();
+        assert!(error.starts_with("profile `field`: `storage = \"remote\"` has no server url"));
+    }
+
+    #[test]
+    fn reject_invalid_version() {
+        let document: Value = toml::from_str("version = \"one\"").unwrap();
+        assert!(migrate(document).is_err());
+        //a version past `u32::MAX` doesn't wrap around to an old one
+        let document: Value = toml::from_str("version = 4294967297").unwrap();
+        assert_eq!(
+            migrate(document).unwrap_err(),
+            "invalid config version: 4294967297"
+        );
+    }
+}
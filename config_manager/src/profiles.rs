@@ -0,0 +1,176 @@
+/*
+Config Profiles:
+Named sets of overrides in `sherlock.toml` that inherit from the base (top level) section.
+
+A profile is a `[profiles.<name>]` table, its keys replace the base keys and nested tables
+are merged key by key, i.e:
+    engines_locations = ["~/.sherlock/engines"]
+    [discovery]
+    recursive = true
+
+    [profiles.acme]
+    engines_locations = ["~/acme/engines", "~/.sherlock/engines"]
+    proxy = "socks5://127.0.0.1:9050"
+    [profiles.acme.discovery]
+    ignore = ["*.disabled"]     # `recursive` is inherited from the base section
+ */
+use toml::value::Table;
+use toml::Value;
+
+/// The environment variable that selects the active profile when none is given explicitly.
+pub const PROFILE_ENV_VAR: &str = "SHERLOCK_PROFILE";
+
+/// The key of the profiles table in the config document.
+const PROFILES_KEY: &str = "profiles";
+
+/// Keys of tables that hold a single variant, i.e: `[storage.local]`.
+/// They're merged only when the profile picks the same variant, otherwise they are replaced.
+const VARIANT_KEYS: [&str; 1] = ["storage"];
+
+/// ## Description
+/// Splits the profiles table out of a config document.
+///
+/// Returns the profiles by name, the document is left with the base section only.
+pub fn take_profiles(document: &mut Table) -> Result<Table, String> {
+    match document.remove(PROFILES_KEY) {
+        None => Ok(Table::new()),
+        Some(Value::Table(profiles)) => {
+            //every profile must be a table of overrides
+            match profiles.iter().find(|(_, profile)| !profile.is_table()) {
+                Some((name, _)) => Err(format!("profile `{}` must be a table", name)),
+                None => Ok(profiles),
+            }
+        }
+        Some(_) => Err(format!("`{}` must be a table", PROFILES_KEY)),
+    }
+}
+
+/// ## Description
+/// Applies a profile's overrides onto the base section.
+///
+/// Tables are merged recursively, any other value (arrays included) replaces the base value.
+/// The schema `version` belongs to the whole file and can't be overridden.
+pub fn apply_profile(base: &mut Table, profile: &Table) {
+    merge(base, profile, true)
+}
+
+// merge `overrides` into `base`, `top_level` marks the base section itself
+fn merge(base: &mut Table, overrides: &Table, top_level: bool) {
+    for (key, value) in overrides {
+        if top_level && key == "version" {
+            continue;
+        }
+        match (base.get_mut(key), value) {
+            //a different variant replaces the base one instead of being added next to it
+            (Some(Value::Table(base_table)), Value::Table(profile_table))
+                if top_level
+                    && VARIANT_KEYS.contains(&key.as_str())
+                    && !profile_table.keys().eq(base_table.keys()) =>
+            {
+                base.insert(key.clone(), value.clone());
+            }
+            (Some(Value::Table(base_table)), Value::Table(profile_table)) => {
+                merge(base_table, profile_table, false)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// ## Description
+/// Resolves the name of the profile to activate.
+///
+/// An explicitly given profile wins over the `SHERLOCK_PROFILE` environment variable,
+/// an empty name means the base section.
+pub fn select_profile(explicit: Option<&str>) -> Option<String> {
+    explicit
+        .map(ToOwned::to_owned)
+        .or_else(|| std::env::var(PROFILE_ENV_VAR).ok())
+        .filter(|profile| !profile.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_nested_tables() {
+        let mut document: Table = toml::from_str(
+            r#"
+            version = 3
+            engines_locations = ["base"]
+            [discovery]
+            recursive = true
+            ignore = [".git"]
+            [profiles.acme]
+            version = 1
+            engines_locations = ["acme", "base"]
+            [profiles.acme.discovery]
+            ignore = []
+            "#,
+        )
+        .unwrap();
+
+        let profiles = take_profiles(&mut document).unwrap();
+        assert!(!document.contains_key(PROFILES_KEY));
+        apply_profile(&mut document, profiles["acme"].as_table().unwrap());
+
+        let expected: Table = toml::from_str(
+            r#"
+            version = 3
+            engines_locations = ["acme", "base"]
+            [discovery]
+            recursive = true
+            ignore = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(document, expected);
+    }
+
+    #[test]
+    fn replace_storage_variant() {
+        let mut document: Table = toml::from_str(
+            r#"
+            [storage.local]
+            path = "store.db"
+            encrypted = false
+            [profiles.secure.storage.local]
+            encrypted = true
+            [profiles.tree.storage.file_tree]
+            root = "./tree"
+            "#,
+        )
+        .unwrap();
+        let profiles = take_profiles(&mut document).unwrap();
+
+        //same variant, merged
+        let mut secure = document.clone();
+        apply_profile(&mut secure, profiles["secure"].as_table().unwrap());
+        let local = secure["storage"]["local"].as_table().unwrap();
+        assert_eq!(local["path"].as_str(), Some("store.db"));
+        assert_eq!(local["encrypted"].as_bool(), Some(true));
+
+        //another variant, replaced
+        let mut tree = document.clone();
+        apply_profile(&mut tree, profiles["tree"].as_table().unwrap());
+        assert_eq!(tree["storage"], profiles["tree"]["storage"]);
+    }
+
+    #[test]
+    fn reject_invalid_profiles() {
+        let mut document: Table = toml::from_str("[profiles]\nacme = 1").unwrap();
+        assert!(take_profiles(&mut document).is_err());
+
+        let mut document: Table = toml::from_str("profiles = 1").unwrap();
+        assert!(take_profiles(&mut document).is_err());
+    }
+
+    #[test]
+    fn explicit_profile_wins() {
+        assert_eq!(select_profile(Some("acme")), Some("acme".to_owned()));
+        assert_eq!(select_profile(Some("")), None);
+    }
+}
@@ -0,0 +1,341 @@
+/*
+Storage Configs:
+The `storage` section of `sherlock.toml`, one of:
+    [storage.local]         # the managed local store
+    path = "~/.sherlock/store.db"
+    encrypted = true
+
+    [storage.sqlite]        # a plain SQLite database
+    path = "./investigation.db"
+
+    [storage.file_tree]     # records as json files in a directory tree
+    root = "~/.sherlock/store"
+
+    [storage.remote]        # a remote HTTP storage server
+    url = "https://sherlock.example.com/api"
+    token = "vault:remote_token"
+    [storage.remote.tls]
+    verify = true
+    ca_cert = "~/.sherlock/ca.pem"
+
+The optional `sync` section syncs a local storage with a remote storage server, it takes the
+settings of `[storage.remote]`:
+    [sync]
+    url = "https://sherlock.example.com/api"
+    token = "vault:remote_token"
+    interval = 300          # seconds between background syncs, 0 syncs on demand only
+
+The optional `artifacts` section sets how the files produced by engine runs are kept:
+    [artifacts]
+    compress = true         # compress the files that get smaller, true by default
+
+The optional `retention` section sets how long the engine runs are kept, unset limits keep
+them forever:
+    [retention]
+    max_age_days = 365      # days a run is kept after it started
+    closed_case_days = 90   # days the runs of a closed case are kept after it was closed
+    purge_on_start = false  # purge the expired runs when the storage is opened
+ */
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum StrorageType {
+    #[serde(rename = "remote")]
+    Remote(RemoteStorage),
+    #[serde(rename = "local")]
+    Local { path: String, encrypted: bool },
+    #[serde(rename = "sqlite")]
+    Sqlite { path: String },
+    #[serde(rename = "file_tree")]
+    FileTree { root: String },
+}
+
+impl StrorageType {
+    /// ## Description
+    /// Checks that the storage settings are usable, without touching the disk or the network.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use config_manager::StrorageType;
+    /// let storage = StrorageType::Sqlite { path: "".into() };
+    /// assert!(storage.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            StrorageType::Local { path, .. } | StrorageType::Sqlite { path } => {
+                if path.trim().is_empty() {
+                    return Err("storage path can't be empty".to_owned());
+                }
+                Ok(())
+            }
+            StrorageType::FileTree { root } => {
+                if root.trim().is_empty() {
+                    return Err("storage root can't be empty".to_owned());
+                }
+                Ok(())
+            }
+            StrorageType::Remote(remote) => remote.validate(),
+        }
+    }
+}
+
+/// ## Description
+/// Settings of a remote storage server.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RemoteStorage {
+    /// The base url of the server's API, i.e: `https://sherlock.example.com/api`.
+    pub url: String,
+    /// A reference to the auth token, `vault:<secret name>` or `env:<variable>`.
+    /// The token itself never appears in the config file.
+    pub token: Option<String>,
+    #[serde(default)]
+    pub tls: TlsOptions,
+}
+
+impl RemoteStorage {
+    // check the url, the token reference and the tls options
+    fn validate(&self) -> Result<(), String> {
+        let (scheme, rest) = self
+            .url
+            .split_once("://")
+            .ok_or_else(|| format!("invalid storage url: {}", self.url))?;
+        if scheme != "http" && scheme != "https" {
+            return Err(format!("unsupported storage url scheme: {}", scheme));
+        }
+        if rest.split('/').next().unwrap_or_default().is_empty() {
+            return Err(format!("storage url has no host: {}", self.url));
+        }
+        if scheme == "http" && self.tls.ca_cert.is_some() {
+            return Err("`tls.ca_cert` requires an https url".to_owned());
+        }
+
+        if let Some(token) = &self.token {
+            SecretRef::parse(token)?;
+        }
+        Ok(())
+    }
+}
+
+/// ## Description
+/// Settings of the `sync` section, the remote storage server the local storage is synced with.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SyncSettings {
+    #[serde(flatten)]
+    pub remote: RemoteStorage,
+    /// The seconds between background syncs, `0` syncs on demand only.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+impl SyncSettings {
+    /// ## Description
+    /// Checks the sync settings against the storage that would be synced.
+    pub fn validate(&self, storage: &StrorageType) -> Result<(), String> {
+        if let StrorageType::Remote(_) = storage {
+            return Err(
+                "a remote storage can't be synced, `sync` needs a local storage".to_owned(),
+            );
+        }
+        self.remote.validate()
+    }
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+/// ## Description
+/// Settings of the `artifacts` section, how the files produced by engine runs are kept.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ArtifactSettings {
+    /// Compress the files, only kept when it makes them smaller.
+    #[serde(default = "default_compress")]
+    pub compress: bool,
+}
+
+impl Default for ArtifactSettings {
+    fn default() -> Self {
+        ArtifactSettings {
+            compress: default_compress(),
+        }
+    }
+}
+
+fn default_compress() -> bool {
+    true
+}
+
+/// ## Description
+/// Settings of the `retention` section, how long the engine runs are kept.
+///
+/// Cases can have their own policy, these limits apply to the other cases.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RetentionSettings {
+    /// The days a run is kept after it started.
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    /// The days the runs of a closed case are kept after it was closed.
+    #[serde(default)]
+    pub closed_case_days: Option<u32>,
+    /// Purge the expired runs when the storage is opened.
+    #[serde(default)]
+    pub purge_on_start: bool,
+}
+
+/// ## Description
+/// TLS options for connecting to a remote storage server.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TlsOptions {
+    /// Verify the server's certificate, only disable for local testing.
+    #[serde(default = "default_verify")]
+    pub verify: bool,
+    /// A PEM file with an extra CA certificate to trust, i.e: for a self-hosted server.
+    pub ca_cert: Option<String>,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        TlsOptions {
+            verify: true,
+            ca_cert: None,
+        }
+    }
+}
+
+fn default_verify() -> bool {
+    true
+}
+
+/// ## Description
+/// A reference to a secret that is kept outside the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretRef {
+    /// A secret in the vault, by name.
+    Vault(String),
+    /// An environment variable.
+    Env(String),
+}
+
+impl SecretRef {
+    /// ## Description
+    /// Parses a `vault:<secret name>` or `env:<variable>` reference.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use config_manager::SecretRef;
+    /// assert_eq!(
+    ///     SecretRef::parse("vault:remote_token").unwrap(),
+    ///     SecretRef::Vault("remote_token".into())
+    /// );
+    /// ```
+    pub fn parse(reference: &str) -> Result<SecretRef, String> {
+        match reference.split_once(':') {
+            Some(("vault", name)) if !name.is_empty() => Ok(SecretRef::Vault(name.to_owned())),
+            Some(("env", name)) if !name.is_empty() => Ok(SecretRef::Env(name.to_owned())),
+            _ => Err(format!(
+                "invalid secret reference `{}`, expected `vault:<name>` or `env:<variable>`",
+                reference
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<StrorageType, String> {
+        #[derive(Deserialize)]
+        struct Document {
+            storage: StrorageType,
+        }
+        let document: Document = toml::from_str(content).map_err(|error| error.to_string())?;
+        document.storage.validate()?;
+        Ok(document.storage)
+    }
+
+    #[test]
+    fn parse_variants() {
+        let storage = parse("[storage.local]\npath = \"store.db\"\nencrypted = true").unwrap();
+        assert!(matches!(storage, StrorageType::Local { encrypted: true, .. }));
+
+        let storage = parse("[storage.sqlite]\npath = \"store.db\"").unwrap();
+        assert!(matches!(storage, StrorageType::Sqlite { .. }));
+
+        let storage = parse("[storage.file_tree]\nroot = \"./store\"").unwrap();
+        assert!(matches!(storage, StrorageType::FileTree { .. }));
+
+        let storage = parse(
+            "[storage.remote]\nurl = \"https://example.com/api\"\ntoken = \"env:TOKEN\"",
+        )
+        .unwrap();
+        match storage {
+            StrorageType::Remote(remote) => {
+                assert!(remote.tls.verify);
+                assert_eq!(remote.token.as_deref(), Some("env:TOKEN"));
+            }
+            _ => panic!("expected remote storage"),
+        }
+    }
+
+    #[test]
+    fn validate_remote() {
+        assert!(parse("[storage.remote]").is_err()); // missing url
+        assert!(parse("[storage.remote]\nurl = \"example.com\"").is_err());
+        assert!(parse("[storage.remote]\nurl = \"ftp://example.com\"").is_err());
+        assert!(parse("[storage.remote]\nurl = \"https:///api\"").is_err());
+        assert!(parse("[storage.remote]\nurl = \"https://a\"\ntoken = \"secret\"").is_err());
+        assert!(
+            parse("[storage.remote]\nurl = \"http://a\"\n[storage.remote.tls]\nca_cert = \"ca.pem\"")
+                .is_err()
+        );
+        assert!(parse("[storage.remote]\nurl = \"http://localhost:8080\"").is_ok());
+    }
+
+    #[test]
+    fn parse_sync() {
+        #[derive(Deserialize)]
+        struct Document {
+            storage: StrorageType,
+            sync: SyncSettings,
+        }
+        let document: Document = toml::from_str(
+            "[storage.local]\npath = \"store.db\"\nencrypted = true\n\
+             [sync]\nurl = \"https://example.com/api\"\ntoken = \"env:TOKEN\"\n\
+             [sync.tls]\nverify = false",
+        )
+        .unwrap();
+        assert!(document.sync.validate(&document.storage).is_ok());
+        assert_eq!(document.sync.interval, 300);
+        assert!(!document.sync.remote.tls.verify);
+
+        //a remote storage is synced already
+        let remote = parse("[storage.remote]\nurl = \"https://example.com/api\"").unwrap();
+        assert!(document.sync.validate(&remote).is_err());
+    }
+
+    #[test]
+    fn parse_artifacts() {
+        let settings: ArtifactSettings = toml::from_str("").unwrap();
+        assert!(settings.compress);
+        let settings: ArtifactSettings = toml::from_str("compress = false").unwrap();
+        assert!(!settings.compress);
+    }
+
+    #[test]
+    fn parse_retention() {
+        let settings: RetentionSettings = toml::from_str("").unwrap();
+        assert_eq!(settings, RetentionSettings::default());
+        let settings: RetentionSettings =
+            toml::from_str("max_age_days = 90\npurge_on_start = true").unwrap();
+        assert_eq!(settings.max_age_days, Some(90));
+        assert_eq!(settings.closed_case_days, None);
+        assert!(settings.purge_on_start);
+    }
+
+    #[test]
+    fn validate_paths() {
+        assert!(parse("[storage.local]\npath = \"\"\nencrypted = false").is_err());
+        assert!(parse("[storage.file_tree]\nroot = \" \"").is_err());
+    }
+}
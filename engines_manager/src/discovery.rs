@@ -0,0 +1,100 @@
+use glob::Pattern;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file that marks a directory as an engine directory.
+pub const ENGINE_CONFIG_FILE: &str = "config.json";
+
+/// ## Description
+/// Options for discovering engines inside an engines root directory.
+#[derive(Clone, Debug, Default)]
+pub struct DiscoveryOptions {
+    /// Look for engines in nested directories instead of the root's direct children only.
+    pub recursive: bool,
+    /// The maximum depth below the root to look in when `recursive` is set, unlimited if `None`.
+    pub max_depth: Option<usize>,
+    /// Glob patterns of directory names to skip, i.e: `.git`, `*.disabled`.
+    pub ignore: Vec<String>,
+}
+
+/// ## Description
+/// The outcome of loading engines from several roots.
+#[derive(Debug, Default)]
+pub struct DiscoveryReport {
+    /// The loaded engines names and the roots they came from.
+    pub loaded: Vec<(String, PathBuf)>,
+    /// Engines that were skipped because a root with higher precedence has an engine with the same name.
+    pub shadowed: Vec<ShadowedEngine>,
+    /// Config files that couldn't be loaded and the reason.
+    pub failed: Vec<(PathBuf, crate::Error)>,
+}
+
+/// An engine that lost a name clash to an engine from a root with higher precedence.
+#[derive(Debug)]
+pub struct ShadowedEngine {
+    /// The clashing engine name.
+    pub name: String,
+    /// The config file of the skipped engine.
+    pub config: PathBuf,
+    /// The root of the engine that was loaded under that name, `None` if it was added manually.
+    pub shadowed_by: Option<PathBuf>,
+}
+
+/// ## Description
+/// Finds the config files of the engines inside a root directory.
+///
+/// A directory that contains a `config.json` is an engine directory and isn't searched any deeper.
+/// The results are sorted so discovery is deterministic.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use engines_manager::{find_engine_configs, DiscoveryOptions};
+/// let options = DiscoveryOptions { recursive: true, ..Default::default() };
+/// let configs = find_engine_configs("../config_manager/mock_files/engines", &options).unwrap();
+/// assert!(!configs.is_empty());
+/// ```
+pub fn find_engine_configs<P: AsRef<Path>>(
+    root: P,
+    options: &DiscoveryOptions,
+) -> Result<Vec<PathBuf>, String> {
+    //compile the ignore patterns once
+    let ignore = options
+        .ignore
+        .iter()
+        .map(|pattern| Pattern::new(pattern).map_err(|error| format!("{}: {}", pattern, error)))
+        .collect::<Result<Vec<Pattern>, String>>()?;
+
+    let mut configs = vec![];
+    search_dir(root.as_ref(), 1, options, &ignore, &mut configs)
+        .map_err(|error| format!("{}: {}", root.as_ref().display(), error))?;
+    configs.sort();
+    Ok(configs)
+}
+
+// collect the engines configs in the sub directories of `dir`, which is `depth - 1` levels below the root
+fn search_dir(
+    dir: &Path,
+    depth: usize,
+    options: &DiscoveryOptions,
+    ignore: &[Pattern],
+    configs: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let sub_dirs = fs::read_dir(dir)?
+        .filter_map(|e| e.ok()) // unwrap entries
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false)) // keep directories only
+        .filter(|e| !ignore.iter().any(|p| p.matches(&e.file_name().to_string_lossy())))
+        .map(|e| e.path());
+
+    for sub_dir in sub_dirs {
+        let config = sub_dir.join(ENGINE_CONFIG_FILE);
+        if config.is_file() {
+            configs.push(config);
+        } else if options.recursive && options.max_depth.is_none_or(|max| depth < max) {
+            //unreadable nested directories are skipped, only the root has to be readable
+            let _ = search_dir(&sub_dir, depth + 1, options, ignore, configs);
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,21 @@
+use crate::settings::{EngineOverride, EngineSettings};
 use serde::Deserialize;
 use serde_valid::Validate;
-use std::{collections::HashMap, process};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+/// The environment variable with the directory an engine can leave its files in,
+/// i.e: downloaded avatars or page snapshots.
+pub const OUTPUT_DIR_ENV_VAR: &str = "SHERLOCK_OUTPUT_DIR";
+
 // ----------------------------------------- Engine Struct ----------------------------------------
 
 /// ## Description
@@ -8,7 +23,9 @@ use std::{collections::HashMap, process};
 /// and operate it.
 /// The struct instance is created from the engine's `config.json` file
 /// or manually by the user through the UI.
-#[derive(Deserialize, Validate, Debug)]
+///
+/// The clones of an engine share its running processes' limit.
+#[derive(Clone, Deserialize, Validate, Debug)]
 pub struct Engine {
     /// The name of the engine.
     name: String,
@@ -21,6 +38,14 @@ pub struct Engine {
     prefix: Option<String>,
     /// An optional description that describes the engine.
     description: Option<String>,
+    /// The version of the engine, recorded with the runs' evidence.
+    version: Option<String>,
+    /// The user's overrides for the engine's settings.
+    #[serde(skip)]
+    overrides: EngineOverride,
+    /// Keeps track of the engine's running processes.
+    #[serde(skip)]
+    running: Arc<RunningLimit>,
 }
 
 impl Engine {
@@ -44,10 +69,10 @@ impl Engine {
             path: path.to_owned(),
             prefix: prefix.map(ToOwned::to_owned),
             description: description.map(ToOwned::to_owned),
-            commands: match commands { 
-                Some(commands) => commands,
-                None => Vec::new(),
-            },
+            version: None,
+            commands: commands.unwrap_or_default(),
+            overrides: EngineOverride::default(),
+            running: Arc::default(),
         }
     }
 
@@ -61,27 +86,135 @@ impl Engine {
     ///         println!("{}",res);  
     /// ```
     pub fn execute(&self, command_name: &str, query: &str) -> Result<String, EngineError> {
+        self.run(command_name, query, None, &|| false, None)
+    }
+
+    /// ## Description
+    /// Executes a given command, the engine gets `output_dir` in the `SHERLOCK_OUTPUT_DIR`
+    /// environment variable to leave its files in.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     let output = engine.execute_with_output("user", "user123", Path::new("/tmp/run"))?;
+    /// ```
+    pub fn execute_with_output(
+        &self,
+        command_name: &str,
+        query: &str,
+        output_dir: &Path,
+    ) -> Result<String, EngineError> {
+        self.run(command_name, query, Some(output_dir), &|| false, None)
+    }
+
+    /// ## Description
+    /// Executes a given command until `cancelled` returns true, the process is killed then and
+    /// the execution fails with `EngineError::Cancelled`. `cancelled` is checked while the
+    /// process runs, it should be cheap.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     let stop = AtomicBool::new(false);
+    ///     let output = engine.execute_cancellable("user", "user123", None, &|| stop.load(Ordering::Relaxed))?;
+    /// ```
+    pub fn execute_cancellable(
+        &self,
+        command_name: &str,
+        query: &str,
+        output_dir: Option<&Path>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<String, EngineError> {
+        self.run(command_name, query, output_dir, cancelled, None)
+    }
+
+    /// ## Description
+    /// Executes a given command like `execute_cancellable`, `invocation` is set to how its process
+    /// is started right before it's started: the argv and the SHA-256 of the binary/script it's
+    /// started from, unlike `get_invocation` that may describe the binary before it changed.
+    /// It's left `None` if no process was started.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     let mut invocation = None;
+    ///     let output = engine.execute_described("user", "user123", None, &|| false, &mut invocation)?;
+    /// ```
+    pub fn execute_described(
+        &self,
+        command_name: &str,
+        query: &str,
+        output_dir: Option<&Path>,
+        cancelled: &dyn Fn() -> bool,
+        invocation: &mut Option<Invocation>,
+    ) -> Result<String, EngineError> {
+        self.run(command_name, query, output_dir, cancelled, Some(invocation))
+    }
+
+    /// ## Description
+    /// Describes how a command would be executed: the argv, the engine's version and the
+    /// SHA-256 of its binary/script (`None` if it can't be read).
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     let invocation = engine.get_invocation("user", "user123")?;
+    ///     println!("{}", invocation.argv.join(" "));
+    /// ```
+    pub fn get_invocation(&self, command_name: &str, query: &str) -> Result<Invocation, EngineError> {
+        let command = self
+            .commands
+            .iter()
+            .find(|c| c.get_name() == command_name)
+            .ok_or(EngineError::UnknownCommand)?;
+        let settings = self.get_settings();
+        Ok(Invocation {
+            engine: self.name.clone(),
+            version: self.version.clone(),
+            binary_digest: self.get_binary_digest(&settings),
+            argv: self.get_argv(command, query, &settings),
+        })
+    }
+
+    fn run(
+        &self,
+        command_name: &str,
+        query: &str,
+        output_dir: Option<&Path>,
+        cancelled: &dyn Fn() -> bool,
+        invocation: Option<&mut Option<Invocation>>,
+    ) -> Result<String, EngineError> {
         //get the command
         let command = self.commands.iter().find(|c|c.get_name() == command_name);//get the command
 
         match command {
             Some(command) => {
-                //get the args for the command as a vector.
-                let args: Vec<String> = command
-                    .parse_args(query) //replace the queryholder with the requested query
-                    .split(' ') //split args by spaces
-                    .map(ToOwned::to_owned)
-                    .collect();
-
-                //handle the optional prefix
-                let output = if let Some(prefix) = &self.prefix {
-                    process::Command::new(prefix)
-                        .arg(&self.path)
-                        .args(&args)
-                        .output().map_err(|_|EngineError::ExecutionFailed)?
-                } else {
-                    process::Command::new(&self.path).args(&args).output().map_err(|_|EngineError::ExecutionFailed)?
-                };
+                let settings = self.get_settings();
+                if !settings.enabled {
+                    return Err(EngineError::Disabled);
+                }
+
+                let argv = self.get_argv(command, query, &settings);
+                let mut process = process::Command::new(&argv[0]);
+                process.args(&argv[1..]).envs(settings.get_env());
+                if let Some(output_dir) = output_dir {
+                    process.env(OUTPUT_DIR_ENV_VAR, output_dir);
+                }
+                if let Some(working_dir) = &settings.working_dir {
+                    process.current_dir(working_dir);
+                }
+
+                //wait for a free slot if the engine has a concurrency limit
+                let _slot = self.running.acquire(settings.concurrency);
+                if cancelled() {
+                    return Err(EngineError::Cancelled);
+                }
+                //described last, the digest is of the binary as it's started
+                if let Some(invocation) = invocation {
+                    *invocation = Some(Invocation {
+                        engine: self.name.clone(),
+                        version: self.version.clone(),
+                        binary_digest: self.get_binary_digest(&settings),
+                        argv: argv.clone(),
+                    });
+                }
+                let output = run_process(process, settings.get_timeout(), cancelled)?;
 
                 Ok(std::str::from_utf8(&output.stdout).map_err(|_|EngineError::UnknownError)?.to_owned())
             }
@@ -89,6 +222,68 @@ impl Engine {
         }
     }
 
+    // the program and the arguments a command is executed with
+    fn get_argv(&self, command: &Command, query: &str, settings: &EngineSettings) -> Vec<String> {
+        //handle the optional prefix
+        let mut argv = match &settings.prefix {
+            Some(prefix) => vec![prefix.clone(), self.path.clone()],
+            None => vec![self.path.clone()],
+        };
+        argv.extend(
+            command
+                .parse_args(query) //replace the queryholder with the requested query
+                .split(' ') //split args by spaces
+                .map(ToOwned::to_owned),
+        );
+        argv
+    }
+
+    // the SHA-256 (hex) of the engine's binary/script, bare names are looked up in `PATH`
+    fn get_binary_digest(&self, settings: &EngineSettings) -> Option<String> {
+        let path = Path::new(&self.path);
+        let binary = if path.components().count() > 1 || path.is_absolute() {
+            match &settings.working_dir {
+                Some(working_dir) if path.is_relative() => Path::new(working_dir).join(path),
+                _ => path.to_path_buf(),
+            }
+        } else {
+            env::split_paths(&env::var_os("PATH")?)
+                .map(|dir| dir.join(path))
+                .find(|candidate| candidate.is_file())
+                .unwrap_or_else(|| PathBuf::from(path))
+        };
+        let content = fs::read(binary).ok()?;
+        Some(
+            Sha256::digest(&content)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        )
+    }
+
+    /// ## Description
+    /// Gets the settings the engine is executed with, after applying the user's overrides.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     let settings = engine.get_settings();
+    ///     println!("enabled: {}, timeout: {:?}", settings.enabled, settings.timeout);
+    /// ```
+    pub fn get_settings(&self) -> EngineSettings {
+        EngineSettings::resolve(self.prefix.as_ref(), &self.overrides)
+    }
+
+    /// ## Description
+    /// Replaces the user's overrides for the engine's settings.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     engine.set_overrides(EngineOverride { timeout: Some(30), ..Default::default() });
+    /// ```
+    pub fn set_overrides(&mut self, overrides: EngineOverride) {
+        self.overrides = overrides;
+    }
+
     /// ## Description
     /// Gets the name of the engine
     /// ## Example
@@ -98,7 +293,7 @@ impl Engine {
     ///         println!("{}",engine_name);  
     /// ```
     pub fn get_name(&self) -> &str {
-        return &self.name;
+        &self.name
     }
 
     /// ## Description
@@ -133,6 +328,19 @@ impl Engine {
         self.description.as_ref()
     }
 
+    /// ## Description
+    /// Gets the version the engine declares in its config.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    ///     if let Some(version) = engine.get_version() {
+    ///         println!("engine version: {}", version);
+    ///     }
+    /// ```
+    pub fn get_version(&self) -> Option<&String> {
+        self.version.as_ref()
+    }
+
     /// ## Description
     /// Creates a new engine command.
     /// ## Example
@@ -189,6 +397,20 @@ impl Engine {
 
 // ------------------------------------------ Aux Structs ------------------------------------------
 
+/// ## Description
+/// How an engine's command is executed, kept as evidence of the run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Invocation {
+    /// The name of the engine.
+    pub engine: String,
+    /// The version the engine declares in its config.
+    pub version: Option<String>,
+    /// The SHA-256 (hex) of the engine's binary/script, `None` if it can't be read.
+    pub binary_digest: Option<String>,
+    /// The program and the arguments, with the query in place.
+    pub argv: Vec<String>,
+}
+
 /// A struct that is used by the `Engine` struct to hold commands information.
 #[derive(Clone, Deserialize, Validate, Debug)]
 pub struct Command {
@@ -280,6 +502,93 @@ impl Command {
     }
 }
 
+/// Limits the number of an engine's processes that run at the same time.
+#[derive(Debug, Default)]
+struct RunningLimit {
+    running: Mutex<usize>,
+    released: Condvar,
+}
+
+impl RunningLimit {
+    // blocks until there are less than `limit` running processes and takes a slot
+    fn acquire(&self, limit: Option<usize>) -> RunningSlot<'_> {
+        let mut running = self.running.lock().unwrap();
+        if let Some(limit) = limit {
+            while *running >= limit.max(1) {
+                running = self.released.wait(running).unwrap();
+            }
+        }
+        *running += 1;
+        RunningSlot { limit: self }
+    }
+}
+
+/// A taken `RunningLimit` slot, freed when dropped.
+struct RunningSlot<'a> {
+    limit: &'a RunningLimit,
+}
+
+impl Drop for RunningSlot<'_> {
+    fn drop(&mut self) {
+        *self.limit.running.lock().unwrap() -= 1;
+        self.limit.released.notify_one();
+    }
+}
+
+// Runs a process to completion and collects its output,
+// killing it if the timeout passes or it's cancelled first.
+fn run_process(
+    mut command: process::Command,
+    timeout: Option<Duration>,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<process::Output, EngineError> {
+    let mut child = command
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|_| EngineError::ExecutionFailed)?;
+
+    //drain the pipes on their own threads so a chatty process can't fill them and block
+    let stdout = drain_pipe(child.stdout.take());
+    let stderr = drain_pipe(child.stderr.take());
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let status = loop {
+        match child.try_wait().map_err(|_| EngineError::ExecutionFailed)? {
+            Some(status) => break status,
+            None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(EngineError::Timeout);
+            }
+            None if cancelled() => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(EngineError::Cancelled);
+            }
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+
+    Ok(process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+// read a child's pipe to the end on a new thread
+fn drain_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
 // ------------------------------------------ Custom Error ------------------------------------------
 /// ## Description
 /// Custom error struct for the Engines Manager crate.
@@ -295,6 +604,12 @@ pub enum EngineError {
     ExecutionFailed,
     /// Occurs when an unknown command has given.
     UnknownCommand,
+    /// Occurs when executing an engine that was disabled by the user.
+    Disabled,
+    /// Occurs when a command runs longer than the engine's timeout.
+    Timeout,
+    /// Occurs when a command was cancelled, its process was killed.
+    Cancelled,
     /// Defualt Error
     UnknownError,
 }
@@ -308,6 +623,9 @@ impl std::fmt::Display for EngineError {
             EngineError::UnknownCommand => f.write_str("UnknownCommand"),
             EngineError::UnknownError => f.write_str("UnknownError"),
             EngineError::InvalidEnginePath => f.write_str("InvalidEnginePath"),
+            EngineError::Disabled => f.write_str("Disabled"),
+            EngineError::Timeout => f.write_str("Timeout"),
+            EngineError::Cancelled => f.write_str("Cancelled"),
         }
     }
 }
@@ -321,6 +639,9 @@ impl std::error::Error for EngineError {
             EngineError::UnknownCommand => "Unknown command has given",
             EngineError::UnknownError => "Unknown error",
             EngineError::InvalidEnginePath =>"Invalid engine path has provided",
+            EngineError::Disabled => "The engine is disabled",
+            EngineError::Timeout => "The command has timed out",
+            EngineError::Cancelled => "The command was cancelled",
         }
     }
 }
@@ -55,6 +55,7 @@ mod command_tests {
 #[cfg(test)]
 mod engine_tests {
     use crate::engine::*;
+    use crate::settings::EngineOverride;
     use serde_valid::json::FromJsonReader;
     use std::fs;
 
@@ -140,6 +141,51 @@ mod engine_tests {
         );
     }
 
+    #[test]
+    fn invocation() {
+        let path = "../config_manager/mock_files/engines/facebook_engine/config.json";
+        let engine = Engine::from_json_reader(fs::File::open(path).unwrap()).unwrap();
+        let invocation = engine.get_invocation("user", "user123").unwrap();
+        assert_eq!(invocation.version.as_deref(), Some("1.0.0"));
+        assert_eq!(
+            invocation.argv,
+            vec![
+                "python3",
+                "../config_manager/mock_files/engines/facebook_engine/facebook_engine.py",
+                "-search_user",
+                "user123"
+            ]
+        );
+        let script =
+            fs::read("../config_manager/mock_files/engines/facebook_engine/facebook_engine.py")
+                .unwrap();
+        let digest: String = sha2::Sha256::digest(&script)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(invocation.binary_digest, Some(digest));
+        assert_eq!(
+            engine.get_invocation("search", "user123").unwrap_err(),
+            EngineError::UnknownCommand
+        );
+
+        //bare names are found in `PATH`, unknown binaries have no digest
+        let commands = vec![Command::new("env", "$query", None).unwrap()];
+        let engine = Engine::new("engine", "printenv", None, Some(commands.clone()), None);
+        assert!(engine.get_invocation("env", "HOME").unwrap().binary_digest.is_some());
+
+        //a run is described by how its process was started, nothing was started for an unknown command
+        let mut invocation = None;
+        engine.execute_described("env", "HOME", None, &|| false, &mut invocation).unwrap();
+        assert_eq!(invocation, Some(engine.get_invocation("env", "HOME").unwrap()));
+        let mut invocation = None;
+        assert!(engine.execute_described("search", "HOME", None, &|| false, &mut invocation).is_err());
+        assert_eq!(invocation, None);
+
+        let engine = Engine::new("engine", "./missing", None, Some(commands), None);
+        assert_eq!(engine.get_invocation("env", "HOME").unwrap().binary_digest, None);
+    }
+
     #[test]
     fn check_name() {
         let engine = Engine::new("Engine", "path", None, None, None);
@@ -160,4 +206,101 @@ mod engine_tests {
             &"description".to_owned()
         );
     }
+
+    #[test]
+    fn disabled_engine() {
+        let commands = vec![Command::new("search", "$query", None).unwrap()];
+        let mut engine = Engine::new("engine", "echo", None, Some(commands), None);
+        engine.set_overrides(EngineOverride {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        assert_eq!(
+            engine.execute("search", "test").unwrap_err(),
+            EngineError::Disabled
+        );
+    }
+
+    #[test]
+    fn timeout_kills_process() {
+        let commands = vec![Command::new("wait", "$query", None).unwrap()];
+        let mut engine = Engine::new("sleeper", "sleep", None, Some(commands), None);
+        engine.set_overrides(EngineOverride {
+            timeout: Some(1),
+            ..Default::default()
+        });
+        assert!(engine.execute("wait", "0").is_ok());
+
+        let start = std::time::Instant::now();
+        assert_eq!(engine.execute("wait", "10").unwrap_err(), EngineError::Timeout);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cancel_kills_process() {
+        let commands = vec![Command::new("wait", "$query", None).unwrap()];
+        let engine = Engine::new("sleeper", "sleep", None, Some(commands), None);
+        let start = std::time::Instant::now();
+        let cancelled = || start.elapsed() > std::time::Duration::from_millis(100);
+        assert_eq!(
+            engine.execute_cancellable("wait", "10", None, &cancelled).unwrap_err(),
+            EngineError::Cancelled
+        );
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn output_dir() {
+        let commands = vec![Command::new("env", "$query", None).unwrap()];
+        let engine = Engine::new("engine", "printenv", None, Some(commands), None);
+        let dir = std::path::Path::new("/tmp/sherlock-run");
+        assert_eq!(
+            engine.execute_with_output("env", OUTPUT_DIR_ENV_VAR, dir).unwrap(),
+            "/tmp/sherlock-run\n"
+        );
+        assert_eq!(engine.execute("env", OUTPUT_DIR_ENV_VAR).unwrap(), "");
+    }
+
+    #[test]
+    fn env_and_working_dir() {
+        let commands = vec![Command::new("env", "$query", None).unwrap()];
+        let mut engine = Engine::new("engine", "printenv", None, Some(commands), None);
+        engine.set_overrides(EngineOverride {
+            env: HashMap::from([("SHERLOCK_TEST_VAR".to_owned(), "value".to_owned())]),
+            proxy: Some("socks5://127.0.0.1:9050".into()),
+            ..Default::default()
+        });
+        assert_eq!(engine.execute("env", "SHERLOCK_TEST_VAR").unwrap(), "value\n");
+        assert_eq!(
+            engine.execute("env", "HTTPS_PROXY").unwrap(),
+            "socks5://127.0.0.1:9050\n"
+        );
+
+        let commands = vec![Command::new("pwd", "-L$query", None).unwrap()];
+        let mut engine = Engine::new("engine", "pwd", None, Some(commands), None);
+        engine.set_overrides(EngineOverride {
+            working_dir: Some("/".into()),
+            ..Default::default()
+        });
+        assert_eq!(engine.execute("pwd", "").unwrap(), "/\n");
+    }
+
+    #[test]
+    fn concurrency_limit() {
+        let commands = vec![Command::new("wait", "$query", None).unwrap()];
+        let mut engine = Engine::new("sleeper", "sleep", None, Some(commands), None);
+        engine.set_overrides(EngineOverride {
+            concurrency: Some(1),
+            ..Default::default()
+        });
+
+        //two runs of the same engine can't overlap
+        let start = std::time::Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| engine.execute("wait", "0.3").unwrap());
+            }
+        });
+        assert!(start.elapsed() >= std::time::Duration::from_millis(600));
+    }
 }
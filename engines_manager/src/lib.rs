@@ -1,14 +1,33 @@
-use engine::Engine;
-pub use engine::EngineError;
+pub use discovery::{
+    find_engine_configs, DiscoveryOptions, DiscoveryReport, ShadowedEngine, ENGINE_CONFIG_FILE,
+};
+pub use engine::{Command, Engine, EngineError, Invocation, OUTPUT_DIR_ENV_VAR};
+pub use settings::{EngineOverride, EngineSettings};
 use serde_valid::json::FromJsonReader;
-use std::{cell::RefCell, collections::HashMap, fs};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
+mod discovery;
 mod engine;
+mod settings;
 
 /// ## Description:
 /// A struct that manages the engines of the system.
+///
+/// The manager can be shared between threads, commands of different engines
+/// (and of the same engine, up to its concurrency limit) are executed in parallel.
 pub struct EnginesManager {
-    engines: RefCell<HashMap<String, Engine>>,
+    /// The engines by name, a command runs on a clone of its engine's `Arc` so the lock isn't
+    /// held while the process runs.
+    engines: RwLock<HashMap<String, Arc<Engine>>>,
+    /// The roots that the engines loaded by `load_engines` came from.
+    origins: RwLock<HashMap<String, PathBuf>>,
+    /// The user's overrides by engine name, applied to engines as they are added.
+    overrides: RwLock<HashMap<String, EngineOverride>>,
 }
 
 impl EnginesManager {
@@ -19,11 +38,14 @@ impl EnginesManager {
     /// ## Example
     /// **Basic usage:**
     /// ```
+    /// # use engines_manager::EnginesManager;
     /// let engines_manager = EnginesManager::init();
     /// ```
     pub fn init() -> EnginesManager {
         EnginesManager {
-            engines: RefCell::new(HashMap::new()),
+            engines: RwLock::new(HashMap::new()),
+            origins: RwLock::new(HashMap::new()),
+            overrides: RwLock::new(HashMap::new()),
         }
     }
 
@@ -31,29 +53,168 @@ impl EnginesManager {
     /// Adds new engine from the engine's json config file.
     /// ## Example
     /// **Basic usage:**
-    /// ```
-    /// # use engine_manager::EnginesManager;
+    /// ```no_run
+    /// # use engines_manager::EnginesManager;
     /// let manager = EnginesManager::init();
     /// manager.add_engine_from_config("engine.json").unwrap();
     /// ```
     // TODO: check that the engine path is valid, add examples and tests.
-    pub fn add_engine_from_config(&self, config_file: &str) -> Result<(), Error> {
-        //open the config file
-        match fs::File::open(config_file) {
-            Ok(fd) => {
-                // create new engine from the config file
-                match Engine::from_json_reader(fd) {
-                    Ok(engine) => {
-                        //check if the engine exists already
-                        if self.engines.borrow().contains_key(engine.get_name()) {
-                            return Err(Error::EngineExists);
-                        }
-                        self.engines.borrow_mut().insert(engine.get_name().into(), engine);
-                        Ok(())
+    pub fn add_engine_from_config<P: AsRef<Path>>(&self, config_file: P) -> Result<(), Error> {
+        let engine = EnginesManager::read_engine_config(config_file)?;
+        self.insert_engine(engine)
+    }
+
+    /// ## Description
+    /// Discovers and adds the engines of several engines roots.
+    ///
+    /// The roots are ordered by precedence, when engines from different roots have the same name
+    /// the one from the earlier root is loaded and the others are reported as shadowed.
+    /// Engines that are loaded already (i.e: added manually) take precedence over all the roots.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use engines_manager::{DiscoveryOptions, EnginesManager};
+    /// let manager = EnginesManager::init();
+    /// let report = manager.load_engines(
+    ///     &["../config_manager/mock_files/engines"],
+    ///     &DiscoveryOptions::default(),
+    /// );
+    /// for (engine, root) in report.loaded {
+    ///     println!("loaded {} from {}", engine, root.display());
+    /// }
+    /// ```
+    pub fn load_engines<P: AsRef<Path>>(
+        &self,
+        roots: &[P],
+        options: &DiscoveryOptions,
+    ) -> DiscoveryReport {
+        let mut report = DiscoveryReport::default();
+
+        for root in roots.iter().map(AsRef::as_ref) {
+            let configs = match find_engine_configs(root, options) {
+                Ok(configs) => configs,
+                Err(error) => {
+                    //unreadable root, move on to the next one
+                    report
+                        .failed
+                        .push((root.to_path_buf(), Error::InvalidConfig(error)));
+                    continue;
+                }
+            };
+
+            for config in configs {
+                let engine = match EnginesManager::read_engine_config(&config) {
+                    Ok(engine) => engine,
+                    Err(error) => {
+                        report.failed.push((config, error));
+                        continue;
                     }
-                    Err(error) => Err(Error::InvalidConfig(error.to_string())), //convert error
+                };
+
+                let name = engine.get_name().to_owned();
+                if self.insert_engine(engine).is_err() {
+                    //name clash, the engine that was loaded first wins
+                    report.shadowed.push(ShadowedEngine {
+                        shadowed_by: self.origins.read().unwrap().get(&name).cloned(),
+                        name,
+                        config,
+                    });
+                    continue;
                 }
+
+                self.origins
+                    .write()
+                    .unwrap()
+                    .insert(name.clone(), root.to_path_buf());
+                report.loaded.push((name, root.to_path_buf()));
             }
+        }
+        report
+    }
+
+    /// ## Description
+    /// Gets the root directory that an engine was discovered in.
+    ///
+    /// Returns `None` for engines that weren't loaded by `load_engines`.
+    pub fn get_engine_root(&self, engine: &str) -> Result<Option<PathBuf>, Error> {
+        if !self.engines.read().unwrap().contains_key(engine) {
+            return Err(Error::UnknownEngine);
+        }
+        Ok(self.origins.read().unwrap().get(engine).cloned())
+    }
+
+    /// ## Description
+    /// Replaces the user's overrides of the engines settings, by engine name.
+    ///
+    /// The overrides are applied to the loaded engines right away and to engines added later on.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use engines_manager::{EngineOverride, EnginesManager};
+    /// # use std::collections::HashMap;
+    /// let manager = EnginesManager::init();
+    /// manager.add_engine("google", "./google", None, None).unwrap();
+    /// manager.set_overrides(HashMap::from([(
+    ///     "google".to_owned(),
+    ///     EngineOverride { enabled: Some(false), ..Default::default() },
+    /// )]));
+    /// assert!(!manager.get_engine_settings("google").unwrap().enabled);
+    /// ```
+    pub fn set_overrides(&self, overrides: HashMap<String, EngineOverride>) {
+        for (name, engine) in self.engines.write().unwrap().iter_mut() {
+            //a running command keeps the settings it was started with
+            Arc::make_mut(engine).set_overrides(overrides.get(name).cloned().unwrap_or_default());
+        }
+        *self.overrides.write().unwrap() = overrides;
+    }
+
+    /// ## Description
+    /// Gets the settings an engine is executed with, after applying the user's overrides.
+    pub fn get_engine_settings(&self, engine: &str) -> Result<EngineSettings, Error> {
+        match self.engines.read().unwrap().get(engine) {
+            Some(engine) => Ok(engine.get_settings()),
+            None => Err(Error::UnknownEngine),
+        }
+    }
+
+    // add an engine with its user's overrides, fails if there is an engine with the same name
+    fn insert_engine(&self, mut engine: Engine) -> Result<(), Error> {
+        let mut engines = self.engines.write().unwrap();
+        //check if the engine exists already
+        if engines.contains_key(engine.get_name()) {
+            return Err(Error::EngineExists);
+        }
+        if let Some(overrides) = self.overrides.read().unwrap().get(engine.get_name()) {
+            engine.set_overrides(overrides.clone());
+        }
+        engines.insert(engine.get_name().into(), Arc::new(engine));
+        Ok(())
+    }
+
+    // get an engine to run a command with, the engines' lock is released right away
+    fn get_engine(&self, engine: &str) -> Result<Arc<Engine>, Error> {
+        self.engines
+            .read()
+            .unwrap()
+            .get(engine)
+            .cloned()
+            .ok_or(Error::UnknownEngine)
+    }
+
+    // convert the error of an engine's command
+    fn command_error(error: EngineError) -> Error {
+        match error {
+            EngineError::UnknownCommand => Error::UnkownCommand,
+            error => Error::Execution(error),
+        }
+    }
+
+    // create an engine from its json config file
+    fn read_engine_config<P: AsRef<Path>>(config_file: P) -> Result<Engine, Error> {
+        //open the config file
+        match fs::File::open(config_file) {
+            // create new engine from the config file
+            Ok(fd) => Engine::from_json_reader(fd).map_err(|error| Error::InvalidConfig(error.to_string())),
             Err(error) => Err(Error::InvalidConfig(error.to_string())), //convert error
         }
     }
@@ -64,7 +225,8 @@ impl EnginesManager {
     /// ## Example
     /// **Basic usage:**
     /// ```
-    ///# let engines_manager =  EnginesManager::init();
+    /// # use engines_manager::EnginesManager;
+    /// # let engines_manager =  EnginesManager::init();
     /// engines_manager.add_engine("engine_name","path_to_engine",None,None)
     ///     .expect("engine exists already");
     /// ```
@@ -76,30 +238,22 @@ impl EnginesManager {
         prefix: Option<&str>,
         description: Option<&str>,
     ) -> Result<(), Error> {
-        //check if the engine exists already
-        if self.engines.borrow().contains_key(name) {
-            return Err(Error::EngineExists);
-        }
-
         // add the engine
-        self.engines.borrow_mut().insert(
-            name.to_owned(),
-            Engine::new(name, path, prefix,None, description),
-        );
-        Ok(())
+        self.insert_engine(Engine::new(name, path, prefix, None, description))
     }
 
     /// ## Description
     /// Gets a list of the engine's commands names.
     /// ## Example
     /// **Basic usage:**
-    /// ```
+    /// ```should_panic
+    /// # use engines_manager::EnginesManager;
     /// # let engines_manager =  EnginesManager::init();
-    /// engine_manager.list_engine_commands("engine_name")
+    /// engines_manager.list_engine_commands("engine_name")
     ///     .expect("unknown engine");
     /// ```
     pub fn list_engine_commands(&self, engine: &str) -> Result<HashMap<String,Option<String>>, Error> {
-        match self.engines.borrow().get(engine) {
+        match self.engines.read().unwrap().get(engine) {
             Some(engine) => {
                 //if the engine exists, list its commands
                 Ok(engine.list_commands())
@@ -115,22 +269,98 @@ impl EnginesManager {
     /// ## Example
     /// **Basic usage:**
     /// ```
+    /// # use engines_manager::EnginesManager;
     /// # let manager = EnginesManager::init();
     /// ```
     // TODO: add an example
     pub fn execute(&self, engine: &str, command: &str, query: &str) -> Result<String, Error> {
-        match self.engines.borrow().get(engine) {
-            Some(engine) => {
-                //if the engine exists, execute its command
-                engine
-                    .execute(command, query)
-                    .map_err(|_| Error::UnkownCommand) //replace error type
-            }
-            None => {
-                //unknown engine
-                Err(Error::UnknownEngine)
-            }
-        }
+        //if the engine exists, execute its command
+        self.get_engine(engine)?
+            .execute(command, query)
+            .map_err(EnginesManager::command_error)
+    }
+
+    /// ## Description
+    /// Executes engine's command, the engine can leave files in `output_dir`
+    /// (see `OUTPUT_DIR_ENV_VAR`).
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let output = manager.execute_with_output("facebook", "user", "user123", dir.path())?;
+    /// ```
+    pub fn execute_with_output(
+        &self,
+        engine: &str,
+        command: &str,
+        query: &str,
+        output_dir: &Path,
+    ) -> Result<String, Error> {
+        self.get_engine(engine)?
+            .execute_with_output(command, query, output_dir)
+            .map_err(EnginesManager::command_error)
+    }
+
+    /// ## Description
+    /// Executes engine's command until `cancelled` returns true, see `Engine::execute_cancellable`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let stop = AtomicBool::new(false);
+    /// let output = manager.execute_cancellable("facebook", "user", "user123", None, &|| stop.load(Ordering::Relaxed))?;
+    /// ```
+    pub fn execute_cancellable(
+        &self,
+        engine: &str,
+        command: &str,
+        query: &str,
+        output_dir: Option<&Path>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<String, Error> {
+        self.get_engine(engine)?
+            .execute_cancellable(command, query, output_dir, cancelled)
+            .map_err(EnginesManager::command_error)
+    }
+
+    /// ## Description
+    /// Executes engine's command like `execute_cancellable`, `invocation` is set to how its
+    /// process is started, see `Engine::execute_described`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let mut invocation = None;
+    /// let output = manager.execute_described("facebook", "user", "user123", None, &|| false, &mut invocation)?;
+    /// ```
+    pub fn execute_described(
+        &self,
+        engine: &str,
+        command: &str,
+        query: &str,
+        output_dir: Option<&Path>,
+        cancelled: &dyn Fn() -> bool,
+        invocation: &mut Option<Invocation>,
+    ) -> Result<String, Error> {
+        self.get_engine(engine)?
+            .execute_described(command, query, output_dir, cancelled, invocation)
+            .map_err(EnginesManager::command_error)
+    }
+
+    /// ## Description
+    /// Describes how an engine's command would be executed, see `Engine::get_invocation`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let invocation = manager.get_invocation("facebook", "user", "user123")?;
+    /// ```
+    pub fn get_invocation(
+        &self,
+        engine: &str,
+        command: &str,
+        query: &str,
+    ) -> Result<Invocation, Error> {
+        //hashing the binary can take a while, the lock isn't held meanwhile
+        self.get_engine(engine)?
+            .get_invocation(command, query)
+            .map_err(|_| Error::UnkownCommand)
     }
 
     /// ## Description
@@ -138,12 +368,14 @@ impl EnginesManager {
     /// ## Example
     /// **Basic usage:**
     /// ```
+    /// # use engines_manager::EnginesManager;
     /// # let manager = EnginesManager::init();
     /// manager.remove_engine("engine_name");
     /// ```
     // TODO: add test
     pub fn remove_engine(&self, engine_name: &str) {
-        self.engines.borrow_mut().remove(engine_name);
+        self.engines.write().unwrap().remove(engine_name);
+        self.origins.write().unwrap().remove(engine_name);
     }
 
     /// ## Description
@@ -151,12 +383,13 @@ impl EnginesManager {
     /// ## Example
     /// **Basic usage:**
     /// ```
+    /// # use engines_manager::EnginesManager;
     /// # let manager = EnginesManager::init();
-    /// let engines: Vec<String> = manager.list_engines()
+    /// let engines: Vec<String> = manager.list_engines();
     /// ```
     // TODO: add an example
     pub fn list_engines(&self) -> Vec<String> {
-        self.engines.borrow().keys().cloned().collect()
+        self.engines.read().unwrap().keys().cloned().collect()
     }
 
     /// ## Description
@@ -164,12 +397,21 @@ impl EnginesManager {
     // TODO: add an example
     pub fn get_engine_description(&self, engine: &str) -> Result<Option<String>, Error> {
         //get the engine
-        match self.engines.borrow().get(engine) {
+        match self.engines.read().unwrap().get(engine) {
             Some(engine) => Ok(engine.get_description().cloned()),
             None => Err(Error::UnknownEngine),
         }
     }
 
+    /// ## Description
+    /// Gets the version an engine declares in its config.
+    pub fn get_engine_version(&self, engine: &str) -> Result<Option<String>, Error> {
+        match self.engines.read().unwrap().get(engine) {
+            Some(engine) => Ok(engine.get_version().cloned()),
+            None => Err(Error::UnknownEngine),
+        }
+    }
+
     /// ## Description
     /// Gets engine's command description.
     // TODO: add an example
@@ -178,7 +420,13 @@ impl EnginesManager {
         engine: &str,
         command: &str,
     ) -> Result<Option<String>, Error> {
-        todo!()
+        match self.engines.read().unwrap().get(engine) {
+            Some(engine) => engine
+                .list_commands()
+                .remove(command)
+                .ok_or(Error::UnkownCommand),
+            None => Err(Error::UnknownEngine),
+        }
     }
 }
 
@@ -188,9 +436,134 @@ pub enum Error {
     UnknownEngine,
     UnkownCommand,
     InvalidConfig(String),
+    /// The engine failed to execute a command, i.e: it's disabled or has timed out.
+    Execution(EngineError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::EngineExists => f.write_str("EngineExists"),
+            Error::UnknownEngine => f.write_str("UnknownEngine"),
+            Error::UnkownCommand => f.write_str("UnknownCommand"),
+            Error::InvalidConfig(error) => write!(f, "InvalidConfig: {}", error),
+            Error::Execution(error) => write!(f, "{}", error),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     //TODO: write tests
+    use super::*;
+
+    // creates an engines root with an engine config for every (directory, engine name) pair
+    fn create_root(root: &str, engines: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(root);
+        let _ = fs::remove_dir_all(&root);
+        for (dir, name) in engines {
+            let dir = root.join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            let config = format!(
+                r#"{{"name":"{}","path":"engine","commands":[{{"name":"search","args":"$query"}}]}}"#,
+                name
+            );
+            fs::write(dir.join(ENGINE_CONFIG_FILE), config).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn first_root_takes_precedence() {
+        let personal = create_root("sherlock_personal_root", &[("google", "google")]);
+        let shared = create_root("sherlock_shared_root", &[("google", "google"), ("bing", "bing")]);
+
+        let manager = EnginesManager::init();
+        let report = manager.load_engines(&[&personal, &shared], &DiscoveryOptions::default());
+
+        assert_eq!(report.loaded.len(), 2);
+        assert_eq!(report.shadowed.len(), 1);
+        assert_eq!(report.shadowed[0].shadowed_by.as_ref(), Some(&personal));
+        assert_eq!(manager.get_engine_root("google").unwrap(), Some(personal));
+        assert_eq!(manager.get_engine_root("bing").unwrap(), Some(shared));
+    }
+
+    #[test]
+    fn recursive_discovery() {
+        let root = create_root(
+            "sherlock_recursive_root",
+            &[
+                ("social/facebook", "facebook"),
+                ("social/deep/twitter", "twitter"),
+                ("old.disabled/myspace", "myspace"),
+                ("google", "google"),
+            ],
+        );
+
+        //only direct children without `recursive`
+        let options = DiscoveryOptions::default();
+        assert_eq!(find_engine_configs(&root, &options).unwrap().len(), 1);
+
+        //nested directories, without the ignored ones
+        let options = DiscoveryOptions {
+            recursive: true,
+            max_depth: None,
+            ignore: vec!["*.disabled".into()],
+        };
+        let manager = EnginesManager::init();
+        manager.load_engines(&[&root], &options);
+        let mut engines = manager.list_engines();
+        engines.sort();
+        assert_eq!(engines, vec!["facebook", "google", "twitter"]);
+
+        //limited depth
+        let options = DiscoveryOptions {
+            recursive: true,
+            max_depth: Some(2),
+            ignore: vec![],
+        };
+        assert_eq!(find_engine_configs(&root, &options).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn set_overrides_while_a_command_runs() {
+        let root =
+            std::env::temp_dir().join(format!("sherlock_running_root-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let config = root.join("sleeper.json");
+        fs::write(
+            &config,
+            r#"{"name":"sleeper","path":"sleep","commands":[{"name":"user","args":"$query"}]}"#,
+        )
+        .unwrap();
+        let manager = Arc::new(EnginesManager::init());
+        manager.add_engine_from_config(&config).unwrap();
+
+        let running = manager.clone();
+        let run = std::thread::spawn(move || running.execute("sleeper", "user", "2"));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        //the engines' lock isn't held by the running command
+        let start = std::time::Instant::now();
+        let mut overrides = HashMap::new();
+        let disabled = EngineOverride {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        overrides.insert("sleeper".to_string(), disabled);
+        manager.set_overrides(overrides);
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert!(!manager.get_engine_settings("sleeper").unwrap().enabled);
+
+        //the running command keeps the settings it started with
+        assert!(run.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn report_unreadable_root() {
+        let manager = EnginesManager::init();
+        let report = manager.load_engines(&["./no_such_root"], &DiscoveryOptions::default());
+        assert!(report.loaded.is_empty());
+        assert_eq!(report.failed.len(), 1);
+    }
 }
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+/// The environment variables that carry an engine's proxy.
+const PROXY_ENV_VARS: [&str; 6] = [
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "ALL_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "all_proxy",
+];
+
+/// ## Description
+/// User overrides for an engine's vendor settings, usually from a `[engines.<name>]` section.
+///
+/// Every `None` field keeps the engine's own setting.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct EngineOverride {
+    /// Enables or disables the engine, disabled engines are listed but can't be executed.
+    pub enabled: Option<bool>,
+    /// Kill the engine's process after this many seconds.
+    pub timeout: Option<u64>,
+    /// Replaces the engine's prefix, i.e: `python3.11`.
+    pub prefix: Option<String>,
+    /// Extra environment variables for the engine's process.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The working directory of the engine's process.
+    pub working_dir: Option<PathBuf>,
+    /// The maximum number of the engine's processes that can run at the same time.
+    pub concurrency: Option<usize>,
+    /// The proxy for the engine, passed through the standard `*_PROXY` environment variables.
+    pub proxy: Option<String>,
+}
+
+/// ## Description
+/// The settings an engine is executed with, its vendor settings merged with the user's overrides.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EngineSettings {
+    pub enabled: bool,
+    pub timeout: Option<u64>,
+    pub prefix: Option<String>,
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+    pub concurrency: Option<usize>,
+    pub proxy: Option<String>,
+}
+
+impl EngineSettings {
+    /// ## Description
+    /// Merges the user's overrides onto the engine's vendor settings.
+    pub fn resolve(vendor_prefix: Option<&String>, overrides: &EngineOverride) -> EngineSettings {
+        EngineSettings {
+            enabled: overrides.enabled.unwrap_or(true),
+            timeout: overrides.timeout,
+            prefix: overrides.prefix.clone().or_else(|| vendor_prefix.cloned()),
+            env: overrides.env.clone(),
+            working_dir: overrides.working_dir.clone(),
+            concurrency: overrides.concurrency,
+            proxy: overrides.proxy.clone(),
+        }
+    }
+
+    /// Gets the timeout as a `Duration`.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    /// ## Description
+    /// Gets the full environment of the engine's process, the proxy variables included.
+    pub fn get_env(&self) -> HashMap<String, String> {
+        let mut env = self.env.clone();
+        if let Some(proxy) = &self.proxy {
+            for var in PROXY_ENV_VARS {
+                env.entry(var.to_owned()).or_insert_with(|| proxy.clone());
+            }
+        }
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_vendor_settings() {
+        let vendor_prefix = "python3".to_owned();
+
+        //no overrides
+        let settings = EngineSettings::resolve(Some(&vendor_prefix), &EngineOverride::default());
+        assert!(settings.enabled);
+        assert_eq!(settings.prefix.as_ref(), Some(&vendor_prefix));
+        assert!(settings.get_env().is_empty());
+
+        //overridden
+        let overrides = EngineOverride {
+            enabled: Some(false),
+            timeout: Some(30),
+            prefix: Some("python3.11".into()),
+            proxy: Some("socks5://127.0.0.1:9050".into()),
+            env: HashMap::from([("HTTP_PROXY".to_owned(), "http://other:8080".to_owned())]),
+            ..Default::default()
+        };
+        let settings = EngineSettings::resolve(Some(&vendor_prefix), &overrides);
+        assert!(!settings.enabled);
+        assert_eq!(settings.get_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(settings.prefix.as_deref(), Some("python3.11"));
+
+        //explicit env vars win over the proxy
+        let env = settings.get_env();
+        assert_eq!(env["HTTP_PROXY"], "http://other:8080");
+        assert_eq!(env["ALL_PROXY"], "socks5://127.0.0.1:9050");
+    }
+}
@@ -0,0 +1,502 @@
+/*
+Channel:
+A bounded multi-producer, single-consumer queue. Every operation takes the lock once, so
+nothing sent can be lost between reading the queue and clearing it. The consumer may be shared
+by threads that take different messages (i.e: the replies of one job each), every message waits
+in the queue for the one that takes it.
+
+A sender blocks while the queue is full, until the receiver makes room or is dropped. A producer
+that can't wait, i.e: one on the receiver's thread, forces its messages past the capacity.
+Every producer holds its own sender: the receiver is done once all of them are dropped, one
+finished producer doesn't end the channel for the others.
+
+The receiver can take what's there, wait for the next message (with a timeout), or be polled as
+an async `Stream` that's woken by the senders. Every pending stream and waiting thread is woken by
+a new message, the ones it isn't for wait again. It can also take only the messages that pass a
+filter, and put back the ones it took too early.
+ */
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// ## Description
+/// Creates a channel that holds at most `capacity` messages, at least one.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// let (tx, rx) = ipc::bounded(2);
+/// tx.send(1).unwrap();
+/// tx.send(2).unwrap();
+/// assert_eq!(tx.try_send(3), Err(ipc::Error::Full));
+/// assert_eq!(rx.drain(), vec![1, 2]);
+/// ```
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            senders: 1,
+            receiver: true,
+            wakers: vec![],
+        }),
+        not_full: Condvar::new(),
+        not_empty: Condvar::new(),
+        capacity: capacity.max(1),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_full: Condvar,
+    not_empty: Condvar,
+    capacity: usize,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    //the live senders, the receiver is done once they're all dropped
+    senders: usize,
+    receiver: bool,
+    //the tasks of the pending streams, all woken by the next message
+    wakers: Vec<Waker>,
+}
+
+impl<T> State<T> {
+    fn wake(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// ## Description
+/// The sending end of a channel, it can be cloned for every producer.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// ## Description
+    /// Sends a message, waits while the channel is full.
+    ///
+    /// Fails with `Error::Disconnected` if the receiver was dropped, the message is dropped.
+    pub fn send(&self, message: T) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        while state.receiver && state.queue.len() >= self.shared.capacity {
+            state = self.shared.not_full.wait(state).unwrap();
+        }
+        if !state.receiver {
+            return Err(Error::Disconnected);
+        }
+        state.queue.push_back(message);
+        state.wake();
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+
+    /// ## Description
+    /// Sends a message without waiting, fails with `Error::Full` if the channel is full.
+    pub fn try_send(&self, message: T) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.receiver {
+            return Err(Error::Disconnected);
+        }
+        if state.queue.len() >= self.shared.capacity {
+            return Err(Error::Full);
+        }
+        state.queue.push_back(message);
+        state.wake();
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+
+    /// ## Description
+    /// Sends a message without waiting, past the capacity if the channel is full.
+    /// Meant for a producer that runs on the receiver's thread, nobody would make room.
+    pub fn force_send(&self, message: T) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.receiver {
+            return Err(Error::Disconnected);
+        }
+        state.queue.push_back(message);
+        state.wake();
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+
+    /// ## Description
+    /// Sends a message without waiting, the oldest message makes room if the channel is full.
+    /// Meant for messages that are fine to lose, i.e: logs nobody reads.
+    pub fn send_lossy(&self, message: T) -> Result<(), Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.receiver {
+            return Err(Error::Disconnected);
+        }
+        if state.queue.len() >= self.shared.capacity {
+            state.queue.pop_front();
+        }
+        state.queue.push_back(message);
+        state.wake();
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            //a waiting receiver learns there's nothing more to come
+            state.wake();
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+/// ## Description
+/// The receiving end of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// ## Description
+    /// Takes every message waiting in the channel, oldest first.
+    pub fn drain(&self) -> Vec<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        let messages = state.queue.drain(..).collect();
+        self.shared.not_full.notify_all();
+        messages
+    }
+
+    /// ## Description
+    /// Takes the messages that pass a filter, oldest first, the others stay in the channel.
+    pub fn drain_where(&self, filter: impl Fn(&T) -> bool) -> Vec<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        let (taken, kept): (VecDeque<T>, _) =
+            state.queue.drain(..).partition(|message| filter(message));
+        state.queue = kept;
+        self.shared.not_full.notify_all();
+        taken.into()
+    }
+
+    /// ## Description
+    /// Puts messages back at the front of the channel, in their order, even if it's full:
+    /// i.e: the replies of a job whose waiter gave up.
+    pub fn put_back(&self, messages: Vec<T>) {
+        let mut state = self.shared.state.lock().unwrap();
+        for message in messages.into_iter().rev() {
+            state.queue.push_front(message);
+        }
+        state.wake();
+        self.shared.not_empty.notify_all();
+    }
+
+    /// ## Description
+    /// Takes the oldest message, waits for one if the channel is empty.
+    ///
+    /// Returns `None` once every sender is gone and every message was taken.
+    pub fn recv(&self) -> Option<T> {
+        self.recv_where(|_| true, None).ok()
+    }
+
+    /// ## Description
+    /// Takes the oldest message, waits at most `timeout` for one if the channel is empty.
+    ///
+    /// Fails with `Error::Timeout` if none came in time,
+    /// and with `Error::Disconnected` once every sender is gone and every message was taken.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use std::time::Duration;
+    /// let (tx, rx) = ipc::bounded(1);
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(rx.recv_timeout(timeout), Err(ipc::Error::Timeout));
+    /// tx.send("found").unwrap();
+    /// assert_eq!(rx.recv_timeout(timeout), Ok("found"));
+    /// drop(tx);
+    /// assert_eq!(rx.recv_timeout(timeout), Err(ipc::Error::Disconnected));
+    /// ```
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, Error> {
+        self.recv_where(|_| true, Some(Instant::now() + timeout))
+    }
+
+    /// ## Description
+    /// Takes the oldest message that passes a filter, waits for one until `deadline` (forever if
+    /// it's `None`). The other messages stay in the channel for the threads that take them.
+    ///
+    /// Fails with `Error::Timeout` if none came in time,
+    /// and with `Error::Disconnected` once every sender is gone and no message passes the filter.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// let (tx, rx) = ipc::bounded(4);
+    /// tx.send(1).unwrap();
+    /// tx.send(2).unwrap();
+    /// assert_eq!(rx.recv_where(|n| n % 2 == 0, None), Ok(2));
+    /// drop(tx);
+    /// assert_eq!(rx.recv_where(|n| n % 2 == 0, None), Err(ipc::Error::Disconnected));
+    /// assert_eq!(rx.recv(), Some(1));
+    /// ```
+    pub fn recv_where(
+        &self,
+        filter: impl Fn(&T) -> bool,
+        deadline: Option<Instant>,
+    ) -> Result<T, Error> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(message) = take_first(&mut state.queue, &filter) {
+                self.shared.not_full.notify_one();
+                return Ok(message);
+            }
+            if state.senders == 0 {
+                return Err(Error::Disconnected);
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(Error::Timeout);
+                    }
+                    self.shared.not_empty.wait_timeout(state, left).unwrap().0
+                }
+                None => self.shared.not_empty.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// ## Description
+    /// Takes the oldest message if there's one, or registers the task to be woken by the next
+    /// message. `Poll::Ready(None)` once every sender is gone and every message was taken.
+    pub fn poll_recv(&self, cx: &mut Context) -> Poll<Option<T>> {
+        self.poll_recv_where(|_| true, cx)
+    }
+
+    /// ## Description
+    /// Like `poll_recv`, only for the messages that pass a filter, the others stay in the channel.
+    pub fn poll_recv_where(
+        &self,
+        filter: impl Fn(&T) -> bool,
+        cx: &mut Context,
+    ) -> Poll<Option<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        match take_first(&mut state.queue, &filter) {
+            Some(message) => {
+                self.shared.not_full.notify_one();
+                Poll::Ready(Some(message))
+            }
+            None if state.senders == 0 => Poll::Ready(None),
+            None => {
+                //a stream that only takes some messages may be pending next to others
+                if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Gets the messages as an async stream, it ends once every sender is gone.
+    pub fn stream(&self) -> Messages<'_, T> {
+        Messages { receiver: self }
+    }
+
+    /// Gets the number of live senders, the producers that may still send.
+    pub fn senders(&self) -> usize {
+        self.shared.state.lock().unwrap().senders
+    }
+
+    /// Gets the number of messages waiting in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    /// Checks whether no message is waiting in the channel.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks whether a sender is still alive, more messages may come.
+    pub fn is_connected(&self) -> bool {
+        self.shared.state.lock().unwrap().senders > 0
+    }
+
+    /// Checks whether every sender is gone and every message was taken.
+    pub fn is_finished(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.senders == 0 && state.queue.is_empty()
+    }
+}
+
+// take the oldest message that passes a filter out of a queue
+fn take_first<T>(queue: &mut VecDeque<T>, filter: impl Fn(&T) -> bool) -> Option<T> {
+    let index = queue.iter().position(filter)?;
+    queue.remove(index)
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver = false;
+        //the blocked senders fail instead of waiting forever
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// ## Description
+/// The messages of a receiver as an async stream, see `Receiver::stream`.
+pub struct Messages<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Stream for Messages<'_, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// ## Description
+/// Custom error for the channels of the IPC crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Occurs when the other end of the channel was dropped.
+    Disconnected,
+    /// Occurs when a message can't be sent without waiting.
+    Full,
+    /// Occurs when no message came in time.
+    Timeout,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Disconnected => write!(f, "the other end of the channel is gone"),
+            Error::Full => write!(f, "the channel is full"),
+            Error::Timeout => write!(f, "no message came in time"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    #[test]
+    fn senders_wait_for_room() {
+        let (tx, rx) = bounded(1);
+        tx.send(0).unwrap();
+        let producer = {
+            let tx = tx.clone();
+            thread::spawn(move || (1..5).try_for_each(|i| tx.send(i)))
+        };
+        let mut received = vec![];
+        while received.len() < 5 {
+            received.extend(rx.drain());
+            thread::yield_now();
+        }
+        producer.join().unwrap().unwrap();
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+
+        //the lossy send keeps the newest messages
+        tx.send_lossy(5).unwrap();
+        tx.send_lossy(6).unwrap();
+        assert_eq!(rx.drain(), vec![6]);
+        //a forced send goes past the capacity
+        tx.send(5).unwrap();
+        tx.force_send(6).unwrap();
+        assert_eq!(rx.drain(), vec![5, 6]);
+
+        //the receiver waits for a message, or for the senders to be gone
+        let producer = {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(7))
+        };
+        assert_eq!(rx.recv(), Some(7));
+        producer.join().unwrap().unwrap();
+
+        //the messages that don't pass a filter wait for another thread
+        tx.send(1).unwrap();
+        tx.force_send(2).unwrap();
+        let even = thread::scope(|scope| {
+            let even = scope.spawn(|| rx.recv_where(|n| n % 2 == 0, None));
+            let odd = scope.spawn(|| rx.recv_where(|n| n % 2 == 1, None));
+            assert_eq!(odd.join().unwrap(), Ok(1));
+            even.join().unwrap()
+        });
+        assert_eq!(even, Ok(2));
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(
+            rx.recv_where(|n| *n > 2, Some(deadline)),
+            Err(Error::Timeout)
+        );
+        tx.send(3).unwrap();
+        tx.force_send(4).unwrap();
+        assert_eq!(rx.drain_where(|n| n % 2 == 0), vec![4]);
+        rx.put_back(vec![1, 2]);
+        assert_eq!(rx.drain(), vec![1, 2, 3]);
+
+        //dropping the receiver releases a blocked sender
+        tx.send(7).unwrap();
+        let blocked = thread::spawn(move || tx.send(8));
+        drop(rx);
+        assert_eq!(blocked.join().unwrap(), Err(Error::Disconnected));
+    }
+
+    #[test]
+    fn wake_every_stream() {
+        struct Count(AtomicUsize);
+        impl std::task::Wake for Count {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let (tx, rx) = bounded(4);
+        let counts: Vec<_> = (0..2)
+            .map(|_| Arc::new(Count(AtomicUsize::new(0))))
+            .collect();
+        //two streams of different messages, and one polled twice
+        for (count, remainder) in counts.iter().zip([0, 1]) {
+            let waker = count.clone().into();
+            let mut cx = Context::from_waker(&waker);
+            assert!(rx
+                .poll_recv_where(|n| n % 2 == remainder, &mut cx)
+                .is_pending());
+            assert!(rx
+                .poll_recv_where(|n| n % 2 == remainder, &mut cx)
+                .is_pending());
+        }
+        tx.send(1).unwrap();
+        for count in &counts {
+            assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        }
+        assert_eq!(rx.drain(), vec![1]);
+    }
+}
@@ -1,98 +1,736 @@
-//TODO: use one lock call in a method
+/*
+IPC:
+The messages between the UI thread and the SherlockManager's thread. `open` creates a pair of
+connected ends, one for each side, with a bounded channel per kind of message: the UI sends jobs,
+the manager sends responds and logs back. Every pair is independent of the others.
 
-#[macro_use]
-extern crate lazy_static;
-use std::sync::Mutex;
+Every job gets an id from the UI's end, the responds and the logs of a job carry it so the UI can
+tell apart the results of jobs in flight. The last respond of a job is `Respond::Done`.
+The responds of a job wait in the channel for whoever takes that job's ones: threads (or tasks)
+that wait for different jobs don't take each other's responds.
+Every job has a priority for the manager to run it by, and the UI can cancel a job that isn't
+finished: the responders see it as cancelled as soon as the cancel is sent.
 
-lazy_static! {
-    static ref GLOBAL_INSTANCE: Mutex<MessagesBox> = Mutex::new(MessagesBox::init());
+The UI can wait for responds and logs (with a timeout) or consume them as async streams instead
+of polling, the responds of every job or the ones of one job. A box may have several UIs, i.e: a
+window and a server for scripts (see `ManagerEnd::connect`), every UI end takes the responds of
+the jobs it sent and the box is open until they're all dropped. The manager hands a `Responder` to
+every thread that answers jobs, each one with its own id: the UI sees which of them finished, and
+its end is no longer pending once all of them did.
+
+The logs go through a log book (see the `log` module), the UI reads the latest ones or subscribes
+to the ones it's interested in. The messages themselves are defined in the `messages` crate.
+ */
+mod channel;
+mod log;
+
+pub use channel::{bounded, Error, Messages, Receiver, Sender};
+use futures_core::Stream;
+pub use log::LogBook;
+pub use messages::{
+    Job, JobId, Level, Log, LogFilter, LogLine, Priority, Reply, Request, Respond, Source,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// The number of latest logs the log book of a messages box keeps by default.
+pub const LOG_LINES: usize = 1000;
+
+/// ## Description
+/// Creates the two ends of a messages box, every channel holds at most `capacity` messages.
+/// The log book keeps the latest `LOG_LINES` logs, see `open_with_logs`.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use ipc::{Job, Respond};
+/// let (ui, manager) = ipc::open(16);
+/// let id = ui.send_job(Job::ListEngines).unwrap();
+/// for request in manager.recieve_jobs() {
+///     let respond = Respond::Message(format!("{:?}", request.job));
+///     manager.send_responds(request.id, vec![respond]).unwrap();
+///     manager.finish_job(request.id).unwrap();
+/// }
+/// manager.finish();
+/// assert_eq!(ui.await_job(id).unwrap().len(), 1);
+/// assert!(!ui.is_pending());
+/// ```
+pub fn open(capacity: usize) -> (UiEnd, ManagerEnd) {
+    open_with_logs(capacity, LOG_LINES)
+}
+
+/// ## Description
+/// Like `open`, the log book keeps the latest `log_lines` logs for `UiEnd::get_logs`.
+pub fn open_with_logs(capacity: usize, log_lines: usize) -> (UiEnd, ManagerEnd) {
+    let (jobs_tx, jobs_rx) = bounded(capacity);
+    let (responds_tx, responds_rx) = bounded(capacity);
+    let logbook = Arc::new(LogBook::new(log_lines));
+    let cancelled = Arc::new(Mutex::new(HashSet::new()));
+    let producers = Arc::new(Producers::default());
+    let shared = Arc::new(UiShared {
+        jobs: jobs_tx,
+        responds: responds_rx,
+        capacity,
+        next_id: AtomicU64::new(1),
+        next_end: AtomicU64::new(1),
+        owners: Mutex::new(Owners::default()),
+    });
+    let manager = ManagerEnd {
+        jobs: jobs_rx,
+        responder: Responder {
+            id: producers.register(),
+            producers,
+            responds: responds_tx,
+            logbook,
+            cancelled,
+        },
+        ui: Arc::downgrade(&shared),
+    };
+    (manager.ui_end(shared), manager)
+}
+
+/// ## Description
+/// The UI's end of a messages box: sends jobs and receives their responds and the logs.
+pub struct UiEnd {
+    shared: Arc<UiShared>,
+    //the number of this end among the UI ends of the box
+    end: u64,
+    //the UI's own subscription to every log
+    logs: Receiver<LogLine>,
+    //the responders own the log book, it's gone once they all finished
+    logbook: Weak<LogBook>,
+    //the jobs cancelled by the UI, shared with the responders
+    cancelled: Arc<Mutex<HashSet<JobId>>>,
+    producers: Arc<Producers>,
+}
+
+// what the UI ends of a box share, the manager sees the UI gone once they're all dropped
+struct UiShared {
+    jobs: Sender<Request>,
+    responds: Receiver<Reply>,
+    capacity: usize,
+    //the ids of the jobs are unique in the box
+    next_id: AtomicU64,
+    next_end: AtomicU64,
+    owners: Mutex<Owners>,
 }
 
-/// A singleton that is used for IPC communication between the UI thread and the SherlockManager's thread.
-pub struct MessagesBox {
-    jobs: Vec<Job>,
-    responds: Vec<Respond>,
-    logs: Vec<Log>,
-    pending: bool,
+// the UI end that sent each job that isn't over
+#[derive(Default)]
+struct Owners {
+    jobs: HashMap<JobId, u64>,
+    //the ends that were dropped, the responds of their jobs are thrown away
+    gone: HashSet<u64>,
+}
+
+/// ## Description
+/// The id of a producer of responds: the manager's end or one of its responders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProducerId(pub u64);
+
+// the producers that didn't finish yet, shared by both ends
+#[derive(Default)]
+struct Producers {
+    state: Mutex<ProducersState>,
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct ProducersState {
+    next_id: u64,
+    live: BTreeSet<ProducerId>,
+}
+
+impl Producers {
+    fn register(&self) -> ProducerId {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = ProducerId(state.next_id);
+        state.live.insert(id);
+        id
+    }
+
+    fn finish(&self, id: ProducerId) {
+        self.state.lock().unwrap().live.remove(&id);
+        self.finished.notify_all();
+    }
 }
 
-impl MessagesBox {
-    fn init() -> Self {
-        MessagesBox {
-            jobs: vec![],
-            responds: vec![],
-            logs: vec![],
-            pending: false,
+impl UiEnd {
+    /// ## Description
+    /// Sends a job to the manager with its default priority (see `Job::get_default_priority`),
+    /// waits while its queue is full. Returns the job's id.
+    pub fn send_job(&self, job: Job) -> Result<JobId, Error> {
+        let priority = job.get_default_priority();
+        self.send_job_with_priority(job, priority)
+    }
+
+    /// ## Description
+    /// Sends a job to the manager, waits while its queue is full. Returns the job's id.
+    ///
+    /// A `Job::Cancel` takes effect at once: the responders see the job as cancelled before the
+    /// manager reads the cancel, i.e: while it waits for one of the job's engines.
+    pub fn send_job_with_priority(&self, job: Job, priority: Priority) -> Result<JobId, Error> {
+        self.send_request(job, priority, |request| self.shared.jobs.send(request))
+    }
+
+    /// ## Description
+    /// Sends a job like `send_job_with_priority` without waiting, fails with `Error::Full` if
+    /// the manager's queue is full. Meant for a UI on the thread that answers the jobs (i.e:
+    /// `SherlockManager::listen`), nobody would make room.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use ipc::{Job, Priority};
+    /// let (ui, manager) = ipc::open(1);
+    /// ui.try_send_job_with_priority(Job::ListEngines, Priority::Normal).unwrap();
+    /// let full = ui.try_send_job_with_priority(Job::ListEngines, Priority::Normal);
+    /// assert_eq!(full, Err(ipc::Error::Full));
+    /// assert_eq!(manager.recieve_jobs().len(), 1);
+    /// ```
+    pub fn try_send_job_with_priority(&self, job: Job, priority: Priority) -> Result<JobId, Error> {
+        self.send_request(job, priority, |request| self.shared.jobs.try_send(request))
+    }
+
+    // send a job owned by this end with `send`
+    fn send_request(
+        &self,
+        job: Job,
+        priority: Priority,
+        send: impl FnOnce(Request) -> Result<(), Error>,
+    ) -> Result<JobId, Error> {
+        if let Job::Cancel { job_id } = job {
+            self.cancelled.lock().unwrap().insert(job_id);
+        }
+        let id = JobId(self.shared.next_id.fetch_add(1, Ordering::Relaxed));
+        //owned before it's sent, its first respond may come right away
+        let mut owners = self.shared.owners.lock().unwrap();
+        owners.jobs.insert(id, self.end);
+        drop(owners);
+        if let Err(error) = send(Request { id, job, priority }) {
+            self.shared.owners.lock().unwrap().jobs.remove(&id);
+            return Err(error);
+        }
+        Ok(id)
+    }
+
+    /// ## Description
+    /// Cancels a job that didn't finish yet, returns the id of the cancel.
+    /// The job's last responds are `Respond::Cancelled` and `Respond::Done`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use ipc::{Job, Respond};
+    /// let (ui, manager) = ipc::open(16);
+    /// let id = ui.send_job(Job::ListEngines).unwrap();
+    /// ui.cancel_job(id).unwrap();
+    /// assert!(manager.is_cancelled(id));
+    /// ```
+    pub fn cancel_job(&self, job_id: JobId) -> Result<JobId, Error> {
+        self.send_job(Job::Cancel { job_id })
+    }
+
+    /// ## Description
+    /// Sends jobs to the manager, waits while its queue is full. Returns their ids in order.
+    pub fn send_jobs(&self, jobs: Vec<Job>) -> Result<Vec<JobId>, Error> {
+        jobs.into_iter().map(|job| self.send_job(job)).collect()
+    }
+
+    /// Takes the responds of every job this end sent received so far.
+    pub fn recieve_responds(&self) -> Vec<Reply> {
+        self.keep(self.shared.responds.drain_where(|reply| self.takes(reply)))
+    }
+
+    /// ## Description
+    /// Takes the responds of one job received so far, the ones of other jobs are kept.
+    /// The job is over once `Respond::Done` was taken.
+    pub fn recieve_job(&self, id: JobId) -> Vec<Respond> {
+        let replies = self.shared.responds.drain_where(|reply| reply.job_id == id);
+        let replies = self.keep(replies);
+        replies.into_iter().map(|reply| reply.respond).collect()
+    }
+
+    /// ## Description
+    /// Waits until the job is done and takes its responds, without the final `Respond::Done`.
+    /// Other threads may wait for other jobs meanwhile, each one takes only its job's responds.
+    ///
+    /// Fails with `Error::Disconnected` if the manager's end is gone before the job is done.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let id = ui.send_job(Job::ListEngines)?;
+    /// let responds = ui.await_job(id)?;
+    /// ```
+    pub fn await_job(&self, id: JobId) -> Result<Vec<Respond>, Error> {
+        self.wait_job(id, None)
+    }
+
+    /// ## Description
+    /// Like `await_job`, fails with `Error::Timeout` if the job isn't done in time.
+    /// The responds received so far stay for the next call.
+    pub fn await_job_timeout(&self, id: JobId, timeout: Duration) -> Result<Vec<Respond>, Error> {
+        self.wait_job(id, Some(Instant::now() + timeout))
+    }
+
+    fn wait_job(&self, id: JobId, deadline: Option<Instant>) -> Result<Vec<Respond>, Error> {
+        let mut replies = vec![];
+        loop {
+            match self
+                .shared
+                .responds
+                .recv_where(|reply| reply.job_id == id, deadline)
+            {
+                Ok(reply) if reply.respond == Respond::Done => {
+                    self.keep(vec![reply]);
+                    break;
+                }
+                Ok(reply) => replies.push(reply),
+                Err(error) => {
+                    //put back what was taken, nothing is lost on a timeout
+                    self.shared.responds.put_back(replies);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(replies.into_iter().map(|reply| reply.respond).collect())
+    }
+
+    /// ## Description
+    /// Waits at most `timeout` for responds, then takes every one received so far, like
+    /// `recieve_responds`.
+    ///
+    /// Fails with `Error::Timeout` if none came in time, and with `Error::Disconnected` once
+    /// every responder finished and every respond was taken.
+    pub fn wait_responds(&self, timeout: Duration) -> Result<Vec<Reply>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let responds = &self.shared.responds;
+            let mut replies = vec![responds.recv_where(|reply| self.takes(reply), Some(deadline))?];
+            replies.extend(responds.drain_where(|reply| self.takes(reply)));
+            //the responds of a dropped end don't count
+            let replies = self.keep(replies);
+            if !replies.is_empty() {
+                return Ok(replies);
+            }
+        }
+    }
+
+    /// ## Description
+    /// Gets the responds of every job this end sent as an async stream, it ends once every
+    /// responder finished.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let mut responds = ui.responds();
+    /// while let Some(reply) = responds.next().await {
+    ///     println!("{}: {:?}", reply.job_id, reply.respond);
+    /// }
+    /// ```
+    pub fn responds(&self) -> Responds<'_> {
+        Responds { ui: self }
+    }
+
+    /// ## Description
+    /// Gets the responds of one job as an async stream, it ends after the job's last respond
+    /// (`Respond::Done` isn't part of it). The responds of other jobs stay for their readers.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let id = ui.send_job(Job::ListEngines)?;
+    /// let mut responds = ui.job_responds(id);
+    /// while let Some(respond) = responds.next().await {
+    ///     println!("{:?}", respond);
+    /// }
+    /// ```
+    pub fn job_responds(&self, id: JobId) -> JobResponds<'_> {
+        JobResponds {
+            ui: self,
+            id,
+            done: false,
         }
     }
 
-    fn get_instance() -> &'static GLOBAL_INSTANCE {
-        &GLOBAL_INSTANCE
+    /// Gets the logs as an async stream, it ends once every responder finished.
+    pub fn logs(&self) -> Messages<'_, LogLine> {
+        self.logs.stream()
     }
 
-    pub fn send_jobs(jobs: Vec<Job>) {
-        let m = MessagesBox::get_instance();
-        m.lock().unwrap().jobs.extend(jobs);
-        m.lock().unwrap().pending = true;
+    /// Gets the ids of the producers that didn't finish yet, the manager's end included.
+    pub fn get_producers(&self) -> Vec<ProducerId> {
+        let state = self.producers.state.lock().unwrap();
+        state.live.iter().copied().collect()
+    }
+
+    /// ## Description
+    /// Checks whether a producer finished: it won't send responds anymore, the ones it sent
+    /// may still wait to be taken.
+    pub fn is_producer_finished(&self, id: ProducerId) -> bool {
+        !self.producers.state.lock().unwrap().live.contains(&id)
+    }
+
+    /// ## Description
+    /// Waits at most `timeout` for a producer to finish, see `is_producer_finished`.
+    ///
+    /// Fails with `Error::Timeout` if it didn't finish in time.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use std::time::Duration;
+    /// let (ui, manager) = ipc::open(16);
+    /// let responder = manager.responder();
+    /// let id = responder.get_id();
+    /// std::thread::spawn(move || responder.finish());
+    /// assert_eq!(ui.await_producer(id, Duration::from_secs(10)), Ok(()));
+    /// assert_eq!(ui.get_producers(), vec![manager.get_id()]);
+    /// ```
+    pub fn await_producer(&self, id: ProducerId, timeout: Duration) -> Result<(), Error> {
+        let state = self.producers.state.lock().unwrap();
+        let (state, _) = self
+            .producers
+            .finished
+            .wait_timeout_while(state, timeout, |state| state.live.contains(&id))
+            .unwrap();
+        match state.live.contains(&id) {
+            true => Err(Error::Timeout),
+            false => Ok(()),
+        }
+    }
+
+    /// Takes the logs the manager sent so far.
+    pub fn recieve_logs(&self) -> Vec<LogLine> {
+        self.logs.drain()
+    }
+
+    /// ## Description
+    /// Gets the latest logs kept by the log book that pass a filter, oldest first.
+    pub fn get_logs(&self, filter: &LogFilter) -> Vec<LogLine> {
+        match self.logbook.upgrade() {
+            Some(logbook) => logbook.get_lines(filter),
+            None => vec![],
+        }
     }
 
-    pub fn recieve_jobs() -> Vec<Job> {
-        let m = MessagesBox::get_instance();
-        let out = m.lock().unwrap().jobs.clone();
-        m.lock().unwrap().jobs.clear();
-        out
+    /// ## Description
+    /// Subscribes to the logs that pass a filter from now on, at most `capacity` of them are
+    /// kept until they're read. The subscription ends once every responder finished.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let errors = ui.subscribe_logs(LogFilter { level: Level::Error, ..Default::default() }, 64);
+    /// for line in errors.drain() {
+    ///     eprintln!("{}", line);
+    /// }
+    /// ```
+    pub fn subscribe_logs(&self, filter: LogFilter, capacity: usize) -> Receiver<LogLine> {
+        match self.logbook.upgrade() {
+            Some(logbook) => logbook.subscribe(filter, capacity),
+            //the sender is dropped right away, the subscription is over
+            None => bounded(capacity).1,
+        }
     }
 
-    pub fn send_responds(responds: Vec<Respond>) {
-        let m = MessagesBox::get_instance();
-        m.lock().unwrap().responds.extend(responds);
-        m.lock().unwrap().pending = true;
+    /// ## Description
+    /// Checks whether more responds may come: some are waiting, or the manager didn't finish.
+    pub fn is_pending(&self) -> bool {
+        !self.shared.responds.is_finished()
     }
 
-    pub fn finish() {
-        let m = MessagesBox::get_instance();
-        m.lock().unwrap().pending = false;
+    // whether this end takes a respond: one of its jobs, of a dropped end or of nobody's
+    fn takes(&self, reply: &Reply) -> bool {
+        let owners = self.shared.owners.lock().unwrap();
+        match owners.jobs.get(&reply.job_id) {
+            Some(end) => *end == self.end || owners.gone.contains(end),
+            None => true,
+        }
     }
 
-    pub fn is_pending() -> bool {
-        let m = MessagesBox::get_instance();
-        m.lock().unwrap().pending
+    // throw away the responds of the dropped ends, a job is forgotten once it's done
+    fn keep(&self, replies: Vec<Reply>) -> Vec<Reply> {
+        let mut owners = self.shared.owners.lock().unwrap();
+        let mut kept = Vec::with_capacity(replies.len());
+        for reply in replies {
+            let owner = owners.jobs.get(&reply.job_id).copied();
+            if reply.respond == Respond::Done {
+                owners.jobs.remove(&reply.job_id);
+            }
+            match owner {
+                Some(end) if end != self.end && owners.gone.contains(&end) => {}
+                _ => kept.push(reply),
+            }
+        }
+        kept
     }
+}
 
-    pub fn recieve_responds() -> Vec<Respond> {
-        let m = MessagesBox::get_instance();
-        let out = m.lock().unwrap().responds.clone();
-        m.lock().unwrap().responds.clear();
-        out
+impl Drop for UiEnd {
+    fn drop(&mut self) {
+        let mut owners = self.shared.owners.lock().unwrap();
+        if owners.jobs.values().any(|end| *end == self.end) {
+            owners.gone.insert(self.end);
+        }
     }
+}
+
+/// ## Description
+/// The responds of the jobs of a UI end as an async stream, see `UiEnd::responds`.
+pub struct Responds<'a> {
+    ui: &'a UiEnd,
+}
+
+impl Stream for Responds<'_> {
+    type Item = Reply;
 
-    pub fn send_log(log: Log) {
-        let m = MessagesBox::get_instance();
-        m.lock().unwrap().logs.push(log);
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Reply>> {
+        let ui = self.ui;
+        loop {
+            match ui
+                .shared
+                .responds
+                .poll_recv_where(|reply| ui.takes(reply), cx)
+            {
+                Poll::Ready(Some(reply)) => {
+                    //a respond of a dropped end is thrown away, the next one is polled
+                    if let Some(reply) = ui.keep(vec![reply]).pop() {
+                        return Poll::Ready(Some(reply));
+                    }
+                }
+                poll => return poll,
+            }
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-pub enum Job {
-    ListEngines,
-    RunEninges {
-        engines_list: Vec<String>,
-        query: String,
-    },
+/// ## Description
+/// The responds of one job as an async stream, see `UiEnd::job_responds`.
+pub struct JobResponds<'a> {
+    ui: &'a UiEnd,
+    id: JobId,
+    done: bool,
 }
 
-#[derive(Clone, Debug)]
-pub enum Log {
-    Error(String),
-    Warning(String),
-    Info(String),
+impl Stream for JobResponds<'_> {
+    type Item = Respond;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Respond>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let id = self.id;
+        match self
+            .ui
+            .shared
+            .responds
+            .poll_recv_where(|reply| reply.job_id == id, cx)
+        {
+            Poll::Ready(Some(reply)) if reply.respond == Respond::Done => {
+                self.ui.keep(vec![reply]);
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(reply) => Poll::Ready(reply.map(|reply| reply.respond)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
-pub enum Respond {
-    EngineResult { engine: String, output: String },
-    Message(String),
-    Error(String),
+/// ## Description
+/// The manager's end of a messages box: receives jobs and sends back responds and logs.
+pub struct ManagerEnd {
+    jobs: Receiver<Request>,
+    responder: Responder,
+    ui: Weak<UiShared>,
+}
+
+impl ManagerEnd {
+    /// ## Description
+    /// Opens another UI end on the box, i.e: for a server that drives the manager next to the
+    /// window. It takes the responds of the jobs it sends, the others are left to their ends.
+    ///
+    /// Fails with `Error::Disconnected` once every UI end was dropped, the box is closed.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use ipc::{Job, Respond};
+    /// let (ui, manager) = ipc::open(16);
+    /// let server = manager.connect().unwrap();
+    /// let (first, second) = (ui.send_job(Job::ListEngines), server.send_job(Job::ListEngines));
+    /// for request in manager.recieve_jobs() {
+    ///     manager.finish_job(request.id).unwrap();
+    /// }
+    /// assert_eq!(server.recieve_responds()[0].job_id, second.unwrap());
+    /// assert_eq!(ui.recieve_responds()[0].job_id, first.unwrap());
+    /// ```
+    pub fn connect(&self) -> Result<UiEnd, Error> {
+        let shared = self.ui.upgrade().ok_or(Error::Disconnected)?;
+        Ok(self.ui_end(shared))
+    }
+
+    // a new UI end of the box
+    fn ui_end(&self, shared: Arc<UiShared>) -> UiEnd {
+        let logbook = &self.responder.logbook;
+        UiEnd {
+            end: shared.next_end.fetch_add(1, Ordering::Relaxed),
+            logs: logbook.subscribe(LogFilter::default(), shared.capacity),
+            logbook: Arc::downgrade(logbook),
+            cancelled: self.responder.cancelled.clone(),
+            producers: self.responder.producers.clone(),
+            shared,
+        }
+    }
+
+    /// Takes the jobs the UI sent so far.
+    pub fn recieve_jobs(&self) -> Vec<Request> {
+        self.jobs.drain()
+    }
+
+    /// ## Description
+    /// Waits at most `timeout` for jobs, then takes every one sent so far.
+    ///
+    /// Fails with `Error::Timeout` if none came in time, and with `Error::Disconnected` once
+    /// the UI's end is gone and every job was taken.
+    pub fn wait_jobs(&self, timeout: Duration) -> Result<Vec<Request>, Error> {
+        let mut jobs = vec![self.jobs.recv_timeout(timeout)?];
+        jobs.extend(self.jobs.drain());
+        Ok(jobs)
+    }
+
+    /// ## Description
+    /// Gets another responder for a thread that answers jobs, with its own id.
+    /// The UI is pending until every responder finished.
+    pub fn responder(&self) -> Responder {
+        self.responder.clone()
+    }
+
+    /// Gets the producer id of the manager's end.
+    pub fn get_id(&self) -> ProducerId {
+        self.responder.id
+    }
+
+    /// ## Description
+    /// Sends responds of a job to the UI without waiting: the manager may answer on the UI's
+    /// thread (i.e: a UI that calls `SherlockManager::handle_jobs`), nobody would make room.
+    ///
+    /// Fails with `Error::Full` when the UI's queue is full, the responds from the first one
+    /// that didn't fit aren't sent: the manager keeps them until the UI reads its responds.
+    pub fn send_responds(&self, job_id: JobId, responds: Vec<Respond>) -> Result<(), Error> {
+        responds
+            .into_iter()
+            .try_for_each(|respond| self.responder.responds.try_send(Reply { job_id, respond }))
+    }
+
+    /// ## Description
+    /// Tells the UI that a job is over, it has no more responds. Doesn't wait either, the job
+    /// stays cancelled if its `Respond::Done` didn't fit.
+    pub fn finish_job(&self, job_id: JobId) -> Result<(), Error> {
+        self.send_responds(job_id, vec![Respond::Done])?;
+        self.clear_cancelled(job_id);
+        Ok(())
+    }
+
+    /// Checks whether the UI cancelled a job, see `Responder::is_cancelled`.
+    pub fn is_cancelled(&self, job_id: JobId) -> bool {
+        self.responder.is_cancelled(job_id)
+    }
+
+    /// Forgets the cancel of a job, see `Responder::clear_cancelled`.
+    pub fn clear_cancelled(&self, job_id: JobId) {
+        self.responder.clear_cancelled(job_id)
+    }
+
+    /// ## Description
+    /// Logs for the UI, see `Responder::send_log`.
+    pub fn send_log(&self, job_id: Option<JobId>, source: Source, log: Log) {
+        self.responder.send_log(job_id, source, log)
+    }
+
+    /// ## Description
+    /// Writes the logs to a file from now on, see `LogBook::write_to_file`.
+    pub fn log_to_file(&self, path: &Path, max_size: u64, keep: usize) -> std::io::Result<()> {
+        self.responder.logbook.write_to_file(path, max_size, keep)
+    }
+
+    /// Closes the manager's end, the responders it handed out keep working.
+    pub fn finish(self) {}
+}
+
+/// ## Description
+/// A producer of responds and logs, every thread that answers jobs has its own.
+/// A clone is another producer, with its own id.
+pub struct Responder {
+    id: ProducerId,
+    producers: Arc<Producers>,
+    responds: Sender<Reply>,
+    logbook: Arc<LogBook>,
+    cancelled: Arc<Mutex<HashSet<JobId>>>,
+}
+
+impl Responder {
+    /// Gets the id the UI knows this producer by.
+    pub fn get_id(&self) -> ProducerId {
+        self.id
+    }
+
+    /// ## Description
+    /// Sends responds of a job to the UI, waits while its queue is full.
+    pub fn send_responds(&self, job_id: JobId, responds: Vec<Respond>) -> Result<(), Error> {
+        responds
+            .into_iter()
+            .try_for_each(|respond| self.responds.send(Reply { job_id, respond }))
+    }
+
+    /// Tells the UI that a job is over, it has no more responds.
+    pub fn finish_job(&self, job_id: JobId) -> Result<(), Error> {
+        self.clear_cancelled(job_id);
+        self.send_responds(job_id, vec![Respond::Done])
+    }
+
+    /// ## Description
+    /// Checks whether the UI cancelled a job, it's cancelled until it's finished.
+    /// A job is cancelled as soon as the UI sent the `Job::Cancel`, before it's received.
+    pub fn is_cancelled(&self, job_id: JobId) -> bool {
+        self.cancelled.lock().unwrap().contains(&job_id)
+    }
+
+    /// Forgets the cancel of a job that was over already or never sent.
+    pub fn clear_cancelled(&self, job_id: JobId) {
+        self.cancelled.lock().unwrap().remove(&job_id);
+    }
+
+    /// ## Description
+    /// Logs for the UI without waiting, the oldest logs are dropped if the UI doesn't keep up.
+    /// `job_id` is the job the log is about, `None` for the manager's own logs.
+    pub fn send_log(&self, job_id: Option<JobId>, source: Source, log: Log) {
+        self.logbook.write(LogLine::new(job_id, source, log))
+    }
+
+    /// Tells the UI this producer is done, the other ones keep it pending.
+    pub fn finish(self) {}
+}
+
+impl Clone for Responder {
+    fn clone(&self) -> Self {
+        Responder {
+            id: self.producers.register(),
+            producers: self.producers.clone(),
+            responds: self.responds.clone(),
+            logbook: self.logbook.clone(),
+            cancelled: self.cancelled.clone(),
+        }
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.producers.finish(self.id);
+    }
 }
 
 #[cfg(test)]
@@ -102,36 +740,259 @@ mod tests {
 
     #[test]
     fn clear_queues_after_read() {
+        let (ui, manager) = open(8);
         // send a job
-        MessagesBox::send_jobs(vec![Job::ListEngines]);
-        assert_eq!(GLOBAL_INSTANCE.lock().unwrap().jobs.len(), 1);
+        let id = ui.send_job(Job::ListEngines).unwrap();
         // recieve a job
-        let jobs = MessagesBox::recieve_jobs();
-        // make sure that the instance's jobs queue is clear
+        let jobs = manager.recieve_jobs();
+        // make sure that the queue is clear
         // and that the recievied jobs vector is not.
-        assert_eq!(GLOBAL_INSTANCE.lock().unwrap().jobs.len(), 0);
-        assert_ne!(jobs.len(), GLOBAL_INSTANCE.lock().unwrap().jobs.len());
+        assert_eq!(
+            jobs,
+            vec![Request {
+                id,
+                job: Job::ListEngines,
+                priority: Priority::Normal
+            }]
+        );
+        assert!(manager.recieve_jobs().is_empty());
+
+        //another messages box doesn't see the jobs of this one
+        let (other, _other_manager) = open(8);
+        other.send_jobs(vec![Job::ListEngines]).unwrap();
+        assert!(manager.recieve_jobs().is_empty());
+    }
+
+    #[test]
+    fn answer_on_the_ui_thread() {
+        let (ui, manager) = open(2);
+        let id = ui.send_job(Job::ListEngines).unwrap();
+        //the responds don't grow past the queue, nobody reads them meanwhile
+        let responds = (0..3).map(|i| Respond::Message(i.to_string())).collect();
+        assert_eq!(manager.send_responds(id, responds), Err(Error::Full));
+        assert_eq!(manager.finish_job(id), Err(Error::Full));
+        assert_eq!(ui.recieve_job(id).len(), 2);
+        manager.finish_job(id).unwrap();
+        assert_eq!(ui.await_job(id).unwrap(), vec![]);
     }
 
     #[test]
     fn multithread_test() {
-        thread::spawn(move || {
-            for i in 1..10 {
-                let msg = Respond::Message(i.to_string());
-                let responds = vec![msg];
-                println!("sent{:?}", responds);
-                MessagesBox::send_responds(responds);
+        let (ui, manager) = open(4);
+        let id = ui.send_job(Job::ListEngines).unwrap();
+        //two producers, the UI is pending until both finished
+        let producers: Vec<_> = (0..2)
+            .map(|producer| {
+                let responder = manager.responder();
+                thread::spawn(move || {
+                    for i in 1..10 {
+                        let msg = Respond::Message(format!("{}-{}", producer, i));
+                        let responds = vec![msg];
+                        println!("sent{:?}", responds);
+                        responder.send_responds(id, responds).unwrap();
+                    }
+                    responder.finish();
+                })
+            })
+            .collect();
+        manager.finish();
+
+        let handle = thread::spawn(move || {
+            let mut recieved = vec![];
+            loop {
+                match ui.wait_responds(Duration::from_secs(10)) {
+                    Ok(res) => {
+                        println!("recieved {:?}", res);
+                        recieved.extend(res);
+                    }
+                    Err(Error::Disconnected) => break,
+                    Err(error) => panic!("{}", error),
+                }
             }
-            MessagesBox::finish();
+            assert!(!ui.is_pending());
+            recieved
         });
 
-        let handle = thread::spawn(move || {
-            while MessagesBox::is_pending() {
-                let res = MessagesBox::recieve_responds();
-                println!("recieved {:?}", res);
+        //nothing is lost even though the queue is smaller than what was sent
+        let recieved = handle.join().unwrap();
+        producers
+            .into_iter()
+            .for_each(|producer| producer.join().unwrap());
+        assert_eq!(recieved.len(), 18);
+        assert!(recieved.iter().all(|reply| reply.job_id == id));
+    }
+
+    #[test]
+    fn streams_and_timeouts() {
+        let (ui, manager) = open(4);
+        let id = ui.send_job(Job::ListEngines).unwrap();
+        let short = Duration::from_millis(10);
+        assert_eq!(ui.wait_responds(short), Err(Error::Timeout));
+
+        //a timed out job keeps what it got so far
+        manager
+            .send_responds(id, vec![Respond::Message("half".into())])
+            .unwrap();
+        assert_eq!(ui.await_job_timeout(id, short), Err(Error::Timeout));
+        manager.finish_job(id).unwrap();
+        manager.send_log(
+            Some(id),
+            Source::Engine("facebook".into()),
+            Log::Info("done".into()),
+        );
+        let filter = LogFilter {
+            level: Level::Error,
+            ..Default::default()
+        };
+        assert!(ui.get_logs(&filter).is_empty());
+        assert_eq!(ui.get_logs(&LogFilter::default()).len(), 1);
+        //every producer finishes on its own
+        let responder = manager.responder();
+        let manager_id = manager.get_id();
+        assert_eq!(ui.get_producers(), vec![manager_id, responder.get_id()]);
+        let responder_id = responder.get_id();
+        responder.finish();
+        assert!(ui.is_producer_finished(responder_id));
+        assert!(!ui.is_producer_finished(manager_id));
+        assert_eq!(ui.await_producer(manager_id, short), Err(Error::Timeout));
+        manager.finish();
+        assert_eq!(ui.await_producer(manager_id, short), Ok(()));
+        assert!(ui.get_producers().is_empty());
+
+        let replies: Vec<Reply> = block_on_all(ui.responds());
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].respond, Respond::Message("half".into()));
+        let logs: Vec<LogLine> = block_on_all(ui.logs());
+        assert_eq!(logs[0].job_id, Some(id));
+    }
+
+    // collect a stream on this thread, parking until its wakers are called
+    fn block_on_all<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        struct Unpark(thread::Thread);
+        impl std::task::Wake for Unpark {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = std::sync::Arc::new(Unpark(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut items = vec![];
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return items,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn responds_of_jobs_in_flight() {
+        let (ui, manager) = open(8);
+        let ids = ui
+            .send_jobs(vec![Job::ListEngines, Job::ListEngines])
+            .unwrap();
+        assert_ne!(ids[0], ids[1]);
+        let worker = thread::spawn(move || {
+            //the second job is answered first
+            for request in manager.recieve_jobs().into_iter().rev() {
+                let engines = Respond::Engines(vec![request.id.to_string()]);
+                manager.send_responds(request.id, vec![engines]).unwrap();
+                let source = Source::Component("test".into());
+                manager.send_log(Some(request.id), source, Log::Info("listed".into()));
+                manager.finish_job(request.id).unwrap();
+            }
+        });
+        worker.join().unwrap();
+
+        assert_eq!(ui.recieve_logs()[0].job_id, Some(ids[1]));
+        assert_eq!(
+            ui.await_job(ids[0]).unwrap(),
+            vec![Respond::Engines(vec!["#1".into()])]
+        );
+        //the responds of the other job were kept
+        assert_eq!(
+            ui.recieve_job(ids[1]),
+            vec![Respond::Engines(vec!["#2".into()]), Respond::Done]
+        );
+        assert!(!ui.is_pending());
+        assert_eq!(ui.await_job(ids[0]), Err(Error::Disconnected));
+    }
+
+    #[test]
+    fn waiters_of_different_jobs() {
+        let (ui, manager) = open(4);
+        let ids = ui
+            .send_jobs(vec![Job::ListEngines, Job::ListProfiles])
+            .unwrap();
+        let ui = &ui;
+        thread::scope(|scope| {
+            //each waiter gets its job's responds, whichever comes first
+            let waiters: Vec<_> = ids
+                .iter()
+                .map(|&id| scope.spawn(move || ui.await_job(id)))
+                .collect();
+            for id in ids.iter().rev() {
+                thread::sleep(Duration::from_millis(20));
+                let respond = Respond::Message(id.to_string());
+                manager.send_responds(*id, vec![respond]).unwrap();
+                manager.finish_job(*id).unwrap();
+            }
+            for (waiter, id) in waiters.into_iter().zip(&ids) {
+                let responds = waiter.join().unwrap().unwrap();
+                assert_eq!(responds, vec![Respond::Message(id.to_string())]);
             }
         });
 
-        handle.join().unwrap();
+        //the stream of a job leaves the other job's responds
+        manager.recieve_jobs();
+        let ids = ui
+            .send_jobs(vec![Job::ListEngines, Job::ListProfiles])
+            .unwrap();
+        for id in &ids {
+            manager
+                .send_responds(*id, vec![Respond::Message(id.to_string())])
+                .unwrap();
+            manager.finish_job(*id).unwrap();
+        }
+        let responds = block_on_all(ui.job_responds(ids[1]));
+        assert_eq!(responds, vec![Respond::Message(ids[1].to_string())]);
+        assert_eq!(ui.recieve_responds().len(), 2);
+    }
+
+    #[test]
+    fn several_ui_ends() {
+        let (window, manager) = open(4);
+        let server = manager.connect().unwrap();
+        let ids = [&window, &server].map(|ui| ui.send_job(Job::ListEngines).unwrap());
+        assert_ne!(ids[0], ids[1]);
+        for id in ids {
+            manager.finish_job(id).unwrap();
+        }
+        //each end takes its job's responds
+        let replies = window.recieve_responds();
+        assert_eq!(
+            replies.iter().map(|reply| reply.job_id).collect::<Vec<_>>(),
+            vec![ids[0]]
+        );
+        assert_eq!(server.recieve_responds()[0].job_id, ids[1]);
+
+        //the responds of a dropped end are thrown away
+        let id = server.send_job(Job::ListEngines).unwrap();
+        drop(server);
+        manager.finish_job(id).unwrap();
+        assert_eq!(
+            window.wait_responds(Duration::from_millis(10)),
+            Err(Error::Timeout)
+        );
+
+        //the box is closed once every UI end is gone
+        assert_eq!(manager.recieve_jobs().len(), 3);
+        drop(window);
+        assert!(manager.connect().is_err());
+        assert_eq!(
+            manager.wait_jobs(Duration::from_millis(10)),
+            Err(Error::Disconnected)
+        );
     }
 }
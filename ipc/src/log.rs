@@ -0,0 +1,239 @@
+/*
+Log:
+The log book of the manager and its engines (the lines are defined in `messages::LogLine`).
+It keeps the latest lines in a ring buffer, hands them to the subscribers whose filter they pass,
+and can write them to a file that's rotated once it's too big: `sherlock.log` becomes
+`sherlock.log.1`, and so on up to the kept count.
+
+A subscriber that doesn't keep up loses its oldest lines, it never blocks the others.
+ */
+use crate::{bounded, Receiver, Sender};
+use messages::{Log, LogFilter, LogLine, Source};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// ## Description
+/// Keeps the latest lines, fans them out to the subscribers and writes them to the log file.
+pub struct LogBook {
+    state: Mutex<State>,
+}
+
+struct State {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    subscribers: Vec<(LogFilter, Sender<LogLine>)>,
+    file: Option<LogFile>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl LogBook {
+    /// Creates a log book that keeps the latest `capacity` lines, at least one.
+    pub fn new(capacity: usize) -> LogBook {
+        LogBook {
+            state: Mutex::new(State {
+                lines: VecDeque::new(),
+                capacity: capacity.max(1),
+                subscribers: vec![],
+                file: None,
+            }),
+        }
+    }
+
+    /// ## Description
+    /// Adds a line, the oldest one is dropped if the book is full.
+    ///
+    /// A log file that can't be written is closed, the line stays in the book.
+    pub fn write(&self, line: LogLine) {
+        let mut state = self.state.lock().unwrap();
+        //the subscribers that are gone are dropped on the way
+        state.subscribers.retain(|(filter, subscriber)| {
+            !filter.matches(&line) || subscriber.send_lossy(line.clone()).is_ok()
+        });
+        let failed = state.file.as_mut().and_then(|file| {
+            let error = file.write(&line).err()?;
+            Some(format!("{}: {}", file.path.display(), error))
+        });
+        if let Some(error) = failed {
+            state.file = None;
+            let failed = LogLine::new(None, Source::Component("ipc".into()), Log::Error(error));
+            push(&mut state, failed);
+        }
+        push(&mut state, line);
+    }
+
+    /// Gets the lines of the book that pass a filter, oldest first.
+    pub fn get_lines(&self, filter: &LogFilter) -> Vec<LogLine> {
+        let state = self.state.lock().unwrap();
+        state
+            .lines
+            .iter()
+            .filter(|line| filter.matches(line))
+            .cloned()
+            .collect()
+    }
+
+    /// ## Description
+    /// Subscribes to the lines that pass a filter from now on, the subscription keeps at most
+    /// `capacity` of them until they're read. It ends when the book is dropped.
+    pub fn subscribe(&self, filter: LogFilter, capacity: usize) -> Receiver<LogLine> {
+        let (sender, receiver) = bounded(capacity);
+        self.state
+            .lock()
+            .unwrap()
+            .subscribers
+            .push((filter, sender));
+        receiver
+    }
+
+    /// ## Description
+    /// Writes the lines to a file from now on, appending to it if it exists.
+    /// Once the file grows over `max_size` bytes it's rotated, keeping `keep` old files.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use ipc::LogBook;
+    /// let logs = LogBook::new(1000);
+    /// logs.write_to_file("./sherlock.log".as_ref(), 10 * 1024 * 1024, 3).unwrap();
+    /// ```
+    pub fn write_to_file(&self, path: &Path, max_size: u64, keep: usize) -> io::Result<()> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let size = file.metadata()?.len();
+        self.state.lock().unwrap().file = Some(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        });
+        Ok(())
+    }
+}
+
+// keep a line in the ring buffer
+fn push(state: &mut State, line: LogLine) {
+    if state.lines.len() >= state.capacity {
+        state.lines.pop_front();
+    }
+    state.lines.push_back(line);
+}
+
+impl LogFile {
+    fn write(&mut self, line: &LogLine) -> io::Result<()> {
+        let text = format!("{}\n", line);
+        if self.size > 0 && self.size + text.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(text.as_bytes())?;
+        self.size += text.len() as u64;
+        Ok(())
+    }
+
+    // shift the old files up, the oldest one is dropped, and start an empty file
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |index: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", index));
+            PathBuf::from(path)
+        };
+        if self.keep > 0 {
+            for index in (1..self.keep).rev() {
+                if rotated(index).exists() {
+                    fs::rename(rotated(index), rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::{JobId, Level};
+
+    fn line(source: &str, log: Log) -> LogLine {
+        LogLine::new(Some(JobId(1)), Source::Engine(source.into()), log)
+    }
+
+    #[test]
+    fn ring_buffer_and_subscriptions() {
+        let book = LogBook::new(2);
+        let errors = book.subscribe(
+            LogFilter {
+                level: Level::Warning,
+                ..Default::default()
+            },
+            8,
+        );
+        let facebook = book.subscribe(
+            LogFilter {
+                source: Some(Source::Engine("facebook".into())),
+                ..Default::default()
+            },
+            8,
+        );
+        book.write(line("facebook", Log::Trace("argv".into())));
+        book.write(line("twitter", Log::Error("timeout".into())));
+        book.write(line("facebook", Log::Info("done".into())));
+
+        //only the latest lines are kept
+        let kept = book.get_lines(&LogFilter::default());
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].log, Log::Error("timeout".into()));
+        assert_eq!(errors.drain().len(), 1);
+        assert_eq!(facebook.drain().len(), 2);
+
+        //a dropped subscriber is forgotten, the subscriptions end with the book
+        drop(errors);
+        book.write(line("facebook", Log::Error("blocked".into())));
+        assert_eq!(book.state.lock().unwrap().subscribers.len(), 1);
+        drop(book);
+        assert_eq!(facebook.drain().len(), 1);
+        assert!(facebook.is_finished());
+    }
+
+    #[test]
+    fn rotate_log_files() {
+        let dir = std::env::temp_dir().join(format!("sherlock-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sherlock.log");
+        let book = LogBook::new(8);
+        book.write_to_file(&path, 100, 2).unwrap();
+        for i in 0..10 {
+            book.write(line("facebook", Log::Info(format!("line {}", i))));
+        }
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.ends_with("[engine:facebook] #1 line 9\n"));
+        //the time is in RFC 3339
+        let at = current.split(' ').next().unwrap();
+        assert_eq!((at.len(), &at[10..11], &at[19..20]), (24, "T", "."));
+        assert!(at.ends_with('Z'));
+        assert!(current.len() <= 100);
+        assert!(dir.join("sherlock.log.2").exists());
+        assert!(!dir.join("sherlock.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
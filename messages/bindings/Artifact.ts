@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * A file produced by an engine run, i.e: a downloaded avatar.
+ */
+export type Artifact = { 
+/**
+ * The path of the file in the run's output directory, with `/` separators.
+ */
+name: string, 
+/**
+ * The SHA-256 of the content (hex), the content's address in the blob store.
+ */
+digest: string, 
+/**
+ * The size of the content in bytes.
+ */
+size: number, };
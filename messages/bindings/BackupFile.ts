@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * A file of the archive with its checksum.
+ */
+export type BackupFile = { 
+/**
+ * Its path in the archive, under the name of its item.
+ */
+path: string, size: number, 
+/**
+ * The hex SHA-256 of its content.
+ */
+sha256: string, };
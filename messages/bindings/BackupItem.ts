@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupPart } from "./BackupPart";
+
+/**
+ * ## Description
+ * A file or directory of the workspace kept in a backup.
+ */
+export type BackupItem = { part: BackupPart, 
+/**
+ * Its path in the archive, i.e: `engines/0`.
+ */
+name: string, 
+/**
+ * Where it was backed up from, `~` stands for the home directory.
+ * Restores use the locations of the current config instead.
+ */
+target: string, is_dir: boolean, };
@@ -0,0 +1,25 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupFile } from "./BackupFile";
+import type { BackupItem } from "./BackupItem";
+
+/**
+ * ## Description
+ * The description of a backup, the first entry of the archive.
+ */
+export type BackupManifest = { 
+/**
+ * The archive layout, see `sherlock_manager::BACKUP_FORMAT`.
+ */
+format: number, 
+/**
+ * The version of sherlock that made the backup.
+ */
+sherlock_version: string, 
+/**
+ * The schema version of the config file, see `config_manager::CURRENT_VERSION`.
+ */
+config_version: number, created_at: string, 
+/**
+ * The profile that was active, its storage is the one that was copied.
+ */
+profile: string | null, items: Array<BackupItem>, files: Array<BackupFile>, };
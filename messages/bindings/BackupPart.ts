@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * What a part of a backup holds.
+ */
+export type BackupPart = "config" | "vault" | "engines" | "storage";
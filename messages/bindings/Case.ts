@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CaseStatus } from "./CaseStatus";
+import type { RetentionPolicy } from "./RetentionPolicy";
+
+/**
+ * ## Description
+ * An investigation, the queries and engine runs of an investigation are kept under its case
+ * along with the entities found on the way and the analysts' notes.
+ */
+export type Case = { id: string, title: string, status: CaseStatus, 
+/**
+ * The analyst in charge of the case.
+ */
+owner: string | null, tags: Array<string>, created_at: string, 
+/**
+ * When the case was closed (or archived), `None` while it's open.
+ */
+closed_at: string | null, 
+/**
+ * How long the runs of the case are kept, `None` follows the global policy.
+ */
+retention: RetentionPolicy | null, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Case } from "./Case";
+import type { Entity } from "./Entity";
+import type { Note } from "./Note";
+import type { Query } from "./Query";
+import type { Run } from "./Run";
+import type { RunResult } from "./RunResult";
+
+/**
+ * ## Description
+ * Everything stored about a case, i.e: to show or to export it.
+ */
+export type CaseFile = { case: Case, queries: Array<Query>, runs: Array<Run>, results: Array<RunResult>, entities: Array<Entity>, notes: Array<Note>, };
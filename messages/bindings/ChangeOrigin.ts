@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * Where a change of the journal was made.
+ */
+export type ChangeOrigin = "Local" | "Remote";
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChangeOrigin } from "./ChangeOrigin";
+
+/**
+ * ## Description
+ * A field that was changed on both sides of a sync, resolved by keeping the latest change.
+ */
+export type Conflict = { 
+/**
+ * The kind of the record, i.e: `cases`.
+ */
+kind: string, record_id: string, field: string, 
+/**
+ * When the field was changed locally.
+ */
+local_at: string, 
+/**
+ * When the field was changed remotely.
+ */
+remote_at: string, 
+/**
+ * The side whose value was kept.
+ */
+winner: ChangeOrigin, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * What a frontend shows of an engine before running it.
+ */
+export type EngineInfo = { name: string, description: string | null, version: string | null, 
+/**
+ * The engine's commands with their descriptions.
+ */
+commands: { [key in string]?: string | null }, enabled: boolean, 
+/**
+ * The timeout of a command in seconds, after the user's overrides.
+ */
+timeout: number | null, };
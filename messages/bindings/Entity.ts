@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EntityKind } from "./EntityKind";
+
+/**
+ * ## Description
+ * Something found during an investigation, i.e: an email address in an engine's output.
+ */
+export type Entity = { id: string, case_id: string, 
+/**
+ * The run the entity was extracted from, `None` when an analyst added it.
+ */
+run_id: string | null, kind: EntityKind, value: string, created_at: string, };
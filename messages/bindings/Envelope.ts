@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Message } from "./Message";
+
+/**
+ * ## Description
+ * A message as it's sent over the wire, with the version of the protocol it was written in.
+ */
+export type Envelope = { version: number, message: Message, };
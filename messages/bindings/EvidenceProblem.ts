@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * Something wrong found while verifying the evidence log.
+ */
+export type EvidenceProblem = { "Modified": { seq: number, } } | { "Broken": { seq: number, } } | { "Missing": { from: number, to: number, } } | { "RecordChanged": { seq: number, record_id: string, } } | { "RecordMissing": { seq: number, record_id: string, } } | { "Unlogged": { kind: string, record_id: string, } } | "WrongHead";
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EvidenceProblem } from "./EvidenceProblem";
+
+/**
+ * ## Description
+ * The outcome of verifying the evidence log.
+ */
+export type EvidenceReport = { 
+/**
+ * How many entries were checked.
+ */
+entries: number, 
+/**
+ * The hash of the last entry, `None` if the log is empty.
+ */
+head: string | null, problems: Array<EvidenceProblem>, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * The kinds of indexed records.
+ */
+export type HitKind = "Result" | "Note" | "Entity";
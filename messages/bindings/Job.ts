@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CaseStatus } from "./CaseStatus";
+import type { JobId } from "./JobId";
+import type { RestoreOptions } from "./RestoreOptions";
+import type { RetentionPolicy } from "./RetentionPolicy";
+import type { SearchFilter } from "./SearchFilter";
+
+/**
+ * ## Description
+ * What a frontend asks the manager to do.
+ */
+export type Job = "ListEngines" | { "DescribeEngine": { engine: string, } } | { "RunEninges": { engines_list: Array<string>, 
+/**
+ * The engine command to run with every engine, i.e: `user`.
+ */
+command: string, query: string, } } | "ListProfiles" | { "SwitchProfile": { profile: string | null, } } | "Sync" | "SyncStatus" | { "CreateCase": { title: string, owner: string | null, } } | { "OpenCase": { case_id: string, } } | "ListCases" | { "AddNote": { text: string, author: string | null, } } | { "SetCaseStatus": { status: CaseStatus, } } | { "SetCaseTags": { tags: Array<string>, } } | { "SetCaseRetention": { retention: RetentionPolicy | null, } } | { "Search": { query: string, filter: SearchFilter, } } | "VerifyEvidence" | { "ExportEvidence": { path: string, } } | { "Purge": { dry_run: boolean, } } | { "Backup": { path: string, } } | { "Restore": { path: string, options: RestoreOptions, } } | { "Cancel": { job_id: JobId, } };
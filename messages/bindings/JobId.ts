@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * The id a frontend gives a job, the job's responds and logs carry it.
+ *
+ * It's a `number` in TypeScript, which is exact up to 2^53 - 1 (`Number.MAX_SAFE_INTEGER`):
+ * a frontend keeps its ids below that. The ids of an `ipc::UiEnd` are counted from 1.
+ */
+export type JobId = number;
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * How important a log is, from the most important to the most verbose.
+ */
+export type Level = "Error" | "Warning" | "Info" | "Debug" | "Trace";
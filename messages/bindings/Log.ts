@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Log = { "Error": string } | { "Warning": string } | { "Info": string } | { "Debug": string } | { "Trace": string };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JobId } from "./JobId";
+import type { Level } from "./Level";
+import type { Source } from "./Source";
+
+/**
+ * ## Description
+ * Which lines a reader wants, the unset fields let every line pass.
+ */
+export type LogFilter = { 
+/**
+ * The most verbose level that passes.
+ */
+level: Level, source: Source | null, job_id: JobId | null, };
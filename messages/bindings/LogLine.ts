@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JobId } from "./JobId";
+import type { Log } from "./Log";
+import type { Source } from "./Source";
+
+/**
+ * ## Description
+ * A log with where and when it comes from, and the job it's about.
+ */
+export type LogLine = { 
+/**
+ * The job the log is about, `None` for the manager's own logs.
+ */
+job_id: JobId | null, source: Source, log: Log, 
+/**
+ * When it was logged, in milliseconds since the Unix epoch.
+ */
+at: number, };
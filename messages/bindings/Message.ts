@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogLine } from "./LogLine";
+import type { Reply } from "./Reply";
+import type { Request } from "./Request";
+
+/**
+ * ## Description
+ * Any message between a frontend and the manager.
+ */
+export type Message = { "Request": Request } | { "Reply": Reply } | { "Log": LogLine };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * A note an analyst wrote in a case.
+ */
+export type Note = { id: string, case_id: string, author: string | null, text: string, created_at: string, updated_at: string, };
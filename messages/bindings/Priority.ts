@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * How soon a job runs, the manager runs the jobs of a higher priority first and the jobs of a
+ * priority in the order they were sent.
+ */
+export type Priority = "Low" | "Normal" | "High";
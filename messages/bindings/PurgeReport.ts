@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PurgedRun } from "./PurgedRun";
+
+/**
+ * ## Description
+ * What a purge deleted, or would delete for a dry run.
+ */
+export type PurgeReport = { dry_run: boolean, 
+/**
+ * When the purge was made.
+ */
+at: string, runs: Array<PurgedRun>, };
@@ -0,0 +1,28 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Artifact } from "./Artifact";
+
+/**
+ * ## Description
+ * An expired run and the records purged with it.
+ */
+export type PurgedRun = { run_id: string, case_id: string, query_id: string, engine: string, started_at: string, 
+/**
+ * Why the run expired, i.e: `older than 30 days`.
+ */
+reason: string, 
+/**
+ * The ids of the run's results.
+ */
+results: Array<string>, 
+/**
+ * The ids of the entities extracted from the run's output.
+ */
+entities: Array<string>, 
+/**
+ * The files of the run, the ones another run shares stay in the blob store.
+ */
+artifacts: Array<Artifact>, 
+/**
+ * Whether the query goes too, no other run uses it.
+ */
+query_deleted: boolean, };
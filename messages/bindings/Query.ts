@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * A query that was searched for inside a case, i.e: a username or a phone number.
+ */
+export type Query = { id: string, case_id: string, text: string, created_at: string, };
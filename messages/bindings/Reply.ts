@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JobId } from "./JobId";
+import type { Respond } from "./Respond";
+
+/**
+ * ## Description
+ * A respond to the job with the id `job_id`.
+ */
+export type Reply = { job_id: JobId, respond: Respond, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Job } from "./Job";
+import type { JobId } from "./JobId";
+import type { Priority } from "./Priority";
+
+/**
+ * ## Description
+ * A job with the id it was sent with.
+ */
+export type Request = { id: JobId, job: Job, priority: Priority, };
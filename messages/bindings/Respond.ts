@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupManifest } from "./BackupManifest";
+import type { Case } from "./Case";
+import type { CaseFile } from "./CaseFile";
+import type { EngineInfo } from "./EngineInfo";
+import type { EvidenceReport } from "./EvidenceReport";
+import type { PurgeReport } from "./PurgeReport";
+import type { RestoreReport } from "./RestoreReport";
+import type { SearchHit } from "./SearchHit";
+import type { SyncStatus } from "./SyncStatus";
+
+/**
+ * ## Description
+ * What the manager answers a job with.
+ */
+export type Respond = { "Engines": Array<string> } | { "Engine": EngineInfo } | { "EngineResult": { engine: string, output: string, } } | { "Progress": { done: number, total: number, } } | { "Message": string } | { "Error": string } | { "Profiles": { active: string | null, available: Array<string>, } } | { "Cases": { active: string | null, available: Array<Case>, } } | { "Case": CaseFile } | { "Search": Array<SearchHit> } | { "Evidence": EvidenceReport } | { "Purge": PurgeReport } | { "Sync": SyncStatus | null } | { "Backup": BackupManifest } | { "Restore": RestoreReport } | "Cancelled" | "Done";
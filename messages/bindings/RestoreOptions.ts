@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * How a backup is restored.
+ */
+export type RestoreOptions = { 
+/**
+ * Only check the archive and report what would be restored.
+ */
+dry_run: boolean, 
+/**
+ * Replace the files and directories that exist already, i.e: a corrupted storage.
+ */
+overwrite: boolean, };
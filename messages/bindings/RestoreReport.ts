@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupManifest } from "./BackupManifest";
+import type { RestoredItem } from "./RestoredItem";
+
+/**
+ * ## Description
+ * What a restore wrote, or would write for a dry run.
+ */
+export type RestoreReport = { dry_run: boolean, manifest: BackupManifest, targets: Array<RestoredItem>, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupPart } from "./BackupPart";
+
+/**
+ * ## Description
+ * Where an item of a backup is restored.
+ */
+export type RestoredItem = { part: BackupPart, target: string, 
+/**
+ * Whether something was there already, it's replaced.
+ */
+existed: boolean, };
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * How long the runs are kept, unset limits keep them forever.
+ */
+export type RetentionPolicy = { 
+/**
+ * The days a run is kept after it started.
+ */
+max_age_days: number | null, 
+/**
+ * The days the runs of a closed (or archived) case are kept after it was closed.
+ */
+closed_case_days: number | null, };
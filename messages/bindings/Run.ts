@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Artifact } from "./Artifact";
+import type { RunStatus } from "./RunStatus";
+
+/**
+ * ## Description
+ * A single execution of an engine's command for a query.
+ */
+export type Run = { id: string, case_id: string, query_id: string, engine: string, command: string, started_at: string, finished_at: string | null, status: RunStatus, 
+/**
+ * The files the engine left in its output directory, their contents are in the blob store.
+ */
+artifacts: Array<Artifact>, };
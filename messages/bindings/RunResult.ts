@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * The output an engine run produced.
+ */
+export type RunResult = { id: string, run_id: string, output: string, created_at: string, };
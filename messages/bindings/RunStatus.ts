@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * The state of an engine run.
+ */
+export type RunStatus = "Running" | "Succeeded" | { "Failed": string };
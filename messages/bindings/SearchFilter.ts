@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * ## Description
+ * The filters of a search, records must match every given filter.
+ */
+export type SearchFilter = { case_id: string | null, 
+/**
+ * The engine of the run, notes don't match an engine.
+ */
+engine: string | null, from: string | null, to: string | null, };
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { HitKind } from "./HitKind";
+
+/**
+ * ## Description
+ * A record that matched a search.
+ */
+export type SearchHit = { kind: HitKind, 
+/**
+ * The id of the result, note or entity.
+ */
+id: string, case_id: string, run_id: string | null, engine: string | null, at: string, 
+/**
+ * The part of the text around the first match.
+ */
+snippet: string, 
+/**
+ * The byte ranges of the matches in the snippet.
+ */
+highlights: Array<[number, number]>, 
+/**
+ * The number of matches, hits are sorted by it.
+ */
+score: number, };
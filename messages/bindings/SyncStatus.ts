@@ -0,0 +1,25 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Conflict } from "./Conflict";
+import type { SyncPhase } from "./SyncPhase";
+
+/**
+ * ## Description
+ * The sync status shown to the user.
+ */
+export type SyncStatus = { phase: SyncPhase, 
+/**
+ * When the last successful sync finished.
+ */
+last_sync: string | null, 
+/**
+ * Why the last sync failed.
+ */
+error: string | null, 
+/**
+ * The number of local changes that weren't pushed yet.
+ */
+pending: number, 
+/**
+ * The conflicts that were resolved since they were last cleared, oldest first.
+ */
+conflicts: Array<Conflict>, };
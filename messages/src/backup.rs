@@ -0,0 +1,93 @@
+/*
+Backup:
+The description of a backup archive and the reports of a restore, the archives themselves are
+made and read by `sherlock_manager`.
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use ts_rs::TS;
+
+/// ## Description
+/// What a part of a backup holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupPart {
+    Config,
+    Vault,
+    Engines,
+    Storage,
+}
+
+/// ## Description
+/// A file or directory of the workspace kept in a backup.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct BackupItem {
+    pub part: BackupPart,
+    /// Its path in the archive, i.e: `engines/0`.
+    pub name: String,
+    /// Where it was backed up from, `~` stands for the home directory.
+    /// Restores use the locations of the current config instead.
+    pub target: String,
+    pub is_dir: bool,
+}
+
+/// ## Description
+/// A file of the archive with its checksum.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct BackupFile {
+    /// Its path in the archive, under the name of its item.
+    pub path: String,
+    #[ts(type = "number")]
+    pub size: u64,
+    /// The hex SHA-256 of its content.
+    pub sha256: String,
+}
+
+/// ## Description
+/// The description of a backup, the first entry of the archive.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub struct BackupManifest {
+    /// The archive layout, see `sherlock_manager::BACKUP_FORMAT`.
+    pub format: u32,
+    /// The version of sherlock that made the backup.
+    pub sherlock_version: String,
+    /// The schema version of the config file, see `config_manager::CURRENT_VERSION`.
+    pub config_version: u32,
+    pub created_at: DateTime<Utc>,
+    /// The profile that was active, its storage is the one that was copied.
+    pub profile: Option<String>,
+    pub items: Vec<BackupItem>,
+    pub files: Vec<BackupFile>,
+}
+
+/// ## Description
+/// How a backup is restored.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct RestoreOptions {
+    /// Only check the archive and report what would be restored.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Replace the files and directories that exist already, i.e: a corrupted storage.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// ## Description
+/// What a restore wrote, or would write for a dry run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub struct RestoreReport {
+    pub dry_run: bool,
+    pub manifest: BackupManifest,
+    pub targets: Vec<RestoredItem>,
+}
+
+/// ## Description
+/// Where an item of a backup is restored.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct RestoredItem {
+    pub part: BackupPart,
+    pub target: PathBuf,
+    /// Whether something was there already, it's replaced.
+    pub existed: bool,
+}
@@ -0,0 +1,379 @@
+/*
+Messages:
+The schema of every message between a frontend and the SherlockManager: the jobs a frontend
+sends, the responds and logs it gets back. `ipc` carries them inside the process, the Tauri
+events and other frontends get them as JSON.
+
+On the wire every message is wrapped in an `Envelope` with the version of the protocol, a message
+of another version is refused rather than misread. The version is bumped whenever a message
+changes in a way an older frontend can't read.
+
+The TypeScript definitions of the messages are generated into `bindings/` by `cargo test`, the
+Tauri frontend imports them from there. They carry the doc comments, so the Rust examples of the
+exported types are only given to rustdoc (`cfg_attr(any(doc, doctest), doc = ...)`).
+ */
+mod backup;
+mod log;
+
+pub use backup::{
+    BackupFile, BackupItem, BackupManifest, BackupPart, RestoreOptions, RestoreReport, RestoredItem,
+};
+pub use log::{Level, Log, LogFilter, LogLine, Source};
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+use storage_manager::{
+    Case, CaseFile, CaseStatus, EvidenceReport, PurgeReport, RetentionPolicy, SearchFilter,
+    SearchHit, SyncStatus,
+};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// The version of the protocol, see `Envelope`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// ## Description
+/// The id a frontend gives a job, the job's responds and logs carry it.
+///
+/// It's a `number` in TypeScript, which is exact up to 2^53 - 1 (`Number.MAX_SAFE_INTEGER`):
+/// a frontend keeps its ids below that. The ids of an `ipc::UiEnd` are counted from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, TS)]
+pub struct JobId(#[ts(type = "number")] pub u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// ## Description
+/// How soon a job runs, the manager runs the jobs of a higher priority first and the jobs of a
+/// priority in the order they were sent.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, TS,
+)]
+pub enum Priority {
+    /// i.e: a sweep of every engine, it waits for the other jobs.
+    Low,
+    #[default]
+    Normal,
+    /// i.e: a lookup the user waits for.
+    High,
+}
+
+/// ## Description
+/// What a frontend asks the manager to do.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub enum Job {
+    ListEngines,
+    DescribeEngine {
+        engine: String,
+    },
+    RunEninges {
+        engines_list: Vec<String>,
+        /// The engine command to run with every engine, i.e: `user`.
+        command: String,
+        query: String,
+    },
+    ListProfiles,
+    SwitchProfile {
+        profile: Option<String>,
+    },
+    Sync,
+    SyncStatus,
+    CreateCase {
+        title: String,
+        owner: Option<String>,
+    },
+    OpenCase {
+        case_id: Uuid,
+    },
+    ListCases,
+    AddNote {
+        text: String,
+        author: Option<String>,
+    },
+    SetCaseStatus {
+        status: CaseStatus,
+    },
+    SetCaseTags {
+        tags: Vec<String>,
+    },
+    SetCaseRetention {
+        retention: Option<RetentionPolicy>,
+    },
+    Search {
+        query: String,
+        #[serde(default)]
+        filter: SearchFilter,
+    },
+    VerifyEvidence,
+    ExportEvidence {
+        path: String,
+    },
+    Purge {
+        dry_run: bool,
+    },
+    Backup {
+        path: String,
+    },
+    Restore {
+        path: String,
+        #[serde(default)]
+        options: RestoreOptions,
+    },
+    /// Cancels a job that didn't finish yet, its engines are killed.
+    Cancel {
+        job_id: JobId,
+    },
+}
+
+impl Job {
+    /// ## Description
+    /// Gets the priority a job is sent with unless another one is given: a run of several
+    /// engines is a sweep, a run of one engine or a cancel is something the user waits for.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use messages::{Job, Priority};
+    /// let sweep = Job::RunEninges {
+    ///     engines_list: vec!["facebook".into(), "twitter".into()],
+    ///     command: "user".into(),
+    ///     query: "user123".into(),
+    /// };
+    /// assert_eq!(sweep.get_default_priority(), Priority::Low);
+    /// assert_eq!(Job::ListEngines.get_default_priority(), Priority::Normal);
+    /// ```
+    pub fn get_default_priority(&self) -> Priority {
+        match self {
+            Job::RunEninges { engines_list, .. } if engines_list.len() > 1 => Priority::Low,
+            Job::RunEninges { .. } | Job::Cancel { .. } => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// ## Description
+/// What the manager answers a job with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub enum Respond {
+    Engines(Vec<String>),
+    Engine(EngineInfo),
+    EngineResult {
+        engine: String,
+        output: String,
+    },
+    /// How far a job with several steps is, i.e: the engines that ran out of the list.
+    Progress {
+        done: usize,
+        total: usize,
+    },
+    Message(String),
+    Error(String),
+    Profiles {
+        active: Option<String>,
+        available: Vec<String>,
+    },
+    Cases {
+        active: Option<Uuid>,
+        available: Vec<Case>,
+    },
+    Case(CaseFile),
+    Search(Vec<SearchHit>),
+    Evidence(EvidenceReport),
+    Purge(PurgeReport),
+    /// The sync state, `None` without a remote storage.
+    Sync(Option<SyncStatus>),
+    Backup(BackupManifest),
+    Restore(RestoreReport),
+    /// The job was cancelled, its `Done` follows.
+    Cancelled,
+    /// The last respond of a job.
+    Done,
+}
+
+/// ## Description
+/// What a frontend shows of an engine before running it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct EngineInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    /// The engine's commands with their descriptions.
+    pub commands: BTreeMap<String, Option<String>>,
+    pub enabled: bool,
+    /// The timeout of a command in seconds, after the user's overrides.
+    #[ts(type = "number | null")]
+    pub timeout: Option<u64>,
+}
+
+/// ## Description
+/// A job with the id it was sent with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub struct Request {
+    pub id: JobId,
+    pub job: Job,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+/// ## Description
+/// A respond to the job with the id `job_id`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub struct Reply {
+    pub job_id: JobId,
+    pub respond: Respond,
+}
+
+/// ## Description
+/// Any message between a frontend and the manager.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+pub enum Message {
+    Request(Request),
+    Reply(Reply),
+    Log(LogLine),
+}
+
+/// ## Description
+/// A message as it's sent over the wire, with the version of the protocol it was written in.
+#[cfg_attr(
+    any(doc, doctest),
+    doc = r##"
+## Example
+**Basic usage:**
+```
+# use messages::{Envelope, Job, JobId, Message, Priority, Request};
+let message = Message::Request(Request {
+    id: JobId(1),
+    job: Job::ListEngines,
+    priority: Priority::Normal,
+});
+let json = Envelope::new(message.clone()).to_json();
+let expected = r#"{"Request":{"id":1,"job":"ListEngines","priority":"Normal"}}"#;
+assert_eq!(json, format!(r#"{{"version":1,"message":{}}}"#, expected));
+assert_eq!(Envelope::from_json(&json).unwrap().message, message);
+```
+"##
+)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Envelope {
+    pub version: u32,
+    pub message: Message,
+}
+
+impl Envelope {
+    /// Wraps a message in the current version of the protocol.
+    pub fn new(message: Message) -> Envelope {
+        Envelope {
+            version: PROTOCOL_VERSION,
+            message,
+        }
+    }
+
+    /// Writes the envelope as JSON.
+    pub fn to_json(&self) -> String {
+        //the messages only hold strings, numbers and maps with string keys
+        serde_json::to_string(self).expect("a message is always valid JSON")
+    }
+
+    /// ## Description
+    /// Reads an envelope from JSON.
+    ///
+    /// Fails with `Error::Version` if it was written in another version of the protocol,
+    /// and with `Error::Json` if it isn't a valid message.
+    pub fn from_json(json: &str) -> Result<Envelope, Error> {
+        //the version is read first, a message of another version may not parse
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } =
+            serde_json::from_str(json).map_err(|error| Error::Json(error.to_string()))?;
+        if version != PROTOCOL_VERSION {
+            return Err(Error::Version(version));
+        }
+        serde_json::from_str(json).map_err(|error| Error::Json(error.to_string()))
+    }
+}
+
+/// ## Description
+/// Custom error for reading the messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Occurs when the JSON isn't a valid message.
+    Json(String),
+    /// Occurs when the message was written in an unsupported version of the protocol.
+    Version(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Json(error) => write!(f, "invalid message: {}", error),
+            Error::Version(version) => write!(
+                f,
+                "unsupported protocol version {}, expected {}",
+                version, PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = vec![
+            Message::Request(Request {
+                id: JobId(2),
+                job: Job::RunEninges {
+                    engines_list: vec!["facebook".into()],
+                    command: "user".into(),
+                    query: "user123".into(),
+                },
+                priority: Priority::High,
+            }),
+            Message::Reply(Reply {
+                job_id: JobId(2),
+                respond: Respond::Progress { done: 1, total: 1 },
+            }),
+            Message::Log(LogLine::new(
+                Some(JobId(2)),
+                Source::Engine("facebook".into()),
+                Log::Warning("slow".into()),
+            )),
+        ];
+        for message in messages {
+            let json = Envelope::new(message.clone()).to_json();
+            assert_eq!(Envelope::from_json(&json).unwrap().message, message);
+        }
+
+        //the fields with a default can be left out
+        let request: Request = serde_json::from_str(r#"{"id":3,"job":"ListCases"}"#).unwrap();
+        assert_eq!(request.priority, Priority::Normal);
+        let job: Job = serde_json::from_str(r#"{"Search":{"query":"user123"}}"#).unwrap();
+        assert_eq!(
+            job,
+            Job::Search {
+                query: "user123".into(),
+                filter: SearchFilter::default()
+            }
+        );
+    }
+
+    #[test]
+    fn refuse_other_versions() {
+        let json = r#"{"version":2,"message":{"Future":{}}}"#;
+        assert_eq!(Envelope::from_json(json), Err(Error::Version(2)));
+        assert!(matches!(
+            Envelope::from_json(r#"{"version":1,"message":{"Future":{}}}"#),
+            Err(Error::Json(_))
+        ));
+        assert!(Envelope::from_json("not json").is_err());
+    }
+}
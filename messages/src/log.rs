@@ -0,0 +1,186 @@
+/*
+Log:
+The logs of the manager and its engines. Every line has a level, a source (a component or an
+engine) and the job it's about if any, the time is in milliseconds since the Unix epoch.
+A line is shown (i.e: in the log file) with its time in RFC 3339, in UTC.
+ */
+use crate::JobId;
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use ts_rs::TS;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub enum Log {
+    Error(String),
+    Warning(String),
+    Info(String),
+    Debug(String),
+    Trace(String),
+}
+
+impl Log {
+    /// Gets the level of the log.
+    pub fn get_level(&self) -> Level {
+        match self {
+            Log::Error(_) => Level::Error,
+            Log::Warning(_) => Level::Warning,
+            Log::Info(_) => Level::Info,
+            Log::Debug(_) => Level::Debug,
+            Log::Trace(_) => Level::Trace,
+        }
+    }
+
+    /// Gets the text of the log.
+    pub fn get_message(&self) -> &str {
+        match self {
+            Log::Error(message)
+            | Log::Warning(message)
+            | Log::Info(message)
+            | Log::Debug(message)
+            | Log::Trace(message) => message,
+        }
+    }
+}
+
+/// ## Description
+/// How important a log is, from the most important to the most verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, TS)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self {
+            Level::Error => "ERROR",
+            Level::Warning => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        write!(f, "{}", level)
+    }
+}
+
+/// ## Description
+/// What a log comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+pub enum Source {
+    /// A part of sherlock, i.e: `storage`.
+    Component(String),
+    /// An engine, by name.
+    Engine(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Component(name) => write!(f, "{}", name),
+            Source::Engine(name) => write!(f, "engine:{}", name),
+        }
+    }
+}
+
+/// ## Description
+/// A log with where and when it comes from, and the job it's about.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+pub struct LogLine {
+    /// The job the log is about, `None` for the manager's own logs.
+    pub job_id: Option<JobId>,
+    pub source: Source,
+    pub log: Log,
+    /// When it was logged, in milliseconds since the Unix epoch.
+    #[ts(type = "number")]
+    pub at: u64,
+}
+
+impl LogLine {
+    /// Creates a line logged now.
+    pub fn new(job_id: Option<JobId>, source: Source, log: Log) -> LogLine {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        LogLine {
+            job_id,
+            source,
+            log,
+            at: at.as_millis() as u64,
+        }
+    }
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = DateTime::from_timestamp_millis(self.at as i64).unwrap_or_default();
+        write!(
+            f,
+            "{} {} [{}]",
+            at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.log.get_level(),
+            self.source
+        )?;
+        if let Some(job_id) = self.job_id {
+            write!(f, " {}", job_id)?;
+        }
+        write!(f, " {}", self.log.get_message())
+    }
+}
+
+/// ## Description
+/// Which lines a reader wants, the unset fields let every line pass.
+#[cfg_attr(
+    any(doc, doctest),
+    doc = r##"
+## Example
+**Basic usage:**
+```
+# use messages::{Level, Log, LogFilter, LogLine, Source};
+let filter = LogFilter {
+    level: Level::Warning,
+    ..Default::default()
+};
+let source = Source::Engine("facebook".into());
+assert!(filter.matches(&LogLine::new(None, source.clone(), Log::Error("timeout".into()))));
+assert!(!filter.matches(&LogLine::new(None, source, Log::Debug("argv".into()))));
+```
+"##
+)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct LogFilter {
+    /// The most verbose level that passes.
+    pub level: Level,
+    pub source: Option<Source>,
+    pub job_id: Option<JobId>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            level: Level::Trace,
+            source: None,
+            job_id: None,
+        }
+    }
+}
+
+impl LogFilter {
+    /// Checks whether a line passes the filter.
+    pub fn matches(&self, line: &LogLine) -> bool {
+        line.log.get_level() <= self.level
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| *source == line.source)
+            && self.job_id.is_none_or(|job_id| line.job_id == Some(job_id))
+    }
+}
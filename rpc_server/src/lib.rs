@@ -0,0 +1,993 @@
+/*
+RPC Server:
+A JSON-RPC 2.0 server on a Unix domain socket, for the frontends that don't run in the process,
+i.e: a CLI or a script. It drives the manager through a messages box (see `ipc`) like the Tauri
+window does, `SherlockManager::serve_rpc` answers its jobs.
+
+Protocol:
+    One message per line, the calls of a batch (a JSON array) are answered in a batch. Params are
+    given by name or by position, jobs, responds and logs are the JSON forms of the `messages`.
+
+    list_engines                        ["facebook", ..]
+    describe_engine {engine}            the engine's `EngineInfo`
+    run {engines, command, query, priority?}
+                                        {"job_id": <id>}, the responds follow as notifications
+    job {job, priority?}                {"job_id": <id>} for any `Job`, the responds follow as
+                                        notifications
+    cancel {job_id}                     null, the job ends with `Cancelled`
+    subscribe {logs?, responds?}        null, `logs` is the `LogFilter` of the logs to get and
+                                        `responds` gets the responds of every client's jobs,
+                                        it replaces the client's previous subscription
+
+Notifications:
+    respond {job_id, respond}           a respond of a job started by the client, `Done` is the
+                                        last one
+    log {job_id, source, log, at}       a log that passes the filter of a subscription, the logs
+                                        of a job may come before the answer with its id
+
+Errors:
+    The standard codes of JSON-RPC 2.0, and `-32000` when the manager answers with an error.
+
+The socket is only accessible by its owner, its clients are trusted to drive the manager. The
+server's UI end may share its messages box with other UIs, i.e: the window of a running instance.
+Every client has a thread that writes its answers and notifications in order, the server never
+waits for a client while it routes the responds.
+ */
+#![cfg(unix)]
+use ipc::{Job, JobId, LogFilter, Priority, Reply, Respond, UiEnd};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex, RwLock, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The message isn't valid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// The message isn't a JSON-RPC 2.0 call.
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The manager answered the job with an error.
+pub const JOB_FAILED: i64 = -32000;
+
+/// The longest message the server reads, longer ones close the connection.
+const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+/// How long a client may keep the server waiting for it to read.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the server's threads check whether it stopped.
+const POLL: Duration = Duration::from_millis(100);
+/// The logs a subscription holds for a client that reads slowly, older ones are dropped.
+const SUBSCRIPTION_CAPACITY: usize = 256;
+/// The messages waiting to be written to a client, one that doesn't read is disconnected.
+const OUTBOX_CAPACITY: usize = 1024;
+
+/// ## Description
+/// A JSON-RPC server, it serves its clients in its own threads from `bind` until it's stopped
+/// (see `get_stop_handle`) or dropped.
+pub struct RpcServer {
+    shared: Arc<Shared>,
+    accept: Option<JoinHandle<()>>,
+    dispatch: Option<JoinHandle<()>>,
+}
+
+/// ## Description
+/// Stops a server from any thread, see `RpcServer::get_stop_handle`.
+#[derive(Clone)]
+pub struct StopHandle {
+    shared: Weak<Shared>,
+}
+
+// what the threads of a server share
+struct Shared {
+    path: PathBuf,
+    //dropped once the server stops, the manager sees the UI gone
+    ui: RwLock<Option<UiEnd>>,
+    routes: Mutex<Routes>,
+    connections: Mutex<Vec<Weak<Connection>>>,
+    stopped: AtomicBool,
+}
+
+// where the responds of the jobs go
+#[derive(Default)]
+struct Routes {
+    jobs: HashMap<JobId, Route>,
+    //the responds that came before their job was routed
+    unclaimed: HashMap<JobId, Vec<Respond>>,
+    //the clients that get the responds of every job
+    watchers: Vec<Weak<Connection>>,
+}
+
+enum Route {
+    //sent to a client as notifications
+    Notify(Arc<Connection>),
+    //collected by a call that waits for the job
+    Collect(mpsc::Sender<Respond>),
+}
+
+// a client's connection, the messages queued from any thread are written by its writer
+struct Connection {
+    stream: UnixStream,
+    outbox: Mutex<VecDeque<String>>,
+    ready: Condvar,
+    closed: AtomicBool,
+    //no message is queued anymore, it's closed once the queued ones are written
+    finished: AtomicBool,
+    //the stop flag of the thread that notifies the logs, see `subscribe`
+    subscription: Mutex<Option<Arc<AtomicBool>>>,
+}
+
+// a refused call
+struct RpcError(i64, String);
+
+#[derive(Deserialize)]
+struct Call {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct DescribeParams {
+    engine: String,
+}
+
+#[derive(Deserialize)]
+struct RunParams {
+    engines: Vec<String>,
+    command: String,
+    query: String,
+    #[serde(default)]
+    priority: Option<Priority>,
+}
+
+#[derive(Deserialize)]
+struct JobParams {
+    job: Job,
+    #[serde(default)]
+    priority: Option<Priority>,
+}
+
+#[derive(Deserialize)]
+struct CancelParams {
+    job_id: JobId,
+}
+
+#[derive(Deserialize)]
+struct SubscribeParams {
+    #[serde(default)]
+    logs: Option<LogFilter>,
+    #[serde(default)]
+    responds: bool,
+}
+
+impl RpcServer {
+    /// ## Description
+    /// Listens on a Unix socket at `path` and serves its clients with the UI's end of a
+    /// messages box, the manager answers the jobs from the other end.
+    ///
+    /// A socket left at `path` by a server that's gone is replaced. Fails if another server
+    /// listens on it or `path` is something else than a socket. The socket is created in a
+    /// private directory and linked at `path` once only its owner can access it.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use rpc_server::RpcServer;
+    /// let (ui, manager) = ipc::open(64);
+    /// let server = RpcServer::bind("/tmp/sherlock.sock".as_ref(), ui).unwrap();
+    /// //answer the jobs of `manager` until the server is shut down
+    /// server.shutdown();
+    /// ```
+    pub fn bind(path: &Path, ui: UiEnd) -> Result<RpcServer, String> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err("not a socket".into());
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err("another server listens on the socket".into());
+            }
+            fs::remove_file(path).map_err(|error| error.to_string())?;
+        }
+        let listener = bind_private(path).map_err(|error| error.to_string())?;
+        let shared = Arc::new(Shared {
+            path: path.into(),
+            ui: RwLock::new(Some(ui)),
+            routes: Mutex::new(Routes::default()),
+            connections: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+        });
+        let accept = thread::spawn({
+            let shared = shared.clone();
+            move || shared.accept(listener)
+        });
+        let dispatch = thread::spawn({
+            let shared = shared.clone();
+            move || shared.dispatch()
+        });
+        Ok(RpcServer {
+            shared,
+            accept: Some(accept),
+            dispatch: Some(dispatch),
+        })
+    }
+
+    /// Gets the path of the socket.
+    pub fn get_path(&self) -> &Path {
+        &self.shared.path
+    }
+
+    /// ## Description
+    /// Gets a handle that stops the server from another thread, i.e: the one that answers its
+    /// jobs with `SherlockManager::serve_rpc`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use rpc_server::RpcServer;
+    /// let (ui, manager) = ipc::open(64);
+    /// let server = RpcServer::bind("/tmp/sherlock.sock".as_ref(), ui).unwrap();
+    /// let stop = server.get_stop_handle();
+    /// std::thread::spawn(move || stop.stop());
+    /// //the UI's end is gone once the server stopped
+    /// let poll = std::time::Duration::from_secs(1);
+    /// while manager.wait_jobs(poll) != Err(ipc::Error::Disconnected) {}
+    /// ```
+    pub fn get_stop_handle(&self) -> StopHandle {
+        StopHandle {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+
+    /// Checks whether the server was stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.shared.stopped.load(Ordering::SeqCst)
+    }
+
+    /// ## Description
+    /// Stops the server, like dropping it: the clients are disconnected, the socket is removed
+    /// and the UI's end is dropped once the last call returns.
+    pub fn shutdown(self) {}
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.shared.stop();
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        if let Some(dispatch) = self.dispatch.take() {
+            let _ = dispatch.join();
+        }
+    }
+}
+
+impl StopHandle {
+    /// ## Description
+    /// Stops the server without waiting for its threads: the clients are disconnected, the
+    /// socket is removed and the UI's end is dropped. Stopping it again does nothing.
+    pub fn stop(&self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.stop();
+        }
+    }
+
+    /// Checks whether the server was stopped, or dropped.
+    pub fn is_stopped(&self) -> bool {
+        self.shared
+            .upgrade()
+            .is_none_or(|shared| shared.stopped.load(Ordering::SeqCst))
+    }
+}
+
+impl Shared {
+    // stop serving, the threads end on their own
+    fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        //wake the accepting thread up, no client is accepted after it
+        let _ = UnixStream::connect(&self.path);
+        let _ = fs::remove_file(&self.path);
+        let connections = std::mem::take(&mut *self.connections.lock().unwrap());
+        for connection in connections.iter().filter_map(Weak::upgrade) {
+            connection.close();
+        }
+        //waits for the calls that are sending a job
+        self.ui.write().unwrap().take();
+    }
+
+    // use the UI's end while the server runs
+    fn with_ui<T>(
+        &self,
+        use_ui: impl FnOnce(&UiEnd) -> Result<T, ipc::Error>,
+    ) -> Result<T, ipc::Error> {
+        match self.ui.read().unwrap().as_ref() {
+            Some(ui) => use_ui(ui),
+            None => Err(ipc::Error::Disconnected),
+        }
+    }
+
+    // serve every client in its own thread
+    fn accept(self: &Arc<Self>, listener: UnixListener) {
+        for stream in listener.incoming() {
+            if self.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let Ok(writer) = stream.try_clone() else {
+                continue;
+            };
+            //a client that doesn't read is disconnected rather than blocking the others
+            let _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+            let connection = Arc::new(Connection {
+                stream: writer,
+                outbox: Mutex::new(VecDeque::new()),
+                ready: Condvar::new(),
+                closed: AtomicBool::new(false),
+                finished: AtomicBool::new(false),
+                subscription: Mutex::new(None),
+            });
+            let mut connections = self.connections.lock().unwrap();
+            connections.retain(|connection| connection.strong_count() > 0);
+            connections.push(Arc::downgrade(&connection));
+            thread::spawn({
+                let connection = connection.clone();
+                move || connection.write_queued()
+            });
+            let shared = self.clone();
+            thread::spawn(move || shared.serve(connection, stream));
+        }
+    }
+
+    // route the responds of the manager until it's gone or the server stopped
+    fn dispatch(&self) {
+        while !self.stopped.load(Ordering::SeqCst) {
+            match self.with_ui(|ui| ui.wait_responds(POLL)) {
+                Ok(replies) => replies.into_iter().for_each(|reply| self.route(reply)),
+                Err(ipc::Error::Timeout) => continue,
+                Err(_) => break,
+            }
+        }
+        //the calls that wait for a job are let go
+        *self.routes.lock().unwrap() = Routes::default();
+    }
+
+    // the responds are queued for the connections' writers, no client is waited for here
+    fn route(&self, reply: Reply) {
+        let mut routes = self.routes.lock().unwrap();
+        let owner = match routes.jobs.get(&reply.job_id) {
+            Some(Route::Notify(connection)) => Some(connection.clone()),
+            _ => None,
+        };
+        routes.watchers.retain(|watcher| watcher.strong_count() > 0);
+        for watcher in routes.watchers.iter().filter_map(Weak::upgrade) {
+            if owner
+                .as_ref()
+                .is_none_or(|owner| !Arc::ptr_eq(owner, &watcher))
+            {
+                watcher.notify("respond", &reply);
+            }
+        }
+        let done = reply.respond == Respond::Done;
+        match routes.jobs.get(&reply.job_id) {
+            Some(route) => route.deliver(reply.clone()),
+            None => routes
+                .unclaimed
+                .entry(reply.job_id)
+                .or_default()
+                .push(reply.respond),
+        }
+        if done {
+            routes.jobs.remove(&reply.job_id);
+        }
+    }
+
+    // route the responds of a job, the ones that came already are delivered first
+    fn claim(&self, job_id: JobId, route: Route) {
+        let mut routes = self.routes.lock().unwrap();
+        let mut done = false;
+        for respond in routes.unclaimed.remove(&job_id).unwrap_or_default() {
+            done |= respond == Respond::Done;
+            route.deliver(Reply { job_id, respond });
+        }
+        if !done {
+            routes.jobs.insert(job_id, route);
+        }
+    }
+
+    // answer the calls of a client until it disconnects
+    fn serve(&self, connection: Arc<Connection>, stream: UnixStream) {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.by_ref().take(MAX_MESSAGE_SIZE).read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(size) if size as u64 == MAX_MESSAGE_SIZE && !line.ends_with('\n') => {
+                    let error = RpcError(INVALID_REQUEST, "the message is too long".into());
+                    let _ = connection.send(&answer(Value::Null, Err(error)));
+                    break;
+                }
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => {}
+            }
+            let mut started = Vec::new();
+            let answered = match self.handle(&connection, &line, &mut started) {
+                Some(answer) => connection.send(&answer),
+                None => Ok(()),
+            };
+            //the responds of the jobs follow the answer with their ids,
+            //they're dropped if the client is gone rather than kept unclaimed
+            for job_id in started {
+                self.claim(job_id, Route::Notify(connection.clone()));
+            }
+            if answered.is_err() {
+                break;
+            }
+        }
+        connection.finish();
+    }
+
+    // answer a message, a call or a batch of calls
+    fn handle(
+        &self,
+        connection: &Arc<Connection>,
+        line: &str,
+        started: &mut Vec<JobId>,
+    ) -> Option<Value> {
+        match serde_json::from_str(line) {
+            Err(error) => Some(answer(
+                Value::Null,
+                Err(RpcError(PARSE_ERROR, error.to_string())),
+            )),
+            Ok(Value::Array(calls)) if calls.is_empty() => Some(answer(
+                Value::Null,
+                Err(RpcError(INVALID_REQUEST, "empty batch".into())),
+            )),
+            Ok(Value::Array(calls)) => {
+                let answers: Vec<Value> = calls
+                    .into_iter()
+                    .filter_map(|call| self.call(connection, call, started))
+                    .collect();
+                (!answers.is_empty()).then_some(Value::Array(answers))
+            }
+            Ok(call) => self.call(connection, call, started),
+        }
+    }
+
+    // answer a call, a call without an id is a notification and isn't answered
+    fn call(
+        &self,
+        connection: &Arc<Connection>,
+        call: Value,
+        started: &mut Vec<JobId>,
+    ) -> Option<Value> {
+        let id = call.get("id").cloned();
+        match serde_json::from_value::<Call>(call) {
+            Ok(call) if call.jsonrpc == "2.0" => {
+                let result = self.method(connection, &call.method, call.params, started);
+                id.map(|id| answer(id, result))
+            }
+            _ => {
+                let error = RpcError(INVALID_REQUEST, "not a JSON-RPC 2.0 call".into());
+                Some(answer(id.unwrap_or(Value::Null), Err(error)))
+            }
+        }
+    }
+
+    fn method(
+        &self,
+        connection: &Arc<Connection>,
+        method: &str,
+        params: Value,
+        started: &mut Vec<JobId>,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "list_engines" => match self.ask(Job::ListEngines)? {
+                Respond::Engines(engines) => to_value(engines),
+                respond => Err(unexpected(respond)),
+            },
+            "describe_engine" => {
+                let DescribeParams { engine } = parse_params(params)?;
+                match self.ask(Job::DescribeEngine { engine })? {
+                    Respond::Engine(info) => to_value(info),
+                    respond => Err(unexpected(respond)),
+                }
+            }
+            "run" => {
+                let RunParams {
+                    engines,
+                    command,
+                    query,
+                    priority,
+                } = parse_params(params)?;
+                let job = Job::RunEninges {
+                    engines_list: engines,
+                    command,
+                    query,
+                };
+                self.start(job, priority, started)
+            }
+            "job" => {
+                let JobParams { job, priority } = parse_params(params)?;
+                self.start(job, priority, started)
+            }
+            "cancel" => {
+                let CancelParams { job_id } = parse_params(params)?;
+                let cancel = self.with_ui(|ui| ui.cancel_job(job_id)).map_err(internal)?;
+                //nobody waits for the responds of the cancel itself
+                let (tx, _) = mpsc::channel();
+                self.claim(cancel, Route::Collect(tx));
+                Ok(Value::Null)
+            }
+            "subscribe" => {
+                let SubscribeParams { logs, responds } = parse_params(params)?;
+                self.subscribe(connection, logs, responds);
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError(
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            )),
+        }
+    }
+
+    // send a job and wait for its answer
+    fn ask(&self, job: Job) -> Result<Respond, RpcError> {
+        let job_id = self.with_ui(|ui| ui.send_job(job)).map_err(internal)?;
+        let (tx, rx) = mpsc::channel();
+        self.claim(job_id, Route::Collect(tx));
+        let mut answer = None;
+        let mut done = false;
+        //the route is dropped after `Done`, or when the manager is gone
+        for respond in rx {
+            match respond {
+                Respond::Error(error) => return Err(RpcError(JOB_FAILED, error)),
+                Respond::Done => done = true,
+                respond => {
+                    answer.get_or_insert(respond);
+                }
+            }
+        }
+        match answer {
+            Some(answer) if done => Ok(answer),
+            _ => Err(RpcError(INTERNAL_ERROR, "the job wasn't answered".into())),
+        }
+    }
+
+    // send a job whose responds are notified
+    fn start(
+        &self,
+        job: Job,
+        priority: Option<Priority>,
+        started: &mut Vec<JobId>,
+    ) -> Result<Value, RpcError> {
+        let priority = priority.unwrap_or_else(|| job.get_default_priority());
+        let job_id = self
+            .with_ui(|ui| ui.send_job_with_priority(job, priority))
+            .map_err(internal)?;
+        started.push(job_id);
+        Ok(json!({ "job_id": job_id }))
+    }
+
+    // replace the subscription of a client
+    fn subscribe(&self, connection: &Arc<Connection>, logs: Option<LogFilter>, responds: bool) {
+        let mut routes = self.routes.lock().unwrap();
+        routes
+            .watchers
+            .retain(|watcher| watcher.as_ptr() != Arc::as_ptr(connection));
+        if responds {
+            routes.watchers.push(Arc::downgrade(connection));
+        }
+        drop(routes);
+        //the thread of the previous logs stops
+        let stopped = Arc::new(AtomicBool::new(false));
+        let previous = connection
+            .subscription
+            .lock()
+            .unwrap()
+            .replace(stopped.clone());
+        if let Some(previous) = previous {
+            previous.store(true, Ordering::SeqCst);
+        }
+        let Some(filter) = logs else {
+            return;
+        };
+        let Ok(lines) = self.with_ui(|ui| Ok(ui.subscribe_logs(filter, SUBSCRIPTION_CAPACITY)))
+        else {
+            return;
+        };
+        let connection = connection.clone();
+        thread::spawn(move || loop {
+            let line = lines.recv_timeout(POLL);
+            //the logs taken after the subscription was replaced aren't sent
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            match line {
+                Ok(line) => connection.notify("log", &line),
+                Err(ipc::Error::Timeout) if !connection.closed.load(Ordering::SeqCst) => continue,
+                Err(_) => break,
+            }
+        });
+    }
+}
+
+impl Route {
+    fn deliver(&self, reply: Reply) {
+        match self {
+            Route::Notify(connection) => connection.notify("respond", &reply),
+            //the call stopped waiting, i.e: the job failed
+            Route::Collect(tx) => {
+                let _ = tx.send(reply.respond);
+            }
+        }
+    }
+}
+
+impl Connection {
+    // queue a message for the writer, fails once the client is gone
+    fn send(&self, message: &Value) -> io::Result<()> {
+        let mut outbox = self.outbox.lock().unwrap();
+        if self.closed.load(Ordering::SeqCst) || self.finished.load(Ordering::SeqCst) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        if outbox.len() >= OUTBOX_CAPACITY {
+            drop(outbox);
+            self.close();
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let mut line = message.to_string();
+        line.push('\n');
+        outbox.push_back(line);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    fn notify(&self, method: &str, params: &impl Serialize) {
+        //a client that went away misses it
+        let _ = self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    // write the queued messages in order until the client is gone
+    fn write_queued(&self) {
+        let mut writer = &self.stream;
+        loop {
+            let mut outbox = self.outbox.lock().unwrap();
+            let line = loop {
+                if self.closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                match outbox.pop_front() {
+                    Some(line) => break line,
+                    None if self.finished.load(Ordering::SeqCst) => {
+                        drop(outbox);
+                        self.close();
+                        return;
+                    }
+                    None => outbox = self.ready.wait(outbox).unwrap(),
+                }
+            };
+            drop(outbox);
+            if writer.write_all(line.as_bytes()).is_err() {
+                self.close();
+                return;
+            }
+        }
+    }
+
+    // close once the queued messages are written
+    fn finish(&self) {
+        let _outbox = self.outbox.lock().unwrap();
+        self.finished.store(true, Ordering::SeqCst);
+        self.ready.notify_all();
+    }
+
+    fn close(&self) {
+        let _outbox = self.outbox.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.ready.notify_all();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// bind a socket only its owner can access: it's bound in a private directory next to `path`
+// and linked at `path` afterwards, fails if something is at `path` by then
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    static BOUND: AtomicUsize = AtomicUsize::new(0);
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = parent.join(format!(
+        ".rpc-{}-{}",
+        std::process::id(),
+        BOUND.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let socket = dir.join("s");
+    let bound = UnixListener::bind(&socket).and_then(|listener| {
+        fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+        fs::hard_link(&socket, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&dir);
+    bound
+}
+
+// the answer to a call
+fn answer(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError(code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+// the params of a method, by name or by position
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Null => json!({}),
+        params => params,
+    };
+    serde_json::from_value(params).map_err(|error| RpcError(INVALID_PARAMS, error.to_string()))
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(internal)
+}
+
+fn internal(error: impl ToString) -> RpcError {
+    RpcError(INTERNAL_ERROR, error.to_string())
+}
+
+fn unexpected(respond: Respond) -> RpcError {
+    RpcError(INTERNAL_ERROR, format!("unexpected respond {:?}", respond))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipc::{Log, ManagerEnd, Source};
+
+    // answers the jobs like a manager with a `facebook` engine until the UI is gone
+    fn fake_manager(manager: ManagerEnd) {
+        loop {
+            let requests = match manager.wait_jobs(POLL) {
+                Ok(requests) => requests,
+                Err(ipc::Error::Timeout) => continue,
+                Err(_) => break,
+            };
+            for request in requests {
+                let responds = match request.job {
+                    Job::ListEngines => vec![Respond::Engines(vec!["facebook".into()])],
+                    Job::DescribeEngine { engine } => {
+                        vec![Respond::Error(format!("unknown engine {}", engine))]
+                    }
+                    Job::RunEninges { engines_list, .. } => engines_list
+                        .into_iter()
+                        .map(|engine| Respond::EngineResult {
+                            engine,
+                            output: "found".into(),
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                manager.send_log(
+                    Some(request.id),
+                    Source::Component("fake".into()),
+                    Log::Info("ran".into()),
+                );
+                manager.send_responds(request.id, responds).unwrap();
+                manager.finish_job(request.id).unwrap();
+            }
+        }
+    }
+
+    fn start(name: &str) -> (RpcServer, PathBuf, JoinHandle<()>) {
+        let path =
+            std::env::temp_dir().join(format!("sherlock-rpc-{}-{}.sock", std::process::id(), name));
+        let (ui, manager) = ipc::open(16);
+        let server = RpcServer::bind(&path, ui).unwrap();
+        (server, path, thread::spawn(move || fake_manager(manager)))
+    }
+
+    struct Client {
+        writer: UnixStream,
+        reader: BufReader<UnixStream>,
+    }
+
+    impl Client {
+        fn connect(path: &Path) -> Client {
+            let writer = UnixStream::connect(path).unwrap();
+            writer
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            Client {
+                reader: BufReader::new(writer.try_clone().unwrap()),
+                writer,
+            }
+        }
+
+        fn send(&mut self, message: &str) {
+            writeln!(self.writer, "{}", message).unwrap();
+        }
+
+        fn read(&mut self) -> Value {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn call(&mut self, message: &str) -> Value {
+            self.send(message);
+            self.read()
+        }
+    }
+
+    #[test]
+    fn answer_calls() {
+        let (server, path, manager) = start("calls");
+        //only the owner can access the socket, from the start
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mut client = Client::connect(&path);
+
+        let engines = client.call(r#"{"jsonrpc":"2.0","id":1,"method":"list_engines"}"#);
+        assert_eq!(
+            engines,
+            json!({"jsonrpc": "2.0", "id": 1, "result": ["facebook"]})
+        );
+        let code = |answer: Value| answer["error"]["code"].as_i64().unwrap();
+        let describe =
+            r#"{"jsonrpc":"2.0","id":2,"method":"describe_engine","params":["twitter"]}"#;
+        assert_eq!(code(client.call(describe)), JOB_FAILED);
+        let describe = r#"{"jsonrpc":"2.0","id":3,"method":"describe_engine"}"#;
+        assert_eq!(code(client.call(describe)), INVALID_PARAMS);
+        assert_eq!(
+            code(client.call(r#"{"jsonrpc":"2.0","id":4,"method":"fly"}"#)),
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            code(client.call(r#"{"jsonrpc":"1.0","id":5,"method":"list_engines"}"#)),
+            INVALID_REQUEST
+        );
+        assert_eq!(code(client.call("{not json")), PARSE_ERROR);
+
+        //the notifications of a batch aren't answered
+        let batch = client.call(
+            r#"[{"jsonrpc":"2.0","id":6,"method":"list_engines"},
+                {"jsonrpc":"2.0","method":"list_engines"},
+                {"jsonrpc":"2.0","id":7,"method":"cancel","params":{"job_id":99}}]"#
+                .replace('\n', "")
+                .as_str(),
+        );
+        let ids: Vec<_> = batch
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|answer| answer["id"].clone())
+            .collect();
+        assert_eq!(ids, vec![json!(6), json!(7)]);
+
+        //a second server can't take the socket over
+        let (ui, _manager) = ipc::open(1);
+        assert!(RpcServer::bind(&path, ui).is_err());
+        server.shutdown();
+        manager.join().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn notify_responds_and_logs() {
+        let (server, path, manager) = start("notify");
+        let mut client = Client::connect(&path);
+        //the second subscription replaces the first one, the logs come once
+        for id in [0, 1] {
+            let subscribe = client.call(
+                &json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "subscribe",
+                    "params": {"logs": {"level": "Info"}}
+                })
+                .to_string(),
+            );
+            assert_eq!(subscribe["result"], Value::Null);
+        }
+
+        let run = r#"{"jsonrpc":"2.0","id":2,"method":"run","params":{"engines":["facebook"],"command":"user","query":"user123"}}"#;
+        client.send(run);
+        let mut job_id = Value::Null;
+        let mut responds = Vec::new();
+        let mut logs = Vec::new();
+        while !responds.contains(&json!("Done")) || logs.is_empty() {
+            let message = client.read();
+            match message["method"].as_str() {
+                None => job_id = message["result"]["job_id"].clone(),
+                Some("respond") => {
+                    //the answer comes before the responds
+                    assert_eq!(message["params"]["job_id"], job_id);
+                    responds.push(message["params"]["respond"].clone());
+                }
+                Some("log") => logs.push(message["params"]["job_id"].clone()),
+                Some(method) => panic!("unexpected notification {}", method),
+            }
+        }
+        assert!(job_id.is_u64());
+        assert_eq!(logs, vec![job_id]);
+        assert_eq!(
+            responds,
+            vec![
+                json!({"EngineResult": {"engine": "facebook", "output": "found"}}),
+                json!("Done")
+            ]
+        );
+        //nothing else comes
+        client
+            .reader
+            .get_ref()
+            .set_read_timeout(Some(POLL * 3))
+            .unwrap();
+        let mut line = String::new();
+        assert!(client.reader.read_line(&mut line).is_err());
+        drop(server);
+        manager.join().unwrap();
+    }
+
+    #[test]
+    fn forget_the_jobs_of_a_gone_client() {
+        let (server, path, manager) = start("gone");
+        let mut client = Client::connect(&path);
+        let run = r#"{"jsonrpc":"2.0","id":1,"method":"run","params":{"engines":["facebook"],"command":"user","query":"user123"}}"#;
+        client.send(run);
+        client.writer.shutdown(Shutdown::Both).unwrap();
+        //the job's responds are dropped once it's done, none is left unclaimed
+        let forgotten = || {
+            let routes = server.shared.routes.lock().unwrap();
+            routes.jobs.is_empty() && routes.unclaimed.is_empty()
+        };
+        let start = std::time::Instant::now();
+        thread::sleep(POLL * 3);
+        while !forgotten() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(POLL);
+        }
+        drop(server);
+        manager.join().unwrap();
+    }
+
+    #[test]
+    fn stop_from_another_thread() {
+        let (server, path, manager) = start("stop");
+        let stop = server.get_stop_handle();
+        let mut client = Client::connect(&path);
+        thread::spawn(move || stop.stop()).join().unwrap();
+        assert!(server.is_stopped());
+        //the manager sees the UI gone while the server is still there
+        manager.join().unwrap();
+        assert!(!path.exists());
+        let mut line = String::new();
+        assert_eq!(client.reader.read_line(&mut line).unwrap(), 0);
+        drop(server);
+    }
+}
@@ -0,0 +1,874 @@
+/*
+Backup:
+A backup is a single gzipped tar archive of the whole workspace: the config file, the vault
+(still encrypted), the engines roots, and a copy of the storage taken while it's in use
+(the database or the records' tree, the runs' files and the sync state). Encrypted storages
+stay encrypted, the archive holds nothing that wasn't already on disk.
+
+The archive starts with `manifest.json` (see `messages::BackupManifest`): the format and
+versions it was made with, the parts and where they were backed up from, and the size and
+SHA-256 of every file. Paths under the user's home are kept as `~/...` so a backup can be
+restored on another machine.
+
+Restoring puts every part where the current config keeps it, the manifest's locations are
+only informative. The files are extracted next to their locations and checked on the way:
+the versions must be supported, every file must match the manifest, the config must parse
+and keep the parts at the same locations. Only then are they renamed over what's there,
+existing files are only replaced when asked. A dry run stops after the checks.
+ */
+use chrono::{DateTime, Utc};
+use config_manager::{ConfigManager, CURRENT_VERSION};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+use storage_manager::{Backend, StorageManager};
+use uuid::Uuid;
+
+pub use messages::{
+    BackupFile, BackupItem, BackupManifest, BackupPart, RestoreOptions, RestoreReport,
+    RestoredItem,
+};
+
+/// The version of the archive layout, archives of a newer format can't be restored.
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// The first entry of every archive.
+const MANIFEST: &str = "manifest.json";
+
+/// ## Description
+/// Backs up the workspace of a config into an archive: the config file, its vault if there's one
+/// (locked or not, it's encrypted), the engines roots and a copy of the opened storage.
+///
+/// The storage must be the one of the config, in-memory and remote storages can't be copied.
+/// ## Example
+/// **Basic usage:**
+/// ```ignore
+/// let manifest = create_backup(Path::new("./sherlock.backup"), &configs, &storage)?;
+/// println!("{} files backed up", manifest.files.len());
+/// ```
+pub fn create_backup(
+    archive: &Path,
+    configs: &ConfigManager,
+    storage: &StorageManager,
+) -> Result<BackupManifest, String> {
+    let config = configs
+        .get_source()
+        .ok_or_else(|| "the config wasn't loaded from a file".to_owned())?;
+    //the storage is copied aside first, the other parts are read in place
+    let staging = std::env::temp_dir().join(format!("sherlock-backup-{}", Uuid::new_v4()));
+    let done = storage
+        .snapshot(&staging)
+        .map_err(|error| error.to_string())
+        .and_then(|copied| {
+            let mut sources = vec![(BackupPart::Config, file_item(config, "sherlock.toml"))];
+            if let Some(vault) = configs.get_vault().filter(|vault| vault.exists()) {
+                sources.push((BackupPart::Vault, file_item(&vault, "vault")));
+            }
+            let roots = configs.get_engines_locations();
+            for (index, root) in roots.iter().enumerate().filter(|(_, root)| root.is_dir()) {
+                sources.push((BackupPart::Engines, (index.to_string(), root.clone())));
+            }
+            let mut items = vec![];
+            for (part, (name, location)) in sources {
+                items.push(item(part, &name, &location)?);
+            }
+            for (name, location) in copied {
+                let mut storage = item(BackupPart::Storage, &name, &staging.join(&name))?;
+                storage.0.target = collapse_home(&absolute(&location)?);
+                items.push(storage);
+            }
+            write_archive(archive, configs, items)
+        });
+    let _ = fs::remove_dir_all(&staging);
+    if done.is_err() {
+        let _ = fs::remove_file(archive);
+    }
+    done
+}
+
+/// ## Description
+/// Restores a backup to the locations of a config, after checking the archive.
+///
+/// Every part goes where `configs` keeps it, the config file where `configs` was loaded from:
+/// the locations the manifest was made from are never used. The backed up config must keep
+/// the parts at the same locations, so it still finds them once it's restored.
+/// The files are extracted and checked next to their locations, then renamed over them;
+/// what was there is put back if the restore fails. It fails without replacing anything if the
+/// archive was made by a newer version, doesn't match its manifest, or a location exists and
+/// `overwrite` isn't set. A storage database that's replaced loses its write ahead log,
+/// the storage must be closed.
+/// ## Example
+/// **Basic usage:**
+/// ```ignore
+/// let options = RestoreOptions { dry_run: true, overwrite: false };
+/// let report = restore_backup(Path::new("./sherlock.backup"), &configs, &options)?;
+/// for item in report.targets.iter().filter(|item| item.existed) {
+///     println!("{} would be replaced", item.target.display());
+/// }
+/// ```
+pub fn restore_backup(
+    archive: &Path,
+    configs: &ConfigManager,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, String> {
+    let destinations = Destinations::of(configs);
+    if options.dry_run {
+        let (manifest, config) = read_checked(archive)?;
+        let targets = destinations.targets(&manifest)?;
+        destinations.check_config(&manifest, &config)?;
+        return Ok(RestoreReport {
+            dry_run: true,
+            manifest,
+            targets,
+        });
+    }
+
+    let id = Uuid::new_v4();
+    let mut checker = Checker::default();
+    let mut targets: Vec<RestoredItem> = vec![];
+    let mut staged: Vec<PathBuf> = vec![];
+    //the files are checked as they're extracted, nothing's replaced before they all are
+    let extracted = read_archive(archive, |path, content, mode| {
+        checker.check(archive, path, content)?;
+        let manifest = checker
+            .manifest
+            .as_ref()
+            .ok_or("the manifest wasn't read")?;
+        if path == MANIFEST {
+            targets = destinations.targets(manifest)?;
+            let existing: Vec<String> = targets
+                .iter()
+                .filter(|item| item.existed)
+                .map(|item| item.target.display().to_string())
+                .collect();
+            if !existing.is_empty() && !options.overwrite {
+                return Err(format!("already exists: {}", existing.join(", ")));
+            }
+            for (item, restored) in manifest.items.iter().zip(&targets) {
+                let location = sibling(&restored.target, "restore", id);
+                let dir = match item.is_dir {
+                    true => location.as_path(),
+                    false => location.parent().unwrap_or(Path::new(".")),
+                };
+                staged.push(location.clone());
+                fs::create_dir_all(dir).map_err(|error| io_error(dir, error))?;
+            }
+            return Ok(());
+        }
+        //the file is under the staged location of its item
+        let (index, item) = manifest
+            .items
+            .iter()
+            .enumerate()
+            .find(|(_, item)| contains(item, path))
+            .ok_or_else(|| format!("{} isn't part of a backed up item", path))?;
+        let location = staged
+            .get(index)
+            .ok_or_else(|| format!("{} has nowhere to go", item.name))?;
+        let target = match path[item.name.len()..].strip_prefix('/') {
+            Some(rest) => location.join(rest),
+            None => location.clone(),
+        };
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir).map_err(|error| io_error(dir, error))?;
+        }
+        fs::write(&target, content).map_err(|error| io_error(&target, error))?;
+        set_mode(&target, mode)
+    });
+    let manifest = extracted.and_then(|_| {
+        let (manifest, config) = checker.finish(archive)?;
+        destinations.check_config(&manifest, &config)?;
+        Ok(manifest)
+    });
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            for location in &staged {
+                let _ = remove(location);
+            }
+            return Err(error);
+        }
+    };
+    swap(&manifest, &targets, &staged, id)?;
+    Ok(RestoreReport {
+        dry_run: false,
+        manifest,
+        targets,
+    })
+}
+
+/// ## Description
+/// Reads the manifest of an archive and checks every file against it, without restoring.
+pub fn check_archive(archive: &Path) -> Result<BackupManifest, String> {
+    read_checked(archive).map(|(manifest, _)| manifest)
+}
+
+// read the whole archive, checking it, with the content of its config
+fn read_checked(archive: &Path) -> Result<(BackupManifest, String), String> {
+    let mut checker = Checker::default();
+    read_archive(archive, |path, content, _| {
+        checker.check(archive, path, content)
+    })?;
+    checker.finish(archive)
+}
+
+// checks the files of an archive against its manifest as they're read
+#[derive(Default)]
+struct Checker {
+    manifest: Option<BackupManifest>,
+    //the files of the manifest, whether they were read
+    seen: HashMap<String, bool>,
+    config: Option<String>,
+}
+
+impl Checker {
+    // check the next file of the archive, the first one is the manifest
+    fn check(&mut self, archive: &Path, path: &str, content: &[u8]) -> Result<(), String> {
+        if path == MANIFEST && self.manifest.is_none() && self.seen.is_empty() {
+            let read: BackupManifest = serde_json::from_slice(content)
+                .map_err(|error| format!("invalid manifest: {}", error))?;
+            check_versions(&read)?;
+            for item in &read.items {
+                check_path(&item.name)?;
+                if item.name.split('/').count() != 2 {
+                    return Err(format!("invalid item in the manifest: {}", item.name));
+                }
+            }
+            for file in &read.files {
+                check_path(&file.path)?;
+                if !read.items.iter().any(|item| contains(item, &file.path)) {
+                    return Err(format!("{} isn't part of a backed up item", file.path));
+                }
+                self.seen.insert(file.path.clone(), false);
+            }
+            self.manifest = Some(read);
+            return Ok(());
+        }
+        let read = self
+            .manifest
+            .as_ref()
+            .ok_or_else(|| format!("{} doesn't start with a manifest", archive.display()))?;
+        let file = read
+            .files
+            .iter()
+            .find(|file| file.path == path)
+            .ok_or_else(|| format!("{} isn't in the manifest", path))?;
+        if self.seen.insert(path.to_owned(), true) != Some(false) {
+            return Err(format!("{} is in the archive twice", path));
+        }
+        if file.size != content.len() as u64 || file.sha256 != sha256_hex(content) {
+            return Err(format!("{} doesn't match its checksum", path));
+        }
+        let is_config = read
+            .items
+            .iter()
+            .any(|item| item.part == BackupPart::Config && item.name == path);
+        if is_config {
+            self.config = Some(String::from_utf8_lossy(content).into_owned());
+        }
+        Ok(())
+    }
+
+    // the manifest and the backed up config, once the whole archive was read
+    fn finish(self, archive: &Path) -> Result<(BackupManifest, String), String> {
+        let manifest = self
+            .manifest
+            .ok_or_else(|| format!("{} doesn't start with a manifest", archive.display()))?;
+        if let Some((path, _)) = self.seen.iter().find(|(_, seen)| !**seen) {
+            return Err(format!("{} is missing from the archive", path));
+        }
+        let config = self
+            .config
+            .ok_or_else(|| "the backup has no config".to_owned())?;
+        ConfigManager::from_toml(&config)
+            .map_err(|error| format!("the backed up config is invalid: {}", error))?;
+        Ok((manifest, config))
+    }
+}
+
+// where the parts of a backup are restored, taken from a config
+struct Destinations {
+    profile: Option<String>,
+    config: Option<PathBuf>,
+    vault: Option<PathBuf>,
+    engines: Vec<PathBuf>,
+    storage: Backend,
+}
+
+impl Destinations {
+    fn of(configs: &ConfigManager) -> Destinations {
+        Destinations {
+            profile: configs.get_profile().map(ToOwned::to_owned),
+            config: configs.get_source().map(Path::to_path_buf),
+            vault: configs.get_vault(),
+            engines: configs.get_engines_locations(),
+            storage: crate::storage_backend(configs),
+        }
+    }
+
+    // the location of an item, by its part
+    fn locate(&self, item: &BackupItem) -> Result<PathBuf, String> {
+        let name = item.name.split_once('/').map_or("", |(_, name)| name);
+        let location = match item.part {
+            BackupPart::Config => self.config.clone(),
+            BackupPart::Vault => self.vault.clone(),
+            BackupPart::Engines => name
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| self.engines.get(index).cloned()),
+            //the database, its blobs and its sync state, see `Backend::locations`
+            BackupPart::Storage => {
+                let (index, is_dir) = match name {
+                    name if name.ends_with(".blobs") => (1, true),
+                    name if name.ends_with(".sync.json") => (2, false),
+                    _ => (0, matches!(self.storage, Backend::FileTree { .. })),
+                };
+                let locations = self.storage.locations();
+                locations
+                    .get(index)
+                    .filter(|_| item.is_dir == is_dir)
+                    .cloned()
+            }
+        };
+        location.ok_or_else(|| format!("the config has no location for {}", item.name))
+    }
+
+    // the locations of the items of a manifest, each one used once
+    fn targets(&self, manifest: &BackupManifest) -> Result<Vec<RestoredItem>, String> {
+        let mut targets: Vec<RestoredItem> = vec![];
+        for item in &manifest.items {
+            let target = self.locate(item)?;
+            if targets.iter().any(|restored| restored.target == target) {
+                return Err(format!("{} is restored twice", target.display()));
+            }
+            targets.push(RestoredItem {
+                part: item.part,
+                existed: fs::symlink_metadata(&target).is_ok(),
+                target,
+            });
+        }
+        Ok(targets)
+    }
+
+    // the backed up config must find the restored parts once it replaces the current one
+    fn check_config(&self, manifest: &BackupManifest, config: &str) -> Result<(), String> {
+        let restored = ConfigManager::from_toml_with_profile(config, self.profile.as_deref())
+            .map_err(|error| format!("the backed up config is invalid: {}", error))?;
+        let restored = Destinations::of(&restored);
+        for item in &manifest.items {
+            if item.part == BackupPart::Config {
+                continue;
+            }
+            let location = self.locate(item)?;
+            if restored.locate(item).ok().as_ref() != Some(&location) {
+                return Err(format!(
+                    "the backed up config doesn't keep {} at {} like the current one",
+                    item.name,
+                    location.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// rename the staged items over their targets, what was there is put back if one fails
+fn swap(
+    manifest: &BackupManifest,
+    targets: &[RestoredItem],
+    staged: &[PathBuf],
+    id: Uuid,
+) -> Result<(), String> {
+    //the locations that were moved aside and where, and the items in place
+    let mut moved: Vec<(PathBuf, PathBuf)> = vec![];
+    let mut placed: Vec<&Path> = vec![];
+    for ((item, restored), location) in manifest.items.iter().zip(targets).zip(staged) {
+        let mut previous = vec![restored.target.clone()];
+        //a stale write ahead log would be applied to the restored database
+        if item.part == BackupPart::Storage && !item.is_dir {
+            for suffix in ["-wal", "-shm"] {
+                let mut log = restored.target.clone().into_os_string();
+                log.push(suffix);
+                previous.push(PathBuf::from(log));
+            }
+        }
+        let mut swapped = Ok(());
+        for previous in previous {
+            if swapped.is_ok() && fs::symlink_metadata(&previous).is_ok() {
+                let aside = sibling(&previous, "previous", id);
+                swapped = fs::rename(&previous, &aside).map_err(|error| io_error(&previous, error));
+                if swapped.is_ok() {
+                    moved.push((previous, aside));
+                }
+            }
+        }
+        let swapped = swapped.and_then(|_| {
+            fs::rename(location, &restored.target)
+                .map_err(|error| io_error(&restored.target, error))
+        });
+        if let Err(error) = swapped {
+            for target in placed.into_iter().rev() {
+                let _ = remove(target);
+            }
+            for (previous, aside) in moved.into_iter().rev() {
+                let _ = fs::rename(aside, previous);
+            }
+            for location in staged {
+                let _ = remove(location);
+            }
+            return Err(error);
+        }
+        placed.push(&restored.target);
+    }
+    //the restore is done, a copy that's left over is only wasted space
+    for (_, aside) in moved {
+        let _ = remove(&aside);
+    }
+    Ok(())
+}
+
+// a hidden location next to another one, i.e: `.store.db.restore-<id>`
+fn sibling(location: &Path, purpose: &str, id: Uuid) -> PathBuf {
+    let name = location
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "item".to_owned());
+    location.with_file_name(format!(".{}.{}-{}", name, purpose, id))
+}
+
+// refuse archives that this version can't restore
+fn check_versions(manifest: &BackupManifest) -> Result<(), String> {
+    if manifest.format > BACKUP_FORMAT {
+        return Err(format!(
+            "the backup format (v{}) is newer than this version of sherlock supports (v{})",
+            manifest.format, BACKUP_FORMAT
+        ));
+    }
+    //older configs are upgraded when they're loaded, newer ones can't be read
+    if manifest.config_version > CURRENT_VERSION {
+        return Err(format!(
+            "the backed up config (v{}) is newer than this version of sherlock supports (v{})",
+            manifest.config_version, CURRENT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+// archive paths are relative and can't climb out of their item
+fn check_path(path: &str) -> Result<(), String> {
+    let safe = !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    match safe {
+        true => Ok(()),
+        false => Err(format!("unsafe path in the archive: {}", path)),
+    }
+}
+
+// whether a file of the archive belongs to an item
+fn contains(item: &BackupItem, path: &str) -> bool {
+    match item.is_dir {
+        true => path
+            .strip_prefix(item.name.as_str())
+            .is_some_and(|rest| rest.starts_with('/')),
+        false => path == item.name,
+    }
+}
+
+// the name of a file in its part's directory and its location
+fn file_item(location: &Path, default: &str) -> (String, PathBuf) {
+    let name = location
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| default.to_owned());
+    (name, location.to_path_buf())
+}
+
+// an item of the manifest with the location it's read from
+fn item(part: BackupPart, name: &str, location: &Path) -> Result<(BackupItem, PathBuf), String> {
+    let metadata = fs::metadata(location).map_err(|error| io_error(location, error))?;
+    let prefix = match part {
+        BackupPart::Config => "config",
+        BackupPart::Vault => "vault",
+        BackupPart::Engines => "engines",
+        BackupPart::Storage => "storage",
+    };
+    let item = BackupItem {
+        part,
+        name: format!("{}/{}", prefix, name),
+        target: collapse_home(&absolute(location)?),
+        is_dir: metadata.is_dir(),
+    };
+    Ok((item, location.to_path_buf()))
+}
+
+// write the manifest then every file of the items
+fn write_archive(
+    archive: &Path,
+    configs: &ConfigManager,
+    items: Vec<(BackupItem, PathBuf)>,
+) -> Result<BackupManifest, String> {
+    let mut files = vec![];
+    for (item, location) in &items {
+        list_files(location, &item.name, &mut files)?;
+    }
+    let mut manifest = BackupManifest {
+        format: BACKUP_FORMAT,
+        sherlock_version: env!("CARGO_PKG_VERSION").to_owned(),
+        config_version: configs.get_version(),
+        created_at: Utc::now(),
+        profile: configs.get_profile().map(ToOwned::to_owned),
+        items: items.into_iter().map(|(item, _)| item).collect(),
+        files: vec![],
+    };
+    for (path, location) in &files {
+        let content = fs::read(location).map_err(|error| io_error(location, error))?;
+        manifest.files.push(BackupFile {
+            path: path.clone(),
+            size: content.len() as u64,
+            sha256: sha256_hex(&content),
+        });
+    }
+
+    if let Some(dir) = archive.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|error| io_error(dir, error))?;
+    }
+    let file = fs::File::create(archive).map_err(|error| io_error(archive, error))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|error| error.to_string())?;
+    append(&mut builder, MANIFEST, &json, 0o600)?;
+    for ((path, location), file) in files.iter().zip(&manifest.files) {
+        let content = fs::read(location).map_err(|error| io_error(location, error))?;
+        //a file changed while it was backed up would fail the restore's checks
+        if sha256_hex(&content) != file.sha256 {
+            return Err(format!("{} changed during the backup", location.display()));
+        }
+        append(&mut builder, path, &content, mode(location))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|file| file.sync_all())
+        .map_err(|error| io_error(archive, error))?;
+    Ok(manifest)
+}
+
+// the files under a location with their archive path, sorted
+fn list_files(
+    location: &Path,
+    path: &str,
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), String> {
+    if !location.is_dir() {
+        files.push((path.to_owned(), location.to_path_buf()));
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(location)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(|error| io_error(location, error))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        list_files(&entry.path(), &format!("{}/{}", path, name), files)?;
+    }
+    Ok(())
+}
+
+fn append<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+    mode: u32,
+) -> Result<(), String> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, content)
+        .map_err(|error| format!("{}: {}", path, error))
+}
+
+// call `read` with the path, content and mode of every file of an archive, in order
+fn read_archive<F>(archive: &Path, mut read: F) -> Result<(), String>
+where
+    F: FnMut(&str, &[u8], u32) -> Result<(), String>,
+{
+    let file = fs::File::open(archive).map_err(|error| io_error(archive, error))?;
+    let mut entries = tar::Archive::new(GzDecoder::new(file));
+    for entry in entries
+        .entries()
+        .map_err(|error| io_error(archive, error))?
+    {
+        let mut entry = entry.map_err(|error| io_error(archive, error))?;
+        if !entry.header().entry_type().is_file() {
+            return Err(format!(
+                "{} holds something else than files",
+                archive.display()
+            ));
+        }
+        let path = entry
+            .path()
+            .map_err(|error| io_error(archive, error))?
+            .to_string_lossy()
+            .into_owned();
+        let mode = entry.header().mode().unwrap_or(0o600);
+        let mut content = vec![];
+        entry
+            .read_to_end(&mut content)
+            .map_err(|error| io_error(archive, error))?;
+        read(&path, &content, mode)?;
+    }
+    Ok(())
+}
+
+// remove a file or a directory, nothing to remove is fine
+fn remove(location: &Path) -> Result<(), String> {
+    let removed = match location.is_dir() {
+        true => fs::remove_dir_all(location),
+        false => fs::remove_file(location),
+    };
+    match removed {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(io_error(location, error)),
+        _ => Ok(()),
+    }
+}
+
+fn absolute(location: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(location).map_err(|error| io_error(location, error))
+}
+
+// write paths under the home directory as `~/...`, the home differs between machines
+fn collapse_home(location: &Path) -> String {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match home.and_then(|home| location.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) => format!("~/{}", rest.display()),
+        None => location.display().to_string(),
+    }
+}
+
+// the permissions of a file, engines need their executables to stay executable
+#[cfg(unix)]
+fn mode(location: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(location)
+        .map(|metadata| metadata.permissions().mode() & 0o777)
+        .unwrap_or(0o600)
+}
+
+#[cfg(not(unix))]
+fn mode(_location: &Path) -> u32 {
+    0o600
+}
+
+#[cfg(unix)]
+fn set_mode(location: &Path, mode: u32) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(location, fs::Permissions::from_mode(mode & 0o777))
+        .map_err(|error| io_error(location, error))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_location: &Path, _mode: u32) -> Result<(), String> {
+    Ok(())
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn io_error(location: &Path, error: io::Error) -> String {
+    format!("{}: {}", location.display(), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage_manager::{Backend, Vault};
+
+    #[test]
+    fn backup_and_restore() {
+        let dir = std::env::temp_dir().join(format!("sherlock-workspace-{}", Uuid::new_v4()));
+        let engine = dir.join("engines/demo/engine.sh");
+        fs::create_dir_all(engine.parent().unwrap()).unwrap();
+        fs::write(&engine, "#!/bin/sh\necho demo\n").unwrap();
+        #[cfg(unix)]
+        set_mode(&engine, 0o755).unwrap();
+        let config = dir.join("sherlock.toml");
+        fs::write(
+            &config,
+            format!(
+                "version = {}\nengines_locations = [\"{}\"]\nvault = \"{}\"\n\
+                 [storage.sqlite]\npath = \"{}\"\n",
+                CURRENT_VERSION,
+                dir.join("engines").display(),
+                dir.join("vault").display(),
+                dir.join("store.db").display()
+            ),
+        )
+        .unwrap();
+        //the vault is backed up locked
+        Vault::open(dir.join("vault"), "secret")
+            .unwrap()
+            .set("token", "abc")
+            .unwrap();
+        let storage = StorageManager::open(Backend::Sqlite {
+            path: dir.join("store.db"),
+            encrypted: false,
+        })
+        .unwrap();
+        let case = storage.create_case("nightjar", None).unwrap();
+
+        let archive = std::env::temp_dir().join(format!("sherlock-{}.backup", Uuid::new_v4()));
+        let configs = ConfigManager::from_file(&config).unwrap();
+        let manifest = create_backup(&archive, &configs, &storage).unwrap();
+        drop(storage);
+        let parts: Vec<BackupPart> = manifest.items.iter().map(|item| item.part).collect();
+        assert_eq!(
+            parts[..3],
+            [BackupPart::Config, BackupPart::Vault, BackupPart::Engines]
+        );
+        assert!(parts[3..].iter().all(|part| *part == BackupPart::Storage));
+        assert_eq!(check_archive(&archive).unwrap(), manifest);
+
+        //a dry run only reports, the workspace is still there
+        let options = RestoreOptions {
+            dry_run: true,
+            overwrite: false,
+        };
+        let report = restore_backup(&archive, &configs, &options).unwrap();
+        assert!(report.targets.iter().all(|item| item.existed));
+        assert_eq!(report.targets[0].target, config);
+        let options = RestoreOptions {
+            dry_run: false,
+            overwrite: false,
+        };
+        assert!(restore_backup(&archive, &configs, &options).is_err());
+
+        //a lost workspace comes back whole
+        fs::remove_dir_all(&dir).unwrap();
+        restore_backup(&archive, &configs, &options).unwrap();
+        assert!(
+            Vault::open(dir.join("vault"), "secret")
+                .unwrap()
+                .get("token")
+                == Some("abc")
+        );
+        #[cfg(unix)]
+        assert_eq!(mode(&engine), 0o755);
+        let storage = StorageManager::open(Backend::Sqlite {
+            path: dir.join("store.db"),
+            encrypted: false,
+        })
+        .unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap().title, "nightjar");
+        drop(storage);
+
+        //an existing workspace is replaced, nothing's left next to it
+        let overwrite = RestoreOptions {
+            dry_run: false,
+            overwrite: true,
+        };
+        restore_backup(&archive, &configs, &overwrite).unwrap();
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(
+            names.iter().all(|name| !name.starts_with('.')),
+            "{:?}",
+            names
+        );
+
+        //an archive that doesn't match its manifest is refused
+        let mut content = vec![];
+        GzDecoder::new(fs::File::open(&archive).unwrap())
+            .read_to_end(&mut content)
+            .unwrap();
+        let at = content
+            .windows(9)
+            .position(|window| window == b"echo demo")
+            .unwrap();
+        content[at + 5] = b'D';
+        let mut encoder =
+            GzEncoder::new(fs::File::create(&archive).unwrap(), Compression::default());
+        io::Write::write_all(&mut encoder, &content).unwrap();
+        encoder.finish().unwrap();
+        let error = check_archive(&archive).unwrap_err();
+        assert!(error.ends_with("doesn't match its checksum"), "{}", error);
+        //restoring it leaves the workspace as it was
+        let error = restore_backup(&archive, &configs, &overwrite).unwrap_err();
+        assert!(error.ends_with("doesn't match its checksum"), "{}", error);
+        assert_eq!(
+            fs::read_to_string(&engine).unwrap(),
+            "#!/bin/sh\necho demo\n"
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), names.len());
+
+        //and so is one made by a newer version
+        let newer = BackupManifest {
+            format: BACKUP_FORMAT + 1,
+            ..manifest
+        };
+        assert!(check_versions(&newer).is_err());
+
+        fs::remove_file(&archive).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restore_ignores_the_manifest_locations() {
+        let dir = std::env::temp_dir().join(format!("sherlock-workspace-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("sherlock.toml");
+        fs::write(
+            &config,
+            format!(
+                "version = {}\nengines_locations = []\n[storage.sqlite]\npath = \"{}\"\n",
+                CURRENT_VERSION,
+                dir.join("store.db").display()
+            ),
+        )
+        .unwrap();
+        let configs = ConfigManager::from_file(&config).unwrap();
+        let archive = dir.join("crafted.backup");
+        let options = RestoreOptions {
+            dry_run: false,
+            overwrite: true,
+        };
+
+        //a crafted item is restored where the config is, not where the manifest says
+        let elsewhere = dir.join("elsewhere");
+        let (mut crafted, location) = item(BackupPart::Config, "sherlock.toml", &config).unwrap();
+        crafted.target = elsewhere.display().to_string();
+        write_archive(&archive, &configs, vec![(crafted, location)]).unwrap();
+        let report = restore_backup(&archive, &configs, &options).unwrap();
+        assert_eq!(report.targets[0].target, config);
+        assert!(!elsewhere.exists());
+
+        //an item the config has no location for is refused
+        let engines = dir.join("engines");
+        fs::create_dir_all(&engines).unwrap();
+        fs::write(engines.join("engine.sh"), "echo demo\n").unwrap();
+        let items = vec![
+            item(BackupPart::Config, "sherlock.toml", &config).unwrap(),
+            item(BackupPart::Engines, "7", &engines).unwrap(),
+        ];
+        write_archive(&archive, &configs, items).unwrap();
+        let error = restore_backup(&archive, &configs, &options).unwrap_err();
+        assert_eq!(error, "the config has no location for engines/7");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+/*
+Executor:
+The workers that run the engine tasks through the EnginesManager, so a slow engine doesn't hold
+the thread that submitted it, the one that answers the messages box (see `jobs`). A run of
+several engines is a task per engine, the engines' own concurrency limits still apply.
+
+Every task is submitted for a job with its own cancel flag, setting it kills the task's engine.
+Every task gets exactly one result with the id of its job, a failed task is reported on its own
+and doesn't stop the others. Shutting the executor down kills the engines that are running and
+reports the tasks that didn't start as failed.
+ */
+use engines_manager::{EnginesManager, Invocation};
+use messages::JobId;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// ## Description
+/// What a worker runs.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Task {
+    RunEngine {
+        engine: String,
+        command: String,
+        query: String,
+    },
+}
+
+/// ## Description
+/// What a task ended with.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TaskResult {
+    EngineResult {
+        engine: String,
+        output: String,
+    },
+    /// The task failed, i.e: its engine timed out or the executor was shut down.
+    Failed {
+        task: Task,
+        error: String,
+    },
+}
+
+impl TaskResult {
+    /// ## Description
+    /// Gets what an engine run ended with, its output or why it failed.
+    pub fn into_output(self) -> Result<String, String> {
+        match self {
+            TaskResult::EngineResult { output, .. } => Ok(output),
+            TaskResult::Failed { error, .. } => Err(error),
+        }
+    }
+}
+
+/// ## Description
+/// A task submitted for a job.
+pub(crate) struct Work {
+    pub job_id: JobId,
+    /// Which of the job's steps it is, i.e: the index of a run's engine.
+    pub step: usize,
+    pub task: Task,
+    /// Where the engine leaves its files, see `storage_manager::PendingRun`.
+    pub output_dir: Option<PathBuf>,
+    /// Kills the task's engine once it's set, i.e: when the job is cancelled.
+    pub cancel: Arc<AtomicBool>,
+}
+
+impl Work {
+    /// Creates the work of a job's task, without an output directory.
+    pub fn new(job_id: JobId, task: Task) -> Work {
+        Work {
+            job_id,
+            step: 0,
+            task,
+            output_dir: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// ## Description
+/// A task's result with the job it was submitted for.
+#[derive(Debug)]
+pub(crate) struct Finished {
+    pub job_id: JobId,
+    pub step: usize,
+    pub result: TaskResult,
+    /// How the engine's process was started, for the run's provenance.
+    pub invocation: Option<Invocation>,
+}
+
+pub(crate) struct Executor {
+    tasks: Option<mpsc::Sender<Work>>,
+    stopped: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Executor {
+    /// ## Description
+    /// Starts `workers` threads that run the tasks with `engines`, `on_result` gets the result
+    /// of every task from the worker that ran it.
+    pub fn new<F>(engines: Arc<EnginesManager>, workers: usize, on_result: F) -> Executor
+    where
+        F: Fn(Finished) + Send + Sync + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<Work>();
+        let rx = Arc::new(Mutex::new(rx));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let on_result = Arc::new(on_result);
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let (engines, rx, stopped, on_result) = (
+                    engines.clone(),
+                    rx.clone(),
+                    stopped.clone(),
+                    on_result.clone(),
+                );
+                thread::spawn(move || loop {
+                    //the lock is only held while waiting for a task
+                    let work = match rx.lock().unwrap().recv() {
+                        Ok(work) => work,
+                        Err(_) => break,
+                    };
+                    let cancelled =
+                        || stopped.load(Ordering::SeqCst) || work.cancel.load(Ordering::SeqCst);
+                    let mut invocation = None;
+                    let result = match stopped.load(Ordering::SeqCst) {
+                        true => TaskResult::Failed {
+                            task: work.task.clone(),
+                            error: "the executor was shut down".into(),
+                        },
+                        false => run(&engines, &work, &cancelled, &mut invocation),
+                    };
+                    on_result(Finished {
+                        job_id: work.job_id,
+                        step: work.step,
+                        result,
+                        invocation,
+                    });
+                })
+            })
+            .collect();
+        Executor {
+            tasks: Some(tx),
+            stopped,
+            workers,
+        }
+    }
+
+    /// Queues a task for the next free worker.
+    pub fn submit(&self, work: Work) {
+        if let Some(tasks) = &self.tasks {
+            //the workers only stop once the executor is dropped
+            let _ = tasks.send(work);
+        }
+    }
+
+    /// ## Description
+    /// Stops the workers once every task has a result, like dropping the executor: the
+    /// engines that are running are killed and the queued tasks fail.
+    pub fn shutdown(self) {}
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        //the workers stop once the queue is empty
+        self.tasks.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// run a task, the engine's process is killed once `cancelled` returns true
+fn run(
+    engines: &EnginesManager,
+    work: &Work,
+    cancelled: &dyn Fn() -> bool,
+    invocation: &mut Option<Invocation>,
+) -> TaskResult {
+    match &work.task {
+        Task::RunEngine {
+            engine,
+            command,
+            query,
+        } => {
+            let output_dir = work.output_dir.as_deref();
+            match engines
+                .execute_described(engine, command, query, output_dir, cancelled, invocation)
+            {
+                Ok(output) => TaskResult::EngineResult {
+                    engine: engine.clone(),
+                    output,
+                },
+                Err(error) => TaskResult::Failed {
+                    error: error.to_string(),
+                    task: work.task.clone(),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, time};
+
+    // an engines manager with an `echo` and a `sleep` engine
+    fn engines(dir: &str) -> Arc<EnginesManager> {
+        let dir = std::env::temp_dir().join(format!("{}-{}", dir, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let engines = EnginesManager::init();
+        for (name, path) in [("echo", "echo"), ("sleeper", "sleep")] {
+            let config = dir.join(format!("{}.json", name));
+            let json = format!(
+                r#"{{"name":"{}","path":"{}","commands":[{{"name":"user","args":"$query"}}]}}"#,
+                name, path
+            );
+            fs::write(&config, json).unwrap();
+            engines.add_engine_from_config(&config).unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        Arc::new(engines)
+    }
+
+    fn run_engine(engine: &str, query: &str) -> Task {
+        Task::RunEngine {
+            engine: engine.into(),
+            command: "user".into(),
+            query: query.into(),
+        }
+    }
+
+    #[test]
+    fn run_tasks_and_report_errors() {
+        let (tx, rx) = mpsc::channel();
+        let executor = Executor::new(engines("sherlock-executor"), 2, move |result| {
+            tx.send(result).unwrap()
+        });
+        executor.submit(Work::new(JobId(1), run_engine("echo", "user123")));
+        executor.submit(Work::new(JobId(2), run_engine("unknown", "user123")));
+        let mut finished: Vec<Finished> = rx.iter().take(2).collect();
+        //the results come with their job, and how the engine was started
+        finished.sort_by_key(|finished| finished.job_id);
+        assert_eq!(finished[0].invocation.as_ref().unwrap().argv[1], "user123");
+        assert!(finished[1].invocation.is_none());
+        let results: Vec<TaskResult> = finished.into_iter().map(|f| f.result).collect();
+        executor.shutdown();
+
+        assert_eq!(
+            results,
+            vec![
+                TaskResult::EngineResult {
+                    engine: "echo".into(),
+                    output: "user123\n".into()
+                },
+                TaskResult::Failed {
+                    task: run_engine("unknown", "user123"),
+                    error: "UnknownEngine".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel_one_task() {
+        let (tx, rx) = mpsc::channel();
+        let executor = Executor::new(engines("sherlock-executor-cancel"), 2, move |result| {
+            tx.send(result).unwrap()
+        });
+        let slow = Work::new(JobId(1), run_engine("sleeper", "10"));
+        let cancel = slow.cancel.clone();
+        executor.submit(slow);
+        executor.submit(Work::new(JobId(2), run_engine("sleeper", "0.3")));
+        thread::sleep(time::Duration::from_millis(100));
+        let start = time::Instant::now();
+        cancel.store(true, Ordering::SeqCst);
+        let first = rx.recv().unwrap();
+        assert!(start.elapsed() < time::Duration::from_secs(5));
+        assert_eq!(first.job_id, JobId(1));
+        assert_eq!(first.result.into_output(), Err("Cancelled".into()));
+        //the other task runs on
+        assert!(rx.recv().unwrap().result.into_output().is_ok());
+    }
+
+    #[test]
+    fn shutdown_kills_running_tasks() {
+        let (tx, rx) = mpsc::channel();
+        let executor = Executor::new(engines("sherlock-executor-shutdown"), 1, move |result| {
+            tx.send(result).unwrap()
+        });
+        executor.submit(Work::new(JobId(1), run_engine("sleeper", "10")));
+        executor.submit(Work::new(JobId(2), run_engine("echo", "user123")));
+        thread::sleep(time::Duration::from_millis(200));
+        let start = time::Instant::now();
+        executor.shutdown();
+        assert!(start.elapsed() < time::Duration::from_secs(5));
+
+        //the running task was killed, the queued one didn't run
+        let results: Vec<TaskResult> = rx
+            .iter()
+            .map(|finished: Finished| finished.result)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                TaskResult::Failed {
+                    task: run_engine("sleeper", "10"),
+                    error: "Cancelled".into()
+                },
+                TaskResult::Failed {
+                    task: run_engine("echo", "user123"),
+                    error: "the executor was shut down".into()
+                },
+            ]
+        );
+    }
+}
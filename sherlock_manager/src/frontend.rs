@@ -0,0 +1,161 @@
+/*
+Frontend:
+What the manager emits its events to and listens for tasks from. The Tauri window is a frontend
+with the `tauri` feature, `Headless` is one for tests and servers that keeps the latest events.
+
+Events are named like the Tauri events (`log-event`, `profile-event`, ...) with the JSON form of a
+`messages` type as payload, tasks come as the JSON form of a `Job` in `task-event`. A task and its
+responds are also emitted in `job-event` as a `messages::Message` with the task's job id, the id
+a `Job::Cancel` takes.
+ */
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// A handler of an event sent by a frontend, it gets the event's payload.
+pub type Handler = Box<dyn Fn(Option<&str>) + Send>;
+
+/// ## Description
+/// A frontend the manager is attached to, see `SherlockManager::attach`.
+pub trait Frontend {
+    /// Emits an event to the frontend, fails if it can't be delivered.
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String>;
+
+    /// Calls `handler` on every `event` the frontend sends.
+    fn listen(&self, event: &str, handler: Handler);
+}
+
+#[cfg(feature = "tauri")]
+impl Frontend for tauri::Window {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        tauri::Window::emit(self, event, payload).map_err(|error| error.to_string())
+    }
+
+    fn listen(&self, event: &str, handler: Handler) {
+        tauri::Window::listen(self, event, move |event| handler(event.payload()));
+    }
+}
+
+/// ## Description
+/// An event the manager emitted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Emitted {
+    pub event: String,
+    pub payload: Value,
+}
+
+/// ## Description
+/// A frontend without a window: it keeps the latest events the manager emitted, and sends the
+/// manager the events of a caller. Clones share the same events and handlers.
+/// ## Example
+/// **Basic usage:**
+/// ```ignore
+/// let headless = Headless::new(64);
+/// manager.attach(headless.clone());
+/// headless.send("task-event", r#""ListEngines""#);
+/// for emitted in headless.take_events() {
+///     println!("{}: {}", emitted.event, emitted.payload);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Headless {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    capacity: usize,
+    events: Mutex<VecDeque<Emitted>>,
+    handlers: Mutex<Vec<(String, Handler)>>,
+}
+
+impl Headless {
+    /// Creates a frontend that keeps at most `capacity` events, the oldest ones are dropped.
+    pub fn new(capacity: usize) -> Headless {
+        Headless {
+            inner: Arc::new(Inner {
+                capacity: capacity.max(1),
+                events: Mutex::new(VecDeque::new()),
+                handlers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Takes the events emitted so far, oldest first.
+    pub fn take_events(&self) -> Vec<Emitted> {
+        self.inner.events.lock().unwrap().drain(..).collect()
+    }
+
+    /// ## Description
+    /// Sends an event to the manager like a window would, i.e: a task in `task-event`.
+    /// Returns whether the manager listens for it.
+    pub fn send(&self, event: &str, payload: &str) -> bool {
+        let handlers = self.inner.handlers.lock().unwrap();
+        let mut listened = false;
+        for (_, handler) in handlers.iter().filter(|(name, _)| name == event) {
+            handler(Some(payload));
+            listened = true;
+        }
+        listened
+    }
+
+    /// ## Description
+    /// Drops the handlers, the manager stops listening to the frontend once the tasks it
+    /// received are answered.
+    pub fn close(&self) {
+        self.inner.handlers.lock().unwrap().clear();
+    }
+}
+
+impl Frontend for Headless {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        let mut events = self.inner.events.lock().unwrap();
+        if events.len() == self.inner.capacity {
+            events.pop_front();
+        }
+        events.push_back(Emitted {
+            event: event.into(),
+            payload,
+        });
+        Ok(())
+    }
+
+    fn listen(&self, event: &str, handler: Handler) {
+        self.inner
+            .handlers
+            .lock()
+            .unwrap()
+            .push((event.into(), handler));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::mpsc;
+
+    #[test]
+    fn keep_events_and_send_tasks() {
+        let headless = Headless::new(2);
+        for level in ["first", "second", "third"] {
+            headless.emit("log-event", json!({ "Info": level })).unwrap();
+        }
+        let events = headless.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].payload, json!({ "Info": "second" }));
+        assert!(headless.take_events().is_empty());
+
+        let (tx, rx) = mpsc::channel();
+        headless.listen(
+            "task-event",
+            Box::new(move |payload| tx.send(payload.map(ToOwned::to_owned)).unwrap()),
+        );
+        assert!(headless.send("task-event", "\"ListEngines\""));
+        assert!(!headless.send("other-event", "{}"));
+        assert_eq!(rx.recv().unwrap().as_deref(), Some("\"ListEngines\""));
+        headless.close();
+        assert!(rx.recv().is_err());
+    }
+}
@@ -0,0 +1,459 @@
+/*
+Jobs:
+The loop that answers the jobs of the messages box, see `SherlockManager::handle_jobs`. It also
+runs in steps in the frontend's loop, whose tasks are jobs of the box too (see
+`SherlockManager::listen`). The engines of the runs are executed on the executor's workers while
+the manager's thread keeps taking the new jobs: a job of a higher priority is answered while an
+engine runs, and the engine of a cancelled job is killed at once. The runs are recorded by the
+manager's thread, in the case that was active when they started.
+
+The engines of a run take the free workers side by side, a job of a low priority leaves one to
+the others. The jobs that replace the storage and the
+engines (a profile switch, a restore) wait until no engine runs, no engine starts meanwhile.
+
+The responds never grow past the UI's queue: the ones it has no room for are kept (see `Backlog`)
+and no engine starts until they're sent, the UI reads its responds first.
+ */
+use crate::{
+    executor::{Executor, Finished, Task, Work},
+    provenance,
+    scheduler::{Scheduled, Scheduler},
+    SherlockManager, TASK_WORKERS,
+};
+use messages::{Job, JobId, Log, Priority, Reply, Request, Respond};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time,
+};
+use storage_manager::PendingRun;
+
+/// How long the loop waits for an engine before it looks at the messages box again.
+pub(crate) const POLL: time::Duration = time::Duration::from_millis(20);
+
+/// ## Description
+/// What a runner leaves to the next one: the jobs it took from the messages box and the responds
+/// the UI had no room for, see `JobRunner::run`.
+#[derive(Default)]
+pub(crate) struct Backlog {
+    scheduler: Scheduler,
+    unsent: VecDeque<Reply>,
+}
+
+impl Backlog {
+    /// Checks whether nothing is left to answer.
+    pub fn is_empty(&self) -> bool {
+        self.scheduler.is_empty() && self.unsent.is_empty()
+    }
+}
+
+// an engine step that runs on a worker, its job stays in the scheduler meanwhile
+struct Running {
+    pending: PendingRun,
+    cancel: Arc<AtomicBool>,
+}
+
+pub(crate) struct JobRunner<'a> {
+    manager: &'a SherlockManager,
+    ipc: &'a ipc::ManagerEnd,
+    scheduler: Scheduler,
+    //by job and step
+    running: HashMap<(JobId, usize), Running>,
+    //the responds the UI had no room for, oldest first
+    unsent: VecDeque<Reply>,
+    //started with the first engine step
+    executor: Option<Executor>,
+    finished_tx: mpsc::Sender<Finished>,
+    finished_rx: mpsc::Receiver<Finished>,
+    handled: usize,
+}
+
+impl<'a> JobRunner<'a> {
+    /// Creates a runner that goes on with the jobs a previous one left, see `into_backlog`.
+    pub fn new(
+        manager: &'a SherlockManager,
+        ipc: &'a ipc::ManagerEnd,
+        backlog: Backlog,
+    ) -> JobRunner<'a> {
+        let (finished_tx, finished_rx) = mpsc::channel();
+        JobRunner {
+            manager,
+            ipc,
+            scheduler: backlog.scheduler,
+            running: HashMap::new(),
+            unsent: backlog.unsent,
+            executor: None,
+            finished_tx,
+            finished_rx,
+            handled: 0,
+        }
+    }
+
+    /// ## Description
+    /// Runs the jobs given and the ones sent meanwhile until none is left, or until the UI has
+    /// no room for their responds and no engine runs: the UI may be on this thread, the jobs
+    /// left are in the backlog (see `into_backlog`). Returns how many were finished.
+    ///
+    /// When the messages box fails, the engines that run are killed and every job that was taken
+    /// from it is answered with the error, the UI doesn't wait for them.
+    pub fn run(&mut self, requests: Vec<Request>) -> Result<usize, String> {
+        for request in requests {
+            self.scheduler.push(Scheduled::new(request));
+        }
+        let outcome = self.run_all();
+        if let Err(error) = &outcome {
+            self.abort(error);
+        }
+        outcome
+    }
+
+    // answer the jobs until none is left or the messages box fails
+    fn run_all(&mut self) -> Result<usize, String> {
+        loop {
+            self.step(POLL)?;
+            let blocked = self.running.is_empty() && !self.unsent.is_empty();
+            if self.is_idle() || blocked {
+                return Ok(self.handled);
+            }
+        }
+    }
+
+    /// Gets the jobs that are left for the next runner, once no engine runs.
+    pub fn into_backlog(self) -> Backlog {
+        Backlog {
+            scheduler: self.scheduler,
+            unsent: self.unsent,
+        }
+    }
+
+    /// ## Description
+    /// Takes the new jobs of the messages box, answers the ones that are done at once and
+    /// starts the engine steps that can start, then waits at most `timeout` for a running step
+    /// to end and answers it.
+    ///
+    /// Fails when the messages box fails, see `abort`.
+    pub fn step(&mut self, timeout: time::Duration) -> Result<(), String> {
+        //the jobs sent meanwhile are scheduled while the engines run
+        for request in self.ipc.recieve_jobs() {
+            self.scheduler.push(Scheduled::new(request));
+        }
+        for ((id, _), running) in &self.running {
+            if self.ipc.is_cancelled(*id) {
+                running.cancel.store(true, Ordering::SeqCst);
+            }
+        }
+        self.flush()?;
+        self.start_jobs()?;
+        //the jobs that can't start wait for a running one
+        if self.running.is_empty() {
+            return Ok(());
+        }
+        match self.finished_rx.recv_timeout(timeout) {
+            Ok(finished) => self.finish_step(finished),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Checks whether every job taken from the messages box was answered and its responds sent.
+    pub fn is_idle(&self) -> bool {
+        self.running.is_empty() && self.scheduler.is_empty() && self.unsent.is_empty()
+    }
+
+    // answer the jobs that are done at once and start the engine steps that can start
+    fn start_jobs(&mut self) -> Result<(), String> {
+        loop {
+            let (ipc, running) = (self.ipc, &self.running);
+            let busy = running.len();
+            //no engine starts while a job waits for them to end, or the UI for room
+            let waiting = !self.unsent.is_empty()
+                || self
+                    .scheduler
+                    .any(|scheduled| waits_for_engines(&scheduled.request));
+            let next = self.scheduler.pop_where(|scheduled| {
+                let request = &scheduled.request;
+                match &request.job {
+                    //a cancelled job ends once its steps did
+                    _ if ipc.is_cancelled(request.id) => !has_steps(running, request.id),
+                    Job::RunEninges { engines_list, .. }
+                        if scheduled.started < engines_list.len() =>
+                    {
+                        !waiting && busy < workers_for(request.priority)
+                    }
+                    //its last steps run
+                    Job::RunEninges { engines_list, .. } => scheduled.done == engines_list.len(),
+                    _ if waits_for_engines(request) => busy == 0,
+                    _ => true,
+                }
+            });
+            match next {
+                Some(scheduled) => self.start(scheduled)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // answer a job or start its next engine step
+    fn start(&mut self, mut scheduled: Scheduled) -> Result<(), String> {
+        let id = scheduled.request.id;
+        let job = scheduled.request.job.clone();
+        match &job {
+            _ if self.ipc.is_cancelled(id) => self.send(id, vec![Respond::Cancelled])?,
+            //cancelling a job that's over already does nothing
+            //a running job ends once its engines are killed
+            Job::Cancel { job_id } if has_steps(&self.running, *job_id) => {
+                for ((id, _), running) in &self.running {
+                    if id == job_id {
+                        running.cancel.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+            Job::Cancel { job_id } => match self.scheduler.remove(*job_id) {
+                Some(_) => {
+                    self.handled += 1;
+                    self.log(*job_id, Log::Info("cancelled".into()));
+                    self.ipc.clear_cancelled(*job_id);
+                    self.send(*job_id, vec![Respond::Cancelled, Respond::Done])?;
+                }
+                None => self.ipc.clear_cancelled(*job_id),
+            },
+            Job::RunEninges {
+                engines_list,
+                command,
+                query,
+            } if scheduled.started < engines_list.len() => {
+                let step = scheduled.started;
+                scheduled.started += 1;
+                let engine = &engines_list[step];
+                return match self.start_run(engine, command, query) {
+                    Ok(pending) => {
+                        self.submit((id, step), pending, engine, command, query);
+                        self.scheduler.push(scheduled);
+                        Ok(())
+                    }
+                    Err(error) => self.answer_step(scheduled, step, Err(error)),
+                };
+            }
+            Job::RunEninges { .. } => {}
+            job => {
+                let respond = self
+                    .manager
+                    .answer(job.clone())
+                    .unwrap_or_else(Respond::Error);
+                self.send(id, vec![respond])?;
+            }
+        }
+        self.finish_job(scheduled)
+    }
+
+    // save the run of an engine step in the active case
+    fn start_run(&self, engine: &str, command: &str, query: &str) -> Result<PendingRun, String> {
+        let case = self.manager.get_active_case()?;
+        let storage = self.manager.storage_manager.borrow();
+        storage
+            .start_run(case.id, query, engine, command)
+            .map_err(|error| error.to_string())
+    }
+
+    // run an engine step on a worker
+    fn submit(
+        &mut self,
+        (id, step): (JobId, usize),
+        pending: PendingRun,
+        engine: &str,
+        command: &str,
+        query: &str,
+    ) {
+        let task = Task::RunEngine {
+            engine: engine.into(),
+            command: command.into(),
+            query: query.into(),
+        };
+        let mut work = Work::new(id, task);
+        work.step = step;
+        work.output_dir = Some(pending.get_output_dir().to_owned());
+        let cancel = work.cancel.clone();
+        let manager = self.manager;
+        let finished_tx = &self.finished_tx;
+        self.executor
+            .get_or_insert_with(|| {
+                let finished_tx = finished_tx.clone();
+                Executor::new(
+                    manager.engines_manager.clone(),
+                    TASK_WORKERS,
+                    move |finished| {
+                        //the runner is gone once the messages box failed
+                        let _ = finished_tx.send(finished);
+                    },
+                )
+            })
+            .submit(work);
+        self.running.insert((id, step), Running { pending, cancel });
+    }
+
+    // record the run of an engine step that ended and answer it
+    fn finish_step(&mut self, finished: Finished) -> Result<(), String> {
+        let Some(running) = self.running.remove(&(finished.job_id, finished.step)) else {
+            return Ok(());
+        };
+        let output = finished.result.into_output();
+        let storage = self.manager.storage_manager.borrow();
+        let recorded = storage
+            .finish_run(running.pending, output, &provenance(finished.invocation))
+            .map_err(|error| error.to_string())
+            .and_then(|(_, output)| output);
+        drop(storage);
+        let Some(scheduled) = self.scheduler.remove(finished.job_id) else {
+            return Ok(());
+        };
+        match self.ipc.is_cancelled(finished.job_id) {
+            //it's ended once it's taken again
+            true => {
+                self.scheduler.push(scheduled);
+                Ok(())
+            }
+            false => self.answer_step(scheduled, finished.step, recorded),
+        }
+    }
+
+    // send the result of an engine step with the progress, the job is finished after its last
+    fn answer_step(
+        &mut self,
+        mut scheduled: Scheduled,
+        step: usize,
+        result: Result<String, String>,
+    ) -> Result<(), String> {
+        let id = scheduled.request.id;
+        let Job::RunEninges { engines_list, .. } = &scheduled.request.job else {
+            return self.finish_job(scheduled);
+        };
+        let engine = engines_list[step].clone();
+        let total = engines_list.len();
+        let result = match result {
+            Ok(output) => Respond::EngineResult {
+                engine: engine.clone(),
+                output,
+            },
+            Err(error) => {
+                self.ipc.send_log(
+                    Some(id),
+                    ipc::Source::Engine(engine.clone()),
+                    Log::Error(error.clone()),
+                );
+                Respond::Error(format!("{}: {}", engine, error))
+            }
+        };
+        scheduled.done += 1;
+        let progress = Respond::Progress {
+            done: scheduled.done,
+            total,
+        };
+        match scheduled.done < total {
+            true => {
+                self.scheduler.push(scheduled);
+                self.send(id, vec![result, progress])
+            }
+            false => {
+                self.send(id, vec![result, progress])?;
+                self.finish_job(scheduled)
+            }
+        }
+    }
+
+    // end a job after its responds
+    fn finish_job(&mut self, scheduled: Scheduled) -> Result<(), String> {
+        let id = scheduled.request.id;
+        self.ipc.clear_cancelled(id);
+        self.send(id, vec![Respond::Done])?;
+        self.log(
+            id,
+            Log::Info(format!("handled {:?}", scheduled.request.job)),
+        );
+        self.handled += 1;
+        Ok(())
+    }
+
+    /// ## Description
+    /// Kills the running engines and answers every job that was taken with the error, the runs
+    /// that ended are recorded all the same.
+    pub fn abort(&mut self, error: &str) {
+        for running in self.running.values() {
+            running.cancel.store(true, Ordering::SeqCst);
+        }
+        if let Some(executor) = self.executor.take() {
+            executor.shutdown();
+        }
+        //the runs that ended are recorded all the same
+        while let Ok(finished) = self.finished_rx.try_recv() {
+            if let Some(running) = self.running.remove(&(finished.job_id, finished.step)) {
+                let storage = self.manager.storage_manager.borrow();
+                let output = finished.result.into_output();
+                let _ =
+                    storage.finish_run(running.pending, output, &provenance(finished.invocation));
+            }
+        }
+        self.running.clear();
+        for scheduled in self.scheduler.drain() {
+            let id = scheduled.request.id;
+            self.ipc.clear_cancelled(id);
+            let responds = [Respond::Error(error.to_owned()), Respond::Done];
+            let replies = responds.map(|respond| Reply {
+                job_id: id,
+                respond,
+            });
+            self.unsent.extend(replies);
+        }
+        //the ones the UI has no room for are sent by the next runner
+        let _ = self.flush();
+    }
+
+    // send responds of a job after the ones the UI had no room for
+    fn send(&mut self, id: JobId, responds: Vec<Respond>) -> Result<(), String> {
+        let replies = responds.into_iter().map(|respond| Reply {
+            job_id: id,
+            respond,
+        });
+        self.unsent.extend(replies);
+        self.flush()
+    }
+
+    // send the responds the UI had no room for, oldest first
+    fn flush(&mut self) -> Result<(), String> {
+        while let Some(reply) = self.unsent.front() {
+            let respond = reply.respond.clone();
+            match self.ipc.send_responds(reply.job_id, vec![respond]) {
+                Ok(()) => {
+                    self.unsent.pop_front();
+                }
+                Err(ipc::Error::Full) => return Ok(()),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn log(&self, id: JobId, log: Log) {
+        let source = ipc::Source::Component("sherlock_manager".into());
+        self.ipc.send_log(Some(id), source, log)
+    }
+}
+
+// the workers a job's engine steps can take, a low priority job leaves one to the others
+fn workers_for(priority: Priority) -> usize {
+    match priority {
+        Priority::Low => TASK_WORKERS - 1,
+        _ => TASK_WORKERS,
+    }
+}
+
+// whether one of a job's engine steps runs
+fn has_steps(running: &HashMap<(JobId, usize), Running>, id: JobId) -> bool {
+    running.keys().any(|(job_id, _)| *job_id == id)
+}
+
+// whether a job replaces the storage or the engines that the running engine steps use
+fn waits_for_engines(request: &Request) -> bool {
+    matches!(request.job, Job::SwitchProfile { .. } | Job::Restore { .. })
+}
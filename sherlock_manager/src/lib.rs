@@ -1,136 +1,968 @@
 #![allow(unused)] //TODO: remove later
-use config_manager::ConfigManager;
-use engines_manager::EnginesManager;
-use storage_manager::StorageManager;
+use config_manager::{ConfigManager, StrorageType};
+use engines_manager::{DiscoveryOptions, EngineOverride, EngineSettings, EnginesManager};
+use storage_manager::{
+    Backend, Case, CaseFile, CaseStatus, EvidenceExport, EvidenceReport, KeySource, Note,
+    Provenance, PurgeReport, RetentionPolicy, SearchFilter, SearchHit, StorageManager, SyncStatus,
+    Vault,
+};
 
+mod backup;
+mod executor;
+mod frontend;
+mod jobs;
+mod scheduler;
+pub use backup::{
+    check_archive, create_backup, restore_backup, BackupFile, BackupItem, BackupManifest,
+    BackupPart, RestoreOptions, RestoreReport, RestoredItem, BACKUP_FORMAT,
+};
+pub use frontend::{Emitted, Frontend, Handler, Headless};
+
+use messages::{EngineInfo, Job, JobId, Log, Reply, Request, Respond};
+use jobs::JobRunner;
 use serde::{Deserialize, Serialize};
-use serde_json;
-use std::{cell::RefCell, collections::HashMap, fs, io, path, sync::mpsc, thread, time};
-use tauri::Window;
-
-#[derive(Clone, Serialize, Deserialize, Debug)]
-enum Task {
-    ListEngines,
-    RunEngine { engine_name: String, query: String },
-}
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs, io, path,
+    sync::{mpsc, Arc},
+    thread, time,
+};
+use uuid::Uuid;
 
-#[derive(Clone, serde::Serialize)]
-enum Log {
-    Info(String),
-    Error(String),
-}
+/// The environment variable with the passphrase of an encrypted storage.
+pub const PASSPHRASE_ENV_VAR: &str = "SHERLOCK_PASSPHRASE";
 
-#[derive(Clone, Debug, serde::Serialize)]
-enum TaskResult {
-    List(Vec<String>),
-}
+/// The hits a search answers at most.
+const SEARCH_LIMIT: usize = 100;
+/// The workers that run the engine tasks, see `executor`.
+const TASK_WORKERS: usize = 4;
+/// How often `serve_jobs` checks whether the UI dropped its end.
+const SERVE_POLL: time::Duration = time::Duration::from_millis(200);
+/// The messages the box of the frontend holds, when `listen` opens it.
+const FRONTEND_CAPACITY: usize = 64;
+/// The messages the box of the JSON-RPC server holds.
+#[cfg(unix)]
+const RPC_CAPACITY: usize = 64;
 
+//the messages of the frontend's loop, see `listen`
 enum Message {
     Log(Log),
-    Task(Task),
-    TaskResult(TaskResult),
+    Job(Job),
+    //the frontend dropped the handler of its tasks
+    Closed,
+}
+
+// sends `Message::Closed` when the frontend drops the handler that owns it
+struct ClosedGuard(mpsc::Sender<Message>);
+
+impl Drop for ClosedGuard {
+    fn drop(&mut self) {
+        let _ = self.0.send(Message::Closed);
+    }
 }
 
 pub struct SherlockManager {
-    engines_manager: EnginesManager,
-    configs: Option<ConfigManager>,
-    tauri_window: RefCell<Option<tauri::Window>>,
-    //storage_manager: StorageManager,
+    //shared with the executor's workers
+    engines_manager: Arc<EnginesManager>,
+    configs: RefCell<Option<ConfigManager>>,
+    frontend: RefCell<Option<Box<dyn Frontend>>>,
+    storage_manager: RefCell<StorageManager>,
+    //the key of an encrypted storage, taken from `PASSPHRASE_ENV_VAR` at init
+    storage_key: RefCell<Option<KeySource>>,
+    vault: RefCell<Option<Vault>>,
+    //the case that runs are stored under, `None` for the storage's default case
+    active_case: RefCell<Option<Uuid>>,
+    //the manager's end of the UI's messages box, see `open_ipc`
+    ipc: RefCell<Option<ipc::ManagerEnd>>,
+    //what the last runner of the box's jobs left, see `handle_jobs`
+    backlog: RefCell<jobs::Backlog>,
 }
 
 impl SherlockManager {
     //initiate TODO:documentation
     pub fn init() -> SherlockManager {
-        match ConfigManager::init() {
-            Ok(config_manager) => SherlockManager {
-                engines_manager: EnginesManager::init(),
-                configs: Some(config_manager),
-                tauri_window: RefCell::new(None),
-            },
-            Err(error) => {
-                println!("{}", error);
-                SherlockManager {
-                    engines_manager: EnginesManager::init(),
-                    configs: None,
-                    tauri_window: RefCell::new(None),
-                }
-            }
+        SherlockManager::init_with_profile(None)
+    }
+
+    /// ## Description
+    /// Initiates the manager with a config profile,
+    /// falls back to the `SHERLOCK_PROFILE` environment variable when `profile` is `None`.
+    pub fn init_with_profile(profile: Option<&str>) -> SherlockManager {
+        SherlockManager::with_configs(ConfigManager::init_with_profile(profile))
+    }
+
+    /// ## Description
+    /// Initiates the manager from a config file instead of the default locations,
+    /// with an explicitly selected profile.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let manager = SherlockManager::init_from_file(Path::new("./sherlock.toml"), Some("tor"));
+    /// manager.build();
+    /// ```
+    pub fn init_from_file(path: &path::Path, profile: Option<&str>) -> SherlockManager {
+        SherlockManager::with_configs(ConfigManager::from_file_with_profile(path, profile))
+    }
+
+    // create the manager out of the loaded configs, a manager without configs has no engines
+    fn with_configs(configs: Result<ConfigManager, String>) -> SherlockManager {
+        SherlockManager {
+            engines_manager: Arc::new(EnginesManager::init()),
+            //nothing is attached yet to log the error to, `build` tells there's no config
+            configs: RefCell::new(configs.ok()),
+            frontend: RefCell::new(None),
+            storage_manager: RefCell::new(SherlockManager::memory_storage()),
+            storage_key: RefCell::new(
+                std::env::var(PASSPHRASE_ENV_VAR)
+                    .ok()
+                    .map(KeySource::Passphrase),
+            ),
+            vault: RefCell::new(None),
+            active_case: RefCell::new(None),
+            ipc: RefCell::new(None),
+            backlog: RefCell::new(jobs::Backlog::default()),
         }
     }
 
-    //attach to a tauri window TODO: documentation
-    pub fn attach(&self, window: tauri::Window) -> &Self {
-        *self.tauri_window.borrow_mut() = Some(window);
+    /// ## Description
+    /// Attaches a frontend the manager emits its events to, i.e: the Tauri window with the
+    /// `tauri` feature or a `Headless` one. It replaces the previous frontend.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let headless = Headless::new(64);
+    /// manager.attach(headless.clone()).build();
+    /// let logs = headless.take_events();
+    /// ```
+    pub fn attach(&self, frontend: impl Frontend + 'static) -> &Self {
+        *self.frontend.borrow_mut() = Some(Box::new(frontend));
         self
     }
 
+    // emit an event to the frontend, if one is attached
+    fn emit(&self, event: &str, payload: impl Serialize) {
+        if let Some(frontend) = self.frontend.borrow().as_ref() {
+            //the payloads are messages, their JSON is always valid
+            let payload = serde_json::to_value(payload).unwrap_or_default();
+            let _ = frontend.emit(event, payload);
+        }
+    }
+
+    // send a log to the frontend, or to the log book of the messages box when there's none,
+    // it's dropped when nothing is attached
+    fn log(&self, log: Log) {
+        if self.frontend.borrow().is_some() {
+            return self.emit("log-event", log);
+        }
+        if let Some(ipc) = self.ipc.borrow().as_ref() {
+            let source = ipc::Source::Component("sherlock_manager".into());
+            ipc.send_log(None, source, log);
+        }
+    }
+
     //load the engines TODO: documentation
     pub fn build(&self) -> &Self {
         // TODO: improve implementation
-        println!("building");
+        self.log(Log::Info("building".into()));
         //if no ConfigManager do nothing
-        if self.configs.is_none() {
-            println!("no config");
-            return self;
-        }
-
-        let engines_dir = self.configs.as_ref().unwrap().get_engines_location();
-        let mut engines = vec![];
-        // get the directories in the engines directory
-        match fs::read_dir(engines_dir) {
-            Ok(entries) => {
-                engines = entries
-                    .filter_map(|e| e.ok()) // unwrap entries
-                    .filter(|e| e.file_type().is_ok()) // filter out failed reads of the filetype
-                    .filter(|e| e.file_type().unwrap().is_dir()) // filter out entries that aren't directories
-                    .map(|e| e.path())
-                    .collect(); // convert to vector of paths of engines directories
-            }
-            Err(err) => match self.tauri_window.borrow().as_ref() {
-                Some(window) => {
-                    window.emit("log-event", Log::Error(err.to_string()));
+        let configs = self.configs.borrow();
+        let configs = match configs.as_ref() {
+            Some(configs) => configs,
+            None => {
+                self.log(Log::Warning("no config".into()));
+                return self;
+            }
+        };
+
+        let discovery = configs.get_discovery();
+        let options = DiscoveryOptions {
+            recursive: discovery.recursive,
+            max_depth: discovery.max_depth,
+            ignore: discovery.ignore.clone(),
+        };
+
+        //import the engines from every engines root, earlier roots take precedence
+        let report = self
+            .engines_manager
+            .load_engines(&configs.get_engines_locations(), &options);
+
+        //merge the user's overrides onto the engines, the global proxy is every engine's default
+        let overrides = self
+            .engines_manager
+            .list_engines()
+            .into_iter()
+            .map(|engine| {
+                let config = configs.get_engine_config(&engine).cloned().unwrap_or_default();
+                let overrides = EngineOverride {
+                    enabled: config.enabled,
+                    timeout: config.timeout,
+                    prefix: config.prefix,
+                    env: config.env,
+                    working_dir: config
+                        .working_dir
+                        .map(|dir| config_manager::expand_home(&dir)),
+                    concurrency: config.concurrency,
+                    proxy: config
+                        .proxy
+                        .or_else(|| configs.get_proxy().map(ToOwned::to_owned)),
+                };
+                (engine, overrides)
+            })
+            .collect();
+        self.engines_manager.set_overrides(overrides);
+
+        let mut logs = vec![];
+
+        //open the storage backend of the config
+        if let Err(error) = self.open_storage(configs) {
+            //keep persisting the runs of this session rather than losing them
+            *self.storage_manager.borrow_mut() = SherlockManager::memory_storage();
+            logs.push(Log::Error(format!(
+                "{}, falling back to in-memory storage",
+                error
+            )));
+        }
+
+        for engine in configs.get_engines_configs().keys() {
+            if self.engines_manager.get_engine_settings(engine).is_err() {
+                logs.push(Log::Error(format!("[engines.{}]: unknown engine", engine)));
+            }
+        }
+        for (engine, root) in report.loaded {
+            logs.push(Log::Info(format!("loaded {} from {}", engine, root.display())));
+        }
+        for shadowed in report.shadowed {
+            let shadowed_by = match shadowed.shadowed_by {
+                Some(root) => root.display().to_string(),
+                None => "a manually added engine".into(),
+            };
+            logs.push(Log::Info(format!(
+                "engine {} from {} is shadowed by {}",
+                shadowed.name,
+                shadowed.config.display(),
+                shadowed_by
+            )));
+        }
+        for (path, error) in report.failed {
+            logs.push(Log::Error(format!("{}: {:?}", path.display(), error)));
+        }
+        for log in logs {
+            self.log(log);
+        }
+
+        //send a success log to the frontend
+        self.emit("log-event", Log::Info("loaded engines".into()));
+        self
+    }
+
+    pub fn list_engines(&self) -> Vec<String> {
+        self.engines_manager.list_engines()
+    }
+
+    /// ## Description
+    /// Creates the messages box of a UI and hands its end over, the manager keeps the other
+    /// end and answers the jobs in `handle_jobs`. A new box replaces the previous one.
+    ///
+    /// The logs are also written to the log file of the configs if there's one, the configs set
+    /// how many of the latest logs the UI can read.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let ui = manager.open_ipc(64);
+    /// ui.send_jobs(vec![ipc::Job::ListEngines])?;
+    /// manager.handle_jobs()?;
+    /// let responds = ui.recieve_responds();
+    /// ```
+    pub fn open_ipc(&self, capacity: usize) -> ipc::UiEnd {
+        let configs = self.configs.borrow();
+        let settings = configs.as_ref().map(|configs| configs.get_logs());
+        let log_lines = settings.map_or(ipc::LOG_LINES, |settings| settings.lines);
+        let (ui, manager) = ipc::open_with_logs(capacity, log_lines);
+        if let Some(settings) = settings {
+            if let Some(file) = &settings.file {
+                let path = config_manager::expand_home(file);
+                if let Err(error) = manager.log_to_file(&path, settings.max_size, settings.keep) {
+                    manager.send_log(
+                        None,
+                        ipc::Source::Component("sherlock_manager".into()),
+                        ipc::Log::Warning(format!("{}: {}", path.display(), error)),
+                    );
                 }
-                None => {
-                    //TODO: replace with better result handling
-                    println!("{} {}", err.to_string(), engines_dir);
-                    return self;
+            }
+        }
+        *self.ipc.borrow_mut() = Some(manager);
+        self.backlog.take();
+        ui
+    }
+
+    /// ## Description
+    /// Answers the jobs the UI sent through its messages box until none is left, the jobs sent
+    /// meanwhile are answered too. Returns how many were finished, cancelled ones included.
+    ///
+    /// The jobs run by priority (see `scheduler`), the engines run on workers meanwhile so a job
+    /// of a higher priority doesn't wait for them (see `jobs`). A cancelled job ends with
+    /// `Respond::Cancelled` and the engine it was running is killed.
+    ///
+    /// Returns early when the UI's queue is full and no engine runs, the next call sends the
+    /// responds it had no room for and answers the jobs that are left.
+    ///
+    /// Fails if no messages box was opened or the UI dropped its end.
+    pub fn handle_jobs(&self) -> Result<usize, String> {
+        let ipc = self.ipc.borrow();
+        let ipc = ipc
+            .as_ref()
+            .ok_or_else(|| "no messages box was opened".to_owned())?;
+        self.run_jobs(ipc, Vec::new())
+    }
+
+    /// ## Description
+    /// Answers the jobs of the messages box as they come until the UI drops its end, i.e: for a
+    /// frontend that runs in another thread like the JSON-RPC server. Returns how many were
+    /// finished.
+    ///
+    /// Fails if no messages box was opened or the UI dropped its end in the middle of a job.
+    pub fn serve_jobs(&self) -> Result<usize, String> {
+        self.serve_until(&|| false)
+    }
+
+    // answer the jobs of the messages box until `stopped` returns true or the UI is gone
+    fn serve_until(&self, stopped: &dyn Fn() -> bool) -> Result<usize, String> {
+        let ipc = self.ipc.borrow();
+        let ipc = ipc
+            .as_ref()
+            .ok_or_else(|| "no messages box was opened".to_owned())?;
+        let mut handled = 0;
+        while !stopped() {
+            //the responds the UI had no room for are sent once it reads
+            let timeout = match self.backlog.borrow().is_empty() {
+                true => SERVE_POLL,
+                false => jobs::POLL,
+            };
+            match ipc.wait_jobs(timeout) {
+                Ok(requests) => handled += self.run_jobs(ipc, requests)?,
+                Err(ipc::Error::Timeout) if !self.backlog.borrow().is_empty() => {
+                    handled += self.run_jobs(ipc, Vec::new())?
                 }
-            },
+                Err(ipc::Error::Timeout) => continue,
+                Err(_) => break,
+            }
         }
+        Ok(handled)
+    }
+
+    /// ## Description
+    /// Binds a JSON-RPC server on a Unix socket at `path` (see `rpc_server`), its clients send
+    /// jobs through the messages box next to the UI's, the box is opened if there's none. The
+    /// jobs are answered by the manager's loop: `listen` next to the frontend's tasks, or
+    /// `serve_rpc`.
+    ///
+    /// Fails if the socket can't be bound, i.e: another instance serves it.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let server = manager.attach(window).build().bind_rpc(Path::new("/tmp/sherlock.sock"))?;
+    /// //the scripts drive the running instance until the window is closed
+    /// manager.listen();
+    /// ```
+    #[cfg(unix)]
+    pub fn bind_rpc(&self, path: &path::Path) -> Result<rpc_server::RpcServer, String> {
+        let connected = self.ipc.borrow().as_ref().map(|ipc| ipc.connect());
+        let ui = match connected {
+            Some(Ok(ui)) => ui,
+            //the UI of the previous box is gone
+            _ => self.open_ipc(RPC_CAPACITY),
+        };
+        rpc_server::RpcServer::bind(path, ui)
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// ## Description
+    /// Answers the jobs of the messages box until a server bound by `bind_rpc` is stopped
+    /// (see `RpcServer::get_stop_handle`), returns how many were finished.
+    ///
+    /// Fails if the UI dropped its end in the middle of a job.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let server = manager.bind_rpc(Path::new("/tmp/sherlock.sock"))?;
+    /// let stop = server.get_stop_handle();
+    /// thread::spawn(move || {
+    ///     wait_for_a_signal();
+    ///     stop.stop();
+    /// });
+    /// manager.serve_rpc(server)?;
+    /// ```
+    #[cfg(unix)]
+    pub fn serve_rpc(&self, server: rpc_server::RpcServer) -> Result<usize, String> {
+        self.serve_until(&|| server.is_stopped())
+    }
+
+    // run the jobs given and the ones sent meanwhile until none is left or the UI has no room
+    fn run_jobs(&self, ipc: &ipc::ManagerEnd, requests: Vec<Request>) -> Result<usize, String> {
+        let mut jobs = JobRunner::new(self, ipc, self.backlog.take());
+        let outcome = jobs.run(requests);
+        *self.backlog.borrow_mut() = jobs.into_backlog();
+        outcome
+    }
+
+    /// Gets the backend of the opened storage, `Backend::Memory` if the storage couldn't be opened.
+    pub fn get_storage_backend(&self) -> Backend {
+        self.storage_manager.borrow().get_backend().clone()
+    }
+
+    /// ## Description
+    /// Executes an engine's command and persists the run and its output in the active case,
+    /// along with the files the engine left in its output directory.
+    /// The run is logged as evidence with the engine's version, binary digest and argv.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let output = manager.run_engine("facebook", "user", "user123")?;
+    /// ```
+    pub fn run_engine(&self, engine: &str, command: &str, query: &str) -> Result<String, String> {
+        self.run_engine_until(engine, command, query, &|| false)
+    }
+
+    // run an engine like `run_engine`, its process is killed once `cancelled` returns true
+    fn run_engine_until(
+        &self,
+        engine: &str,
+        command: &str,
+        query: &str,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<String, String> {
+        let case = self.get_active_case()?;
+        let storage = self.storage_manager.borrow();
+        let (_, output) = storage
+            .record_run_with_files(case.id, query, engine, command, |output_dir, provenance| {
+                //an unknown engine or command is recorded as a failed run, without provenance
+                let mut invocation = None;
+                let output = self
+                    .engines_manager
+                    .execute_described(
+                        engine,
+                        command,
+                        query,
+                        Some(output_dir),
+                        cancelled,
+                        &mut invocation,
+                    )
+                    .map_err(|error| format!("{:?}", error));
+                *provenance = crate::provenance(invocation);
+                output
+            })
+            .map_err(|error| error.to_string())?;
+        output
+    }
 
-        //import new engine from each config.json file in the engines directories
-        for engine in engines {
-            let engine_config = engine.join("config.json");
-            println!("{}", engine_config.to_str().unwrap());
-            self.engines_manager
-                .add_engine_from_config(engine_config.to_str().unwrap());
+    // open the configured storage with the key given to `unlock_storage`,
+    // or with the passphrase the `SHERLOCK_PASSPHRASE` environment variable had at init
+    fn open_storage(&self, configs: &ConfigManager) -> Result<(), storage_manager::Error> {
+        let backend = storage_backend(configs);
+        let key = self.storage_key.borrow().clone();
+        //an explicit key wins over the vault's storage key
+        let mut storage = match (key, self.vault.borrow_mut().as_mut()) {
+            (None, Some(vault)) => StorageManager::open_with_vault(backend, vault)?,
+            (key, _) => StorageManager::open_with_key(backend, key.as_ref())?,
+        };
+        if let Some(sync) = configs.get_sync() {
+            storage.enable_sync(remote_backend(&sync.remote), self.vault.borrow().as_ref())?;
         }
+        if let Some(blobs) = storage.get_blobs() {
+            blobs.set_compression(configs.get_artifacts().compress);
+        }
+        if configs.get_retention().purge_on_start {
+            //the storage is usable anyway, the purge is tried again on the next start
+            if let Err(error) = storage.purge(&retention_policy(configs), false) {
+                self.log(Log::Warning(format!("purging expired runs: {}", error)));
+            }
+        }
+        *self.storage_manager.borrow_mut() = storage;
+        //the active case belonged to the previous storage
+        *self.active_case.borrow_mut() = None;
+        Ok(())
+    }
 
-        if self.tauri_window.borrow().is_some() {
-            //send a success log to the frontend
-            self.tauri_window
-                .borrow()
-                .as_ref()
-                .unwrap()
-                .emit("log-event", Log::Info("loaded engines".into()));
+    /// ## Description
+    /// Creates a new case and makes it the active case.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let case = manager.create_case("operation nightjar", Some("jane"))?;
+    /// manager.run_engine("facebook", "user", "user123")?; // stored under the new case
+    /// ```
+    pub fn create_case(&self, title: &str, owner: Option<&str>) -> Result<Case, String> {
+        let case = self
+            .storage_manager
+            .borrow()
+            .create_case(title, owner)
+            .map_err(|error| error.to_string())?;
+        *self.active_case.borrow_mut() = Some(case.id);
+        Ok(case)
+    }
+
+    /// ## Description
+    /// Makes an existing case the active case, the runs that follow are stored under it.
+    pub fn open_case(&self, case_id: Uuid) -> Result<Case, String> {
+        let case = self
+            .storage_manager
+            .borrow()
+            .get_case(case_id)
+            .map_err(|error| error.to_string())?;
+        *self.active_case.borrow_mut() = Some(case.id);
+        Ok(case)
+    }
+
+    /// Gets the active case, the storage's default case when no case was opened.
+    pub fn get_active_case(&self) -> Result<Case, String> {
+        let storage = self.storage_manager.borrow();
+        let active = *self.active_case.borrow();
+        match active {
+            Some(case_id) => storage.get_case(case_id),
+            None => storage.default_case(),
         }
-        self
+        .map_err(|error| error.to_string())
     }
 
-    fn do_task(&self, task: Task, tx: mpsc::Sender<Message>) {
-        thread::spawn(move || {
-            //emulate slow responds
-            thread::sleep(time::Duration::from_secs(5));
-            tx.send(Message::TaskResult(TaskResult::List(vec![
-                "google".into(),
-                "instagram".into(),
-                "mysql".into(),
-            ])));
-        });
+    /// Gets everything stored about the active case, for the UI.
+    pub fn load_active_case(&self) -> Result<CaseFile, String> {
+        let case = self.get_active_case()?;
+        self.storage_manager
+            .borrow()
+            .load_case(case.id)
+            .map_err(|error| error.to_string())
     }
 
-    pub fn list_engines(&self) -> Vec<String> {
-        self.engines_manager.list_engines()
+    /// ## Description
+    /// Reads the content of a file produced by a run, by its name in the run's artifacts.
+    pub fn read_artifact(&self, run_id: Uuid, name: &str) -> Result<Vec<u8>, String> {
+        let storage = self.storage_manager.borrow();
+        let run = storage
+            .storage()
+            .get_run(run_id)
+            .map_err(|error| error.to_string())?
+            .ok_or_else(|| format!("unknown run: {}", run_id))?;
+        let artifact = run
+            .artifacts
+            .iter()
+            .find(|artifact| artifact.name == name)
+            .ok_or_else(|| format!("the run has no file named {}", name))?;
+        storage
+            .read_artifact(artifact)
+            .map_err(|error| error.to_string())
+    }
+
+    /// Gets the cases of the storage.
+    pub fn list_cases(&self) -> Result<Vec<Case>, String> {
+        self.storage_manager
+            .borrow()
+            .storage()
+            .list_cases()
+            .map_err(|error| error.to_string())
+    }
+
+    /// Adds an analyst's note to the active case.
+    pub fn add_note(&self, text: &str, author: Option<&str>) -> Result<Note, String> {
+        let case = self.get_active_case()?;
+        self.storage_manager
+            .borrow()
+            .add_note(case.id, author, text)
+            .map_err(|error| error.to_string())
+    }
+
+    /// Sets the status of the active case, i.e: closes it.
+    pub fn set_case_status(&self, status: CaseStatus) -> Result<Case, String> {
+        self.update_active_case(|case| case.set_status(status))
+    }
+
+    /// ## Description
+    /// Sets the retention policy of the active case, it replaces the configured one for the
+    /// case's runs. `None` goes back to the configured policy.
+    pub fn set_case_retention(&self, retention: Option<RetentionPolicy>) -> Result<Case, String> {
+        self.update_active_case(|case| case.retention = retention)
+    }
+
+    /// Replaces the tags of the active case, blank and repeated tags are dropped.
+    pub fn set_case_tags(&self, tags: &[String]) -> Result<Case, String> {
+        let mut kept: Vec<String> = vec![];
+        for tag in tags.iter().map(|tag| tag.trim()) {
+            if !tag.is_empty() && !kept.iter().any(|kept| kept == tag) {
+                kept.push(tag.to_owned());
+            }
+        }
+        self.update_active_case(|case| case.tags = kept)
+    }
+
+    // change the active case and save it
+    fn update_active_case<F: FnOnce(&mut Case)>(&self, update: F) -> Result<Case, String> {
+        let mut case = self.get_active_case()?;
+        update(&mut case);
+        self.storage_manager
+            .borrow()
+            .storage()
+            .save_case(&case)
+            .map_err(|error| error.to_string())?;
+        Ok(case)
+    }
+
+    /// ## Description
+    /// Searches the engine outputs, notes and entities of every case, see
+    /// `StorageManager::search` for the query syntax.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let hits = manager.search("\"555 010 0199\"", &SearchFilter::default())?;
+    /// ```
+    pub fn search(&self, query: &str, filter: &SearchFilter) -> Result<Vec<SearchHit>, String> {
+        self.storage_manager
+            .borrow()
+            .search(query, filter, SEARCH_LIMIT)
+            .map_err(|error| error.to_string())
+    }
+
+    /// ## Description
+    /// Verifies that the runs and results weren't altered since they were collected.
+    pub fn verify_evidence(&self) -> Result<EvidenceReport, String> {
+        self.storage_manager
+            .borrow()
+            .verify_evidence()
+            .map_err(|error| error.to_string())
+    }
+
+    /// ## Description
+    /// Exports the evidence log to a json file, for third-party checking.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let export = manager.export_evidence(Path::new("./evidence.json"))?;
+    /// println!("exported {} entries", export.entries.len());
+    /// ```
+    pub fn export_evidence(&self, path: &path::Path) -> Result<EvidenceExport, String> {
+        let export = self
+            .storage_manager
+            .borrow()
+            .export_evidence()
+            .map_err(|error| error.to_string())?;
+        let json = serde_json::to_vec_pretty(&export).map_err(|error| error.to_string())?;
+        fs::write(path, json).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Ok(export)
+    }
+
+    /// ## Description
+    /// Purges the runs the configured retention policy (or their case's own) expired.
+    /// A dry run only reports what would be purged.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let report = manager.purge_expired(true)?;
+    /// println!("{} runs would be purged", report.runs.len());
+    /// ```
+    pub fn purge_expired(&self, dry_run: bool) -> Result<PurgeReport, String> {
+        let policy = match self.configs.borrow().as_ref() {
+            Some(configs) => retention_policy(configs),
+            None => RetentionPolicy::default(),
+        };
+        self.storage_manager
+            .borrow()
+            .purge(&policy, dry_run)
+            .map_err(|error| error.to_string())
+    }
+
+    /// ## Description
+    /// Backs up the config file, its vault, the engines and the opened storage
+    /// into a single archive, see `create_backup`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let manifest = manager.backup(Path::new("./sherlock.backup"))?;
+    /// ```
+    pub fn backup(&self, archive: &path::Path) -> Result<BackupManifest, String> {
+        let configs = self.configs.borrow();
+        let configs = configs
+            .as_ref()
+            .ok_or_else(|| "no config is loaded".to_owned())?;
+        create_backup(archive, configs, &self.storage_manager.borrow())
+    }
+
+    /// ## Description
+    /// Restores a backup to the locations of the loaded config, see `restore_backup`, then
+    /// reloads the restored config, engines and storage. The vault is locked again, it may have
+    /// been replaced. A failed restore leaves the previous files, they're opened again.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// let options = RestoreOptions { dry_run: true, overwrite: false };
+    /// let report = manager.restore(Path::new("./sherlock.backup"), &options)?;
+    /// ```
+    pub fn restore(
+        &self,
+        archive: &path::Path,
+        options: &RestoreOptions,
+    ) -> Result<RestoreReport, String> {
+        let loaded = self.configs.borrow();
+        let configs = loaded
+            .as_ref()
+            .ok_or_else(|| "no config is loaded".to_owned())?;
+        if options.dry_run {
+            return restore_backup(archive, configs, options);
+        }
+        //close the storage, its files are about to be replaced
+        *self.storage_manager.borrow_mut() = SherlockManager::memory_storage();
+        let vault = self.vault.take();
+        let restored = restore_backup(archive, configs, options);
+        //the restored config keeps everything at the same locations as the loaded one
+        let reloaded = restored
+            .as_ref()
+            .ok()
+            .map(|_| configs.switch_profile(configs.get_profile()));
+        drop(loaded);
+        match reloaded {
+            Some(Ok(reloaded)) => *self.configs.borrow_mut() = Some(reloaded),
+            Some(Err(error)) => self.log(Log::Error(format!("restored config: {}", error))),
+            //nothing was replaced, the previous vault is still the one
+            None => *self.vault.borrow_mut() = vault,
+        }
+        //the storage is opened again whatever happened
+        for engine in self.engines_manager.list_engines() {
+            self.engines_manager.remove_engine(&engine);
+        }
+        self.build();
+        restored
+    }
+
+    /// ## Description
+    /// Answers a job that's done at once, the engine runs are streamed by `handle_jobs` and
+    /// `listen`.
+    ///
+    /// The case jobs answer with the active case.
+    fn answer(&self, job: Job) -> Result<Respond, String> {
+        let as_case = |done: Result<(), String>| done.and_then(|_| self.load_active_case());
+        match job {
+            Job::ListEngines => Ok(Respond::Engines(self.list_engines())),
+            Job::DescribeEngine { engine } => self.describe_engine(&engine).map(Respond::Engine),
+            Job::RunEninges { .. } => Err("engine runs aren't answered at once".into()),
+            Job::Cancel { .. } => Err("jobs are cancelled through the messages box".into()),
+            Job::ListProfiles => Ok(self.profiles_result()),
+            Job::SwitchProfile { profile } => {
+                self.switch_profile(profile.as_deref())?;
+                Ok(self.profiles_result())
+            }
+            Job::Sync => {
+                self.sync()?;
+                Ok(Respond::Sync(self.get_sync_status()))
+            }
+            Job::SyncStatus => Ok(Respond::Sync(self.get_sync_status())),
+            Job::ListCases => self.cases_result(),
+            Job::CreateCase { title, owner } => {
+                as_case(self.create_case(&title, owner.as_deref()).map(|_| ())).map(Respond::Case)
+            }
+            Job::OpenCase { case_id } => {
+                as_case(self.open_case(case_id).map(|_| ())).map(Respond::Case)
+            }
+            Job::AddNote { text, author } => {
+                as_case(self.add_note(&text, author.as_deref()).map(|_| ())).map(Respond::Case)
+            }
+            Job::SetCaseStatus { status } => {
+                as_case(self.set_case_status(status).map(|_| ())).map(Respond::Case)
+            }
+            Job::SetCaseTags { tags } => {
+                as_case(self.set_case_tags(&tags).map(|_| ())).map(Respond::Case)
+            }
+            Job::SetCaseRetention { retention } => {
+                as_case(self.set_case_retention(retention).map(|_| ())).map(Respond::Case)
+            }
+            Job::Search { query, filter } => self.search(&query, &filter).map(Respond::Search),
+            Job::VerifyEvidence => self.verify_evidence().map(Respond::Evidence),
+            Job::ExportEvidence { path } => {
+                let export = self.export_evidence(path::Path::new(&path))?;
+                Ok(Respond::Message(format!(
+                    "exported {} evidence entries to {}",
+                    export.entries.len(),
+                    path
+                )))
+            }
+            Job::Purge { dry_run } => self.purge_expired(dry_run).map(Respond::Purge),
+            Job::Backup { path } => self.backup(path::Path::new(&path)).map(Respond::Backup),
+            Job::Restore { path, options } => self
+                .restore(path::Path::new(&path), &options)
+                .map(Respond::Restore),
+        }
+    }
+
+    // the active and available cases as a result for the frontend
+    fn cases_result(&self) -> Result<Respond, String> {
+        Ok(Respond::Cases {
+            available: self.list_cases()?,
+            active: *self.active_case.borrow(),
+        })
+    }
+
+    /// ## Description
+    /// Syncs the storage with the remote storage server of the `sync` section.
+    ///
+    /// Fails when the storage isn't synced or the sync failed, i.e: the server is unreachable,
+    /// the local storage keeps working either way.
+    pub fn sync(&self) -> Result<SyncStatus, String> {
+        let storage = self.storage_manager.borrow();
+        let sync = storage
+            .get_sync()
+            .ok_or_else(|| "the storage isn't synced".to_owned())?;
+        sync.sync().map_err(|error| error.to_string())
+    }
+
+    /// Gets the sync status for the UI, `None` when the storage isn't synced.
+    pub fn get_sync_status(&self) -> Option<SyncStatus> {
+        let storage = self.storage_manager.borrow();
+        storage.get_sync()?.get_status().ok()
+    }
+
+    /// ## Description
+    /// Opens the configured encrypted storage with a key, i.e: a passphrase the user typed in.
+    ///
+    /// The key is kept for the rest of the session so switching profiles doesn't lock the storage,
+    /// the current storage is kept if the key doesn't unlock the configured one.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// manager.unlock_storage(KeySource::Passphrase(passphrase))?;
+    /// ```
+    pub fn unlock_storage(&self, key: KeySource) -> Result<(), String> {
+        let configs = self.configs.borrow();
+        let configs = configs
+            .as_ref()
+            .ok_or_else(|| "no config is loaded".to_owned())?;
+        let previous = self.storage_key.replace(Some(key));
+        self.open_storage(configs).map_err(|error| {
+            self.storage_key.replace(previous);
+            error.to_string()
+        })
+    }
+
+    /// ## Description
+    /// Unlocks the vault of secrets and reopens the configured storage with them,
+    /// i.e: the `vault:<name>` token of a remote storage.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// manager.unlock_vault(&config_manager::expand_home("~/.sherlock/vault"), &passphrase)?;
+    /// ```
+    pub fn unlock_vault(&self, path: &path::Path, passphrase: &str) -> Result<(), String> {
+        let vault = Vault::open(path, passphrase).map_err(|error| error.to_string())?;
+        let configs = self.configs.borrow();
+        let configs = configs
+            .as_ref()
+            .ok_or_else(|| "no config is loaded".to_owned())?;
+        let previous = self.vault.replace(Some(vault));
+        self.open_storage(configs).map_err(|error| {
+            self.vault.replace(previous);
+            error.to_string()
+        })
+    }
+
+    // the storage used until the configured storage is opened
+    fn memory_storage() -> StorageManager {
+        StorageManager::open(Backend::Memory).expect("in-memory storage can always be opened")
+    }
+
+    /// Gets the name of the active config profile, `None` when the base section is used.
+    pub fn get_active_profile(&self) -> Option<String> {
+        self.configs
+            .borrow()
+            .as_ref()
+            .and_then(|configs| configs.get_profile().map(ToOwned::to_owned))
+    }
+
+    /// Gets the names of the profiles defined in the config file.
+    pub fn list_profiles(&self) -> Vec<String> {
+        self.configs
+            .borrow()
+            .as_ref()
+            .map_or(vec![], |configs| configs.list_profiles().to_vec())
+    }
+
+    /// ## Description
+    /// Switches to another config profile at runtime, `None` selects the base section.
+    ///
+    /// The new profile is loaded before anything is torn down, so on failure
+    /// the manager keeps running with the previous profile.
+    /// On success the engines and the storage are reloaded from the new profile.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```ignore
+    /// manager.switch_profile(Some("acme"))?;
+    /// assert_eq!(manager.get_active_profile(), Some("acme".into()));
+    /// ```
+    pub fn switch_profile(&self, profile: Option<&str>) -> Result<&Self, String> {
+        let switched = match self.configs.borrow().as_ref() {
+            Some(configs) => configs.switch_profile(profile)?,
+            None => return Err("no config".to_owned()),
+        };
+
+        //drop the engines and storage of the previous profile and load the new ones
+        for engine in self.engines_manager.list_engines() {
+            self.engines_manager.remove_engine(&engine);
+        }
+        *self.configs.borrow_mut() = Some(switched);
+        self.build();
+
+        self.emit("profile-event", self.profiles_result());
+        Ok(self)
+    }
+
+    // the active and available profiles as a result for the frontend
+    fn profiles_result(&self) -> Respond {
+        Respond::Profiles {
+            active: self.get_active_profile(),
+            available: self.list_profiles(),
+        }
+    }
+
+    /// ## Description
+    /// Gets the engines root that an engine was loaded from, `None` for manually added engines.
+    pub fn get_engine_root(
+        &self,
+        engine_name: &str,
+    ) -> Result<Option<path::PathBuf>, engines_manager::Error> {
+        self.engines_manager.get_engine_root(engine_name)
+    }
+
+    /// ## Description
+    /// Gets the settings an engine is executed with, its own settings merged with
+    /// its `[engines.<name>]` section and the global proxy.
+    pub fn get_engine_settings(
+        &self,
+        engine_name: &str,
+    ) -> Result<EngineSettings, engines_manager::Error> {
+        self.engines_manager.get_engine_settings(engine_name)
+    }
+
+    /// ## Description
+    /// Describes an engine for a frontend: its description, version, commands and whether it
+    /// runs with the user's settings.
+    ///
+    /// Fails if there is no engine with this name.
+    pub fn describe_engine(&self, engine_name: &str) -> Result<EngineInfo, String> {
+        let unknown = |_| format!("unknown engine {}", engine_name);
+        let settings = self.get_engine_settings(engine_name).map_err(unknown)?;
+        Ok(EngineInfo {
+            name: engine_name.into(),
+            description: self
+                .engines_manager
+                .get_engine_description(engine_name)
+                .map_err(unknown)?,
+            version: self
+                .engines_manager
+                .get_engine_version(engine_name)
+                .map_err(unknown)?,
+            commands: self
+                .list_engine_commands(engine_name)
+                .map_err(unknown)?
+                .into_iter()
+                .collect(),
+            enabled: settings.enabled,
+            timeout: settings.timeout,
+        })
     }
 
     pub fn list_engine_commands(
@@ -140,70 +972,865 @@ impl SherlockManager {
         self.engines_manager.list_engine_commands(engine_name)
     }
 
+    /// ## Description
+    /// Answers the tasks the frontend sends in `task-event` and emits their results, until the
+    /// frontend drops its handler (see `Headless::close`) and the tasks it sent are answered.
+    ///
+    /// The tasks are jobs of the messages box, next to the ones of `bind_rpc`'s clients, and are
+    /// answered by the scheduler of `handle_jobs`: the engine runs are recorded in the active
+    /// case and a task is cancelled with a `Job::Cancel` of its id. Every task and respond is
+    /// emitted in `job-event` with its job id, the responds in the event of their kind too
+    /// (i.e: `engine-event`).
+    ///
+    /// Returns at once if no frontend is attached.
     pub fn listen(&self) {
-        let win_ref = self.tauri_window.borrow();
-        let window = win_ref.as_ref().unwrap(); //TODO:handle calling listen before attaching a window
-                                                //create mpsc channel for task and results
+        //create mpsc channel for task and results
         let (tx, rx) = mpsc::channel();
 
-        let tx_tasks = tx.clone(); //clone for the listener handler
+        //the handler uses the channel to send back Messages, the guard tells when it's dropped
+        let closed = ClosedGuard(tx.clone());
+        match self.frontend.borrow().as_ref() {
+            Some(frontend) => frontend.listen(
+                "task-event",
+                Box::new(move |payload| {
+                    let tx_tasks = &closed.0;
+                    match payload.map(serde_json::from_str::<Job>) {
+                        Some(Ok(task)) => {
+                            //recieved task from the frontend
+                            //send back a message with the task to `listen`, it's dropped
+                            //once `listen` returned
+                            let _ = tx_tasks.send(Message::Job(task));
+                        }
+                        _ => {
+                            //incase of recieving bad task from the frontend
+                            //send back an error log
+                            let invalid = Log::Error("invalid task".into());
+                            let _ = tx_tasks.send(Message::Log(invalid));
+                        }
+                    }
+                }),
+            ),
+            None => return,
+        }
 
-        //listen for task events
-        //and provide a handler that uses the channel to send back Messages
-        window.listen("task-event", move |event| {
-            match serde_json::from_str::<Task>(event.payload().unwrap()) {
-                Ok(task) => {
-                    //recieved task from the frontend
-                    //send back a message with the task to `listen`
-                    tx_tasks.send(Message::Task(task)).unwrap(); //TODO: remove unwraping later
+        //sync in the background at the configured interval
+        let interval = self
+            .configs
+            .borrow()
+            .as_ref()
+            .and_then(|configs| configs.get_sync().map(|sync| sync.interval))
+            .unwrap_or(0);
+        if interval > 0 {
+            let tx_sync = tx.clone();
+            thread::spawn(move || loop {
+                thread::sleep(time::Duration::from_secs(interval));
+                if tx_sync.send(Message::Job(Job::Sync)).is_err() {
+                    break;
                 }
-                Err(_) => {
-                    //incase of recieving bad task from the frontend
-                    //send back an error log
-                    tx_tasks
-                        .send(Message::Log(Log::Error("invalid task".into())))
-                        .unwrap();
-                }
-            }
-        });
+            });
+        }
 
+        //the frontend is a UI of the messages box, the box is opened if there's none
+        let connected = self.ipc.borrow().as_ref().map(|ipc| ipc.connect());
+        let ui = match connected {
+            Some(Ok(ui)) => ui,
+            _ => self.open_ipc(FRONTEND_CAPACITY),
+        };
+        let ipc = self.ipc.borrow();
+        let Some(ipc) = ipc.as_ref() else {
+            return;
+        };
+        let mut jobs = JobRunner::new(self, ipc, self.backlog.take());
+        //the tasks the box can't take yet, and the ones sent that aren't done
+        let mut waiting: VecDeque<Job> = VecDeque::new();
+        let mut sent = HashMap::new();
+        let mut closed = false;
         loop {
+            //this thread empties the box, it doesn't wait for room
+            while let Some(task) = waiting.pop_front() {
+                let priority = task.get_default_priority();
+                match ui.try_send_job_with_priority(task.clone(), priority) {
+                    Ok(id) => {
+                        let request = Request {
+                            id,
+                            job: task.clone(),
+                            priority,
+                        };
+                        self.emit("job-event", messages::Message::Request(request));
+                        sent.insert(id, task);
+                    }
+                    Err(ipc::Error::Full) => {
+                        waiting.push_front(task);
+                        break;
+                    }
+                    Err(error) => self.emit("log-event", Log::Error(error.to_string())),
+                }
+            }
+            if let Err(error) = jobs.step(time::Duration::ZERO) {
+                //the frontend's end keeps the box open, it doesn't fail meanwhile
+                jobs.abort(&error);
+                for reply in ui.recieve_responds() {
+                    self.emit_reply(&mut sent, reply);
+                }
+                self.emit("log-event", Log::Error(error));
+                break;
+            }
+            for reply in ui.recieve_responds() {
+                self.emit_reply(&mut sent, reply);
+            }
+            if closed && waiting.is_empty() && sent.is_empty() && jobs.is_idle() {
+                break;
+            }
             //listen for Messages and emits back to the frontent
-            let recieved = rx.recv().unwrap();
-            match recieved {
-                Message::Task(task) => {
+            match rx.recv_timeout(jobs::POLL) {
+                Ok(Message::Job(task)) => {
                     //recieved a task
-                    window.emit("log-event", Log::Info(format!("{:?}", task)));
-                    self.do_task(task, tx.clone())
+                    self.emit("log-event", Log::Info(format!("{:?}", task)));
+                    waiting.push_back(task);
                 }
-                Message::Log(log) => {
+                Ok(Message::Log(log)) => {
                     //recieved a log
-                    window.emit("log-event", log).unwrap();
+                    self.emit("log-event", log);
                 }
+                Ok(Message::Closed) => closed = true,
+                Err(_) => {}
+            }
+        }
+        //the responds the UI had no room for are sent by the next runner
+        *self.backlog.borrow_mut() = jobs.into_backlog();
+    }
 
-                Message::TaskResult(result) => {
-                    //recieved a result
-                    window.emit("log-event", Log::Info(format!("{:?}", result)));
+    // emit a respond to a task of the frontend in `job-event` and in the event of its kind,
+    // a failed task is reported as an error log
+    fn emit_reply(&self, sent: &mut HashMap<JobId, Job>, reply: Reply) {
+        self.emit("job-event", messages::Message::Reply(reply.clone()));
+        match reply.respond {
+            Respond::Progress { .. } | Respond::Cancelled => {}
+            Respond::Done => {
+                sent.remove(&reply.job_id);
+            }
+            Respond::Message(message) => self.emit("log-event", Log::Info(message)),
+            Respond::Error(error) => {
+                self.emit("log-event", Log::Error(error));
+                //a failed sync sends the status too, it tells the UI why
+                if sent.get(&reply.job_id) == Some(&Job::Sync) {
+                    self.emit("sync-event", Respond::Sync(self.get_sync_status()));
                 }
             }
+            respond => self.emit(event_name(&respond), respond),
         }
     }
 }
 
+// the provenance of a run from how its engine was started,
+// an unknown engine or command has none
+fn provenance(invocation: Option<engines_manager::Invocation>) -> Provenance {
+    match invocation {
+        Some(invocation) => Provenance {
+            engine_version: invocation.version,
+            engine_digest: invocation.binary_digest,
+            argv: invocation.argv,
+        },
+        None => Provenance::default(),
+    }
+}
+
+// the frontend event a respond is emitted with
+fn event_name(respond: &Respond) -> &'static str {
+    match respond {
+        Respond::Profiles { .. } => "profile-event",
+        Respond::Engine(_) | Respond::Engines(_) | Respond::EngineResult { .. } => "engine-event",
+        Respond::Cases { .. } | Respond::Case(_) => "case-event",
+        Respond::Sync(_) => "sync-event",
+        Respond::Search(_) => "search-event",
+        Respond::Evidence(_) => "evidence-event",
+        Respond::Purge(_) => "purge-event",
+        Respond::Backup(_) | Respond::Restore(_) => "backup-event",
+        _ => "log-event",
+    }
+}
+
+// the retention policy of the `retention` section
+fn retention_policy(configs: &ConfigManager) -> RetentionPolicy {
+    let retention = configs.get_retention();
+    RetentionPolicy {
+        max_age_days: retention.max_age_days,
+        closed_case_days: retention.closed_case_days,
+    }
+}
+
+// the backend of the `storage` section
+fn storage_backend(configs: &ConfigManager) -> Backend {
+    match configs.get_storage() {
+        StrorageType::Local { path, encrypted } => Backend::Sqlite {
+            path: config_manager::expand_home(path),
+            encrypted: *encrypted,
+        },
+        StrorageType::Sqlite { path } => Backend::Sqlite {
+            path: config_manager::expand_home(path),
+            encrypted: false,
+        },
+        StrorageType::FileTree { root } => Backend::FileTree {
+            root: config_manager::expand_home(root),
+        },
+        StrorageType::Remote(remote) => remote_backend(remote),
+    }
+}
+
+// the backend of a remote storage server's settings
+fn remote_backend(remote: &config_manager::RemoteStorage) -> Backend {
+    Backend::Remote {
+        url: remote.url.clone(),
+        token: remote.token.clone(),
+        verify_tls: remote.tls.verify,
+        ca_cert: remote
+            .tls
+            .ca_cert
+            .as_ref()
+            .map(|ca_cert| config_manager::expand_home(ca_cert)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    // a manager with the test profiles of `mock_files/profiles.toml`, a `mock_files/sherlock.toml`
+    // would be picked up by the default config locations
+    fn test_manager(profile: Option<&str>) -> SherlockManager {
+        SherlockManager::init_from_file(path::Path::new("./mock_files/profiles.toml"), profile)
+    }
+
     #[test]
     fn build_from_config() {
         SherlockManager::init().build();
     }
 
+    #[test]
+    fn logs_without_a_frontend() {
+        //the logs go to the messages box's log book
+        let manager = test_manager(Some("tree"));
+        let ui = manager.open_ipc(8);
+        manager.build();
+        let logs = ui.recieve_logs();
+        assert_eq!(logs[0].log, Log::Info("building".into()));
+        assert_eq!(
+            logs[0].source,
+            ipc::Source::Component("sherlock_manager".into())
+        );
+        assert_eq!(logs[0].job_id, None);
+
+        //a manager without configs logs that it has none
+        let manager = SherlockManager::init_from_file(path::Path::new("./unknown.toml"), None);
+        let ui = manager.open_ipc(8);
+        manager.build();
+        let logs = ui.recieve_logs();
+        assert_eq!(logs[1].log, Log::Warning("no config".into()));
+    }
+
     #[test]
     fn list_engines() {
         let engines = SherlockManager::init().build().list_engines();
         println!("{:?}", engines);
     }
 
+    #[test]
+    fn switch_profile() {
+        let manager = SherlockManager::init_with_profile(None);
+        manager.build();
+        assert_eq!(manager.get_active_profile(), None);
+
+        //a failed switch keeps the current profile and engines
+        let engines = manager.list_engines();
+        assert!(manager.switch_profile(Some("unknown")).is_err());
+        assert_eq!(manager.list_engines(), engines);
+
+        for profile in manager.list_profiles() {
+            manager.switch_profile(Some(&profile)).unwrap();
+            assert_eq!(manager.get_active_profile(), Some(profile));
+        }
+    }
+
+    #[test]
+    fn engine_settings() {
+        let manager = SherlockManager::init_with_profile(Some("tor"));
+        manager.build();
+        let settings = manager.get_engine_settings("facebook").unwrap();
+        assert_eq!(settings.timeout, Some(60));
+        assert_eq!(settings.proxy.as_deref(), Some("socks5://127.0.0.1:9050"));
+    }
+
+    #[test]
+    fn storage_from_config() {
+        let manager = test_manager(None);
+        manager.build();
+        assert!(matches!(
+            manager.get_storage_backend(),
+            Backend::Sqlite {
+                encrypted: false,
+                ..
+            }
+        ));
+
+        manager.switch_profile(Some("tree")).unwrap();
+        assert!(matches!(
+            manager.get_storage_backend(),
+            Backend::FileTree { .. }
+        ));
+    }
+
+    #[test]
+    fn jobs_from_the_messages_box() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        assert!(manager.handle_jobs().is_err());
+
+        let ui = manager.open_ipc(8);
+        let run = ipc::Job::RunEninges {
+            engines_list: vec!["facebook".into(), "unknown".into()],
+            command: "user".into(),
+            query: "user123".into(),
+        };
+        let ids = ui.send_jobs(vec![ipc::Job::ListEngines, run]).unwrap();
+        assert_eq!(manager.handle_jobs().unwrap(), 2);
+        //the engines run side by side, their results come as they end
+        let responds = ui.await_job(ids[1]).unwrap();
+        assert!(responds.contains(&ipc::Respond::EngineResult {
+            engine: "facebook".into(),
+            output: "test output\n".into()
+        }));
+        assert!(responds.iter().any(
+            |respond| matches!(respond, ipc::Respond::Error(error) if error.starts_with("unknown"))
+        ));
+        assert_eq!(responds[1], ipc::Respond::Progress { done: 1, total: 2 });
+        assert_eq!(responds[3], ipc::Respond::Progress { done: 2, total: 2 });
+        assert_eq!(
+            ui.await_job(ids[0]).unwrap(),
+            vec![ipc::Respond::Engines(manager.list_engines())]
+        );
+        let logs = ui.recieve_logs();
+        assert_eq!(logs[1].job_id, Some(ids[1]));
+        assert_eq!(logs[1].source, ipc::Source::Engine("unknown".into()));
+        assert_eq!(logs[1].log.get_level(), ipc::Level::Error);
+
+        //a new messages box replaces the old one
+        let other = manager.open_ipc(8);
+        assert!(!ui.is_pending());
+        other.send_jobs(vec![ipc::Job::ListEngines]).unwrap();
+        assert_eq!(manager.handle_jobs().unwrap(), 1);
+
+        //the jobs of the tauri window are answered too
+        let id = other.send_job(ipc::Job::ListProfiles).unwrap();
+        manager.handle_jobs().unwrap();
+        assert!(matches!(
+            &other.await_job(id).unwrap()[0],
+            ipc::Respond::Profiles { available, .. } if *available == manager.list_profiles()
+        ));
+
+        //a job answers more than the box holds, the UI on this thread reads them in turns
+        let small = manager.open_ipc(2);
+        let run = ipc::Job::RunEninges {
+            engines_list: vec!["facebook".into(); 4],
+            command: "user".into(),
+            query: "user123".into(),
+        };
+        let id = small.send_job(run).unwrap();
+        let mut handled = 0;
+        let mut responds = vec![];
+        while !responds.contains(&ipc::Respond::Done) {
+            handled += manager.handle_jobs().unwrap();
+            let batch = small.recieve_job(id);
+            assert!(batch.len() <= 2);
+            responds.extend(batch);
+        }
+        assert_eq!(handled, 1);
+        assert_eq!(responds.len(), 9);
+    }
+
+    // add a `sleeper` engine that sleeps for the query's seconds, its config is kept in `dir`
+    fn add_sleeper(manager: &SherlockManager, dir: &str) -> path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", dir, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.json");
+        let sleeper = r#"{"name":"sleeper","path":"sleep","commands":[{"name":"user","args":"$query"}]}"#;
+        fs::write(&config, sleeper).unwrap();
+        manager.engines_manager.add_engine_from_config(&config).unwrap();
+        dir
+    }
+
+    #[test]
+    fn schedule_and_cancel_jobs() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        let dir = add_sleeper(&manager, "sherlock-sleeper");
+        let run = |engines: &[&str], query: &str| ipc::Job::RunEninges {
+            engines_list: engines.iter().map(|engine| engine.to_string()).collect(),
+            command: "user".into(),
+            query: query.into(),
+        };
+        let ui = manager.open_ipc(16);
+
+        //a lookup is answered while the engines of the sweep sent first run
+        let sweep = ui.send_job(run(&["sleeper", "sleeper"], "1")).unwrap();
+        let user = Uuid::new_v4().to_string();
+        let lookup = ui.send_job(run(&["facebook"], &user)).unwrap();
+        let describe = ui
+            .send_job(ipc::Job::DescribeEngine {
+                engine: "facebook".into(),
+            })
+            .unwrap();
+        assert_eq!(manager.handle_jobs().unwrap(), 3);
+        let replies: Vec<ipc::JobId> = ui.recieve_responds().iter().map(|r| r.job_id).collect();
+        let first_of_sweep = replies.iter().position(|id| *id == sweep).unwrap();
+        assert!(replies[..first_of_sweep].contains(&lookup));
+        assert!(replies[..first_of_sweep].contains(&describe));
+        assert_eq!(replies.last(), Some(&sweep));
+        //the runs are recorded like the ones of `run_engine`
+        let storage = manager.storage_manager.borrow();
+        let case = storage.default_case().unwrap();
+        let queries = storage.storage().list_queries(case.id).unwrap();
+        let query = queries.iter().find(|query| query.text == user).unwrap();
+        let runs = storage.storage().list_runs(query.id).unwrap();
+        assert_eq!(runs[0].status, storage_manager::RunStatus::Succeeded);
+        drop(storage);
+
+        //the engines of a sweep run side by side
+        let sweep = ui.send_job(run(&["sleeper"; 3], "1")).unwrap();
+        let start = time::Instant::now();
+        assert_eq!(manager.handle_jobs().unwrap(), 1);
+        assert!(start.elapsed() < time::Duration::from_millis(2500));
+        let responds = ui.await_job(sweep).unwrap();
+        assert_eq!(responds.len(), 6);
+        assert_eq!(responds[5], ipc::Respond::Progress { done: 3, total: 3 });
+
+        //a cancelled job doesn't run
+        let sweep = ui.send_job(run(&["facebook", "facebook"], "user123")).unwrap();
+        ui.cancel_job(sweep).unwrap();
+        assert_eq!(manager.handle_jobs().unwrap(), 2);
+        assert_eq!(ui.await_job(sweep).unwrap(), vec![ipc::Respond::Cancelled]);
+
+        //the engines of a running job are killed
+        let slow = ui.send_job(run(&["sleeper", "sleeper"], "10")).unwrap();
+        let start = time::Instant::now();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(time::Duration::from_millis(200));
+                ui.cancel_job(slow).unwrap();
+            });
+            manager.handle_jobs().unwrap();
+        });
+        assert!(start.elapsed() < time::Duration::from_secs(5));
+        assert_eq!(ui.await_job(slow).unwrap(), vec![ipc::Respond::Cancelled]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn serve_jobs_until_the_ui_is_gone() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        let ui = manager.open_ipc(8);
+        let describe = |engine: &str| ipc::Job::DescribeEngine {
+            engine: engine.into(),
+        };
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let id = ui.send_job(describe("facebook")).unwrap();
+                let info = match &ui.await_job(id).unwrap()[0] {
+                    ipc::Respond::Engine(info) => info.clone(),
+                    respond => panic!("unexpected respond {:?}", respond),
+                };
+                assert_eq!(info.name, "facebook");
+                assert!(info.commands.contains_key("user"));
+                let id = ui.send_job(describe("unknown")).unwrap();
+                assert!(matches!(&ui.await_job(id).unwrap()[0], ipc::Respond::Error(_)));
+            });
+            assert_eq!(manager.serve_jobs().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn listen_to_a_headless_frontend() {
+        let manager = test_manager(Some("tree"));
+        let headless = Headless::new(64);
+        manager.attach(headless.clone()).build();
+        let events = headless.take_events();
+        assert_eq!(
+            events.last().unwrap(),
+            &Emitted {
+                event: "log-event".into(),
+                payload: serde_json::json!({ "Info": "loaded engines" })
+            }
+        );
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                //the handler is there once the manager listens
+                while !headless.send("task-event", r#""ListProfiles""#) {
+                    thread::sleep(time::Duration::from_millis(10));
+                }
+                headless.send("task-event", r#""Sync""#);
+                headless.send("task-event", "not a task");
+                headless.close();
+            });
+            manager.listen();
+        });
+        let events = headless.take_events();
+        assert!(events.iter().any(|emitted| emitted.event == "profile-event"));
+        //the storage isn't synced, the UI gets the status once
+        let synced = events.iter().filter(|emitted| emitted.event == "sync-event");
+        assert_eq!(synced.count(), 1);
+        assert!(events.contains(&Emitted {
+            event: "log-event".into(),
+            payload: serde_json::json!({ "Error": "invalid task" })
+        }));
+    }
+
+    // the messages of the frontend's tasks in `job-event`
+    fn job_events(events: &[Emitted]) -> Vec<messages::Message> {
+        events
+            .iter()
+            .filter(|emitted| emitted.event == "job-event")
+            .map(|emitted| serde_json::from_value(emitted.payload.clone()).unwrap())
+            .collect()
+    }
+
+    // call a method of the JSON-RPC server at `path`, returns the result
+    #[cfg(unix)]
+    fn call_rpc(path: &path::Path, method: &str) -> serde_json::Value {
+        use io::{BufRead, Write};
+        let mut stream = std::os::unix::net::UnixStream::connect(path).unwrap();
+        stream
+            .set_read_timeout(Some(time::Duration::from_secs(10)))
+            .unwrap();
+        writeln!(stream, r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#, method).unwrap();
+        let mut line = String::new();
+        io::BufReader::new(stream).read_line(&mut line).unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()["result"].clone()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serve_rpc_until_stopped() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        let path = std::env::temp_dir().join(format!("sherlock-serve-{}.sock", std::process::id()));
+        let server = manager.bind_rpc(&path).unwrap();
+        let stop = server.get_stop_handle();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                assert_eq!(call_rpc(&path, "list_engines"), serde_json::json!(["facebook"]));
+                stop.stop();
+            });
+            assert_eq!(manager.serve_rpc(server).unwrap(), 1);
+        });
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rpc_next_to_the_frontend() {
+        let manager = test_manager(Some("tree"));
+        let headless = Headless::new(64);
+        manager.attach(headless.clone()).build();
+        let path = std::env::temp_dir().join(format!("sherlock-next-{}.sock", std::process::id()));
+        let server = manager.bind_rpc(&path).unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !headless.send("task-event", r#""ListProfiles""#) {
+                    thread::sleep(time::Duration::from_millis(10));
+                }
+                //a script drives the running instance while the window is open
+                assert_eq!(call_rpc(&path, "list_engines"), serde_json::json!(["facebook"]));
+                headless.close();
+            });
+            manager.listen();
+        });
+        let events = headless.take_events();
+        assert!(events.iter().any(|emitted| emitted.event == "profile-event"));
+        server.shutdown();
+    }
+
+    #[test]
+    fn run_tasks_of_the_frontend() {
+        let manager = test_manager(Some("tree"));
+        let headless = Headless::new(64);
+        manager.attach(headless.clone()).build();
+        let case = manager
+            .create_case(&format!("case {}", Uuid::new_v4()), None)
+            .unwrap();
+        let run = r#"{"RunEninges":{"engines_list":["facebook","unknown"],"command":"user","query":"user123"}}"#;
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !headless.send("task-event", run) {
+                    thread::sleep(time::Duration::from_millis(10));
+                }
+                headless.send("task-event", r#""ListEngines""#);
+                headless.close();
+            });
+            //returns once the tasks sent before closing are answered
+            manager.listen();
+        });
+        let events = headless.take_events();
+        let emitted = |event: &str, payload: serde_json::Value| Emitted {
+            event: event.into(),
+            payload,
+        };
+        assert!(events.contains(&emitted(
+            "engine-event",
+            serde_json::json!({ "EngineResult": { "engine": "facebook", "output": "test output\n" } })
+        )));
+        assert!(events.contains(&emitted(
+            "engine-event",
+            serde_json::json!({ "Engines": ["facebook"] })
+        )));
+        assert!(events.contains(&emitted(
+            "log-event",
+            serde_json::json!({ "Error": "unknown: UnknownEngine" })
+        )));
+
+        //the tasks are jobs of the messages box, their responds come with the job's id
+        let messages = job_events(&events);
+        let messages::Message::Request(request) = &messages[0] else {
+            panic!("the run wasn't sent first: {:?}", messages[0]);
+        };
+        let reply = |respond: Respond| {
+            messages::Message::Reply(Reply {
+                job_id: request.id,
+                respond,
+            })
+        };
+        assert!(messages.contains(&reply(Respond::Progress { done: 2, total: 2 })));
+        assert!(messages.contains(&reply(Respond::Done)));
+        //the runs are recorded in the active case like the ones of `run_engine`
+        let file = manager.load_active_case().unwrap();
+        assert_eq!(file.case.id, case.id);
+        assert_eq!(file.runs.len(), 2);
+    }
+
+    #[test]
+    fn cancel_a_task_of_the_frontend() {
+        let manager = test_manager(Some("tree"));
+        let headless = Headless::new(64);
+        manager.attach(headless.clone()).build();
+        let dir = add_sleeper(&manager, "sherlock-frontend-sleeper");
+        let run = serde_json::json!({
+            "RunEninges": { "engines_list": ["sleeper"], "command": "user", "query": "10" }
+        });
+        let run = run.to_string();
+        let start = time::Instant::now();
+        let (id, events) = thread::scope(|scope| {
+            let frontend = scope.spawn(|| {
+                while !headless.send("task-event", &run) {
+                    thread::sleep(time::Duration::from_millis(10));
+                }
+                //the frontend learns the id of its task from `job-event`
+                let mut events = vec![];
+                let id = loop {
+                    events.extend(headless.take_events());
+                    let sent = job_events(&events)
+                        .into_iter()
+                        .find_map(|message| match message {
+                            messages::Message::Request(request) => Some(request.id),
+                            _ => None,
+                        });
+                    match sent {
+                        Some(id) => break id,
+                        None => thread::sleep(time::Duration::from_millis(10)),
+                    }
+                };
+                let cancel = serde_json::json!({ "Cancel": { "job_id": id } });
+                headless.send("task-event", &cancel.to_string());
+                headless.close();
+                (id, events)
+            });
+            manager.listen();
+            let (id, mut events) = frontend.join().unwrap();
+            events.extend(headless.take_events());
+            (id, events)
+        });
+        //the engine was killed
+        assert!(start.elapsed() < time::Duration::from_secs(5));
+        let responds: Vec<Respond> = job_events(&events)
+            .into_iter()
+            .filter_map(|message| match message {
+                messages::Message::Reply(reply) if reply.job_id == id => Some(reply.respond),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(responds, vec![Respond::Cancelled, Respond::Done]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn runs_are_persisted() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        assert_eq!(manager.run_engine("facebook", "user", "user123").unwrap(), "test output\n");
+        assert!(manager.run_engine("facebook", "unknown", "user123").is_err());
+
+        //both runs are stored under the default case
+        let storage = manager.storage_manager.borrow();
+        let case = storage.default_case().unwrap();
+        let queries = storage.storage().list_queries(case.id).unwrap();
+        let runs = storage.storage().list_runs(queries.last().unwrap().id).unwrap();
+        assert!(matches!(runs[0].status, storage_manager::RunStatus::Failed(_)));
+        let runs = storage.storage().list_runs(queries[queries.len() - 2].id).unwrap();
+        let results = storage.storage().list_results(runs[0].id).unwrap();
+        assert_eq!(results[0].output, "test output\n");
+    }
+
+    #[test]
+    fn failed_restore_keeps_the_storage() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        let options = RestoreOptions {
+            dry_run: false,
+            overwrite: true,
+        };
+        let missing = std::env::temp_dir().join(format!("missing-{}.backup", Uuid::new_v4()));
+        assert!(manager.restore(&missing, &options).is_err());
+        //the storage of the config is opened again, not the in-memory one
+        assert!(matches!(
+            manager.storage_manager.borrow().get_backend(),
+            Backend::FileTree { .. }
+        ));
+    }
+
+    #[test]
+    fn runs_in_the_active_case() {
+        let manager = test_manager(Some("tree"));
+        manager.build();
+        let title = format!("case {}", Uuid::new_v4());
+        let case = manager.create_case(&title, Some("jane")).unwrap();
+        assert_eq!(manager.get_active_case().unwrap(), case);
+        assert!(manager.list_cases().unwrap().contains(&case));
+
+        manager.run_engine("facebook", "user", "user123").unwrap();
+        manager.add_note("nothing yet", Some("jane")).unwrap();
+        manager.set_case_status(CaseStatus::Closed).unwrap();
+        let tags = [" alias ".to_owned(), "alias".to_owned(), "".to_owned()];
+        manager.set_case_tags(&tags).unwrap();
+        let file = manager.load_active_case().unwrap();
+        assert_eq!(file.runs.len(), 1);
+        //the file the engine left is kept with the run
+        let avatar = manager.read_artifact(file.runs[0].id, "avatar.txt").unwrap();
+        assert_eq!(avatar, b"user123's avatar");
+        assert_eq!(file.notes[0].author.as_deref(), Some("jane"));
+        assert_eq!(file.case.status, CaseStatus::Closed);
+        assert!(file.case.closed_at.is_some());
+        assert_eq!(file.case.tags, vec!["alias".to_owned()]);
+
+        //the output of the run is searchable
+        let hits = manager.search("\"test output\"", &SearchFilter::default()).unwrap();
+        assert!(hits.iter().any(|hit| hit.case_id == case.id));
+
+        //the run and its result are logged as evidence
+        let path = std::env::temp_dir().join(format!("evidence-{}.json", case.id));
+        manager.export_evidence(&path).unwrap();
+        let export: EvidenceExport = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(export.verify().is_empty());
+        let logged: Vec<_> = export
+            .entries
+            .iter()
+            .filter(|entry| entry.run_id == file.runs[0].id)
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0].engine_version.as_deref(), Some("1.0.0"));
+        assert_eq!(logged[0].argv[2..], ["-search_user", "user123"]);
+        assert!(logged[0].engine_digest.is_some());
+        assert_eq!(logged[0].artifacts, file.runs[0].artifacts);
+
+        //the case's own policy expires its runs, a dry run keeps them
+        let retention = RetentionPolicy {
+            max_age_days: None,
+            closed_case_days: Some(0),
+        };
+        manager.set_case_retention(Some(retention)).unwrap();
+        let report = manager.purge_expired(true).unwrap();
+        assert!(report.runs.iter().any(|run| run.run_id == file.runs[0].id));
+        assert_eq!(manager.load_active_case().unwrap().runs.len(), 1);
+
+        //an unknown case leaves the active case alone
+        assert!(manager.open_case(Uuid::new_v4()).is_err());
+        assert_eq!(manager.get_active_case().unwrap().id, case.id);
+
+        //reopening the storage goes back to the default case
+        manager.switch_profile(Some("tree")).unwrap();
+        assert_ne!(manager.get_active_case().unwrap().id, case.id);
+        assert_eq!(manager.open_case(case.id).unwrap().title, title);
+    }
+
+    #[test]
+    fn unlock_encrypted_storage() {
+        //without a passphrase the encrypted storage stays locked
+        let manager = test_manager(Some("secure"));
+        //whatever the environment the tests run in
+        manager.storage_key.replace(None);
+        manager.build();
+        assert_eq!(manager.get_storage_backend(), Backend::Memory);
+
+        manager
+            .unlock_storage(KeySource::Passphrase("secret".into()))
+            .unwrap();
+        assert!(matches!(
+            manager.get_storage_backend(),
+            Backend::Sqlite {
+                encrypted: true,
+                ..
+            }
+        ));
+
+        //a wrong passphrase keeps the current storage
+        let other = test_manager(Some("secure"));
+        other.build();
+        assert!(other
+            .unlock_storage(KeySource::Passphrase("wrong".into()))
+            .is_err());
+    }
+
+    #[test]
+    fn unlock_with_vault() {
+        let dir = path::Path::new("./target/sherlock");
+        let _ = fs::remove_file(dir.join("vaulted.db"));
+        let _ = fs::remove_file(dir.join("vault"));
+        let _ = fs::remove_dir_all(dir.join("vaulted.blobs"));
+
+        //the storage key is generated in the vault on first use
+        let manager = test_manager(Some("vaulted"));
+        manager.build();
+        manager.unlock_vault(&dir.join("vault"), "secret").unwrap();
+        assert!(matches!(
+            manager.get_storage_backend(),
+            Backend::Sqlite {
+                encrypted: true,
+                ..
+            }
+        ));
+        assert!(manager.unlock_vault(&dir.join("vault"), "wrong").is_err());
+    }
+
+    #[test]
+    fn synced_storage() {
+        let manager = SherlockManager::init_with_profile(None);
+        manager.build();
+        assert_eq!(manager.get_sync_status(), None);
+        assert!(manager.sync().is_err());
+
+        //the synced profile's server isn't running, the storage works offline
+        let dir = path::Path::new("./target/sherlock");
+        let _ = fs::remove_file(dir.join("synced.db"));
+        let _ = fs::remove_file(dir.join("synced.sync.json"));
+        let manager = test_manager(Some("synced"));
+        manager.build();
+        manager.storage_manager.borrow().default_case().unwrap();
+        assert_eq!(manager.get_sync_status().unwrap().pending, 8);
+        assert!(manager.sync().is_err());
+        let status = manager.get_sync_status().unwrap();
+        assert_eq!(status.phase, storage_manager::SyncPhase::Offline);
+        assert_eq!(status.pending, 8);
+    }
+
     #[test]
     fn list_engine_commands() {
         //TODO: make this tesk more general
@@ -0,0 +1,137 @@
+/*
+Scheduler:
+The jobs of the messages box that the manager received and didn't finish yet. The next job is the
+oldest one of the highest priority. A run of several engines is a step per engine, it's put back
+once a step starts: its next step starts on another worker, and a job of a higher priority sent
+meanwhile runs before the rest of it. It's finished after its last step.
+
+A job that can't start yet is skipped (see `pop_where`), i.e: an engine step while every worker
+is busy, the jobs after it don't wait for it.
+ */
+use ipc::{JobId, Request};
+use std::cmp::Reverse;
+
+/// ## Description
+/// A job and how far it went.
+pub(crate) struct Scheduled {
+    pub request: Request,
+    /// The engines of a run whose steps were started.
+    pub started: usize,
+    /// The engines of a run that ran already.
+    pub done: usize,
+}
+
+impl Scheduled {
+    pub fn new(request: Request) -> Scheduled {
+        Scheduled {
+            request,
+            started: 0,
+            done: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    jobs: Vec<Scheduled>,
+}
+
+impl Scheduler {
+    /// Adds a job, or puts back one that isn't finished.
+    pub fn push(&mut self, scheduled: Scheduled) {
+        self.jobs.push(scheduled);
+    }
+
+    /// Takes the oldest job of the highest priority.
+    pub fn pop(&mut self) -> Option<Scheduled> {
+        self.pop_where(|_| true)
+    }
+
+    /// Takes the oldest job of the highest priority among the ones `filter` accepts.
+    pub fn pop_where<F>(&mut self, filter: F) -> Option<Scheduled>
+    where
+        F: Fn(&Scheduled) -> bool,
+    {
+        let (index, _) = self
+            .jobs
+            .iter()
+            .enumerate()
+            .filter(|(_, scheduled)| filter(scheduled))
+            .max_by_key(|(_, scheduled)| {
+                (scheduled.request.priority, Reverse(scheduled.request.id))
+            })?;
+        Some(self.jobs.swap_remove(index))
+    }
+
+    /// Checks whether one of the jobs matches `filter`.
+    pub fn any<F>(&self, filter: F) -> bool
+    where
+        F: Fn(&Scheduled) -> bool,
+    {
+        self.jobs.iter().any(filter)
+    }
+
+    /// Checks whether no job is left.
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Takes every job out of the queue, i.e: when they can't be run anymore.
+    pub fn drain(&mut self) -> Vec<Scheduled> {
+        std::mem::take(&mut self.jobs)
+    }
+
+    /// Takes a job out of the queue, i.e: when it's cancelled.
+    pub fn remove(&mut self, id: JobId) -> Option<Scheduled> {
+        let index = self
+            .jobs
+            .iter()
+            .position(|scheduled| scheduled.request.id == id)?;
+        Some(self.jobs.swap_remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipc::{Job, Priority};
+
+    #[test]
+    fn priorities_then_order() {
+        let mut scheduler = Scheduler::default();
+        for (id, priority) in [
+            (1, Priority::Low),
+            (2, Priority::Normal),
+            (3, Priority::High),
+            (4, Priority::Normal),
+        ] {
+            scheduler.push(Scheduled::new(Request {
+                id: JobId(id),
+                job: Job::ListEngines,
+                priority,
+            }));
+        }
+        assert!(scheduler.remove(JobId(4)).is_some());
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop())
+            .map(|scheduled| scheduled.request.id.0)
+            .collect();
+        assert_eq!(order, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn skip_the_jobs_that_cant_start() {
+        let mut scheduler = Scheduler::default();
+        for (id, priority) in [(1, Priority::Low), (2, Priority::High), (3, Priority::Low)] {
+            scheduler.push(Scheduled::new(Request {
+                id: JobId(id),
+                job: Job::ListEngines,
+                priority,
+            }));
+        }
+        let low = |scheduled: &Scheduled| scheduled.request.priority == Priority::Low;
+        assert!(scheduler.any(low));
+        assert_eq!(scheduler.pop_where(low).unwrap().request.id, JobId(1));
+        assert_eq!(scheduler.pop_where(|_| false).map(|_| ()), None);
+        assert_eq!(scheduler.pop().unwrap().request.id, JobId(2));
+    }
+}
@@ -0,0 +1,67 @@
+/*
+Storage API:
+The wire types of the remote storage API, shared by `RemoteStorage` and the reference server
+(`storage_server`, where the endpoints are documented).
+ */
+use crate::{Run, RunResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The version of the API, the client refuses servers with another version.
+pub const API_VERSION: u32 = 1;
+/// The number of records in a page when the client doesn't ask for another size.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// The largest page the server returns.
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// ## Description
+/// The answer of `GET /v1/status`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub api_version: u32,
+}
+
+/// ## Description
+/// A page of a list, `next` is the cursor of the following page (`None` on the last page).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+/// ## Description
+/// The body of `PUT /v1/runs/{id}/outcome`, a finished run saved together with its results.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunOutcome {
+    pub run: Run,
+    pub results: Vec<RunResult>,
+}
+
+/// ## Description
+/// The body of the error answers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// ## Description
+/// The revision of a record, a digest of its content.
+///
+/// The server sends it as the `ETag` of a record, a write with an `If-Match` header is refused
+/// (`409 Conflict`) when the record changed since that revision.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::{api, Case};
+/// let mut case = Case::new("case");
+/// let revision = api::revision(&case);
+/// case.title = "renamed".into();
+/// assert_ne!(api::revision(&case), revision);
+/// ```
+pub fn revision<T: Serialize>(record: &T) -> String {
+    let json = serde_json::to_vec(record).unwrap_or_default();
+    Sha256::digest(&json)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
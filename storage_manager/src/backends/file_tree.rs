@@ -0,0 +1,438 @@
+use crate::evidence::check_append;
+use crate::model::Record;
+use crate::retention::{overwrite_file, wipe_file};
+use crate::{
+    Case, Change, Entity, Error, EvidenceEntry, Note, Query, Run, RunFilter, RunResult, Storage,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+const JOURNAL: &str = "journal.jsonl";
+const EVIDENCE: &str = "evidence.jsonl";
+
+/// ## Description
+/// A storage backend that keeps every record as a json file in a directory tree:
+/// `<root>/<kind>/<id>.json`, i.e: `store/cases/5f0c...e1.json`.
+///
+/// Easy to inspect and to put under version control, but lists read every file of their kind.
+/// The journal is a json file per line in `<root>/journal.jsonl`,
+/// the evidence log likewise in `<root>/evidence.jsonl`.
+/// Deleted records are zeroed before they're unlinked, and so are the erased journal values.
+pub struct FileTreeStorage {
+    root: PathBuf,
+    // where the last page of a log ended: its last sequence number and the offset of the line
+    // after it, the next page is read from there instead of from the start of the log
+    resume: Mutex<HashMap<&'static str, (u64, u64)>>,
+}
+
+impl FileTreeStorage {
+    /// ## Description
+    /// Opens a directory tree storage, creating the root directory if needed.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<FileTreeStorage, Error> {
+        fs::create_dir_all(root.as_ref()).map_err(|error| Error::Io(error.to_string()))?;
+        Ok(FileTreeStorage {
+            root: root.as_ref().to_path_buf(),
+            resume: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn record_path<T: Record>(&self, id: Uuid) -> PathBuf {
+        self.root.join(T::KIND).join(format!("{}.json", id))
+    }
+
+    // write to a temporary file first so a crash can't leave a half written record
+    fn put<T: Record>(&self, record: &T) -> Result<(), Error> {
+        let path = self.record_path::<T>(record.id());
+        fs::create_dir_all(self.root.join(T::KIND))
+            .map_err(|error| Error::Io(error.to_string()))?;
+        let json = serde_json::to_vec_pretty(record)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, json).map_err(|error| Error::Io(error.to_string()))?;
+        fs::rename(&temp, &path).map_err(|error| Error::Io(error.to_string()))
+    }
+
+    fn get<T: Record>(&self, id: Uuid) -> Result<Option<T>, Error> {
+        match fs::read(self.record_path::<T>(id)) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map(Some)
+                .map_err(|error| Error::Serialization(error.to_string())),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(Error::Io(error.to_string())),
+        }
+    }
+
+    // delete a record, its file is zeroed first
+    fn remove<T: Record>(&self, id: Uuid) -> Result<(), Error> {
+        wipe_file(&self.record_path::<T>(id)).map_err(|error| Error::Io(error.to_string()))
+    }
+
+    // read every record of a kind that matches the filter, ordered by creation time
+    fn all<T: Record>(&self, filter: impl Fn(&T) -> bool) -> Result<Vec<T>, Error> {
+        let entries = match fs::read_dir(self.root.join(T::KIND)) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(Error::Io(error.to_string())),
+        };
+
+        let mut records = vec![];
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let json = fs::read(&path).map_err(|error| Error::Io(error.to_string()))?;
+                let record: T = serde_json::from_slice(&json)
+                    .map_err(|error| Error::Serialization(error.to_string()))?;
+                if filter(&record) {
+                    records.push(record);
+                }
+            }
+        }
+        records.sort_by_key(|record| record.created_at());
+        Ok(records)
+    }
+
+    // append json lines to a log file of the root
+    fn append_lines<T: serde::Serialize>(&self, file: &str, items: &[T]) -> Result<(), Error> {
+        let lines = to_lines(items)?;
+        //a single write so a crash can't interleave or split the lines
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.root.join(file))
+            .and_then(|mut log| log.write_all(&lines))
+            .map_err(|error| Error::Io(error.to_string()))
+    }
+
+    // read the json lines of a log file of the root that match `keep`, up to `limit` of them
+    fn read_lines<T, F>(&self, file: &str, keep: F, limit: usize) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        F: Fn(&T) -> bool,
+    {
+        let log = match fs::File::open(self.root.join(file)) {
+            Ok(log) => log,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(Error::Io(error.to_string())),
+        };
+        let mut items = vec![];
+        for line in BufReader::new(log).lines() {
+            let line = line.map_err(|error| Error::Io(error.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let item: T = serde_json::from_str(&line)
+                .map_err(|error| Error::Serialization(error.to_string()))?;
+            if keep(&item) {
+                items.push(item);
+                if items.len() == limit {
+                    break;
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    // read the entries of a log file of the root after a sequence number, up to `limit` of them
+    // the logs are appended in sequence order, a page starts where the previous one ended
+    fn read_after<T>(
+        &self,
+        file: &'static str,
+        after: u64,
+        limit: usize,
+        seq_of: fn(&T) -> u64,
+    ) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut log = match fs::File::open(self.root.join(file)) {
+            Ok(log) => log,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(Error::Io(error.to_string())),
+        };
+        let size = log
+            .metadata()
+            .map_err(|error| Error::Io(error.to_string()))?
+            .len();
+        let mut offset = match self.resume.lock().unwrap().get(file) {
+            Some((seq, offset)) if *seq <= after && *offset <= size => *offset,
+            _ => 0,
+        };
+        log.seek(SeekFrom::Start(offset))
+            .map_err(|error| Error::Io(error.to_string()))?;
+
+        let mut reader = BufReader::new(log);
+        let mut items = vec![];
+        let mut line = String::new();
+        let mut last_seq = None;
+        while items.len() < limit {
+            line.clear();
+            let read = reader
+                .read_line(&mut line)
+                .map_err(|error| Error::Io(error.to_string()))?;
+            //the end of the log, or a line that is still being written
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            offset += read as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let item: T = serde_json::from_str(&line)
+                .map_err(|error| Error::Serialization(error.to_string()))?;
+            last_seq = Some(seq_of(&item));
+            if seq_of(&item) > after {
+                items.push(item);
+            }
+        }
+        if let Some(seq) = last_seq {
+            self.resume.lock().unwrap().insert(file, (seq, offset));
+        }
+        Ok(items)
+    }
+}
+
+// the json lines of items, a line per item
+fn to_lines<T: serde::Serialize>(items: &[T]) -> Result<Vec<u8>, Error> {
+    let mut lines = vec![];
+    for item in items {
+        serde_json::to_writer(&mut lines, item)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+impl Storage for FileTreeStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        self.put(case)
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        self.get(id)
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        self.all(|_: &Case| true)
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        self.put(query)
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        self.get(id)
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        self.all(|query: &Query| query.case_id == case_id)
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        self.put(run)
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        self.get(id)
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        self.all(|run: &Run| run.query_id == query_id)
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        self.put(result)
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        self.get(id)
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        self.all(|result: &RunResult| result.run_id == run_id)
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        self.put(entity)
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        self.get(id)
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        self.all(|entity: &Entity| entity.case_id == case_id)
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        self.put(note)
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        self.get(id)
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        self.all(|note: &Note| note.case_id == case_id)
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        //the entity value lives on the queries, load the matching ones once
+        let queries = match &filter.value {
+            Some(value) => self.all(|query: &Query| &query.text == value)?,
+            None => vec![],
+        };
+        self.all(|run: &Run| filter.matches(run, queries.iter().find(|q| q.id == run.query_id)))
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        for result in self.all(|result: &RunResult| result.run_id == id)? {
+            self.remove::<RunResult>(result.id)?;
+        }
+        self.remove::<Run>(id)
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.remove::<Query>(id)
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.remove::<Entity>(id)
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        self.append_lines(JOURNAL, changes)
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        self.read_after(JOURNAL, after, limit, |change: &Change| change.seq)
+    }
+
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        let mut changes: Vec<Change> = self.read_lines(JOURNAL, |_| true, usize::MAX)?;
+        let mut redacted = false;
+        for change in changes.iter_mut() {
+            if record_ids.contains(&change.record_id) && !change.value.is_null() {
+                change.value = serde_json::Value::Null;
+                redacted = true;
+            }
+        }
+        if !redacted {
+            return Ok(());
+        }
+        //the lines move, the next page is read from the start
+        self.resume.lock().unwrap().remove(JOURNAL);
+        //the old file is zeroed, it would leave the old values in the freed blocks
+        overwrite_file(&self.root.join(JOURNAL), &to_lines(&changes)?)
+            .map_err(|error| Error::Io(error.to_string()))
+    }
+
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        let entries: Vec<EvidenceEntry> = self.read_lines(EVIDENCE, |_| true, usize::MAX)?;
+        let last_seq = entries.last().map_or(0, |last| last.seq);
+        let existing = entries.iter().find(|e| e.seq == entry.seq);
+        match check_append(entry, last_seq, existing)? {
+            true => self.append_lines(EVIDENCE, std::slice::from_ref(entry)),
+            false => Ok(()),
+        }
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        self.read_after(EVIDENCE, after, limit, |entry: &EvidenceEntry| entry.seq)
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        let mut entries: Vec<EvidenceEntry> = self.read_lines(EVIDENCE, |_| true, usize::MAX)?;
+        let mut redacted = false;
+        for entry in entries.iter_mut() {
+            if record_ids.contains(&entry.record_id) && !entry.redacted {
+                entry.redact();
+                redacted = true;
+            }
+        }
+        if !redacted {
+            return Ok(());
+        }
+        //the lines move, the next page is read from the start
+        self.resume.lock().unwrap().remove(EVIDENCE);
+        overwrite_file(&self.root.join(EVIDENCE), &to_lines(&entries)?)
+            .map_err(|error| Error::Io(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_tree_backend() {
+        let root = std::env::temp_dir().join(format!(
+            "sherlock_file_tree_backend_test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let storage = FileTreeStorage::open(&root).unwrap();
+        crate::storage::tests::check_backend(&storage);
+
+        //records are plain json files
+        let case = &storage.list_cases().unwrap()[0];
+        assert!(root
+            .join("cases")
+            .join(format!("{}.json", case.id))
+            .is_file());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn page_through_the_journal() {
+        let root = std::env::temp_dir().join(format!(
+            "sherlock_file_tree_journal_test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let storage = FileTreeStorage::open(&root).unwrap();
+        let record_id = Uuid::new_v4();
+        let changes: Vec<Change> = (1..=25)
+            .map(|seq| Change {
+                seq,
+                kind: "cases".into(),
+                record_id,
+                field: "title".into(),
+                value: serde_json::json!(format!("title {}", seq)),
+                at: chrono::Utc::now(),
+                origin: crate::ChangeOrigin::Local,
+            })
+            .collect();
+        storage.append_changes(&changes).unwrap();
+
+        //every page starts where the previous one ended
+        let mut paged = vec![];
+        loop {
+            let after = paged.last().map_or(0, |change: &Change| change.seq);
+            let page = storage.list_changes(after, 10).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            paged.extend(page);
+        }
+        assert_eq!(paged, changes);
+        //going back reads the journal from the start
+        assert_eq!(storage.list_changes(3, 2).unwrap(), changes[3..5].to_vec());
+
+        //a redacted journal is read again from the start
+        storage.list_changes(20, 10).unwrap();
+        storage.redact_changes(&[record_id]).unwrap();
+        let redacted = storage.list_changes(20, 10).unwrap();
+        assert_eq!(redacted.len(), 5);
+        assert!(redacted.iter().all(|change| change.value.is_null()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+use crate::evidence::check_append;
+use crate::model::Record;
+use crate::{
+    Case, Change, Entity, Error, EvidenceEntry, Note, Query, Run, RunFilter, RunResult, Storage,
+};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// ## Description
+/// A storage backend that keeps everything in memory, used for tests
+/// and when the configured storage is unavailable.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: RwLock<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    cases: Vec<Case>,
+    queries: Vec<Query>,
+    runs: Vec<Run>,
+    results: Vec<RunResult>,
+    entities: Vec<Entity>,
+    notes: Vec<Note>,
+    changes: Vec<Change>,
+    evidence: Vec<EvidenceEntry>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty in-memory storage.
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+// insert a record or replace the record with the same id, keeping the insertion order
+fn upsert<T: Record>(records: &mut Vec<T>, record: &T) {
+    match records.iter_mut().find(|r| r.id() == record.id()) {
+        Some(existing) => *existing = record.clone(),
+        None => records.push(record.clone()),
+    }
+}
+
+fn find<T: Record>(records: &[T], id: Uuid) -> Option<T> {
+    records.iter().find(|r| r.id() == id).cloned()
+}
+
+impl Storage for MemoryStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().cases, case);
+        Ok(())
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        Ok(find(&self.tables.read().unwrap().cases, id))
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        Ok(self.tables.read().unwrap().cases.clone())
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().queries, query);
+        Ok(())
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        Ok(find(&self.tables.read().unwrap().queries, id))
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .queries
+            .iter()
+            .filter(|q| q.case_id == case_id)
+            .cloned()
+            .collect())
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().runs, run);
+        Ok(())
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        Ok(find(&self.tables.read().unwrap().runs, id))
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .runs
+            .iter()
+            .filter(|r| r.query_id == query_id)
+            .cloned()
+            .collect())
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().results, result);
+        Ok(())
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        Ok(find(&self.tables.read().unwrap().results, id))
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .results
+            .iter()
+            .filter(|r| r.run_id == run_id)
+            .cloned()
+            .collect())
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().entities, entity);
+        Ok(())
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        Ok(find(&self.tables.read().unwrap().entities, id))
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .entities
+            .iter()
+            .filter(|e| e.case_id == case_id)
+            .cloned()
+            .collect())
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        upsert(&mut self.tables.write().unwrap().notes, note);
+        Ok(())
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        Ok(find(&self.tables.read().unwrap().notes, id))
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .notes
+            .iter()
+            .filter(|n| n.case_id == case_id)
+            .cloned()
+            .collect())
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        let tables = self.tables.read().unwrap();
+        let mut runs: Vec<Run> = tables
+            .runs
+            .iter()
+            .filter(|r| filter.matches(r, tables.queries.iter().find(|q| q.id == r.query_id)))
+            .cloned()
+            .collect();
+        runs.sort_by_key(|run| run.started_at);
+        Ok(runs)
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.results.retain(|result| result.run_id != id);
+        tables.runs.retain(|run| run.id != id);
+        Ok(())
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.tables.write().unwrap().queries.retain(|q| q.id != id);
+        Ok(())
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.tables.write().unwrap().entities.retain(|e| e.id != id);
+        Ok(())
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.changes.extend_from_slice(changes);
+        Ok(())
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        let tables = self.tables.read().unwrap();
+        //the changes are kept in order, skip to the first one after `after`
+        let start = tables.changes.partition_point(|change| change.seq <= after);
+        Ok(tables
+            .changes
+            .iter()
+            .skip(start)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        for change in tables.changes.iter_mut() {
+            if record_ids.contains(&change.record_id) {
+                change.value = serde_json::Value::Null;
+            }
+        }
+        Ok(())
+    }
+
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        let last_seq = tables.evidence.last().map_or(0, |last| last.seq);
+        let existing = tables.evidence.iter().find(|e| e.seq == entry.seq);
+        if check_append(entry, last_seq, existing)? {
+            tables.evidence.push(entry.clone());
+        }
+        Ok(())
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        let tables = self.tables.read().unwrap();
+        let start = tables.evidence.partition_point(|entry| entry.seq <= after);
+        Ok(tables
+            .evidence
+            .iter()
+            .skip(start)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        for entry in tables.evidence.iter_mut() {
+            if record_ids.contains(&entry.record_id) {
+                entry.redact();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_backend() {
+        crate::storage::tests::check_backend(&MemoryStorage::new());
+    }
+}
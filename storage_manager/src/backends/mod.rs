@@ -0,0 +1,9 @@
+mod file_tree;
+mod memory;
+mod remote;
+mod sqlite;
+
+pub use file_tree::FileTreeStorage;
+pub use memory::MemoryStorage;
+pub use remote::RemoteStorage;
+pub use sqlite::SqliteStorage;
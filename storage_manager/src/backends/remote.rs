@@ -0,0 +1,492 @@
+/*
+Remote Storage:
+A client of the storage server's REST/JSON API, the endpoints are documented in the reference
+server (`storage_server`).
+
+Every request carries the `Authorization: Bearer <token>` header when a token is set.
+Requests that fail on the way (connection errors, `429` and `5xx` answers) are retried with an
+exponential backoff, all the requests are idempotent so retrying them is safe.
+
+The client remembers the revision of every record it has read or written and sends it as
+`If-Match` when it saves the record again, a record that was changed by someone else in the
+meantime fails with `Error::Conflict` instead of being overwritten. Read it again to resolve it.
+A write retried after its answer got lost is refused by its own first attempt, a `409` for a
+record that already holds what was written is taken as the write's success.
+
+The journal is the server's: changes appended to a remote storage are merged into the server's
+records (see `JournaledStorage`), the listed changes are the ones the server merged or recorded.
+The evidence log is the server's too, an entry appended by another client in the meantime fails
+with `Error::Conflict` (see `StorageManager::record_run_with_files`).
+Deleted records are deleted by the server, which erases their values from its journal.
+ */
+use crate::api::{self, ErrorBody, Page, RunOutcome, Status};
+use crate::model::Record;
+use crate::{
+    Case, Change, Entity, Error, EvidenceEntry, Note, Query, Run, RunFilter, RunResult, Storage,
+};
+use chrono::SecondsFormat;
+use std::{collections::HashMap, fs, path::Path, sync::Mutex, thread, time::Duration};
+use ureq::tls::{Certificate, RootCerts, TlsConfig};
+use uuid::Uuid;
+
+/// How many times a request is sent before giving up.
+const ATTEMPTS: u32 = 4;
+/// The wait before the first retry, doubled on every retry.
+const BACKOFF: Duration = Duration::from_millis(200);
+/// The longest a single request may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ## Description
+/// A storage backend that keeps the records on a remote storage server.
+pub struct RemoteStorage {
+    agent: ureq::Agent,
+    url: String,
+    token: Option<String>,
+    revisions: Mutex<HashMap<Uuid, String>>,
+}
+
+// an answer of the server
+struct Reply {
+    status: u16,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+impl RemoteStorage {
+    /// ## Description
+    /// Connects to a storage server, checking the token and the API version.
+    ///
+    /// `ca_cert` is a PEM file with the CA certificate of a self-hosted server,
+    /// `verify_tls` should only be disabled for local testing.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{RemoteStorage, Storage};
+    /// let storage =
+    ///     RemoteStorage::connect("https://sherlock.example.com/api", Some("token".into()), true, None)
+    ///         .unwrap();
+    /// let cases = storage.list_cases().unwrap();
+    /// ```
+    pub fn connect(
+        url: &str,
+        token: Option<String>,
+        verify_tls: bool,
+        ca_cert: Option<&Path>,
+    ) -> Result<RemoteStorage, Error> {
+        let mut tls = TlsConfig::builder().disable_verification(!verify_tls);
+        if let Some(ca_cert) = ca_cert {
+            let pem = fs::read(ca_cert).map_err(|error| Error::Io(error.to_string()))?;
+            let certificate = Certificate::from_pem(&pem).map_err(|error| {
+                Error::InvalidConfig(format!("{}: {}", ca_cert.display(), error))
+            })?;
+            tls = tls.root_certs(RootCerts::new_with_certs(&[certificate]));
+        }
+        let agent = ureq::Agent::new_with_config(
+            ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .timeout_global(Some(TIMEOUT))
+                .tls_config(tls.build())
+                .build(),
+        );
+
+        let storage = RemoteStorage {
+            agent,
+            url: url.trim_end_matches('/').to_owned(),
+            token,
+            revisions: Mutex::new(HashMap::new()),
+        };
+        let reply = storage.send("/v1/status", &[], None, None)?;
+        let status: Status = storage.parse(reply, "/v1/status")?;
+        if status.api_version != api::API_VERSION {
+            return Err(Error::Remote(format!(
+                "the server speaks version {} of the API, expected version {}",
+                status.api_version,
+                api::API_VERSION
+            )));
+        }
+        Ok(storage)
+    }
+
+    // send a request, a `PUT` with a body or a `GET` without one
+    fn send(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+        if_match: Option<String>,
+    ) -> Result<Reply, Error> {
+        let method = if body.is_some() { "PUT" } else { "GET" };
+        self.request(method, path, query, body, if_match)
+    }
+
+    // send a request, retrying the ones that failed on the way
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+        if_match: Option<String>,
+    ) -> Result<Reply, Error> {
+        let url = format!("{}{}", self.url, path);
+        let mut attempt = 0;
+        loop {
+            let mut headers = vec![("Accept", "application/json".to_owned())];
+            if let Some(token) = &self.token {
+                headers.push(("Authorization", format!("Bearer {}", token)));
+            }
+            if let Some(revision) = &if_match {
+                headers.push(("If-Match", format!("\"{}\"", revision)));
+            }
+            let response = match (method, &body) {
+                ("PUT", Some(body)) => {
+                    let mut request = self.agent.put(&url).content_type("application/json");
+                    for (name, value) in &headers {
+                        request = request.header(*name, value);
+                    }
+                    request.send(body.as_slice())
+                }
+                _ => {
+                    let request = match method {
+                        "DELETE" => self.agent.delete(&url),
+                        _ => self.agent.get(&url),
+                    };
+                    let mut request = request
+                        .query_pairs(query.iter().map(|(name, value)| (*name, value.as_str())));
+                    for (name, value) in &headers {
+                        request = request.header(*name, value);
+                    }
+                    request.call()
+                }
+            };
+
+            attempt += 1;
+            match response {
+                //busy or failing servers are retried
+                Ok(response)
+                    if (response.status() == 429 || response.status().is_server_error())
+                        && attempt < ATTEMPTS => {}
+                Ok(mut response) => {
+                    let etag = response
+                        .headers()
+                        .get("etag")
+                        .and_then(|etag| etag.to_str().ok())
+                        .map(|etag| etag.trim_matches('"').to_owned());
+                    let body = response
+                        .body_mut()
+                        .read_to_vec()
+                        .map_err(|error| Error::Remote(error.to_string()))?;
+                    return Ok(Reply {
+                        status: response.status().as_u16(),
+                        etag,
+                        body,
+                    });
+                }
+                Err(error) if attempt < ATTEMPTS && is_transient(&error) => {}
+                Err(error) => return Err(Error::Remote(format!("{}: {}", url, error))),
+            }
+            //wait a bit longer after every failed attempt
+            thread::sleep(BACKOFF * 2u32.pow(attempt - 1));
+        }
+    }
+
+    // decode a successful answer, or turn the failed one into an error
+    fn parse<T: serde::de::DeserializeOwned>(&self, reply: Reply, path: &str) -> Result<T, Error> {
+        match reply.status {
+            200..=299 => serde_json::from_slice(&reply.body)
+                .map_err(|error| Error::Serialization(error.to_string())),
+            _ => Err(reply_error(reply, path)),
+        }
+    }
+
+    fn remember(&self, id: Uuid, revision: String) {
+        self.revisions.lock().unwrap().insert(id, revision);
+    }
+
+    fn revision_of(&self, id: Uuid) -> Option<String> {
+        self.revisions.lock().unwrap().get(&id).cloned()
+    }
+
+    fn get<T: Record>(&self, id: Uuid) -> Result<Option<T>, Error> {
+        let path = format!("/v1/{}/{}", T::KIND, id);
+        let reply = self.send(&path, &[], None, None)?;
+        if reply.status == 404 {
+            return Ok(None);
+        }
+        let record: T = self.parse(reply, &path)?;
+        self.remember(id, api::revision(&record));
+        Ok(Some(record))
+    }
+
+    fn put<T: Record>(&self, record: &T) -> Result<(), Error> {
+        let path = format!("/v1/{}/{}", T::KIND, record.id());
+        let body =
+            serde_json::to_vec(record).map_err(|error| Error::Serialization(error.to_string()))?;
+        let reply = self.send(&path, &[], Some(body), self.revision_of(record.id()))?;
+        match reply.status {
+            200..=299 => {
+                let revision = reply.etag.unwrap_or_else(|| api::revision(record));
+                self.remember(record.id(), revision);
+                Ok(())
+            }
+            409 if self.holds(record)? => Ok(()),
+            _ => Err(reply_error(reply, &path)),
+        }
+    }
+
+    // whether the server's record is the one given, i.e: a retried write already saved it
+    fn holds<T: Record>(&self, record: &T) -> Result<bool, Error> {
+        let stored: Option<T> = self.get(record.id())?;
+        Ok(stored.is_some_and(|stored| api::revision(&stored) == api::revision(record)))
+    }
+
+    fn delete<T: Record>(&self, id: Uuid) -> Result<(), Error> {
+        let path = format!("/v1/{}/{}", T::KIND, id);
+        let reply = self.request("DELETE", &path, &[], None, None)?;
+        match reply.status {
+            200..=299 | 404 => {
+                self.revisions.lock().unwrap().remove(&id);
+                Ok(())
+            }
+            _ => Err(reply_error(reply, &path)),
+        }
+    }
+
+    // read every page of a list
+    fn list<T: Record>(&self, query: Vec<(&str, String)>) -> Result<Vec<T>, Error> {
+        let path = format!("/v1/{}", T::KIND);
+        let mut records = vec![];
+        let mut cursor: Option<String> = None;
+        let mut restarts = 0;
+        loop {
+            let mut page_query = query.clone();
+            page_query.push(("limit", api::DEFAULT_PAGE_SIZE.to_string()));
+            if let Some(cursor) = cursor {
+                page_query.push(("cursor", cursor));
+            }
+            let reply = self.send(&path, &page_query, None, None)?;
+            //the server forgot the rest of the list, read it again from the start
+            if reply.status == 410 && restarts < 1 {
+                restarts += 1;
+                records.clear();
+                cursor = None;
+                continue;
+            }
+            let page: Page<T> = self.parse(reply, &path)?;
+            for record in &page.items {
+                self.remember(record.id(), api::revision(record));
+            }
+            records.extend(page.items);
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => return Ok(records),
+            }
+        }
+    }
+}
+
+fn is_transient(error: &ureq::Error) -> bool {
+    matches!(
+        error,
+        ureq::Error::Io(_)
+            | ureq::Error::Timeout(_)
+            | ureq::Error::ConnectionFailed
+            | ureq::Error::HostNotFound
+    )
+}
+
+fn reply_error(reply: Reply, path: &str) -> Error {
+    let message = serde_json::from_slice::<ErrorBody>(&reply.body)
+        .map(|body| body.error)
+        .unwrap_or_else(|_| String::from_utf8_lossy(&reply.body).into_owned());
+    match reply.status {
+        401 | 403 => Error::Remote(format!("the server refused the token: {}", message)),
+        409 => Error::Conflict(format!("{}: {}", path, message)),
+        status => Error::Remote(format!("{} answered {}: {}", path, status, message)),
+    }
+}
+
+impl Storage for RemoteStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        self.put(case)
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        self.get(id)
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        self.list(vec![])
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        self.put(query)
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        self.get(id)
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        self.list(vec![("case_id", case_id.to_string())])
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        self.put(run)
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        self.get(id)
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        self.list(vec![("query_id", query_id.to_string())])
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        self.put(result)
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        self.get(id)
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        self.list(vec![("run_id", run_id.to_string())])
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        self.put(entity)
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        self.get(id)
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        self.list(vec![("case_id", case_id.to_string())])
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        self.put(note)
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        self.get(id)
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        self.list(vec![("case_id", case_id.to_string())])
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        let time =
+            |time: &chrono::DateTime<chrono::Utc>| time.to_rfc3339_opts(SecondsFormat::Nanos, true);
+        let query = [
+            ("case_id", filter.case_id.map(|id| id.to_string())),
+            ("value", filter.value.clone()),
+            ("engine", filter.engine.clone()),
+            ("from", filter.from.as_ref().map(time)),
+            ("to", filter.to.as_ref().map(time)),
+        ];
+        self.list(
+            query
+                .into_iter()
+                .filter_map(|(name, value)| value.map(|value| (name, value)))
+                .collect(),
+        )
+    }
+
+    fn save_run_with_results(&self, run: &Run, results: &[RunResult]) -> Result<(), Error> {
+        let path = format!("/v1/runs/{}/outcome", run.id);
+        let outcome = RunOutcome {
+            run: run.clone(),
+            results: results.to_vec(),
+        };
+        let body = serde_json::to_vec(&outcome)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        let reply = self.send(&path, &[], Some(body), self.revision_of(run.id))?;
+        match reply.status {
+            200..=299 => {}
+            409 if self.holds(run)? => {}
+            _ => return Err(reply_error(reply, &path)),
+        }
+        self.remember(run.id, api::revision(run));
+        for result in results {
+            self.remember(result.id, api::revision(result));
+        }
+        Ok(())
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        self.delete::<Run>(id)
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.delete::<Query>(id)
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.delete::<Entity>(id)
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        let path = "/v1/changes";
+        let body =
+            serde_json::to_vec(changes).map_err(|error| Error::Serialization(error.to_string()))?;
+        let reply = self.send(path, &[], Some(body), None)?;
+        match reply.status {
+            200..=299 => Ok(()),
+            _ => Err(reply_error(reply, path)),
+        }
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        let path = "/v1/changes";
+        let query = [
+            ("cursor", after.to_string()),
+            ("limit", limit.min(api::MAX_PAGE_SIZE).to_string()),
+        ];
+        let reply = self.send(path, &query, None, None)?;
+        let page: Page<Change> = self.parse(reply, path)?;
+        Ok(page.items)
+    }
+
+    //the server's storage is journaled, it erases the values of the records it deletes
+    fn redact_changes(&self, _record_ids: &[Uuid]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        let path = "/v1/evidence";
+        let body =
+            serde_json::to_vec(entry).map_err(|error| Error::Serialization(error.to_string()))?;
+        let reply = self.send(path, &[], Some(body), None)?;
+        match reply.status {
+            200..=299 => Ok(()),
+            _ => Err(reply_error(reply, path)),
+        }
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        let path = "/v1/evidence";
+        let query = [
+            ("cursor", after.to_string()),
+            ("limit", limit.min(api::MAX_PAGE_SIZE).to_string()),
+        ];
+        let reply = self.send(path, &query, None, None)?;
+        let page: Page<EvidenceEntry> = self.parse(reply, path)?;
+        Ok(page.items)
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        let path = "/v1/evidence/redacted";
+        let body = serde_json::to_vec(record_ids)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        let reply = self.send(path, &[], Some(body), None)?;
+        match reply.status {
+            200..=299 => Ok(()),
+            _ => Err(reply_error(reply, path)),
+        }
+    }
+}
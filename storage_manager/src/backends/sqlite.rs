@@ -0,0 +1,1564 @@
+/*
+SQLite Storage:
+The records are kept in an embedded SQLite database, one table per kind of record.
+
+The schema is versioned with `PRAGMA user_version`, every entry of `MIGRATIONS` moves the
+database one version up and is applied in its own transaction when the database is opened.
+Migrations are never edited once released, changes to the schema are new migrations.
+
+An encrypted database seals the fields that hold investigation data (case titles and tags,
+query texts, run errors and outputs, entity values and notes) with the storage key, the ids,
+engines, commands and timestamps stay in clear so the indexes keep working. Queries get a keyed
+digest of their text to be found by entity value. The `meta` table keeps what's needed to check the key.
+The values of the journal's changes are sealed as well, they hold the same data as the records,
+and so are the entries of the evidence log (their sequence number, record and hash stay in clear).
+
+Deleted records don't linger in the file: `secure_delete` overwrites them, and the write ahead
+log is checkpointed and truncated after every delete.
+ */
+use crate::crypto::{Cipher, KeySource, SALT_SIZE};
+use crate::evidence::check_append;
+use crate::{
+    crypto, Case, CaseStatus, Change, ChangeOrigin, Entity, EntityKind, Error, EvidenceEntry, Note,
+    Query, RetentionPolicy, Run, RunFilter, RunResult, RunStatus, Storage,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::{Type, Value};
+use rusqlite::{
+    params, Connection, OpenFlags, OptionalExtension, Row, ToSql, Transaction, TransactionBehavior,
+};
+use std::{path::Path, sync::Mutex, time::Duration};
+use uuid::Uuid;
+
+/// The schema migrations, `MIGRATIONS[n]` upgrades the database from version `n` to `n + 1`.
+const MIGRATIONS: &[&str] = &[
+    //v0 to v1: the initial schema
+    "CREATE TABLE cases (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE queries (
+        id TEXT PRIMARY KEY,
+        case_id TEXT NOT NULL,
+        text TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX queries_by_case ON queries (case_id, created_at);
+    CREATE INDEX queries_by_text ON queries (text);
+    CREATE TABLE runs (
+        id TEXT PRIMARY KEY,
+        case_id TEXT NOT NULL,
+        query_id TEXT NOT NULL,
+        engine TEXT NOT NULL,
+        command TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT,
+        status TEXT NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX runs_by_query ON runs (query_id, started_at);
+    CREATE INDEX runs_by_case ON runs (case_id, started_at);
+    CREATE INDEX runs_by_engine ON runs (engine, started_at);
+    CREATE INDEX runs_by_time ON runs (started_at);
+    CREATE TABLE results (
+        id TEXT PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        output TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX results_by_run ON results (run_id, created_at);",
+    //v1 to v2: encryption at rest
+    "CREATE TABLE meta (
+        name TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+    ALTER TABLE queries ADD COLUMN text_digest BLOB;
+    CREATE INDEX queries_by_digest ON queries (text_digest);",
+    //v2 to v3: the journal of changes
+    "CREATE TABLE changes (
+        seq INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        record_id TEXT NOT NULL,
+        field TEXT NOT NULL,
+        value BLOB NOT NULL,
+        at TEXT NOT NULL,
+        origin TEXT NOT NULL
+    );",
+    //v3 to v4: the case model
+    "ALTER TABLE cases ADD COLUMN status TEXT NOT NULL DEFAULT 'open';
+    ALTER TABLE cases ADD COLUMN owner TEXT;
+    ALTER TABLE cases ADD COLUMN tags BLOB;
+    CREATE TABLE entities (
+        id TEXT PRIMARY KEY,
+        case_id TEXT NOT NULL,
+        run_id TEXT,
+        kind TEXT NOT NULL,
+        value BLOB NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX entities_by_case ON entities (case_id, created_at);
+    CREATE TABLE notes (
+        id TEXT PRIMARY KEY,
+        case_id TEXT NOT NULL,
+        author TEXT,
+        text BLOB NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX notes_by_case ON notes (case_id, created_at);",
+    //v4 to v5: the files of the runs
+    "ALTER TABLE runs ADD COLUMN artifacts BLOB;",
+    //v5 to v6: the evidence log
+    "CREATE TABLE evidence (
+        seq INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        record_id TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        entry BLOB NOT NULL
+    );",
+    //v6 to v7: retention
+    "ALTER TABLE cases ADD COLUMN closed_at TEXT;
+    ALTER TABLE cases ADD COLUMN retention TEXT;
+    CREATE INDEX changes_by_record ON changes (record_id);",
+];
+
+/// How long a connection waits for another connection's write lock before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The value sealed in `meta` to check the key with.
+const KEY_CHECK: &[u8] = b"sherlock storage key";
+
+/// ## Description
+/// A storage backend on top of an embedded SQLite database, optionally encrypted.
+///
+/// The connection is guarded by a mutex so the storage can be shared between the UI and
+/// the worker threads, other processes (or other `SqliteStorage`s) can open the same file.
+pub struct SqliteStorage {
+    state: Mutex<State>,
+}
+
+struct State {
+    connection: Connection,
+    cipher: Option<Cipher>,
+    // changes with every key rotation, connections with an outdated key stop writing
+    key_id: Option<Vec<u8>>,
+}
+
+impl SqliteStorage {
+    /// ## Description
+    /// Opens (or creates) a database file and upgrades its schema to the latest version.
+    ///
+    /// Fails if the database is encrypted, use `open_encrypted` instead.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Storage, SqliteStorage};
+    /// let storage = SqliteStorage::open("./store.db").unwrap();
+    /// let cases = storage.list_cases().unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStorage, Error> {
+        SqliteStorage::init(open_file(path)?, None)
+    }
+
+    /// ## Description
+    /// Opens (or creates) an encrypted database file.
+    ///
+    /// A database that isn't encrypted yet is encrypted with the key,
+    /// fails with `Error::WrongKey` if the database was encrypted with another key.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{KeySource, SqliteStorage};
+    /// let key = KeySource::Passphrase("correct horse battery staple".into());
+    /// let storage = SqliteStorage::open_encrypted("./store.db", &key).unwrap();
+    /// ```
+    pub fn open_encrypted<P: AsRef<Path>>(
+        path: P,
+        key: &KeySource,
+    ) -> Result<SqliteStorage, Error> {
+        SqliteStorage::init(open_file(path)?, Some(key))
+    }
+
+    /// ## Description
+    /// Copies a database file to `to` as it is at this point, while it may be in use.
+    /// The copy is a single file, the write ahead log is folded into it.
+    ///
+    /// Fails if `to` already exists.
+    pub fn snapshot<P: AsRef<Path>, Q: AsRef<Path>>(path: P, to: Q) -> Result<(), Error> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(db_error)?;
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        let to = to.as_ref().to_string_lossy();
+        connection
+            .execute("VACUUM INTO ?1", params![to])
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Opens a database that only lives in memory, used for tests.
+    pub fn open_in_memory() -> Result<SqliteStorage, Error> {
+        SqliteStorage::init(Connection::open_in_memory().map_err(db_error)?, None)
+    }
+
+    fn init(mut connection: Connection, key: Option<&KeySource>) -> Result<SqliteStorage, Error> {
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        //deleted and replaced records are overwritten instead of lingering in free pages
+        connection
+            .pragma_update(None, "secure_delete", true)
+            .map_err(db_error)?;
+        migrate(&mut connection)?;
+
+        let key_check = read_meta(&connection, "key_check").map_err(db_error)?;
+        let storage = match (key_check, key) {
+            (None, None) => SqliteStorage::with_state(connection, None, None),
+            (Some(_), None) => {
+                return Err(Error::Crypto(
+                    "the storage is encrypted, a key is needed to open it".into(),
+                ))
+            }
+            (Some(key_check), Some(key)) => {
+                let salt = read_meta(&connection, "salt").map_err(db_error)?;
+                let cipher = Cipher::new(key, salt.as_deref())?;
+                match cipher.open(&key_check, b"meta/key_check") {
+                    Ok(check) if check == KEY_CHECK => {}
+                    _ => return Err(Error::WrongKey),
+                }
+                let key_id = read_meta(&connection, "key_id").map_err(db_error)?;
+                SqliteStorage::with_state(connection, Some(cipher), key_id)
+            }
+            //a new or a plain database, encrypt what's already in it
+            (None, Some(key)) => {
+                let storage = SqliteStorage::with_state(connection, None, None);
+                storage.rotate_key(Some(key))?;
+                storage
+            }
+        };
+        Ok(storage)
+    }
+
+    fn with_state(
+        connection: Connection,
+        cipher: Option<Cipher>,
+        key_id: Option<Vec<u8>>,
+    ) -> SqliteStorage {
+        SqliteStorage {
+            state: Mutex::new(State {
+                connection,
+                cipher,
+                key_id,
+            }),
+        }
+    }
+
+    /// Gets the schema version of the database.
+    pub fn get_schema_version(&self) -> Result<usize, Error> {
+        schema_version(&self.state.lock().unwrap().connection)
+    }
+
+    /// Checks whether the database is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.state.lock().unwrap().cipher.is_some()
+    }
+
+    /// ## Description
+    /// Re-encrypts every record with a new key, `None` decrypts the database.
+    ///
+    /// The records are rewritten in a single transaction, then the database is compacted so
+    /// nothing sealed with the old key (or left in clear) stays in the file.
+    /// Other connections to the database must reopen it with the new key.
+    /// The storage of a `StorageManager` is rotated with `StorageManager::rotate_key`,
+    /// the key of the runs' files is rotated with it.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{KeySource, SqliteStorage};
+    /// let old = KeySource::Passphrase("old passphrase".into());
+    /// let storage = SqliteStorage::open_encrypted("./store.db", &old).unwrap();
+    /// let new = KeySource::Passphrase("new passphrase".into());
+    /// storage.rotate_key(Some(&new)).unwrap();
+    /// ```
+    pub fn rotate_key(&self, key: Option<&KeySource>) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let (salt, cipher) = match key {
+            Some(key @ KeySource::Passphrase(_)) => {
+                let salt = crypto::random_bytes(SALT_SIZE);
+                let cipher = Cipher::new(key, Some(&salt))?;
+                (Some(salt), Some(cipher))
+            }
+            Some(key) => (None, Some(Cipher::new(key, None)?)),
+            None => (None, None),
+        };
+        let key_id = cipher.as_ref().map(|_| crypto::random_bytes(16));
+
+        let State {
+            connection,
+            cipher: old,
+            key_id: old_key_id,
+        } = &mut *state;
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        check_key_id(&transaction, old_key_id)?;
+
+        //read everything with the old key, write it back with the new one
+        let cases = select(
+            &transaction,
+            "SELECT * FROM cases",
+            &[],
+            old.as_ref(),
+            read_case,
+        )?;
+        let queries = select(
+            &transaction,
+            "SELECT * FROM queries",
+            &[],
+            old.as_ref(),
+            read_query,
+        )?;
+        let runs = select(
+            &transaction,
+            "SELECT * FROM runs",
+            &[],
+            old.as_ref(),
+            read_run,
+        )?;
+        let results = select(
+            &transaction,
+            "SELECT * FROM results",
+            &[],
+            old.as_ref(),
+            read_result,
+        )?;
+        let entities = select(
+            &transaction,
+            "SELECT * FROM entities",
+            &[],
+            old.as_ref(),
+            read_entity,
+        )?;
+        let notes = select(
+            &transaction,
+            "SELECT * FROM notes",
+            &[],
+            old.as_ref(),
+            read_note,
+        )?;
+        let changes = select(
+            &transaction,
+            "SELECT * FROM changes",
+            &[],
+            old.as_ref(),
+            read_change,
+        )?;
+        let evidence = select(
+            &transaction,
+            "SELECT * FROM evidence",
+            &[],
+            old.as_ref(),
+            read_evidence,
+        )?;
+        let rewrite = || -> rusqlite::Result<()> {
+            for case in &cases {
+                write_case(&transaction, cipher.as_ref(), case)?;
+            }
+            for query in &queries {
+                write_query(&transaction, cipher.as_ref(), query)?;
+            }
+            for run in &runs {
+                write_run(&transaction, cipher.as_ref(), run)?;
+            }
+            for result in &results {
+                write_result(&transaction, cipher.as_ref(), result)?;
+            }
+            for entity in &entities {
+                write_entity(&transaction, cipher.as_ref(), entity)?;
+            }
+            for note in &notes {
+                write_note(&transaction, cipher.as_ref(), note)?;
+            }
+            for change in &changes {
+                write_change(&transaction, cipher.as_ref(), change)?;
+            }
+            for entry in &evidence {
+                write_evidence(&transaction, cipher.as_ref(), entry)?;
+            }
+
+            transaction.execute("DELETE FROM meta", [])?;
+            if let (Some(cipher), Some(key_id)) = (&cipher, &key_id) {
+                write_meta(
+                    &transaction,
+                    "key_check",
+                    &cipher.seal(KEY_CHECK, b"meta/key_check"),
+                )?;
+                write_meta(&transaction, "key_id", key_id)?;
+            }
+            if let Some(salt) = &salt {
+                write_meta(&transaction, "salt", salt)?;
+            }
+            Ok(())
+        };
+        rewrite().map_err(db_error)?;
+        transaction.commit().map_err(db_error)?;
+        *old = cipher;
+        *old_key_id = key_id;
+
+        //drop the free pages and the write ahead log that hold the old content
+        connection.execute_batch("VACUUM").map_err(db_error)?;
+        connection
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(db_error)
+    }
+
+    // run `write` in a transaction, nothing is saved if it fails
+    // the write lock is taken upfront, a read lock can't be upgraded while another connection writes
+    fn transaction<F>(&self, write: F) -> Result<(), Error>
+    where
+        F: FnOnce(&Transaction, Option<&Cipher>) -> Result<(), rusqlite::Error>,
+    {
+        let mut state = self.state.lock().unwrap();
+        let State {
+            connection,
+            cipher,
+            key_id,
+        } = &mut *state;
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        check_key_id(&transaction, key_id)?;
+        write(&transaction, cipher.as_ref()).map_err(db_error)?;
+        transaction.commit().map_err(db_error)
+    }
+
+    // move the write ahead log into the database and truncate it, deleted content lingers there
+    fn checkpoint(&self) -> Result<(), Error> {
+        let state = self.state.lock().unwrap();
+        state
+            .connection
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(db_error)
+    }
+
+    fn query_all<T>(
+        &self,
+        sql: &str,
+        params: &[&dyn ToSql],
+        read: fn(&Row, Option<&Cipher>) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, Error> {
+        let state = self.state.lock().unwrap();
+        select(&state.connection, sql, params, state.cipher.as_ref(), read)
+    }
+
+    fn query_one<T>(
+        &self,
+        sql: &str,
+        id: Uuid,
+        read: fn(&Row, Option<&Cipher>) -> rusqlite::Result<T>,
+    ) -> Result<Option<T>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut statement = state.connection.prepare_cached(sql).map_err(db_error)?;
+        statement
+            .query_row(params![id.to_string()], |row| {
+                read(row, state.cipher.as_ref())
+            })
+            .optional()
+            .map_err(db_error)
+    }
+}
+
+fn open_file<P: AsRef<Path>>(path: P) -> Result<Connection, Error> {
+    let connection = Connection::open(path).map_err(db_error)?;
+    //write ahead logging lets readers work while another thread or process writes
+    connection
+        .pragma_update(None, "journal_mode", "WAL")
+        .map_err(db_error)?;
+    Ok(connection)
+}
+
+// crypto errors are carried through rusqlite's row conversion errors
+fn db_error(error: rusqlite::Error) -> Error {
+    if let rusqlite::Error::FromSqlConversionFailure(_, _, inner) = &error {
+        if let Some(Error::Crypto(error)) = inner.downcast_ref::<Error>() {
+            return Error::Crypto(error.clone());
+        }
+    }
+    Error::Database(error.to_string())
+}
+
+fn crypto_error(error: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(0, Type::Blob, Box::new(Error::Crypto(error.into())))
+}
+
+fn schema_version(connection: &Connection) -> Result<usize, Error> {
+    connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error)
+}
+
+// apply the migrations the database is missing, each one in its own transaction
+fn migrate(connection: &mut Connection) -> Result<(), Error> {
+    let version = schema_version(connection)?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::Database(format!(
+            "the database schema (v{}) is newer than this version of sherlock supports (v{})",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction().map_err(db_error)?;
+        transaction.execute_batch(migration).map_err(db_error)?;
+        transaction
+            .pragma_update(None, "user_version", from + 1)
+            .map_err(db_error)?;
+        transaction.commit().map_err(db_error)?;
+    }
+    Ok(())
+}
+
+fn read_meta(connection: &Connection, name: &str) -> rusqlite::Result<Option<Vec<u8>>> {
+    connection
+        .query_row("SELECT value FROM meta WHERE name = ?1", [name], |row| {
+            row.get(0)
+        })
+        .optional()
+}
+
+fn write_meta(connection: &Connection, name: &str, value: &[u8]) -> rusqlite::Result<()> {
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO meta (name, value) VALUES (?1, ?2)",
+            params![name, value],
+        )
+        .map(|_| ())
+}
+
+// refuse to write if another connection changed the key since this one was opened
+fn check_key_id(connection: &Connection, key_id: &Option<Vec<u8>>) -> Result<(), Error> {
+    if &read_meta(connection, "key_id").map_err(db_error)? != key_id {
+        return Err(Error::WrongKey);
+    }
+    Ok(())
+}
+
+fn select<T>(
+    connection: &Connection,
+    sql: &str,
+    params: &[&dyn ToSql],
+    cipher: Option<&Cipher>,
+    read: fn(&Row, Option<&Cipher>) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, Error> {
+    let mut statement = connection.prepare_cached(sql).map_err(db_error)?;
+    let rows = statement
+        .query_map(params, |row| read(row, cipher))
+        .map_err(db_error)?;
+    rows.collect::<Result<_, _>>().map_err(db_error)
+}
+
+// timestamps are stored with a fixed width so their text order is their time order
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+// the authenticated context of a sealed field, binds it to its record and column
+fn context(table: &str, column: &str, id: impl std::fmt::Display) -> Vec<u8> {
+    format!("{}/{}/{}", table, column, id).into_bytes()
+}
+
+// seal a field if the database is encrypted
+fn seal(
+    cipher: Option<&Cipher>,
+    table: &str,
+    column: &str,
+    id: impl std::fmt::Display,
+    text: &str,
+) -> Value {
+    match cipher {
+        Some(cipher) => Value::Blob(cipher.seal(text.as_bytes(), &context(table, column, id))),
+        None => Value::Text(text.to_owned()),
+    }
+}
+
+fn unseal(
+    row: &Row,
+    cipher: Option<&Cipher>,
+    table: &str,
+    column: &str,
+    id: impl std::fmt::Display + Copy,
+) -> rusqlite::Result<Option<String>> {
+    match (row.get::<_, Value>(column)?, cipher) {
+        (Value::Null, _) => Ok(None),
+        (Value::Text(text), None) => Ok(Some(text)),
+        (Value::Blob(sealed), Some(cipher)) => {
+            let plaintext = cipher
+                .open(&sealed, &context(table, column, id))
+                .map_err(|_| {
+                    crypto_error(&format!("can't decrypt {}.{} of {}", table, column, id))
+                })?;
+            String::from_utf8(plaintext)
+                .map(Some)
+                .map_err(|_| crypto_error(&format!("{}.{} of {} isn't text", table, column, id)))
+        }
+        (_, Some(_)) => Err(crypto_error(&format!(
+            "{}.{} of {} isn't encrypted",
+            table, column, id
+        ))),
+        (_, None) => Err(crypto_error(&format!(
+            "{}.{} of {} is encrypted",
+            table, column, id
+        ))),
+    }
+}
+
+fn read_uuid(row: &Row, column: &str) -> rusqlite::Result<Uuid> {
+    let text: String = row.get(column)?;
+    Uuid::parse_str(&text)
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error)))
+}
+
+fn read_time(row: &Row, column: &str) -> rusqlite::Result<DateTime<Utc>> {
+    let text: String = row.get(column)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error)))
+}
+
+fn read_case(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Case> {
+    let id = read_uuid(row, "id")?;
+    let status: String = row.get("status")?;
+    let tags = match unseal(row, cipher, "cases", "tags", id)? {
+        Some(tags) => serde_json::from_str(&tags).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error))
+        })?,
+        None => vec![],
+    };
+    let closed_at: Option<String> = row.get("closed_at")?;
+    let retention: Option<String> = row.get("retention")?;
+    Ok(Case {
+        id,
+        title: unseal(row, cipher, "cases", "title", id)?.unwrap_or_default(),
+        status: match status.as_str() {
+            "closed" => CaseStatus::Closed,
+            "archived" => CaseStatus::Archived,
+            _ => CaseStatus::Open,
+        },
+        owner: row.get("owner")?,
+        tags,
+        created_at: read_time(row, "created_at")?,
+        closed_at: match closed_at {
+            Some(_) => Some(read_time(row, "closed_at")?),
+            None => None,
+        },
+        retention: retention
+            .map(|retention| serde_json::from_str::<RetentionPolicy>(&retention))
+            .transpose()
+            .map_err(|error| {
+                rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error))
+            })?,
+    })
+}
+
+fn read_query(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Query> {
+    let id = read_uuid(row, "id")?;
+    Ok(Query {
+        id,
+        case_id: read_uuid(row, "case_id")?,
+        text: unseal(row, cipher, "queries", "text", id)?.unwrap_or_default(),
+        created_at: read_time(row, "created_at")?,
+    })
+}
+
+fn read_run(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Run> {
+    let id = read_uuid(row, "id")?;
+    let status: String = row.get("status")?;
+    let status = match status.as_str() {
+        "running" => RunStatus::Running,
+        "succeeded" => RunStatus::Succeeded,
+        _ => RunStatus::Failed(unseal(row, cipher, "runs", "error", id)?.unwrap_or_default()),
+    };
+    let finished_at: Option<String> = row.get("finished_at")?;
+    let artifacts = match unseal(row, cipher, "runs", "artifacts", id)? {
+        Some(artifacts) => serde_json::from_str(&artifacts).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error))
+        })?,
+        None => vec![],
+    };
+    Ok(Run {
+        id,
+        case_id: read_uuid(row, "case_id")?,
+        query_id: read_uuid(row, "query_id")?,
+        engine: row.get("engine")?,
+        command: row.get("command")?,
+        started_at: read_time(row, "started_at")?,
+        finished_at: match finished_at {
+            Some(_) => Some(read_time(row, "finished_at")?),
+            None => None,
+        },
+        status,
+        artifacts,
+    })
+}
+
+fn read_result(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<RunResult> {
+    let id = read_uuid(row, "id")?;
+    Ok(RunResult {
+        id,
+        run_id: read_uuid(row, "run_id")?,
+        output: unseal(row, cipher, "results", "output", id)?.unwrap_or_default(),
+        created_at: read_time(row, "created_at")?,
+    })
+}
+
+fn read_entity(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Entity> {
+    let id = read_uuid(row, "id")?;
+    let run_id: Option<String> = row.get("run_id")?;
+    let kind: String = row.get("kind")?;
+    Ok(Entity {
+        id,
+        case_id: read_uuid(row, "case_id")?,
+        run_id: match run_id {
+            Some(_) => Some(read_uuid(row, "run_id")?),
+            None => None,
+        },
+        kind: match kind.as_str() {
+            "email" => EntityKind::Email,
+            "phone" => EntityKind::Phone,
+            "url" => EntityKind::Url,
+            "username" => EntityKind::Username,
+            _ => EntityKind::Other,
+        },
+        value: unseal(row, cipher, "entities", "value", id)?.unwrap_or_default(),
+        created_at: read_time(row, "created_at")?,
+    })
+}
+
+fn read_note(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Note> {
+    let id = read_uuid(row, "id")?;
+    Ok(Note {
+        id,
+        case_id: read_uuid(row, "case_id")?,
+        author: row.get("author")?,
+        text: unseal(row, cipher, "notes", "text", id)?.unwrap_or_default(),
+        created_at: read_time(row, "created_at")?,
+        updated_at: read_time(row, "updated_at")?,
+    })
+}
+
+fn read_change(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<Change> {
+    let seq: i64 = row.get("seq")?;
+    let value = unseal(row, cipher, "changes", "value", seq)?.unwrap_or_default();
+    let origin: String = row.get("origin")?;
+    Ok(Change {
+        seq: seq as u64,
+        kind: row.get("kind")?,
+        record_id: read_uuid(row, "record_id")?,
+        field: row.get("field")?,
+        value: serde_json::from_str(&value).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error))
+        })?,
+        at: read_time(row, "at")?,
+        origin: match origin.as_str() {
+            "local" => ChangeOrigin::Local,
+            _ => ChangeOrigin::Remote,
+        },
+    })
+}
+
+fn read_evidence(row: &Row, cipher: Option<&Cipher>) -> rusqlite::Result<EvidenceEntry> {
+    let seq: i64 = row.get("seq")?;
+    let entry = unseal(row, cipher, "evidence", "entry", seq)?.unwrap_or_default();
+    serde_json::from_str(&entry)
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error)))
+}
+
+fn write_case(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    case: &Case,
+) -> rusqlite::Result<()> {
+    let status = match case.status {
+        CaseStatus::Open => "open",
+        CaseStatus::Closed => "closed",
+        CaseStatus::Archived => "archived",
+    };
+    let tags = serde_json::to_string(&case.tags).unwrap_or_default();
+    let retention = case
+        .retention
+        .as_ref()
+        .map(|retention| serde_json::to_string(retention).unwrap_or_default());
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO cases
+             (id, title, status, owner, tags, created_at, closed_at, retention)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?
+        .execute(params![
+            case.id.to_string(),
+            seal(cipher, "cases", "title", case.id, &case.title),
+            status,
+            case.owner,
+            seal(cipher, "cases", "tags", case.id, &tags),
+            timestamp(&case.created_at),
+            case.closed_at.as_ref().map(timestamp),
+            retention,
+        ])
+        .map(|_| ())
+}
+
+fn write_query(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    query: &Query,
+) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO queries (id, case_id, text, text_digest, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?
+        .execute(params![
+            query.id.to_string(),
+            query.case_id.to_string(),
+            seal(cipher, "queries", "text", query.id, &query.text),
+            cipher.map(|cipher| cipher.digest(query.text.as_bytes())),
+            timestamp(&query.created_at)
+        ])
+        .map(|_| ())
+}
+
+fn write_run(connection: &Connection, cipher: Option<&Cipher>, run: &Run) -> rusqlite::Result<()> {
+    let (status, error) = match &run.status {
+        RunStatus::Running => ("running", Value::Null),
+        RunStatus::Succeeded => ("succeeded", Value::Null),
+        RunStatus::Failed(error) => ("failed", seal(cipher, "runs", "error", run.id, error)),
+    };
+    let artifacts = match run.artifacts.is_empty() {
+        true => Value::Null,
+        false => {
+            let artifacts = serde_json::to_string(&run.artifacts).unwrap_or_default();
+            seal(cipher, "runs", "artifacts", run.id, &artifacts)
+        }
+    };
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO runs
+             (id, case_id, query_id, engine, command, started_at, finished_at, status, error,
+              artifacts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?
+        .execute(params![
+            run.id.to_string(),
+            run.case_id.to_string(),
+            run.query_id.to_string(),
+            run.engine,
+            run.command,
+            timestamp(&run.started_at),
+            run.finished_at.as_ref().map(timestamp),
+            status,
+            error,
+            artifacts,
+        ])
+        .map(|_| ())
+}
+
+fn write_result(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    result: &RunResult,
+) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO results (id, run_id, output, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?
+        .execute(params![
+            result.id.to_string(),
+            result.run_id.to_string(),
+            seal(cipher, "results", "output", result.id, &result.output),
+            timestamp(&result.created_at),
+        ])
+        .map(|_| ())
+}
+
+fn write_entity(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    entity: &Entity,
+) -> rusqlite::Result<()> {
+    let kind = match entity.kind {
+        EntityKind::Email => "email",
+        EntityKind::Phone => "phone",
+        EntityKind::Url => "url",
+        EntityKind::Username => "username",
+        EntityKind::Other => "other",
+    };
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO entities (id, case_id, run_id, kind, value, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?
+        .execute(params![
+            entity.id.to_string(),
+            entity.case_id.to_string(),
+            entity.run_id.map(|id| id.to_string()),
+            kind,
+            seal(cipher, "entities", "value", entity.id, &entity.value),
+            timestamp(&entity.created_at),
+        ])
+        .map(|_| ())
+}
+
+fn write_note(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    note: &Note,
+) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO notes (id, case_id, author, text, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?
+        .execute(params![
+            note.id.to_string(),
+            note.case_id.to_string(),
+            note.author,
+            seal(cipher, "notes", "text", note.id, &note.text),
+            timestamp(&note.created_at),
+            timestamp(&note.updated_at),
+        ])
+        .map(|_| ())
+}
+
+fn write_change(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    change: &Change,
+) -> rusqlite::Result<()> {
+    let origin = match change.origin {
+        ChangeOrigin::Local => "local",
+        ChangeOrigin::Remote => "remote",
+    };
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO changes (seq, kind, record_id, field, value, at, origin)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?
+        .execute(params![
+            change.seq as i64,
+            change.kind,
+            change.record_id.to_string(),
+            change.field,
+            seal(
+                cipher,
+                "changes",
+                "value",
+                change.seq,
+                &change.value.to_string()
+            ),
+            timestamp(&change.at),
+            origin,
+        ])
+        .map(|_| ())
+}
+
+fn write_evidence(
+    connection: &Connection,
+    cipher: Option<&Cipher>,
+    entry: &EvidenceEntry,
+) -> rusqlite::Result<()> {
+    let json = serde_json::to_string(entry).unwrap_or_default();
+    connection
+        .prepare_cached(
+            "INSERT OR REPLACE INTO evidence (seq, kind, record_id, hash, entry)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?
+        .execute(params![
+            entry.seq as i64,
+            entry.kind,
+            entry.record_id.to_string(),
+            entry.hash,
+            seal(cipher, "evidence", "entry", entry.seq, &json),
+        ])
+        .map(|_| ())
+}
+
+impl Storage for SqliteStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_case(connection, cipher, case))
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        self.query_one("SELECT * FROM cases WHERE id = ?1", id, read_case)
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        self.query_all(
+            "SELECT * FROM cases ORDER BY created_at, rowid",
+            &[],
+            read_case,
+        )
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_query(connection, cipher, query))
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        self.query_one("SELECT * FROM queries WHERE id = ?1", id, read_query)
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        self.query_all(
+            "SELECT * FROM queries WHERE case_id = ?1 ORDER BY created_at, rowid",
+            &[&case_id.to_string()],
+            read_query,
+        )
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_run(connection, cipher, run))
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        self.query_one("SELECT * FROM runs WHERE id = ?1", id, read_run)
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        self.query_all(
+            "SELECT * FROM runs WHERE query_id = ?1 ORDER BY started_at, rowid",
+            &[&query_id.to_string()],
+            read_run,
+        )
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_result(connection, cipher, result))
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        self.query_one("SELECT * FROM results WHERE id = ?1", id, read_result)
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        self.query_all(
+            "SELECT * FROM results WHERE run_id = ?1 ORDER BY created_at, rowid",
+            &[&run_id.to_string()],
+            read_result,
+        )
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_entity(connection, cipher, entity))
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        self.query_one("SELECT * FROM entities WHERE id = ?1", id, read_entity)
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        self.query_all(
+            "SELECT * FROM entities WHERE case_id = ?1 ORDER BY created_at, rowid",
+            &[&case_id.to_string()],
+            read_entity,
+        )
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        self.transaction(|connection, cipher| write_note(connection, cipher, note))
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        self.query_one("SELECT * FROM notes WHERE id = ?1", id, read_note)
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        self.query_all(
+            "SELECT * FROM notes WHERE case_id = ?1 ORDER BY created_at, rowid",
+            &[&case_id.to_string()],
+            read_note,
+        )
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        //sealed query texts are looked up by their digest
+        let digest = {
+            let state = self.state.lock().unwrap();
+            match (&state.cipher, &filter.value) {
+                (Some(cipher), Some(value)) => Some(cipher.digest(value.as_bytes())),
+                _ => None,
+            }
+        };
+        let (sql, values) = runs_query(filter, digest);
+        let params: Vec<&dyn ToSql> = values.iter().map(|value| value as &dyn ToSql).collect();
+        self.query_all(&sql, &params, read_run)
+    }
+
+    fn save_run_with_results(&self, run: &Run, results: &[RunResult]) -> Result<(), Error> {
+        self.transaction(|connection, cipher| {
+            for result in results {
+                write_result(connection, cipher, result)?;
+            }
+            write_run(connection, cipher, run)
+        })
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        self.transaction(|connection, _| {
+            connection.execute(
+                "DELETE FROM results WHERE run_id = ?1",
+                params![id.to_string()],
+            )?;
+            connection
+                .execute("DELETE FROM runs WHERE id = ?1", params![id.to_string()])
+                .map(|_| ())
+        })?;
+        self.checkpoint()
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.transaction(|connection, _| {
+            connection
+                .execute("DELETE FROM queries WHERE id = ?1", params![id.to_string()])
+                .map(|_| ())
+        })?;
+        self.checkpoint()
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.transaction(|connection, _| {
+            connection
+                .execute(
+                    "DELETE FROM entities WHERE id = ?1",
+                    params![id.to_string()],
+                )
+                .map(|_| ())
+        })?;
+        self.checkpoint()
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        self.transaction(|connection, cipher| {
+            for change in changes {
+                write_change(connection, cipher, change)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        self.query_all(
+            "SELECT * FROM changes WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+            &[&(after as i64), &(limit as i64)],
+            read_change,
+        )
+    }
+
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.transaction(|connection, cipher| {
+            for id in record_ids {
+                let changes = connection
+                    .prepare_cached("SELECT * FROM changes WHERE record_id = ?1")?
+                    .query_map(params![id.to_string()], |row| read_change(row, cipher))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                //the replaced rows are overwritten, see `secure_delete`
+                for mut change in changes {
+                    change.value = serde_json::Value::Null;
+                    write_change(connection, cipher, &change)?;
+                }
+            }
+            Ok(())
+        })?;
+        self.checkpoint()
+    }
+
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        let mut refused = None;
+        self.transaction(|connection, cipher| {
+            let last_seq: i64 =
+                connection.query_row("SELECT COALESCE(MAX(seq), 0) FROM evidence", [], |row| {
+                    row.get(0)
+                })?;
+            let existing = connection
+                .prepare_cached("SELECT * FROM evidence WHERE seq = ?1")?
+                .query_row(params![entry.seq as i64], |row| read_evidence(row, cipher))
+                .optional()?;
+            match check_append(entry, last_seq as u64, existing.as_ref()) {
+                Ok(true) => write_evidence(connection, cipher, entry),
+                Ok(false) => Ok(()),
+                Err(error) => {
+                    refused = Some(error);
+                    Ok(())
+                }
+            }
+        })?;
+        refused.map_or(Ok(()), Err)
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        self.query_all(
+            "SELECT * FROM evidence WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+            &[&(after as i64), &(limit as i64)],
+            read_evidence,
+        )
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.transaction(|connection, cipher| {
+            for id in record_ids {
+                let entries = connection
+                    .prepare_cached("SELECT * FROM evidence WHERE record_id = ?1")?
+                    .query_map(params![id.to_string()], |row| read_evidence(row, cipher))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                //the replaced rows are overwritten, see `secure_delete`
+                for mut entry in entries.into_iter().filter(|entry| !entry.redacted) {
+                    entry.redact();
+                    write_evidence(connection, cipher, &entry)?;
+                }
+            }
+            Ok(())
+        })?;
+        self.checkpoint()
+    }
+}
+
+// build the query of `find_runs`, only the set fields add a condition so the planner can use
+// their indexes
+fn runs_query(filter: &RunFilter, digest: Option<Vec<u8>>) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    if let Some(case_id) = filter.case_id {
+        conditions.push("runs.case_id = ?");
+        values.push(Value::Text(case_id.to_string()));
+    }
+    if let Some(value) = &filter.value {
+        values.push(Value::Text(value.clone()));
+        match digest {
+            Some(digest) => {
+                conditions.push(
+                    "runs.query_id IN (SELECT id FROM queries WHERE text = ? OR text_digest = ?)",
+                );
+                values.push(Value::Blob(digest));
+            }
+            None => conditions.push("runs.query_id IN (SELECT id FROM queries WHERE text = ?)"),
+        }
+    }
+    if let Some(engine) = &filter.engine {
+        conditions.push("runs.engine = ?");
+        values.push(Value::Text(engine.clone()));
+    }
+    if let Some(from) = &filter.from {
+        conditions.push("runs.started_at >= ?");
+        values.push(Value::Text(timestamp(from)));
+    }
+    if let Some(to) = &filter.to {
+        conditions.push("runs.started_at < ?");
+        values.push(Value::Text(timestamp(to)));
+    }
+    let mut sql = "SELECT runs.* FROM runs".to_owned();
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    sql.push_str(" ORDER BY runs.started_at, runs.rowid");
+    (sql, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf, sync::Arc, thread};
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("store.db")
+    }
+
+    // every byte the database left on disk, the write ahead log included
+    fn disk_content(path: &Path) -> Vec<u8> {
+        let mut content = fs::read(path).unwrap();
+        content.extend(fs::read(path.with_extension("db-wal")).unwrap_or_default());
+        content
+    }
+
+    fn contains(content: &[u8], text: &str) -> bool {
+        content
+            .windows(text.len())
+            .any(|window| window == text.as_bytes())
+    }
+
+    #[test]
+    fn sqlite_backend() {
+        crate::storage::tests::check_backend(&SqliteStorage::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn encrypted_sqlite_backend() {
+        let path = temp_db("sherlock_sqlite_encrypted_backend_test");
+        let key = KeySource::Key(crypto::random_bytes(crypto::KEY_SIZE));
+        let storage = SqliteStorage::open_encrypted(&path, &key).unwrap();
+        assert!(storage.is_encrypted());
+        crate::storage::tests::check_backend(&storage);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn find_runs_uses_the_indexes() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let plan = |filter: &RunFilter| {
+            let (sql, values) = runs_query(filter, None);
+            let params: Vec<&dyn ToSql> = values.iter().map(|value| value as &dyn ToSql).collect();
+            let state = storage.state.lock().unwrap();
+            let mut statement = state
+                .connection
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+                .unwrap();
+            let rows = statement
+                .query_map(&params[..], |row| row.get::<_, String>(3))
+                .unwrap();
+            rows.map(Result::unwrap).collect::<Vec<_>>().join("\n")
+        };
+
+        let by_engine = RunFilter {
+            engine: Some("facebook".into()),
+            ..Default::default()
+        };
+        assert!(plan(&by_engine).contains("runs_by_engine"));
+        let by_case = RunFilter {
+            case_id: Some(Uuid::new_v4()),
+            ..Default::default()
+        };
+        assert!(plan(&by_case).contains("runs_by_case"));
+        let by_value = RunFilter {
+            value: Some("user123".into()),
+            ..Default::default()
+        };
+        assert!(plan(&by_value).contains("queries_by_text"));
+    }
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let path = temp_db("sherlock_sqlite_migrations_test");
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.get_schema_version().unwrap(), MIGRATIONS.len());
+        let case = Case::new("kept");
+        storage.save_case(&case).unwrap();
+        drop(storage);
+
+        //reopening keeps the records
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.list_cases().unwrap(), vec![case]);
+        drop(storage);
+
+        //a database from a newer version is refused
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(connection);
+        assert!(matches!(
+            SqliteStorage::open(&path),
+            Err(Error::Database(_))
+        ));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn no_plaintext_on_disk() {
+        let path = temp_db("sherlock_sqlite_plaintext_test");
+        let key = KeySource::Passphrase("secret".into());
+        let storage = SqliteStorage::open_encrypted(&path, &key).unwrap();
+        let mut case = Case::new("operation-nightjar");
+        case.tags = vec!["smuggling-ring".into()];
+        storage.save_case(&case).unwrap();
+        storage
+            .save_note(&Note::new(case.id, None, "met the informant"))
+            .unwrap();
+        storage
+            .save_entity(&Entity::new(case.id, None, EntityKind::Phone, "+15550100"))
+            .unwrap();
+        let query = Query::new(case.id, "jane.doe@example.com");
+        storage.save_query(&query).unwrap();
+        let mut run = Run::start(&query, "facebook", "user");
+        run.finish(Ok(()));
+        let output = "jane.doe lives at 221B Baker Street";
+        storage
+            .save_run_with_results(&run, &[RunResult::new(run.id, output)])
+            .unwrap();
+        let change = Change {
+            seq: 1,
+            kind: "cases".into(),
+            record_id: case.id,
+            field: "title".into(),
+            value: serde_json::json!("operation-nightjar"),
+            at: Utc::now(),
+            origin: ChangeOrigin::Local,
+        };
+        storage
+            .append_changes(std::slice::from_ref(&change))
+            .unwrap();
+        let provenance = crate::Provenance {
+            argv: vec!["facebook.py".into(), "jane.doe@example.com".into()],
+            ..Default::default()
+        };
+        let mut entry = EvidenceEntry::for_run(&run, &provenance);
+        entry.link(None);
+        storage.append_evidence(&entry).unwrap();
+
+        //the entity value is still found
+        let filter = RunFilter {
+            value: Some("jane.doe@example.com".into()),
+            ..Default::default()
+        };
+        assert_eq!(storage.find_runs(&filter).unwrap(), vec![run.clone()]);
+
+        let content = disk_content(&path);
+        for secret in [
+            "operation-nightjar",
+            "smuggling-ring",
+            "met the informant",
+            "+15550100",
+            "jane.doe@example.com",
+            "221B Baker Street",
+        ] {
+            assert!(!contains(&content, secret), "{} found on disk", secret);
+        }
+        //while the engine names stay searchable
+        assert!(contains(&content, "facebook"));
+        drop(storage);
+
+        //the wrong key is refused, the right one reads everything back
+        assert!(matches!(
+            SqliteStorage::open_encrypted(&path, &KeySource::Passphrase("wrong".into())),
+            Err(Error::WrongKey)
+        ));
+        assert!(matches!(SqliteStorage::open(&path), Err(Error::Crypto(_))));
+        let storage = SqliteStorage::open_encrypted(&path, &key).unwrap();
+        assert_eq!(storage.list_results(run.id).unwrap()[0].output, output);
+        assert_eq!(storage.list_changes(0, 10).unwrap(), vec![change]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn deletes_leave_nothing_on_disk() {
+        let path = temp_db("sherlock_sqlite_delete_test");
+        let storage = SqliteStorage::open(&path).unwrap();
+        let query = Query::new(Case::new("case").id, "user123");
+        let mut run = Run::start(&query, "facebook", "user");
+        run.finish(Ok(()));
+        let result = RunResult::new(run.id, "jane.doe lives at 221B Baker Street");
+        storage
+            .save_run_with_results(&run, std::slice::from_ref(&result))
+            .unwrap();
+        let change = Change {
+            seq: 1,
+            kind: "results".into(),
+            record_id: result.id,
+            field: "output".into(),
+            value: serde_json::json!(result.output),
+            at: Utc::now(),
+            origin: ChangeOrigin::Local,
+        };
+        storage.append_changes(&[change]).unwrap();
+        assert!(contains(&disk_content(&path), "221B Baker Street"));
+
+        storage.delete_run(run.id).unwrap();
+        storage.redact_changes(&[result.id]).unwrap();
+        assert!(!contains(&disk_content(&path), "221B Baker Street"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn key_rotation() {
+        let path = temp_db("sherlock_sqlite_rotation_test");
+
+        //a plain database is encrypted when it's opened with a key
+        let storage = SqliteStorage::open(&path).unwrap();
+        let case = Case::new("operation-nightjar");
+        storage.save_case(&case).unwrap();
+        drop(storage);
+        let old = KeySource::Passphrase("old".into());
+        let storage = SqliteStorage::open_encrypted(&path, &old).unwrap();
+        assert!(!contains(&disk_content(&path), "operation-nightjar"));
+        let change = Change {
+            seq: 1,
+            kind: "cases".into(),
+            record_id: case.id,
+            field: "title".into(),
+            value: serde_json::json!(case.title),
+            at: Utc::now(),
+            origin: ChangeOrigin::Local,
+        };
+        storage
+            .append_changes(std::slice::from_ref(&change))
+            .unwrap();
+
+        //rotating re-encrypts the records
+        let new = KeySource::Key(crypto::random_bytes(crypto::KEY_SIZE));
+        let other = SqliteStorage::open_encrypted(&path, &old).unwrap();
+        storage.rotate_key(Some(&new)).unwrap();
+        assert!(matches!(
+            SqliteStorage::open_encrypted(&path, &old),
+            Err(Error::WrongKey)
+        ));
+        assert_eq!(storage.list_cases().unwrap(), vec![case.clone()]);
+        drop(storage);
+        let storage = SqliteStorage::open_encrypted(&path, &new).unwrap();
+        assert_eq!(storage.list_cases().unwrap(), vec![case.clone()]);
+        assert_eq!(storage.list_changes(0, 10).unwrap(), vec![change]);
+
+        //a connection that still has the old key can't write
+        assert_eq!(other.save_case(&Case::new("stale")), Err(Error::WrongKey));
+
+        //and decrypting leaves a plain database
+        storage.rotate_key(None).unwrap();
+        drop(storage);
+        assert_eq!(
+            SqliteStorage::open(&path).unwrap().list_cases().unwrap(),
+            vec![case]
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let path = temp_db("sherlock_sqlite_concurrent_test");
+
+        //a shared storage (the UI) and a second connection (a worker) write at the same time
+        let shared = Arc::new(SqliteStorage::open(&path).unwrap());
+        let case = Case::new("case");
+        shared.save_case(&case).unwrap();
+        let query = Query::new(case.id, "user123");
+        shared.save_query(&query).unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let shared = shared.clone();
+                let path = path.clone();
+                let query = query.clone();
+                thread::spawn(move || {
+                    let own = SqliteStorage::open(&path).unwrap();
+                    for _ in 0..10 {
+                        let storage: &dyn Storage = if i % 2 == 0 { &own } else { &*shared };
+                        let mut run = Run::start(&query, "facebook", "user");
+                        run.finish(Ok(()));
+                        storage
+                            .save_run_with_results(&run, &[RunResult::new(run.id, "found")])
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let runs = shared.list_runs(query.id).unwrap();
+        assert_eq!(runs.len(), 40);
+        assert!(runs
+            .iter()
+            .all(|run| shared.list_results(run.id).unwrap().len() == 1));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
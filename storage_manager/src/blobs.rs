@@ -0,0 +1,518 @@
+/*
+Blob Store:
+A content-addressed store for the files produced by engine runs. A blob is addressed by the
+SHA-256 of its content, so the same avatar downloaded by a hundred runs is kept once.
+
+Blobs are files under `<root>/<first 2 hex digits>/<other 62 hex digits>`, written to a
+temporary file first and renamed in place. Every blob starts with a header:
+    "SHB1" <flags>                 flags: 1 = deflate compressed, 2 = encrypted
+Compression is only kept when it makes the blob smaller, i.e: images aren't compressed twice.
+
+An encrypted store seals its blobs with a random data key, kept in `<root>/key.json` sealed
+with the storage key (so changing the storage key only reseals the data key). The blobs are
+then named by a keyed digest of their address: the plain SHA-256 of a known file doesn't tell
+whether the store holds it. Opening a plain store with a key encrypts the blobs it has.
+ */
+use crate::crypto::{self, Cipher, KeySource, KEY_SIZE, SALT_SIZE};
+use crate::retention::wipe_file;
+use crate::{Artifact, Error};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 4] = b"SHB1";
+const COMPRESSED: u8 = 1;
+const ENCRYPTED: u8 = 2;
+const KEY_FILE: &str = "key.json";
+/// The address the search index is kept at, no known content has it as its SHA-256.
+const INDEX_ADDRESS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+enum Location {
+    Dir(PathBuf),
+    Memory(Mutex<HashMap<String, Vec<u8>>>),
+}
+
+// the data key of an encrypted store, sealed with the storage key
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    /// The salt of a passphrase, in hex.
+    salt: Option<String>,
+    /// The sealed data key, in hex.
+    key: String,
+}
+
+/// ## Description
+/// A content-addressed store of the files produced by engine runs.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::BlobStore;
+/// let blobs = BlobStore::in_memory();
+/// let digest = blobs.put(b"avatar").unwrap();
+/// assert_eq!(blobs.put(b"avatar").unwrap(), digest);
+/// assert_eq!(blobs.get(&digest).unwrap(), Some(b"avatar".to_vec()));
+/// ```
+pub struct BlobStore {
+    location: Location,
+    cipher: Option<Cipher>,
+    // the key the blobs are sealed with, kept to reseal it with another storage key
+    data_key: Option<Zeroizing<Vec<u8>>>,
+    compress: AtomicBool,
+}
+
+impl BlobStore {
+    /// ## Description
+    /// Opens (or creates) a blob store in a directory, `key` encrypts the blobs.
+    ///
+    /// Fails with `Error::WrongKey` if the store was encrypted with another key,
+    /// and with `Error::Crypto` if it's encrypted and no key was given.
+    pub fn open<P: AsRef<Path>>(root: P, key: Option<&KeySource>) -> Result<BlobStore, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(io_error)?;
+        let key_file = root.join(KEY_FILE);
+        let data_key = match (key_file.is_file(), key) {
+            (false, None) => None,
+            (true, None) => {
+                return Err(Error::Crypto(
+                    "the blobs are encrypted, a key is needed to open them".into(),
+                ))
+            }
+            (true, Some(key)) => Some(read_key(&key_file, key)?),
+            (false, Some(key)) => {
+                let data_key = Zeroizing::new(crypto::random_bytes(KEY_SIZE));
+                write_key(&key_file, key, &data_key)?;
+                Some(data_key)
+            }
+        };
+        let cipher = match &data_key {
+            Some(data_key) => Some(Cipher::new(&KeySource::Key(data_key.to_vec()), None)?),
+            None => None,
+        };
+        let store = BlobStore {
+            location: Location::Dir(root),
+            cipher,
+            data_key,
+            compress: AtomicBool::new(true),
+        };
+        if store.cipher.is_some() {
+            store.encrypt_plain_blobs()?;
+        }
+        Ok(store)
+    }
+
+    /// Creates a blob store that only lives in memory, i.e: for the in memory storage.
+    pub fn in_memory() -> BlobStore {
+        BlobStore {
+            location: Location::Memory(Mutex::new(HashMap::new())),
+            cipher: None,
+            data_key: None,
+            compress: AtomicBool::new(true),
+        }
+    }
+
+    /// Checks whether the blobs are encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Sets whether new blobs are compressed, they are by default.
+    pub fn set_compression(&self, compress: bool) {
+        self.compress.store(compress, Ordering::Relaxed);
+    }
+
+    /// ## Description
+    /// Seals the data key of an encrypted store with a new storage key,
+    /// i.e: after the storage's key was rotated. The blobs are left as they are.
+    pub fn rotate_key(&self, key: &KeySource) -> Result<(), Error> {
+        match (&self.location, &self.data_key) {
+            (Location::Dir(root), Some(data_key)) => write_key(&root.join(KEY_FILE), key, data_key),
+            _ => Err(Error::Crypto("the blobs aren't encrypted".into())),
+        }
+    }
+
+    /// ## Description
+    /// Stores a content, returns its address: the SHA-256 of the content in hex.
+    pub fn put(&self, content: &[u8]) -> Result<String, Error> {
+        let digest = crypto::to_hex(&Sha256::digest(content));
+        let name = self.name(&digest);
+        if self.exists(&name) {
+            return Ok(digest);
+        }
+        let blob = self.encode(&digest, content)?;
+        self.write(&name, &blob)?;
+        Ok(digest)
+    }
+
+    /// ## Description
+    /// Gets a content by its address, `None` if the store doesn't have it.
+    ///
+    /// The content is checked against its address, a damaged blob fails to read.
+    pub fn get(&self, digest: &str) -> Result<Option<Vec<u8>>, Error> {
+        check_address(digest)?;
+        let Some(blob) = self.read(&self.name(digest))? else {
+            return Ok(None);
+        };
+        let content = self.decode(digest, &blob)?;
+        if crypto::to_hex(&Sha256::digest(&content)) != digest {
+            return Err(Error::Io(format!("the blob {} is damaged", digest)));
+        }
+        Ok(Some(content))
+    }
+
+    /// ## Description
+    /// Removes a content, its file is zeroed before it's unlinked. A missing content is ignored.
+    pub fn remove(&self, digest: &str) -> Result<(), Error> {
+        check_address(digest)?;
+        let name = self.name(digest);
+        match &self.location {
+            Location::Dir(root) => wipe_file(&BlobStore::path(root, &name)).map_err(io_error),
+            Location::Memory(blobs) => {
+                blobs.lock().unwrap().remove(&name);
+                Ok(())
+            }
+        }
+    }
+
+    /// ## Description
+    /// Keeps the search index between the times the storage is opened, replacing the one
+    /// kept before. It's compressed and encrypted like the blobs.
+    pub(crate) fn keep_index(&self, index: &[u8]) -> Result<(), Error> {
+        let blob = self.encode(INDEX_ADDRESS, index)?;
+        self.write(&self.name(INDEX_ADDRESS), &blob)
+    }
+
+    /// ## Description
+    /// Takes the kept search index out of the store, `None` if there's none.
+    ///
+    /// Taking it leaves nothing behind, an index that isn't kept again (i.e: the process was
+    /// killed) can't fall behind the records.
+    pub(crate) fn take_index(&self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(blob) = self.read(&self.name(INDEX_ADDRESS))? else {
+            return Ok(None);
+        };
+        self.remove(INDEX_ADDRESS)?;
+        self.decode(INDEX_ADDRESS, &blob).map(Some)
+    }
+
+    /// ## Description
+    /// Stores every file of a directory (and its sub directories) and lists them as artifacts,
+    /// sorted by name. Links are skipped, they could point anywhere.
+    pub fn collect<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<Artifact>, Error> {
+        let dir = dir.as_ref();
+        let mut artifacts = vec![];
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current).map_err(io_error)? {
+                let path = entry.map_err(io_error)?.path();
+                let kind = fs::symlink_metadata(&path).map_err(io_error)?.file_type();
+                if kind.is_dir() {
+                    pending.push(path);
+                } else if kind.is_file() {
+                    let content = fs::read(&path).map_err(io_error)?;
+                    let name = path
+                        .strip_prefix(dir)
+                        .unwrap_or(&path)
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    artifacts.push(Artifact {
+                        name,
+                        digest: self.put(&content)?,
+                        size: content.len() as u64,
+                    });
+                }
+            }
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(artifacts)
+    }
+
+    // the name of a blob, a keyed digest of its address when the store is encrypted
+    fn name(&self, digest: &str) -> String {
+        match &self.cipher {
+            Some(cipher) => crypto::to_hex(&cipher.digest(digest.as_bytes())),
+            None => digest.to_owned(),
+        }
+    }
+
+    fn encode(&self, digest: &str, content: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut flags = 0;
+        let mut body = content.to_vec();
+        if self.compress.load(Ordering::Relaxed) {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(content).map_err(io_error)?;
+            let compressed = encoder.finish().map_err(io_error)?;
+            if compressed.len() < content.len() {
+                flags |= COMPRESSED;
+                body = compressed;
+            }
+        }
+        if let Some(cipher) = &self.cipher {
+            flags |= ENCRYPTED;
+            body = cipher.seal(&body, &context(digest));
+        }
+        Ok([MAGIC.as_slice(), &[flags], &body].concat())
+    }
+
+    fn decode(&self, digest: &str, blob: &[u8]) -> Result<Vec<u8>, Error> {
+        if blob.len() < MAGIC.len() + 1 || &blob[..MAGIC.len()] != MAGIC {
+            return Err(Error::Io(format!("the blob {} is damaged", digest)));
+        }
+        let flags = blob[MAGIC.len()];
+        let mut body = blob[MAGIC.len() + 1..].to_vec();
+        if flags & ENCRYPTED != 0 {
+            let cipher = self
+                .cipher
+                .as_ref()
+                .ok_or_else(|| Error::Crypto(format!("the blob {} is encrypted", digest)))?;
+            body = cipher.open(&body, &context(digest))?;
+        }
+        if flags & COMPRESSED != 0 {
+            let mut content = vec![];
+            DeflateDecoder::new(body.as_slice())
+                .read_to_end(&mut content)
+                .map_err(|_| Error::Io(format!("the blob {} is damaged", digest)))?;
+            body = content;
+        }
+        Ok(body)
+    }
+
+    fn path(root: &Path, name: &str) -> PathBuf {
+        root.join(&name[..2]).join(&name[2..])
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        match &self.location {
+            Location::Dir(root) => BlobStore::path(root, name).is_file(),
+            Location::Memory(blobs) => blobs.lock().unwrap().contains_key(name),
+        }
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        match &self.location {
+            Location::Dir(root) => match fs::read(BlobStore::path(root, name)) {
+                Ok(blob) => Ok(Some(blob)),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(io_error(error)),
+            },
+            Location::Memory(blobs) => Ok(blobs.lock().unwrap().get(name).cloned()),
+        }
+    }
+
+    fn write(&self, name: &str, blob: &[u8]) -> Result<(), Error> {
+        match &self.location {
+            Location::Dir(root) => {
+                let path = BlobStore::path(root, name);
+                let dir = path.parent().expect("blobs are in a sub directory");
+                fs::create_dir_all(dir).map_err(io_error)?;
+                let temp = dir.join(format!(".{}.tmp", &name[2..]));
+                fs::write(&temp, blob).map_err(io_error)?;
+                fs::rename(&temp, &path).map_err(io_error)
+            }
+            Location::Memory(blobs) => {
+                blobs.lock().unwrap().insert(name.to_owned(), blob.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    // encrypt the blobs that were stored before the store was encrypted
+    fn encrypt_plain_blobs(&self) -> Result<(), Error> {
+        let Location::Dir(root) = &self.location else {
+            return Ok(());
+        };
+        for dir in fs::read_dir(root).map_err(io_error)? {
+            let dir = dir.map_err(io_error)?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&dir).map_err(io_error)? {
+                let path = file.map_err(io_error)?.path();
+                let prefix = dir.file_name().unwrap_or_default().to_string_lossy();
+                let rest = path.file_name().unwrap_or_default().to_string_lossy();
+                let digest = format!("{}{}", prefix, rest);
+                let blob = fs::read(&path).map_err(io_error)?;
+                //plain blobs are named by their address, encrypted ones by a keyed digest
+                if digest.len() != 64
+                    || blob
+                        .get(MAGIC.len())
+                        .is_none_or(|flags| flags & ENCRYPTED != 0)
+                {
+                    continue;
+                }
+                let content = self.decode(&digest, &blob)?;
+                self.write(&self.name(&digest), &self.encode(&digest, &content)?)?;
+                fs::remove_file(&path).map_err(io_error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// binds a sealed blob to its address
+fn context(digest: &str) -> Vec<u8> {
+    format!("blobs/{}", digest).into_bytes()
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, Error> {
+    let content = fs::read(path).map_err(io_error)?;
+    serde_json::from_slice(&content).map_err(|error| Error::Serialization(error.to_string()))
+}
+
+// open the data key with the storage key
+fn read_key(path: &Path, key: &KeySource) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let key_file = read_key_file(path)?;
+    let salt = key_file.salt.as_deref().and_then(crypto::from_hex);
+    let sealed = crypto::from_hex(&key_file.key)
+        .ok_or_else(|| Error::Serialization(format!("{} is damaged", path.display())))?;
+    Cipher::new(key, salt.as_deref())?
+        .open(&sealed, b"blobs/key")
+        .map(Zeroizing::new)
+        .map_err(|_| Error::WrongKey)
+}
+
+// seal the data key with the storage key
+fn write_key(path: &Path, key: &KeySource, data_key: &[u8]) -> Result<(), Error> {
+    let salt = match key {
+        KeySource::Passphrase(_) => Some(crypto::random_bytes(SALT_SIZE)),
+        KeySource::Key(_) => None,
+    };
+    let sealed = Cipher::new(key, salt.as_deref())?.seal(data_key, b"blobs/key");
+    let key_file = KeyFile {
+        salt: salt.as_deref().map(crypto::to_hex),
+        key: crypto::to_hex(&sealed),
+    };
+    let content =
+        serde_json::to_vec(&key_file).map_err(|error| Error::Serialization(error.to_string()))?;
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content).map_err(io_error)?;
+    fs::rename(&temp, path).map_err(io_error)
+}
+
+fn check_address(digest: &str) -> Result<(), Error> {
+    if digest.len() != 64 || crypto::from_hex(digest).is_none() {
+        return Err(Error::InvalidRecord(format!(
+            "invalid blob address: {}",
+            digest
+        )));
+    }
+    Ok(())
+}
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::Io(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // the blob files of a store, without the key file
+    fn blob_files(root: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for dir in fs::read_dir(root).unwrap() {
+            let dir = dir.unwrap().path();
+            if dir.is_dir() {
+                files.extend(fs::read_dir(&dir).unwrap().map(|file| file.unwrap().path()));
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn store_and_encrypt() {
+        let root = temp_dir("sherlock_blobs_test");
+        let text = "jane doe ".repeat(100);
+        let plain = BlobStore::open(&root, None).unwrap();
+        let digest = plain.put(text.as_bytes()).unwrap();
+        plain.set_compression(false);
+        let other = plain.put(b"avatar").unwrap();
+        assert_eq!(plain.put(text.as_bytes()).unwrap(), digest);
+        //compressed, and addressed by the content
+        let files = blob_files(&root);
+        assert_eq!(files.len(), 2);
+        assert!(files
+            .iter()
+            .any(|file| fs::metadata(file).unwrap().len() < 100));
+        assert!(root.join(&digest[..2]).join(&digest[2..]).is_file());
+
+        //opening with a key encrypts the blobs, the names don't tell the contents anymore
+        let key = KeySource::Passphrase("correct horse".into());
+        let encrypted = BlobStore::open(&root, Some(&key)).unwrap();
+        assert!(encrypted.is_encrypted());
+        assert!(!root.join(&digest[..2]).join(&digest[2..]).exists());
+        assert_eq!(blob_files(&root).len(), 2);
+        assert_eq!(
+            encrypted.get(&digest).unwrap(),
+            Some(text.clone().into_bytes())
+        );
+        assert_eq!(encrypted.get(&other).unwrap(), Some(b"avatar".to_vec()));
+        assert!(matches!(
+            BlobStore::open(&root, None),
+            Err(Error::Crypto(_))
+        ));
+        let wrong = KeySource::Passphrase("wrong".into());
+        assert!(matches!(
+            BlobStore::open(&root, Some(&wrong)),
+            Err(Error::WrongKey)
+        ));
+
+        //a new storage key only reseals the data key
+        let new = KeySource::Key(vec![7; KEY_SIZE]);
+        encrypted.rotate_key(&new).unwrap();
+        let reopened = BlobStore::open(&root, Some(&new)).unwrap();
+        assert_eq!(reopened.get(&other).unwrap(), Some(b"avatar".to_vec()));
+
+        //damaged blobs fail to read
+        let file = blob_files(&root)
+            .into_iter()
+            .find(|file| fs::metadata(file).unwrap().len() < 100)
+            .unwrap();
+        let mut blob = fs::read(&file).unwrap();
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+        fs::write(&file, blob).unwrap();
+        assert!(reopened.get(&digest).is_err() || reopened.get(&other).is_err());
+        assert!(reopened.get("not a digest").is_err());
+    }
+
+    #[test]
+    fn collect_files() {
+        let dir = temp_dir("sherlock_blobs_collect_test");
+        fs::create_dir_all(dir.join("pages")).unwrap();
+        fs::write(dir.join("avatar.png"), b"png").unwrap();
+        fs::write(dir.join("pages").join("index.html"), b"<html>").unwrap();
+        fs::write(dir.join("copy.png"), b"png").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/etc/hostname", dir.join("link")).unwrap();
+
+        let blobs = BlobStore::in_memory();
+        let artifacts = blobs.collect(&dir).unwrap();
+        let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["avatar.png", "copy.png", "pages/index.html"]);
+        assert_eq!(artifacts[0].digest, artifacts[1].digest);
+        assert_eq!(artifacts[2].size, 6);
+        assert_eq!(
+            blobs.get(&artifacts[2].digest).unwrap(),
+            Some(b"<html>".to_vec())
+        );
+    }
+}
@@ -0,0 +1,175 @@
+/*
+Encryption at rest:
+Records are sealed field by field with XChaCha20-Poly1305, every sealed field carries its own
+random nonce and is bound to its record (id and column) so fields can't be swapped around.
+
+The master key is either derived from a passphrase (Argon2id, the salt is kept next to the
+data) or a random key kept in the vault. Two keys are derived from the master key:
+one for sealing and one for the keyed digests used to look sealed values up.
+ */
+use crate::Error;
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// The size in bytes of the keys.
+pub const KEY_SIZE: usize = 32;
+/// The size in bytes of the passphrase salts.
+pub(crate) const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+
+/// ## Description
+/// Where the encryption key of the storage comes from.
+#[derive(Clone)]
+pub enum KeySource {
+    /// A passphrase, the key is derived from it.
+    Passphrase(String),
+    /// A random key, i.e: the storage key kept in the vault.
+    Key(Vec<u8>),
+}
+
+//never print the secret
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeySource::Passphrase(_) => write!(f, "Passphrase(..)"),
+            KeySource::Key(_) => write!(f, "Key(..)"),
+        }
+    }
+}
+
+/// The keys that seal and digest the records.
+pub(crate) struct Cipher {
+    aead: XChaCha20Poly1305,
+    digest_key: Zeroizing<Vec<u8>>,
+}
+
+impl Cipher {
+    /// Creates the cipher of a key source, `salt` is needed by passphrases only.
+    pub(crate) fn new(source: &KeySource, salt: Option<&[u8]>) -> Result<Cipher, Error> {
+        match (source, salt) {
+            (KeySource::Passphrase(passphrase), Some(salt)) => {
+                let mut master = Zeroizing::new([0u8; KEY_SIZE]);
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, master.as_mut())
+                    .map_err(|error| Error::Crypto(error.to_string()))?;
+                Cipher::from_master(master.as_ref())
+            }
+            (KeySource::Passphrase(_), None) => Err(Error::WrongKey),
+            (KeySource::Key(key), _) if key.len() != KEY_SIZE => Err(Error::Crypto(format!(
+                "the key must be {} bytes long",
+                KEY_SIZE
+            ))),
+            (KeySource::Key(key), _) => Cipher::from_master(key),
+        }
+    }
+
+    fn from_master(master: &[u8]) -> Result<Cipher, Error> {
+        let seal_key = Zeroizing::new(hmac(master, b"sherlock record key"));
+        let aead = XChaCha20Poly1305::new_from_slice(&seal_key)
+            .map_err(|error| Error::Crypto(error.to_string()))?;
+        Ok(Cipher {
+            aead,
+            digest_key: Zeroizing::new(hmac(master, b"sherlock digest key")),
+        })
+    }
+
+    /// Encrypts and authenticates `plaintext`, `context` is authenticated but not stored.
+    pub(crate) fn seal(&self, plaintext: &[u8], context: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad: context,
+        };
+        let ciphertext = self
+            .aead
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .expect("encrypting into memory can't fail");
+        [nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Decrypts a sealed value, fails if it was sealed with another key or context.
+    pub(crate) fn open(&self, sealed: &[u8], context: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < NONCE_SIZE {
+            return Err(Error::Crypto("sealed value is truncated".into()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: context,
+        };
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| Error::Crypto("can't decrypt a sealed value".into()))
+    }
+
+    /// A keyed digest of `data`, equal values have equal digests under the same key.
+    pub(crate) fn digest(&self, data: &[u8]) -> Vec<u8> {
+        hmac(&self.digest_key, data)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("hmac accepts any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Generates `size` random bytes, i.e: a salt or a key.
+pub(crate) fn random_bytes(size: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; size];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let salt = random_bytes(SALT_SIZE);
+        let cipher = Cipher::new(&KeySource::Passphrase("secret".into()), Some(&salt)).unwrap();
+        let sealed = cipher.seal(b"user123", b"queries/text");
+        assert!(!sealed.windows(7).any(|window| window == b"user123"));
+        assert_eq!(cipher.open(&sealed, b"queries/text").unwrap(), b"user123");
+
+        //another context or another key can't open it
+        assert!(cipher.open(&sealed, b"cases/title").is_err());
+        let other = Cipher::new(&KeySource::Passphrase("other".into()), Some(&salt)).unwrap();
+        assert!(other.open(&sealed, b"queries/text").is_err());
+
+        //digests are stable for a key
+        assert_eq!(cipher.digest(b"user123"), cipher.digest(b"user123"));
+        assert_ne!(cipher.digest(b"user123"), other.digest(b"user123"));
+    }
+
+    #[test]
+    fn raw_keys() {
+        assert!(Cipher::new(&KeySource::Key(vec![0; 8]), None).is_err());
+        let key = random_bytes(KEY_SIZE);
+        assert!(Cipher::new(&KeySource::Key(key.clone()), None).is_ok());
+        assert_eq!(from_hex(&to_hex(&key)), Some(key));
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -0,0 +1,182 @@
+/*
+Entities:
+Extracts the entities (urls, email addresses, phone numbers and @usernames) that show up in an
+engine's output, so they're kept with the case the run belongs to.
+
+Urls are looked for first, then email addresses, phone numbers and usernames. The text of
+every match is blanked before looking for the next kind, i.e: the `@example` of
+`jane@example.com` isn't taken for a username.
+ */
+use crate::EntityKind;
+use regex::Regex;
+use std::{ops::Range, sync::OnceLock};
+
+// the patterns of the entities, in the order they're looked for
+fn patterns() -> &'static [(EntityKind, Regex)] {
+    static PATTERNS: OnceLock<Vec<(EntityKind, Regex)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (EntityKind::Url, r#"https?://[^\s<>"']+[^\s<>"'.,;:!?)\]]"#),
+            (
+                EntityKind::Email,
+                r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+            ),
+            //`+` and digits, or separated groups of digits with an optional `+` and area code,
+            //not part of a longer number, a time or a path
+            (
+                EntityKind::Phone,
+                concat!(
+                    r"(?:^|[^\w+.:/-])(\+\d{7,15}|\+?(?:\(\d{1,4}\)[ .-]?|\d{1,4}[ .-])",
+                    r"(?:\(\d{1,4}\)[ .-]?|\d{2,4}[ .-]){0,4}\d{2,4})(?:$|[^\w.:/-]|\.(?:\s|$))",
+                ),
+            ),
+            (EntityKind::Username, r"(?:^|[^\w@])@([A-Za-z0-9_]{2,30})\b"),
+        ]
+        .into_iter()
+        .map(|(kind, pattern)| (kind, Regex::new(pattern).expect("valid entity pattern")))
+        .collect()
+    })
+}
+
+/// ## Description
+/// Extracts the entities of a text, in the order they're found, without duplicates.
+///
+/// Phone numbers are kept with their digits only (and a leading `+`), they must have
+/// between 7 and 15 digits and look like one: a `+` followed by digits, or separated groups of
+/// digits. Dates, ip addresses and bare runs of digits (i.e: timestamps) aren't phones.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::{extract_entities, EntityKind};
+/// let entities = extract_entities("contact jane@example.com or @jane_doe");
+/// assert_eq!(
+///     entities,
+///     vec![
+///         (EntityKind::Email, "jane@example.com".to_owned()),
+///         (EntityKind::Username, "jane_doe".to_owned()),
+///     ]
+/// );
+/// ```
+pub fn extract_entities(text: &str) -> Vec<(EntityKind, String)> {
+    let mut text = text.to_owned();
+    let mut entities: Vec<(EntityKind, String)> = vec![];
+    for (kind, pattern) in patterns() {
+        let mut spans = vec![];
+        for captures in pattern.captures_iter(&text) {
+            let whole = captures.get(0).unwrap();
+            let found = captures.get(1).unwrap_or(whole);
+            let value = match kind {
+                EntityKind::Phone => match phone_value(found.as_str()) {
+                    Some(value) => value,
+                    None => continue,
+                },
+                _ => found.as_str().to_owned(),
+            };
+            spans.push(found.range());
+            let entity = (*kind, value);
+            if !entities.contains(&entity) {
+                entities.push(entity);
+            }
+        }
+        //blank the matches, the spans keep their offsets
+        for span in spans {
+            text.replace_range(span.clone(), &" ".repeat(span.len()));
+        }
+    }
+    entities
+}
+
+/// ## Description
+/// Finds the phone numbers of a text like `extract_entities` does, with their byte ranges.
+/// The search index normalizes the phones of the records and of the queries with it.
+pub(crate) fn find_phones(text: &str) -> Vec<(Range<usize>, String)> {
+    let (_, pattern) = patterns()
+        .iter()
+        .find(|(kind, _)| *kind == EntityKind::Phone)
+        .expect("a phone pattern");
+    pattern
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let found = captures.get(1)?;
+            phone_value(found.as_str()).map(|value| (found.range(), value))
+        })
+        .collect()
+}
+
+// the digits of a phone shaped number (and its leading `+`), `None` if it isn't a phone
+fn phone_value(number: &str) -> Option<String> {
+    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    if !(7..=15).contains(&digits.len()) || !is_phone_like(number) {
+        return None;
+    }
+    match number.starts_with('+') {
+        true => Some(format!("+{}", digits)),
+        false => Some(digits),
+    }
+}
+
+// whether a phone shaped number isn't a date or an ip address
+fn is_phone_like(number: &str) -> bool {
+    let lengths: Vec<usize> = number
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|group| !group.is_empty())
+        .map(str::len)
+        .collect();
+    //i.e: 2024-01-15 or 15.01.2024
+    let date = matches!(lengths.as_slice(), [4, 1..=2, 1..=2] | [1..=2, 1..=2, 4]);
+    let ip = number.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && lengths.len() == 4
+        && lengths.iter().all(|length| *length <= 3);
+    !date && !ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_kinds() {
+        let output = "[+] Found: https://example.com/jane.doe, mail jane.doe@example.com.\n\
+                      Phone: +1 (555) 010-0199, id 12345, follows @nightjar and @nightjar";
+        assert_eq!(
+            extract_entities(output),
+            vec![
+                (EntityKind::Url, "https://example.com/jane.doe".to_owned()),
+                (EntityKind::Email, "jane.doe@example.com".to_owned()),
+                (EntityKind::Phone, "+15550100199".to_owned()),
+                (EntityKind::Username, "nightjar".to_owned()),
+            ]
+        );
+        assert!(extract_entities("nothing to see here").is_empty());
+    }
+
+    #[test]
+    fn phone_shapes() {
+        let phones = |text: &str| -> Vec<String> {
+            extract_entities(text)
+                .into_iter()
+                .filter(|(kind, _)| *kind == EntityKind::Phone)
+                .map(|(_, value)| value)
+                .collect()
+        };
+        assert_eq!(phones("call +44 20 7946 0958."), vec!["+442079460958"]);
+        assert_eq!(phones("call 020 7946 0958, or"), vec!["02079460958"]);
+        assert_eq!(phones("(555) 010-0199"), vec!["5550100199"]);
+        assert_eq!(phones("ext 555-0100"), vec!["5550100"]);
+        assert_eq!(phones("+15550100199"), vec!["+15550100199"]);
+
+        //dates, times, timestamps and other numbers aren't phones
+        for text in [
+            "2024-01-15 12:30:45",
+            "at 2024-01-15T12:30:45Z",
+            "on 15.01.2024 at 09:41",
+            "since 1700000000.123",
+            "epoch 1700000000",
+            "from 192.168.100.200",
+            "see /api/v1/2024/0115/1230",
+            "id 12345",
+        ] {
+            assert!(phones(text).is_empty(), "{} has a phone", text);
+        }
+    }
+}
@@ -0,0 +1,684 @@
+/*
+Evidence Log:
+An append-only, hash-chained log of the engine runs and results that were collected, to prove
+they weren't altered afterwards. Every finished run gets an entry, and so does every result of
+it, with what's needed to reproduce and check the collection: the engine's version and the
+digest of its binary, the argv it was executed with, the timestamps and the digests of the
+output and of the stored record. Purged runs get an entry too, with the reason they were purged
+(see the `retention` module).
+
+Chaining:
+    The entries are numbered from 1 without gaps. Every entry holds `prev`, the hash of the
+    entry before it (64 zeros for the first one), and `hash`, the SHA-256 (hex) of its own
+    canonical form: the entry's json object, redacted, without the `hash` and `redacted` fields,
+    its keys sorted and without whitespace. Changing, removing or reordering an entry breaks the
+    chain from there on.
+
+Redaction:
+    The argv holds the query and the names of the files may too. When a run is purged, the
+    entries of the run and of its results are redacted: every argument and file name is replaced
+    by its SHA-256 (hex) and `redacted` is set. The canonical form is the redacted one, so the
+    chain stays intact, and a redacted argument can still be checked against its digest.
+
+Verification:
+    `verify_chain` checks the chain alone, what a third party can do with an export.
+    `StorageManager::verify_evidence` also checks the entries against the stored records:
+    a record that changed or disappeared since it was logged, or a run that was never logged
+    (i.e: the end of the log was cut off), is reported.
+
+Export:
+    An `EvidenceExport` is the whole chain with its head, as a self-contained json document.
+    Outputs can be checked against the `output_digest` of their entries and the files of a run
+    against the digests of its `artifacts`.
+ */
+use crate::api;
+use crate::model::Record;
+use crate::{Artifact, Error, Run, RunResult, Storage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::VecDeque, sync::Mutex};
+use uuid::Uuid;
+
+/// The `prev` of the first entry of the log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// The format of the exported evidence logs.
+pub const EXPORT_FORMAT: &str = "sherlock-evidence/1";
+
+/// The kind of the entries of purged runs.
+pub(crate) const PURGES: &str = "purges";
+
+// how many entries are read from the storage at a time
+const PAGE_SIZE: usize = 500;
+// how many times an entry is chained again when another writer appended first
+const APPEND_ATTEMPTS: usize = 3;
+
+/// ## Description
+/// How an engine was executed, as recorded in the evidence log.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The version the engine declares in its config.
+    pub engine_version: Option<String>,
+    /// The SHA-256 (hex) of the engine's binary or script, `None` if it couldn't be read.
+    pub engine_digest: Option<String>,
+    /// The program and arguments the engine was executed with.
+    pub argv: Vec<String>,
+}
+
+/// ## Description
+/// An entry of the evidence log, a run or a result as it was collected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceEntry {
+    /// The position of the entry in the log, from 1.
+    pub seq: u64,
+    /// The kind of the logged record, `runs` or `results`, `purges` when a run was purged.
+    pub kind: String,
+    pub record_id: Uuid,
+    pub case_id: Uuid,
+    pub run_id: Uuid,
+    pub engine: String,
+    pub engine_version: Option<String>,
+    pub engine_digest: Option<String>,
+    pub argv: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The SHA-256 (hex) of a result's output, `None` for runs.
+    pub output_digest: Option<String>,
+    /// The files of a run, empty for results.
+    pub artifacts: Vec<Artifact>,
+    /// The revision of the record as it was stored (see `api::revision`).
+    pub record_digest: String,
+    /// Why a run was purged, left out of the other entries (and of their hash).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Whether the arguments and the names of the files were replaced by their digests.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+    pub logged_at: DateTime<Utc>,
+    /// The hash of the previous entry.
+    pub prev: String,
+    /// The hash of this entry.
+    pub hash: String,
+}
+
+impl EvidenceEntry {
+    /// ## Description
+    /// Creates the (unchained) entry of a finished run.
+    pub fn for_run(run: &Run, provenance: &Provenance) -> EvidenceEntry {
+        EvidenceEntry {
+            kind: Run::KIND.to_owned(),
+            record_id: run.id,
+            artifacts: run.artifacts.clone(),
+            record_digest: api::revision(run),
+            ..EvidenceEntry::unchained(run, provenance)
+        }
+    }
+
+    /// ## Description
+    /// Creates the (unchained) entry of a result of a run.
+    pub fn for_result(result: &RunResult, run: &Run, provenance: &Provenance) -> EvidenceEntry {
+        EvidenceEntry {
+            kind: RunResult::KIND.to_owned(),
+            record_id: result.id,
+            output_digest: Some(sha256_hex(result.output.as_bytes())),
+            record_digest: api::revision(result),
+            ..EvidenceEntry::unchained(run, provenance)
+        }
+    }
+
+    /// ## Description
+    /// Creates the (unchained) entry of a run that was purged and why it was, it's redacted.
+    pub fn for_purge(run: &Run, reason: &str) -> EvidenceEntry {
+        let mut entry = EvidenceEntry {
+            kind: PURGES.to_owned(),
+            record_id: run.id,
+            artifacts: run.artifacts.clone(),
+            record_digest: api::revision(run),
+            note: Some(reason.to_owned()),
+            ..EvidenceEntry::unchained(run, &Provenance::default())
+        };
+        entry.redact();
+        entry
+    }
+
+    /// ## Description
+    /// Replaces the arguments and the names of the files by their SHA-256 (hex),
+    /// the hash of the entry stays valid. Redacting an entry again does nothing.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Case, EvidenceEntry, Provenance, Query, Run};
+    /// let query = Query::new(Case::new("case").id, "user123");
+    /// let mut run = Run::start(&query, "facebook", "user");
+    /// run.finish(Ok(()));
+    /// let provenance = Provenance {
+    ///     argv: vec!["facebook.py".into(), "user123".into()],
+    ///     ..Default::default()
+    /// };
+    /// let mut entry = EvidenceEntry::for_run(&run, &provenance);
+    /// let hash = entry.compute_hash();
+    /// entry.redact();
+    /// assert!(!entry.argv.contains(&"user123".to_owned()));
+    /// assert_eq!(entry.compute_hash(), hash);
+    /// ```
+    pub fn redact(&mut self) {
+        if self.redacted {
+            return;
+        }
+        self.digest_fields();
+        self.redacted = true;
+    }
+
+    // replace the arguments and the names of the files by their digests
+    fn digest_fields(&mut self) {
+        for argument in self.argv.iter_mut() {
+            *argument = sha256_hex(argument.as_bytes());
+        }
+        for artifact in self.artifacts.iter_mut() {
+            artifact.name = sha256_hex(artifact.name.as_bytes());
+        }
+    }
+
+    fn unchained(run: &Run, provenance: &Provenance) -> EvidenceEntry {
+        EvidenceEntry {
+            seq: 0,
+            kind: String::new(),
+            record_id: run.id,
+            case_id: run.case_id,
+            run_id: run.id,
+            engine: run.engine.clone(),
+            engine_version: provenance.engine_version.clone(),
+            engine_digest: provenance.engine_digest.clone(),
+            argv: provenance.argv.clone(),
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            output_digest: None,
+            artifacts: vec![],
+            record_digest: String::new(),
+            note: None,
+            redacted: false,
+            logged_at: Utc::now(),
+            prev: String::new(),
+            hash: String::new(),
+        }
+    }
+
+    /// ## Description
+    /// Computes the hash of the entry: the SHA-256 of its canonical form (see the module).
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Case, EvidenceEntry, Provenance, Query, Run};
+    /// let query = Query::new(Case::new("case").id, "user123");
+    /// let mut run = Run::start(&query, "facebook", "user");
+    /// run.finish(Ok(()));
+    /// let entry = EvidenceEntry::for_run(&run, &Provenance::default());
+    /// assert_eq!(entry.compute_hash().len(), 64);
+    /// ```
+    pub fn compute_hash(&self) -> String {
+        let mut canonical = self.clone();
+        if !canonical.redacted {
+            canonical.digest_fields();
+        }
+        //the maps of serde_json's values keep their keys sorted
+        let mut value = serde_json::to_value(canonical).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("hash");
+            fields.remove("redacted");
+        }
+        sha256_hex(value.to_string().as_bytes())
+    }
+
+    // number and hash the entry to follow `head`
+    pub(crate) fn link(&mut self, head: Option<&(u64, String)>) {
+        let (seq, prev) = head.cloned().unwrap_or((0, GENESIS_HASH.to_owned()));
+        self.seq = seq + 1;
+        self.prev = prev;
+        self.hash = self.compute_hash();
+    }
+}
+
+/// ## Description
+/// Something wrong found while verifying the evidence log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum EvidenceProblem {
+    /// The entry's content doesn't match its hash, it was modified.
+    Modified {
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        seq: u64,
+    },
+    /// The entry doesn't follow the one before it: entries were removed, reordered or rehashed.
+    Broken {
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        seq: u64,
+    },
+    /// Entries are missing between two sequence numbers.
+    Missing {
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        from: u64,
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        to: u64,
+    },
+    /// The logged record was changed in the storage since it was logged.
+    RecordChanged {
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        seq: u64,
+        record_id: Uuid,
+    },
+    /// The logged record was removed from the storage.
+    RecordMissing {
+        #[cfg_attr(feature = "ts", ts(type = "number"))]
+        seq: u64,
+        record_id: Uuid,
+    },
+    /// A finished run or a result in the storage has no entry.
+    Unlogged { kind: String, record_id: Uuid },
+    /// The head of an export isn't its last entry.
+    WrongHead,
+}
+
+impl std::fmt::Display for EvidenceProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvidenceProblem::Modified { seq } => write!(f, "entry {} was modified", seq),
+            EvidenceProblem::Broken { seq } => {
+                write!(f, "entry {} doesn't follow the previous entry", seq)
+            }
+            EvidenceProblem::Missing { from, to } => {
+                write!(f, "entries {} to {} are missing", from, to)
+            }
+            EvidenceProblem::RecordChanged { seq, record_id } => {
+                write!(f, "{} was changed since entry {}", record_id, seq)
+            }
+            EvidenceProblem::RecordMissing { seq, record_id } => {
+                write!(f, "{} of entry {} was removed", record_id, seq)
+            }
+            EvidenceProblem::Unlogged { kind, record_id } => {
+                write!(f, "{} {} isn't in the evidence log", kind, record_id)
+            }
+            EvidenceProblem::WrongHead => write!(f, "the head isn't the last entry"),
+        }
+    }
+}
+
+/// ## Description
+/// The outcome of verifying the evidence log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct EvidenceReport {
+    /// How many entries were checked.
+    pub entries: usize,
+    /// The hash of the last entry, `None` if the log is empty.
+    pub head: Option<String>,
+    pub problems: Vec<EvidenceProblem>,
+}
+
+impl EvidenceReport {
+    /// Checks that nothing wrong was found.
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// ## Description
+/// The evidence log exported for third-party checking.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvidenceExport {
+    /// Always `EXPORT_FORMAT`.
+    pub format: String,
+    pub exported_at: DateTime<Utc>,
+    /// The sequence number of the last entry, 0 if the log is empty.
+    pub head_seq: u64,
+    /// The hash of the last entry, `GENESIS_HASH` if the log is empty.
+    pub head_hash: String,
+    pub entries: Vec<EvidenceEntry>,
+}
+
+impl EvidenceExport {
+    /// ## Description
+    /// Exports a chain of entries.
+    pub fn new(entries: Vec<EvidenceEntry>) -> EvidenceExport {
+        let (head_seq, head_hash) = entries.last().map_or((0, GENESIS_HASH.to_owned()), |last| {
+            (last.seq, last.hash.clone())
+        });
+        EvidenceExport {
+            format: EXPORT_FORMAT.to_owned(),
+            exported_at: Utc::now(),
+            head_seq,
+            head_hash,
+            entries,
+        }
+    }
+
+    /// ## Description
+    /// Verifies the exported chain and that it ends at the exported head.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::EvidenceExport;
+    /// let json = r#"{"format": "sherlock-evidence/1", "exported_at": "2024-01-01T00:00:00Z",
+    ///     "head_seq": 0, "head_hash": "0000000000000000000000000000000000000000000000000000000000000000",
+    ///     "entries": []}"#;
+    /// let export: EvidenceExport = serde_json::from_str(json).unwrap();
+    /// assert!(export.verify().is_empty());
+    /// ```
+    pub fn verify(&self) -> Vec<EvidenceProblem> {
+        let mut problems = verify_chain(&self.entries);
+        let (head_seq, head_hash) = self
+            .entries
+            .last()
+            .map_or((0, GENESIS_HASH), |last| (last.seq, last.hash.as_str()));
+        if head_seq != self.head_seq || head_hash != self.head_hash {
+            problems.push(EvidenceProblem::WrongHead);
+        }
+        problems
+    }
+}
+
+/// ## Description
+/// Verifies a chain of entries, from the first entry of the log, in order.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::{verify_chain, Backend, EvidenceProblem, StorageManager};
+/// let storage = StorageManager::open(Backend::Memory).unwrap();
+/// let case = storage.default_case().unwrap();
+/// storage
+///     .record_run(case.id, "user123", "facebook", "user", || Ok("found".into()))
+///     .unwrap();
+/// let mut entries = storage.export_evidence().unwrap().entries;
+/// assert!(verify_chain(&entries).is_empty());
+///
+/// entries[1].argv = vec!["forged".into()];
+/// assert_eq!(verify_chain(&entries), vec![EvidenceProblem::Modified { seq: 2 }]);
+/// ```
+pub fn verify_chain(entries: &[EvidenceEntry]) -> Vec<EvidenceProblem> {
+    let mut problems = vec![];
+    let mut prev: (u64, &str) = (0, GENESIS_HASH);
+    for entry in entries {
+        if entry.compute_hash() != entry.hash {
+            problems.push(EvidenceProblem::Modified { seq: entry.seq });
+        }
+        if entry.seq > prev.0 + 1 {
+            problems.push(EvidenceProblem::Missing {
+                from: prev.0 + 1,
+                to: entry.seq - 1,
+            });
+        }
+        if entry.seq <= prev.0 || entry.prev != prev.1 {
+            problems.push(EvidenceProblem::Broken { seq: entry.seq });
+        }
+        prev = (entry.seq, &entry.hash);
+    }
+    problems
+}
+
+/// ## Description
+/// Appends entries to the evidence log of a storage, chaining them to its last entry.
+///
+/// The head of the log is remembered between appends, so only the entries appended by
+/// someone else in the meantime are read.
+///
+/// The entries are logged once their records are saved. The entries that couldn't be appended
+/// (i.e: the storage was busy) are queued, and appended before the next ones.
+pub(crate) struct EvidenceLog {
+    state: Mutex<LogState>,
+}
+
+#[derive(Default)]
+struct LogState {
+    //the sequence number and hash of the last entry, `None` until it's read
+    head: Option<Option<(u64, String)>>,
+    //the entries waiting to be appended, in order
+    pending: VecDeque<EvidenceEntry>,
+}
+
+impl EvidenceLog {
+    pub(crate) fn new() -> EvidenceLog {
+        EvidenceLog {
+            state: Mutex::new(LogState::default()),
+        }
+    }
+
+    // queue the entries and append every queued entry, the ones left are appended next time
+    pub(crate) fn append(
+        &self,
+        storage: &dyn Storage,
+        entries: Vec<EvidenceEntry>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.pending.extend(entries);
+        state.flush(storage)
+    }
+
+    // append entries now or fail, nothing is queued: i.e: a purge is logged before it's done
+    pub(crate) fn append_now(
+        &self,
+        storage: &dyn Storage,
+        entries: Vec<EvidenceEntry>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.flush(storage)?;
+        state.pending.extend(entries);
+        let appended = state.flush(storage);
+        if appended.is_err() {
+            state.pending.clear();
+        }
+        appended
+    }
+
+    // append the queued entries, i.e: before the log is read
+    pub(crate) fn retry(&self, storage: &dyn Storage) -> Result<(), Error> {
+        self.state.lock().unwrap().flush(storage)
+    }
+}
+
+impl LogState {
+    fn flush(&mut self, storage: &dyn Storage) -> Result<(), Error> {
+        while let Some(mut entry) = self.pending.pop_front() {
+            let mut attempt = 1;
+            loop {
+                let current = match follow(storage, self.head.take().flatten()) {
+                    Ok(current) => current,
+                    Err(error) => {
+                        self.pending.push_front(entry);
+                        return Err(error);
+                    }
+                };
+                entry.link(current.as_ref());
+                match storage.append_evidence(&entry) {
+                    Ok(()) => {
+                        self.head = Some(Some((entry.seq, entry.hash.clone())));
+                        break;
+                    }
+                    //another writer took the sequence number, follow its entries and retry
+                    Err(Error::Conflict(_)) if attempt < APPEND_ATTEMPTS => {
+                        self.head = Some(current);
+                        attempt += 1;
+                    }
+                    Err(error) => {
+                        //the head is read again on the next append
+                        self.pending.push_front(entry);
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// the head after following the entries appended since `head`
+fn follow(
+    storage: &dyn Storage,
+    mut head: Option<(u64, String)>,
+) -> Result<Option<(u64, String)>, Error> {
+    loop {
+        let after = head.as_ref().map_or(0, |(seq, _)| *seq);
+        let page = storage.list_evidence(after, PAGE_SIZE)?;
+        if let Some(last) = page.last() {
+            head = Some((last.seq, last.hash.clone()));
+        }
+        if page.len() < PAGE_SIZE {
+            return Ok(head);
+        }
+    }
+}
+
+/// ## Description
+/// Reads the whole evidence log of a storage.
+pub(crate) fn read_log(storage: &dyn Storage) -> Result<Vec<EvidenceEntry>, Error> {
+    let mut entries: Vec<EvidenceEntry> = vec![];
+    loop {
+        let after = entries.last().map_or(0, |last| last.seq);
+        let page = storage.list_evidence(after, PAGE_SIZE)?;
+        let done = page.len() < PAGE_SIZE;
+        entries.extend(page);
+        if done {
+            return Ok(entries);
+        }
+    }
+}
+
+/// ## Description
+/// Checks an entry before a backend appends it.
+///
+/// `last_seq` is the sequence number of the log's last entry and `existing` the entry that
+/// has the new entry's sequence number, if any. Returns whether the entry must be written:
+/// appending an entry that's already there does nothing, i.e: a retried request.
+pub(crate) fn check_append(
+    entry: &EvidenceEntry,
+    last_seq: u64,
+    existing: Option<&EvidenceEntry>,
+) -> Result<bool, Error> {
+    match existing {
+        //the entry may have been redacted since
+        Some(existing) if *existing == redacted(entry, existing.redacted) => Ok(false),
+        Some(_) => Err(Error::Conflict(format!(
+            "evidence entry {} is taken",
+            entry.seq
+        ))),
+        None if entry.seq == last_seq + 1 => Ok(true),
+        None => Err(Error::Conflict(format!(
+            "evidence entry {} doesn't follow entry {}",
+            entry.seq, last_seq
+        ))),
+    }
+}
+
+// the entry as it's stored once redacted, if it is
+fn redacted(entry: &EvidenceEntry, redact: bool) -> EvidenceEntry {
+    let mut entry = entry.clone();
+    if redact {
+        entry.redact();
+    }
+    entry
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    crate::crypto::to_hex(&Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Case, MemoryStorage, Query};
+
+    fn run() -> (Run, RunResult) {
+        let query = Query::new(Case::new("case").id, "user123");
+        let mut run = Run::start(&query, "facebook", "user");
+        run.finish(Ok(()));
+        let result = RunResult::new(run.id, "found");
+        (run, result)
+    }
+
+    #[test]
+    fn chain() {
+        let storage = MemoryStorage::new();
+        let log = EvidenceLog::new();
+        let provenance = Provenance {
+            engine_version: Some("1.2".into()),
+            engine_digest: Some("ab".repeat(32)),
+            argv: vec!["python3".into(), "engine.py".into(), "user123".into()],
+        };
+        for _ in 0..3 {
+            let (run, result) = run();
+            log.append(
+                &storage,
+                vec![
+                    EvidenceEntry::for_run(&run, &provenance),
+                    EvidenceEntry::for_result(&result, &run, &provenance),
+                ],
+            )
+            .unwrap();
+        }
+        let entries = read_log(&storage).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0].prev, GENESIS_HASH);
+        assert_eq!(entries[5].seq, 6);
+        assert!(verify_chain(&entries).is_empty());
+
+        //another log on the same storage follows the chain
+        let (other, _) = run();
+        EvidenceLog::new()
+            .append(&storage, vec![EvidenceEntry::for_run(&other, &provenance)])
+            .unwrap();
+        let (next, _) = run();
+        log.append(&storage, vec![EvidenceEntry::for_run(&next, &provenance)])
+            .unwrap();
+        let entries = read_log(&storage).unwrap();
+        assert_eq!(entries.len(), 8);
+        assert!(verify_chain(&entries).is_empty());
+
+        //a modified entry
+        let mut modified = entries.clone();
+        modified[2].output_digest = Some("00".repeat(32));
+        assert_eq!(
+            verify_chain(&modified),
+            vec![EvidenceProblem::Modified { seq: 3 }]
+        );
+        //a modified entry that was hashed again breaks the link to it
+        modified[2].hash = modified[2].compute_hash();
+        assert_eq!(
+            verify_chain(&modified),
+            vec![EvidenceProblem::Broken { seq: 4 }]
+        );
+        //a removed entry
+        let mut removed = entries.clone();
+        removed.remove(4);
+        assert_eq!(
+            verify_chain(&removed),
+            vec![
+                EvidenceProblem::Missing { from: 5, to: 5 },
+                EvidenceProblem::Broken { seq: 6 }
+            ]
+        );
+        //a cut off export
+        let mut export = EvidenceExport::new(entries);
+        assert!(export.verify().is_empty());
+        export.entries.pop();
+        assert_eq!(export.verify(), vec![EvidenceProblem::WrongHead]);
+    }
+
+    #[test]
+    fn redaction() {
+        let (run, _) = run();
+        let provenance = Provenance {
+            argv: vec!["engine.py".into(), "user123".into()],
+            ..Default::default()
+        };
+        let mut entry = EvidenceEntry::for_run(&run, &provenance);
+        entry.link(None);
+        let mut redacted = entry.clone();
+        redacted.redact();
+        assert_eq!(redacted.argv[1], sha256_hex(b"user123"));
+        assert!(verify_chain(&[redacted.clone()]).is_empty());
+        //a redacted entry reads back the same
+        let json = serde_json::to_string(&redacted).unwrap();
+        assert_eq!(
+            serde_json::from_str::<EvidenceEntry>(&json).unwrap(),
+            redacted
+        );
+    }
+}
@@ -5,7 +5,1664 @@ This componenet is incharge of the communication with
 
 For more information check the wiki page:
  */
+pub use backends::{FileTreeStorage, MemoryStorage, RemoteStorage, SqliteStorage};
+pub use blobs::BlobStore;
+use chrono::Utc;
+pub use crypto::{KeySource, KEY_SIZE};
+pub use entities::extract_entities;
+use evidence::EvidenceLog;
+pub use evidence::{
+    verify_chain, EvidenceEntry, EvidenceExport, EvidenceProblem, EvidenceReport, Provenance,
+    EXPORT_FORMAT, GENESIS_HASH,
+};
+use model::Record;
+pub use model::{
+    Artifact, Case, CaseFile, CaseStatus, Change, ChangeOrigin, Entity, EntityKind, Note, Query,
+    Run, RunResult, RunStatus,
+};
+pub use retention::{PurgeReport, PurgedRun, RetentionPolicy};
+pub use search::{HitKind, SearchFilter, SearchHit};
+use search::{IndexedStorage, SearchIndex};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+pub use storage::{RunFilter, Storage};
+pub use sync::{Conflict, Connect, JournaledStorage, SyncEngine, SyncPhase, SyncStatus};
+use uuid::Uuid;
+pub use vault::{Vault, STORAGE_KEY_SECRET};
 
-pub struct StorageManager {}
+pub mod api;
+mod backends;
+mod blobs;
+mod crypto;
+mod entities;
+mod evidence;
+mod model;
+mod retention;
+mod search;
+mod storage;
+mod sync;
+mod vault;
 
-impl StorageManager {}
+/// The title of the case that runs are stored under when no case was chosen.
+pub const DEFAULT_CASE_TITLE: &str = "Default";
+
+/// ## Description
+/// The storage backends that StorageManager can work with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backend {
+    /// Everything in memory, lost when the manager is dropped.
+    Memory,
+    /// An embedded SQLite database, optionally encrypted at rest.
+    Sqlite { path: PathBuf, encrypted: bool },
+    /// Records as json files in a directory tree.
+    FileTree { root: PathBuf },
+    /// A remote storage server.
+    Remote {
+        /// The base url of the server's API.
+        url: String,
+        /// A reference to the auth token, resolved when connecting.
+        token: Option<String>,
+        /// Verify the server's certificate.
+        verify_tls: bool,
+        /// An extra CA certificate to trust.
+        ca_cert: Option<PathBuf>,
+    },
+}
+
+impl Backend {
+    /// ## Description
+    /// The files of a local storage, the ones `StorageManager::snapshot` copies: the database,
+    /// its blobs and its sync state, or the records' tree. Empty for the other storages.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use storage_manager::Backend;
+    /// let backend = Backend::Sqlite {
+    ///     path: PathBuf::from("store.db"),
+    ///     encrypted: false,
+    /// };
+    /// assert_eq!(backend.locations()[1], PathBuf::from("store.blobs"));
+    /// ```
+    pub fn locations(&self) -> Vec<PathBuf> {
+        match self {
+            Backend::Sqlite { path, .. } => vec![
+                path.clone(),
+                path.with_extension("blobs"),
+                path.with_extension("sync.json"),
+            ],
+            //the tree holds the blobs, the journal and the sync state too
+            Backend::FileTree { root } => vec![root.clone()],
+            Backend::Memory | Backend::Remote { .. } => vec![],
+        }
+    }
+}
+
+/// ## Description
+/// A run that's saved as started and waits for its engine, see `StorageManager::start_run`.
+///
+/// It can be handed to the thread that executes the engine, the engine leaves its files in
+/// `get_output_dir`. The directory is removed once the run is finished, or dropped.
+pub struct PendingRun {
+    case_id: Uuid,
+    run: Run,
+    output_dir: PathBuf,
+}
+
+impl PendingRun {
+    /// Gets the run as it was saved when it started.
+    pub fn get_run(&self) -> &Run {
+        &self.run
+    }
+
+    /// Gets the private directory the engine leaves its files in.
+    pub fn get_output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+}
+
+impl Drop for PendingRun {
+    fn drop(&mut self) {
+        //empty once the run finished, its files are kept by then
+        if !self.output_dir.as_os_str().is_empty() {
+            let _ = fs::remove_dir_all(&self.output_dir);
+        }
+    }
+}
+
+/// ## Description
+/// A struct that manages the storage of the system.
+pub struct StorageManager {
+    backend: Backend,
+    //keeps the search index up to date
+    storage: Arc<IndexedStorage>,
+    //the database of a sqlite storage, its key is rotated with the blobs' one
+    database: Option<Arc<SqliteStorage>>,
+    sync: Option<SyncEngine>,
+    //the files of the runs and the kept search index, `None` for a remote storage
+    blobs: Option<BlobStore>,
+    evidence: EvidenceLog,
+}
+
+impl StorageManager {
+    /// ## Description
+    /// Opens the storage of the given backend, preparing its location if needed.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let storage = StorageManager::open(Backend::FileTree {
+    ///     root: "./store".into(),
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn open(backend: Backend) -> Result<StorageManager, Error> {
+        StorageManager::open_with_key(backend, None)
+    }
+
+    /// ## Description
+    /// Opens the storage of the given backend, `key` unlocks an encrypted storage.
+    ///
+    /// An encrypted backend fails to open without a key,
+    /// the key is ignored by backends that aren't encrypted.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Backend, KeySource, StorageManager};
+    /// let key = KeySource::Passphrase("correct horse battery staple".into());
+    /// let storage = StorageManager::open_with_key(
+    ///     Backend::Sqlite {
+    ///         path: "./store.db".into(),
+    ///         encrypted: true,
+    ///     },
+    ///     Some(&key),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn open_with_key(
+        backend: Backend,
+        key: Option<&KeySource>,
+    ) -> Result<StorageManager, Error> {
+        StorageManager::open_backend(backend, key, None)
+    }
+
+    /// ## Description
+    /// Opens the storage of the given backend with the secrets of an unlocked vault:
+    /// the key of an encrypted storage and the `vault:<name>` token of a remote storage.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Backend, StorageManager, Vault};
+    /// let mut vault = Vault::open("./vault", "correct horse battery staple").unwrap();
+    /// let backend = Backend::Sqlite {
+    ///     path: "./store.db".into(),
+    ///     encrypted: true,
+    /// };
+    /// let storage = StorageManager::open_with_vault(backend, &mut vault).unwrap();
+    /// ```
+    pub fn open_with_vault(backend: Backend, vault: &mut Vault) -> Result<StorageManager, Error> {
+        let key = match &backend {
+            Backend::Sqlite {
+                encrypted: true, ..
+            } => Some(vault.storage_key()?),
+            _ => None,
+        };
+        StorageManager::open_backend(backend, key.as_ref(), Some(vault))
+    }
+
+    fn open_backend(
+        backend: Backend,
+        key: Option<&KeySource>,
+        vault: Option<&Vault>,
+    ) -> Result<StorageManager, Error> {
+        let database = match &backend {
+            Backend::Sqlite { path, encrypted } => {
+                //the database file is created on first use, its directory must exist
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir).map_err(|error| Error::Io(error.to_string()))?;
+                }
+                Some(Arc::new(match (encrypted, key) {
+                    (true, Some(key)) => SqliteStorage::open_encrypted(path, key)?,
+                    (true, None) => {
+                        return Err(Error::Crypto(
+                            "the storage is encrypted, a key is needed to open it".into(),
+                        ))
+                    }
+                    (false, _) => SqliteStorage::open(path)?,
+                }))
+            }
+            _ => None,
+        };
+        let storage: Arc<dyn Storage> = match &backend {
+            Backend::Memory => Arc::new(MemoryStorage::new()),
+            Backend::Sqlite { .. } => database.clone().expect("the database is open"),
+            Backend::FileTree { root } => Arc::new(FileTreeStorage::open(root)?),
+            Backend::Remote { .. } => Arc::new(connect_remote(&backend, vault)?),
+        };
+        //the blobs are encrypted along with the storage
+        let blobs = match &backend {
+            Backend::Memory => Some(BlobStore::in_memory()),
+            Backend::Sqlite { path, encrypted } => Some(BlobStore::open(
+                path.with_extension("blobs"),
+                key.filter(|_| *encrypted),
+            )?),
+            Backend::FileTree { root } => Some(BlobStore::open(root.join("blobs"), None)?),
+            Backend::Remote { .. } => None,
+        };
+        let index = match &backend {
+            Backend::Memory => None,
+            _ => blobs.as_ref().and_then(SearchIndex::take),
+        };
+        Ok(StorageManager {
+            backend,
+            storage: Arc::new(IndexedStorage::new(storage, index)),
+            database,
+            sync: None,
+            blobs,
+            evidence: EvidenceLog::new(),
+        })
+    }
+
+    /// ## Description
+    /// Creates a manager around a custom storage backend.
+    pub fn with_storage(backend: Backend, storage: Box<dyn Storage>) -> StorageManager {
+        StorageManager {
+            backend,
+            storage: Arc::new(IndexedStorage::new(Arc::from(storage), None)),
+            database: None,
+            sync: None,
+            blobs: None,
+            evidence: EvidenceLog::new(),
+        }
+    }
+
+    /// ## Description
+    /// Syncs the storage with a remote storage server, the storage is journaled from now on.
+    ///
+    /// Nothing is sent until `sync` is called, the server doesn't need to be reachable.
+    /// The sync state is kept next to the storage, i.e: `store.sync.json` next to `store.db`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let mut storage = StorageManager::open(Backend::Sqlite {
+    ///     path: "./store.db".into(),
+    ///     encrypted: false,
+    /// })
+    /// .unwrap();
+    /// let remote = Backend::Remote {
+    ///     url: "https://sherlock.example.com/api".into(),
+    ///     token: Some("env:SHERLOCK_TOKEN".into()),
+    ///     verify_tls: true,
+    ///     ca_cert: None,
+    /// };
+    /// storage.enable_sync(remote, None).unwrap();
+    /// let status = storage.get_sync().unwrap().sync();
+    /// ```
+    pub fn enable_sync(&mut self, remote: Backend, vault: Option<&Vault>) -> Result<(), Error> {
+        let state_path = match &self.backend {
+            Backend::Memory => None,
+            Backend::Sqlite { path, .. } => Some(path.with_extension("sync.json")),
+            Backend::FileTree { root } => Some(root.join("sync.json")),
+            Backend::Remote { .. } => {
+                return Err(Error::InvalidConfig(
+                    "a remote storage can't be synced".into(),
+                ))
+            }
+        };
+        check_remote(&remote)?;
+        let Backend::Remote {
+            url,
+            token,
+            verify_tls,
+            ca_cert,
+        } = remote
+        else {
+            return Err(Error::InvalidConfig(
+                "the storage can only be synced with a remote storage".into(),
+            ));
+        };
+        //resolve the token now, the vault may be locked again by the time the engine connects
+        let token = match token {
+            Some(reference) => Some(resolve_secret(&reference, vault)?),
+            None => None,
+        };
+
+        let local = match &self.sync {
+            Some(sync) => sync.get_local().clone(),
+            None => Arc::new(JournaledStorage::new(self.storage.get_inner().clone())?),
+        };
+        let connect: Connect = Box::new(move || {
+            let remote =
+                RemoteStorage::connect(&url, token.clone(), verify_tls, ca_cert.as_deref())?;
+            Ok(Arc::new(remote) as Arc<dyn Storage>)
+        });
+        self.sync = Some(SyncEngine::new(local.clone(), connect, state_path)?);
+        //the index is kept, the records are the same
+        self.storage = Arc::new(IndexedStorage::new(local, self.storage.take_index()));
+        Ok(())
+    }
+
+    /// ## Description
+    /// Re-encrypts an encrypted sqlite storage with a new key: the database's records, and the
+    /// data key of the runs' files (the files themselves are left as they are).
+    /// Other managers of the storage must open it again with the new key.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::{Backend, KeySource, StorageManager};
+    /// let old = KeySource::Passphrase("old passphrase".into());
+    /// let backend = Backend::Sqlite {
+    ///     path: "./store.db".into(),
+    ///     encrypted: true,
+    /// };
+    /// let storage = StorageManager::open_with_key(backend, Some(&old)).unwrap();
+    /// storage
+    ///     .rotate_key(&KeySource::Passphrase("new passphrase".into()))
+    ///     .unwrap();
+    /// ```
+    pub fn rotate_key(&self, key: &KeySource) -> Result<(), Error> {
+        let (Some(database), Some(blobs)) = (&self.database, &self.blobs) else {
+            return Err(Error::InvalidConfig(
+                "only a sqlite storage has a key".into(),
+            ));
+        };
+        if !database.is_encrypted() {
+            return Err(Error::InvalidConfig("the storage isn't encrypted".into()));
+        }
+        //the database is rotated in a transaction, the blobs' key file is replaced by a rename
+        database.rotate_key(Some(key))?;
+        blobs.rotate_key(key).map_err(|error| {
+            Error::Crypto(format!(
+                "the database was rotated, but not the key of its files: {}",
+                error
+            ))
+        })
+    }
+
+    /// Gets the store of the runs' files, `None` for a remote storage.
+    pub fn get_blobs(&self) -> Option<&BlobStore> {
+        self.blobs.as_ref()
+    }
+
+    /// ## Description
+    /// Reads the content of a file produced by a run.
+    pub fn read_artifact(&self, artifact: &Artifact) -> Result<Vec<u8>, Error> {
+        let blobs = self.blobs.as_ref().ok_or_else(|| {
+            Error::NotFound(format!("{}, the storage doesn't keep files", artifact.name))
+        })?;
+        blobs
+            .get(&artifact.digest)?
+            .ok_or_else(|| Error::NotFound(format!("the content of {}", artifact.name)))
+    }
+
+    /// Gets the sync engine, `None` if the storage isn't synced.
+    pub fn get_sync(&self) -> Option<&SyncEngine> {
+        self.sync.as_ref()
+    }
+
+    /// Gets the backend the storage was opened with.
+    pub fn get_backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// ## Description
+    /// Copies the files of the storage into `dir` as they are at this point: the database or
+    /// the records' tree, the runs' files and the sync state.
+    /// Returns the name of every copy in `dir` with the location it was copied from,
+    /// directories are copied whole.
+    ///
+    /// Fails for the in-memory and remote storages, they don't have files.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let dir = std::env::temp_dir().join(format!("snapshot-{}", uuid::Uuid::new_v4()));
+    /// let storage = StorageManager::open(Backend::Sqlite {
+    ///     path: dir.join("store.db"),
+    ///     encrypted: false,
+    /// })
+    /// .unwrap();
+    /// storage.default_case().unwrap();
+    /// let copied = storage.snapshot(&dir.join("copy")).unwrap();
+    /// assert_eq!(copied[0], ("store.db".to_owned(), dir.join("store.db")));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn snapshot(&self, dir: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+        let mut copied = vec![];
+        match &self.backend {
+            Backend::Sqlite { path, .. } => {
+                fs::create_dir_all(dir).map_err(|error| Error::Io(error.to_string()))?;
+                //the database first, the blobs copied after it are a superset of what it uses
+                let name = file_name(path, "store.db");
+                SqliteStorage::snapshot(path, dir.join(&name))?;
+                copied.push((name, path.clone()));
+                for location in self.backend.locations().into_iter().skip(1) {
+                    if location.exists() {
+                        let name = file_name(&location, "store");
+                        copy_tree(&location, &dir.join(&name))?;
+                        copied.push((name, location));
+                    }
+                }
+            }
+            Backend::FileTree { root } => {
+                let name = file_name(root, "tree");
+                copy_tree(root, &dir.join(&name))?;
+                copied.push((name, root.clone()));
+            }
+            Backend::Memory | Backend::Remote { .. } => {
+                return Err(Error::InvalidConfig(
+                    "only a local storage can be copied".into(),
+                ))
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Gets the storage backend's operations.
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// ## Description
+    /// Gets the case that runs are stored under when no case was chosen, creating it if needed.
+    pub fn default_case(&self) -> Result<Case, Error> {
+        let existing = self
+            .storage
+            .list_cases()?
+            .into_iter()
+            .find(|case| case.title == DEFAULT_CASE_TITLE);
+        match existing {
+            Some(case) => Ok(case),
+            None => {
+                let case = Case::new(DEFAULT_CASE_TITLE);
+                self.storage.save_case(&case)?;
+                Ok(case)
+            }
+        }
+    }
+
+    /// ## Description
+    /// Creates a new open case.
+    pub fn create_case(&self, title: &str, owner: Option<&str>) -> Result<Case, Error> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(Error::InvalidRecord("a case needs a title".into()));
+        }
+        let mut case = Case::new(title);
+        case.owner = owner.map(ToOwned::to_owned);
+        self.storage.save_case(&case)?;
+        Ok(case)
+    }
+
+    /// ## Description
+    /// Adds an analyst's note to a case.
+    pub fn add_note(&self, case_id: Uuid, author: Option<&str>, text: &str) -> Result<Note, Error> {
+        self.get_case(case_id)?;
+        let note = Note::new(case_id, author, text);
+        self.storage.save_note(&note)?;
+        Ok(note)
+    }
+
+    /// ## Description
+    /// Gets a case, `Error::NotFound` if there's none.
+    pub fn get_case(&self, case_id: Uuid) -> Result<Case, Error> {
+        self.storage
+            .get_case(case_id)?
+            .ok_or_else(|| Error::NotFound(format!("case {}", case_id)))
+    }
+
+    /// ## Description
+    /// Loads everything stored about a case: its queries, their runs and results, the
+    /// entities extracted in it and its notes.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.create_case("operation nightjar", Some("jane")).unwrap();
+    /// storage.add_note(case.id, Some("jane"), "same avatar on both").unwrap();
+    /// let file = storage.load_case(case.id).unwrap();
+    /// assert_eq!(file.notes.len(), 1);
+    /// ```
+    pub fn load_case(&self, case_id: Uuid) -> Result<CaseFile, Error> {
+        let case = self.get_case(case_id)?;
+        let queries = self.storage.list_queries(case_id)?;
+        let mut runs = vec![];
+        let mut results = vec![];
+        for query in &queries {
+            for run in self.storage.list_runs(query.id)? {
+                results.extend(self.storage.list_results(run.id)?);
+                runs.push(run);
+            }
+        }
+        Ok(CaseFile {
+            case,
+            queries,
+            runs,
+            results,
+            entities: self.storage.list_entities(case_id)?,
+            notes: self.storage.list_notes(case_id)?,
+        })
+    }
+
+    /// ## Description
+    /// Executes an engine run through `execute` and persists it.
+    ///
+    /// The query and the running run are saved before executing, so runs that never finish
+    /// (i.e: the application crashed) are kept as `Running`.
+    /// The run is updated with its outcome afterwards and the output is saved as its result,
+    /// the entities found in the output are added to the case (once per case).
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, RunStatus, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// let (run, output) = storage
+    ///     .record_run(case.id, "user123", "facebook", "user", || Ok("found".into()))
+    ///     .unwrap();
+    /// assert_eq!(run.status, RunStatus::Succeeded);
+    /// assert_eq!(output.unwrap(), "found");
+    /// ```
+    pub fn record_run<F>(
+        &self,
+        case_id: Uuid,
+        query: &str,
+        engine: &str,
+        command: &str,
+        execute: F,
+    ) -> Result<(Run, Result<String, String>), Error>
+    where
+        F: FnOnce() -> Result<String, String>,
+    {
+        self.record_run_with_files(case_id, query, engine, command, |_, _| execute())
+    }
+
+    /// ## Description
+    /// Executes an engine run like `record_run`, `execute` gets a scratch output directory and
+    /// sets how it executed the engine in the provenance it gets.
+    /// It's `start_run` and `finish_run` around `execute`, see them.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// let (run, _) = storage
+    ///     .record_run_with_files(case.id, "user123", "facebook", "user", |dir, provenance| {
+    ///         provenance.argv = vec!["facebook.py".into(), "-search_user".into(), "user123".into()];
+    ///         std::fs::write(dir.join("avatar.png"), b"png").unwrap();
+    ///         Ok("found".into())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(storage.read_artifact(&run.artifacts[0]).unwrap(), b"png");
+    /// ```
+    pub fn record_run_with_files<F>(
+        &self,
+        case_id: Uuid,
+        query: &str,
+        engine: &str,
+        command: &str,
+        execute: F,
+    ) -> Result<(Run, Result<String, String>), Error>
+    where
+        F: FnOnce(&Path, &mut Provenance) -> Result<String, String>,
+    {
+        let pending = self.start_run(case_id, query, engine, command)?;
+        let mut provenance = Provenance::default();
+        let output = execute(pending.get_output_dir(), &mut provenance);
+        self.finish_run(pending, output, &provenance)
+    }
+
+    /// ## Description
+    /// Saves the query and the running run of an engine run, and prepares the scratch output
+    /// directory of the engine. The engine may be executed on another thread, the run is
+    /// recorded by `finish_run` with what it ended with.
+    ///
+    /// The directory is a new private one next to the storage (in the temporary directory for
+    /// the in-memory and remote storages).
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, Provenance, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// let pending = storage.start_run(case.id, "user123", "facebook", "user").unwrap();
+    /// let dir = pending.get_output_dir().to_owned();
+    /// let output = std::thread::spawn(move || {
+    ///     std::fs::write(dir.join("avatar.png"), b"png").unwrap();
+    ///     Ok("found".into())
+    /// });
+    /// let output = output.join().unwrap();
+    /// let (run, _) = storage.finish_run(pending, output, &Provenance::default()).unwrap();
+    /// assert_eq!(run.artifacts[0].name, "avatar.png");
+    /// ```
+    pub fn start_run(
+        &self,
+        case_id: Uuid,
+        query: &str,
+        engine: &str,
+        command: &str,
+    ) -> Result<PendingRun, Error> {
+        let query = Query::new(case_id, query);
+        self.storage.save_query(&query)?;
+        let run = Run::start(&query, engine, command);
+        self.storage.save_run(&run)?;
+        Ok(PendingRun {
+            case_id,
+            run,
+            output_dir: self.scratch_dir()?,
+        })
+    }
+
+    /// ## Description
+    /// Records a run started by `start_run` with the output of its engine, and how the engine
+    /// was executed.
+    ///
+    /// The files left in the output directory (whether the run failed or not) are kept in the
+    /// blob store and listed in the run's `artifacts`, the directory is removed afterwards.
+    /// A run whose files can't be kept failed, its output is saved all the same.
+    ///
+    /// Once the run is saved it's recorded, whatever happens next: the finished run and its
+    /// result are appended to the evidence log with the provenance (or queued until the log
+    /// can be appended to) and the entities that can't be saved are left out.
+    pub fn finish_run(
+        &self,
+        mut pending: PendingRun,
+        output: Result<String, String>,
+        provenance: &Provenance,
+    ) -> Result<(Run, Result<String, String>), Error> {
+        let case_id = pending.case_id;
+        let mut run = pending.run.clone();
+        let output_dir = std::mem::take(&mut pending.output_dir);
+        let collected = match &self.blobs {
+            Some(blobs) => blobs.collect(&output_dir),
+            None => Ok(vec![]),
+        };
+        let _ = fs::remove_dir_all(&output_dir);
+
+        let mut outcome = output.as_ref().map(|_| ()).map_err(Clone::clone);
+        match collected {
+            Ok(artifacts) => run.artifacts = artifacts,
+            //the run failed, what it printed is kept anyway
+            Err(error) => {
+                let error = format!("the run's files couldn't be kept: {}", error);
+                outcome = Err(match outcome {
+                    Ok(()) => error,
+                    Err(failed) => format!("{}, {}", failed, error),
+                });
+            }
+        }
+        let results = match &output {
+            Ok(output) => vec![RunResult::new(run.id, output)],
+            Err(_) => vec![],
+        };
+        run.finish(outcome.clone());
+        //the outcome and the output are saved together
+        self.storage.save_run_with_results(&run, &results)?;
+        let mut entries = vec![EvidenceEntry::for_run(&run, provenance)];
+        for result in &results {
+            entries.push(EvidenceEntry::for_result(result, &run, provenance));
+        }
+        //the run is saved, the entries that aren't appended now are appended later
+        let _ = self.evidence.append(self.storage.as_ref(), entries);
+        if let Ok(output) = &output {
+            let _ = self.add_entities(case_id, run.id, output);
+        }
+        Ok((run, outcome.and(output)))
+    }
+
+    // add the entities found in a run's output to its case, the ones it has already are skipped
+    fn add_entities(&self, case_id: Uuid, run_id: Uuid, output: &str) -> Result<(), Error> {
+        let known: Vec<(EntityKind, String)> = self
+            .storage
+            .list_entities(case_id)?
+            .into_iter()
+            .map(|entity| (entity.kind, entity.value))
+            .collect();
+        for (kind, value) in extract_entities(output) {
+            if !known.contains(&(kind, value.clone())) {
+                let entity = Entity::new(case_id, Some(run_id), kind, &value);
+                self.storage.save_entity(&entity)?;
+            }
+        }
+        Ok(())
+    }
+
+    // a new private directory with a random name for the files of a run
+    fn scratch_dir(&self) -> Result<PathBuf, Error> {
+        let parent = match &self.backend {
+            Backend::Sqlite { path, .. } => Some(path.with_extension("scratch")),
+            Backend::FileTree { root } => Some(root.join("scratch")),
+            Backend::Memory | Backend::Remote { .. } => None,
+        };
+        if let Some(parent) = &parent {
+            create_private_dir(parent)?;
+        }
+        let parent = parent.unwrap_or_else(std::env::temp_dir);
+        //created here, not found: it can't be a directory (or a link) someone else prepared
+        let dir = parent.join(format!("sherlock-run-{}", Uuid::new_v4()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(&dir)
+            .map_err(|error| Error::Io(error.to_string()))?;
+        Ok(dir)
+    }
+
+    /// ## Description
+    /// Verifies the evidence log: its chain, and its entries against the stored records.
+    ///
+    /// Records merged from a remote storage by a sync were logged by the storage that
+    /// collected them, they aren't reported as unlogged.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// storage
+    ///     .record_run(case.id, "user123", "facebook", "user", || Ok("found".into()))
+    ///     .unwrap();
+    /// let report = storage.verify_evidence().unwrap();
+    /// assert_eq!(report.entries, 2);
+    /// assert!(report.is_intact());
+    /// ```
+    pub fn verify_evidence(&self) -> Result<EvidenceReport, Error> {
+        //the queued entries are unlogged until they're appended
+        let _ = self.evidence.retry(self.storage.as_ref());
+        let entries = evidence::read_log(self.storage.as_ref())?;
+        let mut problems = verify_chain(&entries);
+        //purged runs and their results are gone on purpose
+        let purged: HashSet<Uuid> = entries
+            .iter()
+            .filter(|entry| entry.kind == evidence::PURGES)
+            .map(|entry| entry.run_id)
+            .collect();
+
+        //the last entry of every record is the one it must match
+        let mut logged: HashMap<Uuid, &EvidenceEntry> = HashMap::new();
+        for entry in &entries {
+            logged.insert(entry.record_id, entry);
+        }
+        let mut checked: Vec<&EvidenceEntry> = logged.values().copied().collect();
+        checked.sort_by_key(|entry| entry.seq);
+        for entry in checked {
+            if purged.contains(&entry.run_id) {
+                continue;
+            }
+            let stored = match entry.kind.as_str() {
+                kind if kind == Run::KIND => self
+                    .storage
+                    .get_run(entry.record_id)?
+                    .map(|r| api::revision(&r)),
+                kind if kind == RunResult::KIND => self
+                    .storage
+                    .get_result(entry.record_id)?
+                    .map(|r| api::revision(&r)),
+                _ => continue,
+            };
+            let (seq, record_id) = (entry.seq, entry.record_id);
+            match stored {
+                None => problems.push(EvidenceProblem::RecordMissing { seq, record_id }),
+                Some(digest) if digest != entry.record_digest => {
+                    problems.push(EvidenceProblem::RecordChanged { seq, record_id })
+                }
+                Some(_) => (),
+            }
+        }
+
+        let merged = self.merged_records()?;
+        for case in self.storage.list_cases()? {
+            let filter = RunFilter {
+                case_id: Some(case.id),
+                ..Default::default()
+            };
+            for run in self.storage.find_runs(&filter)? {
+                //running runs are logged when they finish
+                if run.status == RunStatus::Running || merged.contains(&run.id) {
+                    continue;
+                }
+                let mut records = vec![(Run::KIND, run.id)];
+                for result in self.storage.list_results(run.id)? {
+                    records.push((RunResult::KIND, result.id));
+                }
+                for (kind, record_id) in records {
+                    if !logged.contains_key(&record_id) {
+                        problems.push(EvidenceProblem::Unlogged {
+                            kind: kind.to_owned(),
+                            record_id,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(EvidenceReport {
+            entries: entries.len(),
+            head: entries.last().map(|last| last.hash.clone()),
+            problems,
+        })
+    }
+
+    /// ## Description
+    /// Exports the evidence log for third-party checking, see `EvidenceExport::verify`.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let export = storage.export_evidence().unwrap();
+    /// let json = serde_json::to_string_pretty(&export).unwrap();
+    /// ```
+    pub fn export_evidence(&self) -> Result<EvidenceExport, Error> {
+        let _ = self.evidence.retry(self.storage.as_ref());
+        Ok(EvidenceExport::new(evidence::read_log(
+            self.storage.as_ref(),
+        )?))
+    }
+
+    /// ## Description
+    /// Purges the runs that expired under their retention policy: a case's own policy, or
+    /// `policy` for the cases without one. See the `retention` module for what's deleted.
+    ///
+    /// A dry run only reports what would be purged. Every purged run is recorded in the
+    /// evidence log, the report lists what was purged.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, RetentionPolicy, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// storage
+    ///     .record_run(case.id, "user123", "facebook", "user", || Ok("found".into()))
+    ///     .unwrap();
+    /// let policy = RetentionPolicy {
+    ///     max_age_days: Some(0),
+    ///     closed_case_days: None,
+    /// };
+    /// let report = storage.purge(&policy, true).unwrap();
+    /// assert_eq!(report.runs.len(), 1);
+    /// assert_eq!(storage.load_case(case.id).unwrap().runs.len(), 1);
+    /// ```
+    pub fn purge(&self, policy: &RetentionPolicy, dry_run: bool) -> Result<PurgeReport, Error> {
+        let now = Utc::now();
+        let mut expired: Vec<(Run, PurgedRun)> = vec![];
+        let mut kept: Vec<Run> = vec![];
+        for case in self.storage.list_cases()? {
+            let policy = case.retention.as_ref().unwrap_or(policy);
+            let filter = RunFilter {
+                case_id: Some(case.id),
+                ..Default::default()
+            };
+            let runs = self.storage.find_runs(&filter)?;
+            let reasons: Vec<Option<String>> = runs
+                .iter()
+                .map(|run| match run.status {
+                    RunStatus::Running => None,
+                    _ => policy.expired(run, &case, now),
+                })
+                .collect();
+            let entities = self.storage.list_entities(case.id)?;
+            let mut deleted_queries = HashSet::new();
+            for (run, reason) in runs.iter().zip(&reasons) {
+                let Some(reason) = reason else {
+                    kept.push(run.clone());
+                    continue;
+                };
+                //the query goes with the last of its runs
+                let query_used = runs
+                    .iter()
+                    .zip(&reasons)
+                    .any(|(other, reason)| other.query_id == run.query_id && reason.is_none());
+                let results = self.storage.list_results(run.id)?;
+                let purged = PurgedRun {
+                    run_id: run.id,
+                    case_id: case.id,
+                    query_id: run.query_id,
+                    engine: run.engine.clone(),
+                    started_at: run.started_at,
+                    reason: reason.clone(),
+                    results: results.iter().map(|result| result.id).collect(),
+                    entities: entities
+                        .iter()
+                        .filter(|entity| entity.run_id == Some(run.id))
+                        .map(|entity| entity.id)
+                        .collect(),
+                    artifacts: run.artifacts.clone(),
+                    query_deleted: !query_used && deleted_queries.insert(run.query_id),
+                };
+                expired.push((run.clone(), purged));
+            }
+        }
+        let mut report = PurgeReport {
+            dry_run,
+            at: now,
+            runs: vec![],
+        };
+        if dry_run {
+            report.runs = expired.into_iter().map(|(_, purged)| purged).collect();
+            return Ok(report);
+        }
+
+        //files another run shares are kept
+        let shared: HashSet<&str> = kept
+            .iter()
+            .flat_map(|run| &run.artifacts)
+            .map(|artifact| artifact.digest.as_str())
+            .collect();
+        for (run, purged) in expired {
+            //logged before the run goes, a purge that can't be logged isn't done
+            let entry = EvidenceEntry::for_purge(&run, &purged.reason);
+            self.evidence
+                .append_now(self.storage.as_ref(), vec![entry])?;
+            for entity in &purged.entities {
+                self.storage.delete_entity(*entity)?;
+            }
+            self.storage.delete_run(run.id)?;
+            if purged.query_deleted {
+                self.storage.delete_query(run.query_id)?;
+            }
+            if let Some(blobs) = &self.blobs {
+                for artifact in &run.artifacts {
+                    if !shared.contains(artifact.digest.as_str()) {
+                        blobs.remove(&artifact.digest)?;
+                    }
+                }
+            }
+            //the entries keep the digests of the query and of the files' names
+            let mut records = vec![run.id];
+            records.extend(&purged.results);
+            self.storage.redact_evidence(&records)?;
+            report.runs.push(purged);
+        }
+        Ok(report)
+    }
+
+    // the runs merged from the remote storage by a sync
+    fn merged_records(&self) -> Result<HashSet<Uuid>, Error> {
+        let mut merged = HashSet::new();
+        let Some(sync) = &self.sync else {
+            return Ok(merged);
+        };
+        let mut after = 0;
+        loop {
+            let changes = sync.get_local().list_changes(after, 500)?;
+            let Some(last) = changes.last() else {
+                return Ok(merged);
+            };
+            after = last.seq;
+            for change in &changes {
+                if change.kind == Run::KIND && change.origin == ChangeOrigin::Remote {
+                    merged.insert(change.record_id);
+                }
+            }
+        }
+    }
+
+    /// ## Description
+    /// Searches the engine outputs, notes and entities of every case, the best hits first.
+    ///
+    /// The first search indexes the storage unless the index was kept when it was last
+    /// closed, the records are indexed as they're saved from then on.
+    /// See the `search` module for the query syntax.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Backend, SearchFilter, StorageManager};
+    /// let storage = StorageManager::open(Backend::Memory).unwrap();
+    /// let case = storage.default_case().unwrap();
+    /// storage
+    ///     .record_run(case.id, "user123", "facebook", "user", || {
+    ///         Ok("phone: +1 (555) 010-0199".into())
+    ///     })
+    ///     .unwrap();
+    /// let hits = storage
+    ///     .search("555-010-0199", &SearchFilter::default(), 10)
+    ///     .unwrap();
+    /// assert_eq!(hits[0].engine.as_deref(), Some("facebook"));
+    /// ```
+    pub fn search(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let journal = self.sync.as_ref().map(|sync| sync.get_local().as_ref());
+        self.storage.search(query, filter, limit, journal)
+    }
+}
+
+impl Drop for StorageManager {
+    //the index is kept for the next time the storage is opened
+    fn drop(&mut self) {
+        if let (Some(blobs), Some(index)) = (&self.blobs, self.storage.take_index()) {
+            if self.backend != Backend::Memory {
+                let _ = index.keep(blobs);
+            }
+        }
+    }
+}
+
+// create a directory only the user can read, i.e: for the files of a run
+fn create_private_dir(dir: &Path) -> Result<(), Error> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .map_err(|error| Error::Io(error.to_string()))
+}
+
+// the file name of a location, `default` for locations like `.`
+fn file_name(location: &Path, default: &str) -> String {
+    location
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| default.to_owned())
+}
+
+// copy a file, or a directory with everything under it
+fn copy_tree(from: &Path, to: &Path) -> Result<(), Error> {
+    let io_error = |error: std::io::Error| Error::Io(format!("{}: {}", from.display(), error));
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ()).map_err(io_error);
+    }
+    fs::create_dir_all(to).map_err(io_error)?;
+    for entry in fs::read_dir(from).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+// check a remote backend's settings without connecting
+fn check_remote(remote: &Backend) -> Result<(), Error> {
+    if let Backend::Remote { url, ca_cert, .. } = remote {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Error::InvalidConfig(format!("invalid url: {}", url)));
+        }
+        if let Some(ca_cert) = ca_cert.as_ref().filter(|ca_cert| !ca_cert.is_file()) {
+            return Err(Error::InvalidConfig(format!(
+                "can't find the CA certificate: {}",
+                ca_cert.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn connect_remote(remote: &Backend, vault: Option<&Vault>) -> Result<RemoteStorage, Error> {
+    check_remote(remote)?;
+    match remote {
+        Backend::Remote {
+            url,
+            token,
+            verify_tls,
+            ca_cert,
+        } => {
+            let token = match token {
+                Some(reference) => Some(resolve_secret(reference, vault)?),
+                None => None,
+            };
+            RemoteStorage::connect(url, token, *verify_tls, ca_cert.as_deref())
+        }
+        _ => Err(Error::InvalidConfig("not a remote storage".into())),
+    }
+}
+
+/// ## Description
+/// Resolves a `vault:<name>` or `env:<variable>` secret reference.
+///
+/// Vault secrets need an unlocked vault.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::resolve_secret;
+/// std::env::set_var("SHERLOCK_DOC_TOKEN", "abc");
+/// assert_eq!(resolve_secret("env:SHERLOCK_DOC_TOKEN", None).unwrap(), "abc");
+/// assert!(resolve_secret("vault:remote_token", None).is_err());
+/// ```
+pub fn resolve_secret(reference: &str, vault: Option<&Vault>) -> Result<String, Error> {
+    match reference.split_once(':') {
+        Some(("env", name)) => std::env::var(name).map_err(|_| {
+            Error::InvalidConfig(format!("the environment variable {} isn't set", name))
+        }),
+        Some(("vault", name)) => {
+            let vault = vault
+                .ok_or_else(|| Error::Crypto(format!("`{}` needs an unlocked vault", reference)))?;
+            vault
+                .get(name)
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| Error::InvalidConfig(format!("no `{}` secret in the vault", name)))
+        }
+        _ => Err(Error::InvalidConfig(format!(
+            "invalid secret reference `{}`",
+            reference
+        ))),
+    }
+}
+
+/// ## Description
+/// Custom error for the Storage Manager crate.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Occurs when the storage can't be read or written.
+    Io(String),
+    /// Occurs when the storage settings are invalid.
+    InvalidConfig(String),
+    /// Occurs when a record can't be encoded or decoded.
+    Serialization(String),
+    /// Occurs when the database rejects a query or its schema can't be upgraded.
+    Database(String),
+    /// Occurs when encrypted data can't be sealed or opened, or a key is missing.
+    Crypto(String),
+    /// Occurs when the key doesn't unlock the encrypted storage or the vault.
+    WrongKey,
+    /// Occurs when the remote storage server can't be reached or refuses a request.
+    Remote(String),
+    /// Occurs when a record was changed by someone else since it was read.
+    Conflict(String),
+    /// Occurs when a record that's needed doesn't exist, i.e: the case of a note.
+    NotFound(String),
+    /// Occurs when a record is refused before it's saved, i.e: a case without a title.
+    InvalidRecord(String),
+    /// Occurs when a search query can't be parsed.
+    InvalidQuery(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "storage io error: {}", error),
+            Error::InvalidConfig(error) => write!(f, "invalid storage config: {}", error),
+            Error::Serialization(error) => write!(f, "storage serialization error: {}", error),
+            Error::Database(error) => write!(f, "storage database error: {}", error),
+            Error::Crypto(error) => write!(f, "storage encryption error: {}", error),
+            Error::WrongKey => write!(f, "wrong key, the storage can't be unlocked"),
+            Error::Remote(error) => write!(f, "remote storage error: {}", error),
+            Error::Conflict(error) => write!(f, "conflicting change: {}", error),
+            Error::NotFound(error) => write!(f, "not found: {}", error),
+            Error::InvalidRecord(error) => write!(f, "invalid record: {}", error),
+            Error::InvalidQuery(error) => write!(f, "invalid search query: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    #[test]
+    fn open_prepares_locations() {
+        let root =
+            std::env::temp_dir().join(format!("sherlock_open_storage_test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        StorageManager::open(Backend::FileTree {
+            root: root.join("tree"),
+        })
+        .unwrap();
+        assert!(root.join("tree").is_dir());
+
+        StorageManager::open(Backend::Sqlite {
+            path: root.join("db/store.db"),
+            encrypted: false,
+        })
+        .unwrap();
+        assert!(root.join("db/store.db").is_file());
+
+        //an encrypted database needs a key
+        let encrypted = Backend::Sqlite {
+            path: root.join("db/secure.db"),
+            encrypted: true,
+        };
+        assert!(matches!(
+            StorageManager::open(encrypted.clone()),
+            Err(Error::Crypto(_))
+        ));
+        let key = KeySource::Passphrase("secret".into());
+        StorageManager::open_with_key(encrypted, Some(&key)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn open_remote() {
+        let remote = |url: &str, ca_cert: Option<&str>| Backend::Remote {
+            url: url.into(),
+            token: None,
+            verify_tls: true,
+            ca_cert: ca_cert.map(Into::into),
+        };
+        assert!(matches!(
+            StorageManager::open(remote("example.com", None)),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            StorageManager::open(remote("https://example.com", Some("./no_ca.pem"))),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn enable_sync() {
+        let remote = |url: &str| Backend::Remote {
+            url: url.into(),
+            token: None,
+            verify_tls: true,
+            ca_cert: None,
+        };
+        let mut manager = StorageManager::open(Backend::Memory).unwrap();
+        assert!(matches!(
+            manager.enable_sync(remote("example.com"), None),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            manager.enable_sync(Backend::Memory, None),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        //the storage is journaled, and usable without reaching the server
+        manager
+            .enable_sync(remote("http://127.0.0.1:9"), None)
+            .unwrap();
+        manager.default_case().unwrap();
+        let status = manager.get_sync().unwrap().get_status().unwrap();
+        assert_eq!(status.phase, SyncPhase::Idle);
+        assert_eq!(status.pending, 8);
+    }
+
+    #[test]
+    fn search_follows_the_storage() {
+        let mut manager = StorageManager::open(Backend::Memory).unwrap();
+        let remote = Backend::Remote {
+            url: "http://127.0.0.1:9".into(),
+            token: None,
+            verify_tls: true,
+            ca_cert: None,
+        };
+        manager.enable_sync(remote, None).unwrap();
+        let case = manager.create_case("nightjar", None).unwrap();
+        let all = SearchFilter::default();
+        assert!(manager.search("avatar", &all, 10).unwrap().is_empty());
+
+        //saved through the manager, or only journaled (i.e: pulled by a sync)
+        manager.add_note(case.id, None, "same avatar").unwrap();
+        let note = Note::new(case.id, None, "another avatar");
+        manager.storage().save_note(&note).unwrap();
+        let hits = manager.search("avatar", &all, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.kind == HitKind::Note));
+        let filter = SearchFilter {
+            case_id: Some(Uuid::new_v4()),
+            ..Default::default()
+        };
+        assert!(manager.search("avatar", &filter, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_index_is_kept() {
+        let root =
+            std::env::temp_dir().join(format!("sherlock_kept_index_test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let backend = Backend::Sqlite {
+            path: root.join("store.db"),
+            encrypted: true,
+        };
+        let key = KeySource::Key(vec![7; KEY_SIZE]);
+        let all = SearchFilter::default();
+        let manager = StorageManager::open_with_key(backend.clone(), Some(&key)).unwrap();
+        let case = manager.default_case().unwrap();
+        let (_, output) = manager
+            .record_run(case.id, "user123", "facebook", "user", || {
+                Ok("phone: +1 (555) 010-0199".into())
+            })
+            .unwrap();
+        output.unwrap();
+        assert!(manager.search("avatar", &all, 10).unwrap().is_empty());
+        //saved past the manager
+        let note = Note::new(case.id, None, "same avatar");
+        manager.storage().save_note(&note).unwrap();
+        drop(manager);
+
+        //kept with the blobs, encrypted like them
+        let mut pending = vec![root.join("store.blobs")];
+        while let Some(path) = pending.pop() {
+            match path.is_dir() {
+                true => pending.extend(fs::read_dir(&path).unwrap().map(|e| e.unwrap().path())),
+                false => {
+                    assert!(!String::from_utf8_lossy(&fs::read(&path).unwrap()).contains("avatar"))
+                }
+            }
+        }
+        let manager = StorageManager::open_with_key(backend, Some(&key)).unwrap();
+        let index = manager.storage.take_index().expect("a kept index");
+        assert_eq!(index.search("avatar", &all, 10).unwrap()[0].id, note.id);
+        //the output and the phone entity
+        assert_eq!(index.search("555-010-0199", &all, 10).unwrap().len(), 2);
+        drop(manager);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn runs_whose_files_cant_be_kept() {
+        let root =
+            std::env::temp_dir().join(format!("sherlock_unkept_files_test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let manager = StorageManager::open(Backend::FileTree { root: root.clone() }).unwrap();
+        let case = manager.default_case().unwrap();
+        //the blobs can't be written
+        fs::remove_dir_all(root.join("blobs")).unwrap();
+        fs::write(root.join("blobs"), b"").unwrap();
+
+        let mut scratch = PathBuf::new();
+        let (run, output) = manager
+            .record_run_with_files(case.id, "user123", "facebook", "user", |dir, _| {
+                scratch = dir.to_path_buf();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = fs::metadata(dir).unwrap().permissions().mode();
+                    assert_eq!(mode & 0o777, 0o700);
+                }
+                fs::write(dir.join("avatar.png"), b"png").unwrap();
+                Ok("found".into())
+            })
+            .unwrap();
+        assert!(scratch.starts_with(root.join("scratch")) && !scratch.exists());
+        assert!(output.is_err());
+        assert!(matches!(&run.status, RunStatus::Failed(error) if error.contains("files")));
+        let saved = manager.storage().get_run(run.id).unwrap().unwrap();
+        assert_eq!(saved.status, run.status);
+        let results = manager.storage().list_results(run.id).unwrap();
+        assert_eq!(results[0].output, "found");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rotate_key() {
+        let root =
+            std::env::temp_dir().join(format!("sherlock_rotate_key_test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let backend = Backend::Sqlite {
+            path: root.join("store.db"),
+            encrypted: true,
+        };
+        let old = KeySource::Key(vec![1; KEY_SIZE]);
+        let new = KeySource::Key(vec![2; KEY_SIZE]);
+        let manager = StorageManager::open_with_key(backend.clone(), Some(&old)).unwrap();
+        let case = manager.default_case().unwrap();
+        let (run, _) = manager
+            .record_run_with_files(case.id, "user123", "facebook", "user", |dir, _| {
+                fs::write(dir.join("avatar.png"), b"png").unwrap();
+                Ok("found".into())
+            })
+            .unwrap();
+        manager.rotate_key(&new).unwrap();
+        drop(manager);
+
+        //the database and the files open with the new key only
+        assert!(matches!(
+            StorageManager::open_with_key(backend.clone(), Some(&old)),
+            Err(Error::WrongKey)
+        ));
+        let manager = StorageManager::open_with_key(backend, Some(&new)).unwrap();
+        assert_eq!(manager.read_artifact(&run.artifacts[0]).unwrap(), b"png");
+        drop(manager);
+        assert!(matches!(
+            StorageManager::open(Backend::Memory)
+                .unwrap()
+                .rotate_key(&new),
+            Err(Error::InvalidConfig(_))
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn record_runs() {
+        let manager = StorageManager::open(Backend::Memory).unwrap();
+        let case = manager.default_case().unwrap();
+        assert_eq!(manager.default_case().unwrap(), case);
+
+        //successful run with its result
+        let (run, _) = manager
+            .record_run(
+                case.id,
+                "user123",
+                "facebook",
+                "user",
+                || Ok("found".into()),
+            )
+            .unwrap();
+        let storage = manager.storage();
+        assert_eq!(
+            storage.get_run(run.id).unwrap().unwrap().status,
+            RunStatus::Succeeded
+        );
+        assert_eq!(storage.list_results(run.id).unwrap()[0].output, "found");
+
+        //failed run without a result
+        let (run, output) = manager
+            .record_run(case.id, "user123", "facebook", "user", || {
+                Err("timeout".into())
+            })
+            .unwrap();
+        assert_eq!(output, Err("timeout".into()));
+        assert_eq!(run.status, RunStatus::Failed("timeout".into()));
+        assert!(storage.list_results(run.id).unwrap().is_empty());
+        assert_eq!(storage.list_queries(case.id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn evidence_log() {
+        let manager = StorageManager::open(Backend::Memory).unwrap();
+        let case = manager.default_case().unwrap();
+        let provenance = Provenance {
+            engine_version: Some("1.0".into()),
+            engine_digest: None,
+            argv: vec!["facebook.py".into(), "user123".into()],
+        };
+        let (run, _) = manager
+            .record_run_with_files(case.id, "user123", "facebook", "user", |_, recorded| {
+                *recorded = provenance.clone();
+                Ok("found".into())
+            })
+            .unwrap();
+        //a failed run has no result to log
+        let (_, output) = manager
+            .record_run(case.id, "user123", "facebook", "user", || {
+                Err("timeout".into())
+            })
+            .unwrap();
+        assert!(output.is_err());
+        let report = manager.verify_evidence().unwrap();
+        assert_eq!(report.entries, 3);
+        assert!(report.is_intact());
+        let export = manager.export_evidence().unwrap();
+        assert_eq!(export.entries[0].argv, provenance.argv);
+        let digest = crypto::to_hex(&sha2::Sha256::digest(b"found"));
+        assert_eq!(export.entries[1].output_digest, Some(digest));
+
+        //an altered result and a run that went around the log are found
+        let storage = manager.storage();
+        let mut result = storage.list_results(run.id).unwrap().remove(0);
+        result.output = "nothing found".into();
+        storage.save_result(&result).unwrap();
+        let query = Query::new(case.id, "user123");
+        let mut unlogged = Run::start(&query, "facebook", "user");
+        unlogged.finish(Ok(()));
+        storage.save_run(&unlogged).unwrap();
+        let report = manager.verify_evidence().unwrap();
+        assert_eq!(
+            report.problems,
+            vec![
+                EvidenceProblem::RecordChanged {
+                    seq: 2,
+                    record_id: result.id
+                },
+                EvidenceProblem::Unlogged {
+                    kind: "runs".into(),
+                    record_id: unlogged.id
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn evidence_appended_later() {
+        let root = std::env::temp_dir().join(format!(
+            "sherlock_evidence_later_test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let manager = StorageManager::open(Backend::FileTree { root: root.clone() }).unwrap();
+        let case = manager.default_case().unwrap();
+        //the log can't be read or appended to
+        fs::create_dir_all(root.join("evidence.jsonl")).unwrap();
+        let (run, output) = manager
+            .record_run(case.id, "user123", "facebook", "user", || {
+                Ok("jane@example.com".into())
+            })
+            .unwrap();
+        assert_eq!(output.unwrap(), "jane@example.com");
+        assert_eq!(run.status, RunStatus::Succeeded);
+        assert_eq!(manager.storage().list_entities(case.id).unwrap().len(), 1);
+        assert!(manager.verify_evidence().is_err());
+
+        //the queued entries are appended once it can be
+        fs::remove_dir(root.join("evidence.jsonl")).unwrap();
+        let report = manager.verify_evidence().unwrap();
+        assert_eq!(report.entries, 2);
+        assert!(report.is_intact());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn purge_expired_runs() {
+        let manager = StorageManager::open(Backend::Memory).unwrap();
+        let mut expired = manager.create_case("expired", None).unwrap();
+        expired.retention = Some(RetentionPolicy {
+            max_age_days: Some(0),
+            closed_case_days: None,
+        });
+        manager.storage().save_case(&expired).unwrap();
+        let kept = manager.create_case("kept", None).unwrap();
+        let record = |case_id: Uuid, file: &'static [u8], output: &'static str| {
+            let (run, _) = manager
+                .record_run_with_files(case_id, "user123", "facebook", "user", |dir, provenance| {
+                    provenance.argv = vec!["facebook.py".into(), "user123".into()];
+                    fs::write(dir.join("avatar.png"), file).unwrap();
+                    Ok(output.into())
+                })
+                .unwrap();
+            run
+        };
+        record(expired.id, b"png", "jane@example.com");
+        let unique = record(expired.id, b"gif", "jane@example.com");
+        let shared = record(kept.id, b"png", "found");
+        let all = SearchFilter::default();
+        assert!(!manager.search("jane", &all, 10).unwrap().is_empty());
+
+        //a dry run only lists the expired runs
+        let report = manager.purge(&RetentionPolicy::default(), true).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.runs[0].entities.len(), 1);
+        assert_eq!(manager.load_case(expired.id).unwrap().runs.len(), 2);
+
+        let report = manager.purge(&RetentionPolicy::default(), false).unwrap();
+        let purged_ids: Vec<Uuid> = report
+            .runs
+            .iter()
+            .flat_map(|run| [vec![run.run_id], run.results.clone()].concat())
+            .collect();
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.runs[0].reason, "older than 0 days");
+        let file = manager.load_case(expired.id).unwrap();
+        assert!(file.queries.is_empty() && file.runs.is_empty());
+        assert!(file.results.is_empty() && file.entities.is_empty());
+        assert_eq!(
+            manager.load_case(kept.id).unwrap().runs,
+            vec![shared.clone()]
+        );
+        assert!(manager.search("jane", &all, 10).unwrap().is_empty());
+
+        //the files only the purged runs had are gone
+        let blobs = manager.get_blobs().unwrap();
+        assert_eq!(blobs.get(&unique.artifacts[0].digest).unwrap(), None);
+        assert_eq!(manager.read_artifact(&shared.artifacts[0]).unwrap(), b"png");
+
+        //the purges are logged, the log stays intact
+        let report = manager.verify_evidence().unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        let export = manager.export_evidence().unwrap();
+        let purges: Vec<_> = export
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == "purges")
+            .collect();
+        assert_eq!(purges.len(), 2);
+        assert_eq!(purges[1].record_id, unique.id);
+        assert_eq!(purges[1].note.as_deref(), Some("older than 0 days"));
+
+        //the entries of the purged runs don't hold the query or the files' names anymore
+        for entry in &export.entries {
+            let purged = purged_ids.contains(&entry.record_id);
+            assert_eq!(entry.redacted, purged);
+            assert_eq!(entry.argv.contains(&"user123".to_owned()), !purged);
+            let names = entry.artifacts.iter().any(|a| a.name == "avatar.png");
+            assert_eq!(names, !purged && entry.record_id == shared.id);
+        }
+        assert!(manager
+            .purge(&RetentionPolicy::default(), false)
+            .unwrap()
+            .runs
+            .is_empty());
+    }
+
+    #[test]
+    fn cases() {
+        let manager = StorageManager::open(Backend::Memory).unwrap();
+        assert!(matches!(
+            manager.create_case(" ", None),
+            Err(Error::InvalidRecord(_))
+        ));
+        let case = manager.create_case("nightjar", Some("jane")).unwrap();
+        assert_eq!(case.owner.as_deref(), Some("jane"));
+        assert_eq!(case.status, CaseStatus::Open);
+        assert!(matches!(
+            manager.add_note(Uuid::new_v4(), None, "lost"),
+            Err(Error::NotFound(_))
+        ));
+        manager
+            .add_note(case.id, Some("jane"), "check the avatars")
+            .unwrap();
+
+        //the entities of the outputs are kept once per case
+        for _ in 0..2 {
+            let (_, output) = manager
+                .record_run(case.id, "user123", "facebook", "user", || {
+                    Ok("https://facebook.com/user123 jane@example.com".into())
+                })
+                .unwrap();
+            assert!(output.is_ok());
+        }
+        let file = manager.load_case(case.id).unwrap();
+        assert_eq!(file.case, case);
+        assert_eq!((file.queries.len(), file.runs.len()), (2, 2));
+        assert_eq!(file.results.len(), 2);
+        assert_eq!(file.notes[0].text, "check the avatars");
+        let entities: Vec<_> = file.entities.iter().map(|e| e.kind).collect();
+        assert_eq!(entities, vec![EntityKind::Url, EntityKind::Email]);
+        assert_eq!(file.entities[0].run_id, Some(file.runs[0].id));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::RetentionPolicy;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// ## Description
+/// An investigation, the queries and engine runs of an investigation are kept under its case
+/// along with the entities found on the way and the analysts' notes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Case {
+    pub id: Uuid,
+    pub title: String,
+    #[serde(default)]
+    pub status: CaseStatus,
+    /// The analyst in charge of the case.
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the case was closed (or archived), `None` while it's open.
+    #[serde(default)]
+    pub closed_at: Option<DateTime<Utc>>,
+    /// How long the runs of the case are kept, `None` follows the global policy.
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+}
+
+/// ## Description
+/// Where an investigation stands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum CaseStatus {
+    #[default]
+    Open,
+    Closed,
+    /// Closed and kept for the records only.
+    Archived,
+}
+
+impl Case {
+    /// ## Description
+    /// Creates a new case with a fresh id.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::Case;
+    /// let case = Case::new("missing person");
+    /// assert_eq!(case.title, "missing person");
+    /// ```
+    pub fn new(title: &str) -> Case {
+        Case {
+            id: Uuid::new_v4(),
+            title: title.to_owned(),
+            status: CaseStatus::Open,
+            owner: None,
+            tags: vec![],
+            created_at: Utc::now(),
+            closed_at: None,
+            retention: None,
+        }
+    }
+
+    /// ## Description
+    /// Changes the status of the case, closing it stamps `closed_at` and reopening it clears it.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Case, CaseStatus};
+    /// let mut case = Case::new("missing person");
+    /// case.set_status(CaseStatus::Closed);
+    /// assert!(case.closed_at.is_some());
+    /// ```
+    pub fn set_status(&mut self, status: CaseStatus) {
+        self.closed_at = match status {
+            CaseStatus::Open => None,
+            //archiving a closed case keeps the time it was closed
+            _ => self.closed_at.or_else(|| Some(Utc::now())),
+        };
+        self.status = status;
+    }
+}
+
+/// ## Description
+/// A query that was searched for inside a case, i.e: a username or a phone number.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Query {
+    pub id: Uuid,
+    pub case_id: Uuid,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Query {
+    /// Creates a new query of a case.
+    pub fn new(case_id: Uuid, text: &str) -> Query {
+        Query {
+            id: Uuid::new_v4(),
+            case_id,
+            text: text.to_owned(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// ## Description
+/// The state of an engine run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed(String),
+}
+
+/// ## Description
+/// A single execution of an engine's command for a query.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Run {
+    pub id: Uuid,
+    pub case_id: Uuid,
+    pub query_id: Uuid,
+    pub engine: String,
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub status: RunStatus,
+    /// The files the engine left in its output directory, their contents are in the blob store.
+    #[serde(default)]
+    pub artifacts: Vec<Artifact>,
+}
+
+/// ## Description
+/// A file produced by an engine run, i.e: a downloaded avatar.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Artifact {
+    /// The path of the file in the run's output directory, with `/` separators.
+    pub name: String,
+    /// The SHA-256 of the content (hex), the content's address in the blob store.
+    pub digest: String,
+    /// The size of the content in bytes.
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub size: u64,
+}
+
+impl Run {
+    /// ## Description
+    /// Creates a running engine run of a query.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::{Case, Query, Run, RunStatus};
+    /// let query = Query::new(Case::new("case").id, "user123");
+    /// let mut run = Run::start(&query, "facebook", "user");
+    /// run.finish(Ok(()));
+    /// assert_eq!(run.status, RunStatus::Succeeded);
+    /// ```
+    pub fn start(query: &Query, engine: &str, command: &str) -> Run {
+        Run {
+            id: Uuid::new_v4(),
+            case_id: query.case_id,
+            query_id: query.id,
+            engine: engine.to_owned(),
+            command: command.to_owned(),
+            started_at: Utc::now(),
+            finished_at: None,
+            status: RunStatus::Running,
+            artifacts: vec![],
+        }
+    }
+
+    /// Marks the run as finished, successfully or with an error.
+    pub fn finish(&mut self, outcome: Result<(), String>) {
+        self.finished_at = Some(Utc::now());
+        self.status = match outcome {
+            Ok(()) => RunStatus::Succeeded,
+            Err(error) => RunStatus::Failed(error),
+        };
+    }
+}
+
+/// ## Description
+/// The output an engine run produced.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct RunResult {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub output: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RunResult {
+    /// Creates a new result of a run.
+    pub fn new(run_id: Uuid, output: &str) -> RunResult {
+        RunResult {
+            id: Uuid::new_v4(),
+            run_id,
+            output: output.to_owned(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// ## Description
+/// Something found during an investigation, i.e: an email address in an engine's output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Entity {
+    pub id: Uuid,
+    pub case_id: Uuid,
+    /// The run the entity was extracted from, `None` when an analyst added it.
+    pub run_id: Option<Uuid>,
+    pub kind: EntityKind,
+    pub value: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// ## Description
+/// The kinds of entities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum EntityKind {
+    Email,
+    Phone,
+    Url,
+    Username,
+    Other,
+}
+
+impl Entity {
+    /// Creates a new entity of a case.
+    pub fn new(case_id: Uuid, run_id: Option<Uuid>, kind: EntityKind, value: &str) -> Entity {
+        Entity {
+            id: Uuid::new_v4(),
+            case_id,
+            run_id,
+            kind,
+            value: value.to_owned(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// ## Description
+/// A note an analyst wrote in a case.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Note {
+    pub id: Uuid,
+    pub case_id: Uuid,
+    pub author: Option<String>,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Note {
+    /// Creates a new note of a case.
+    pub fn new(case_id: Uuid, author: Option<&str>, text: &str) -> Note {
+        let now = Utc::now();
+        Note {
+            id: Uuid::new_v4(),
+            case_id,
+            author: author.map(ToOwned::to_owned),
+            text: text.to_owned(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Replaces the text of the note.
+    pub fn edit(&mut self, text: &str) {
+        self.text = text.to_owned();
+        self.updated_at = Utc::now();
+    }
+}
+
+/// ## Description
+/// Everything stored about a case, i.e: to show or to export it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct CaseFile {
+    pub case: Case,
+    pub queries: Vec<Query>,
+    pub runs: Vec<Run>,
+    pub results: Vec<RunResult>,
+    pub entities: Vec<Entity>,
+    pub notes: Vec<Note>,
+}
+
+/// ## Description
+/// A change of one field of a record, the unit of the journal that storages are synced with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    /// The position in the journal, assigned when the change is appended.
+    pub seq: u64,
+    /// The kind of the record, i.e: `cases`.
+    pub kind: String,
+    pub record_id: Uuid,
+    /// The name of the field, `$deleted` when the record was deleted.
+    pub field: String,
+    /// The new value of the field.
+    pub value: serde_json::Value,
+    /// When the field was changed, where it was changed.
+    pub at: DateTime<Utc>,
+    pub origin: ChangeOrigin,
+}
+
+/// ## Description
+/// Where a change of the journal was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum ChangeOrigin {
+    /// By this storage's user.
+    Local,
+    /// In another storage, and synced to this one.
+    Remote,
+}
+
+/// ## Description
+/// Common access to the stored records, used by the backends' generic helpers.
+pub(crate) trait Record: Clone + Serialize + serde::de::DeserializeOwned {
+    /// The name of the records' collection, i.e: a table or a directory name.
+    const KIND: &'static str;
+    fn id(&self) -> Uuid;
+    fn created_at(&self) -> DateTime<Utc>;
+}
+
+impl Record for Case {
+    const KIND: &'static str = "cases";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl Record for Query {
+    const KIND: &'static str = "queries";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl Record for Run {
+    const KIND: &'static str = "runs";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+}
+
+impl Record for RunResult {
+    const KIND: &'static str = "results";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl Record for Entity {
+    const KIND: &'static str = "entities";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl Record for Note {
+    const KIND: &'static str = "notes";
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
@@ -0,0 +1,192 @@
+/*
+Retention:
+Personal data is only kept as long as a retention policy allows. A policy sets how long a run is
+kept after it started and how long after its case was closed, a case's own policy replaces the
+global one. Runs that are still running are never purged.
+
+Purging an expired run deletes it with its results, the entities extracted from its output,
+its query once no other run uses it, and the files no other run shares. The backends delete
+securely: files are zeroed before they're unlinked, SQLite overwrites the deleted content
+(`secure_delete`), and the journal's values of the deleted records are erased. Synced storages
+delete the records on the other side too (see `JournaledStorage`).
+
+Every purged run is recorded in the evidence log as a `purges` entry with the reason. The log
+keeps the digests of what was purged, and the argv of the purged runs: its entries can't be
+changed without breaking the chain.
+
+A dry run lists what would be purged without deleting anything.
+ */
+use crate::{Artifact, Case, CaseStatus, Run};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{ErrorKind, Seek, SeekFrom, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+/// ## Description
+/// How long the runs are kept, unset limits keep them forever.
+//the TypeScript bindings of the messages crate leave the example out
+#[cfg_attr(
+    any(doc, doctest),
+    doc = r##"
+## Example
+**Basic usage:**
+```
+# use storage_manager::{Case, Query, RetentionPolicy, Run};
+let policy = RetentionPolicy {
+    max_age_days: Some(30),
+    closed_case_days: None,
+};
+let case = Case::new("case");
+let run = Run::start(&Query::new(case.id, "user123"), "facebook", "user");
+assert_eq!(policy.expired(&run, &case, chrono::Utc::now()), None);
+```
+"##
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct RetentionPolicy {
+    /// The days a run is kept after it started.
+    pub max_age_days: Option<u32>,
+    /// The days the runs of a closed (or archived) case are kept after it was closed.
+    pub closed_case_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    /// ## Description
+    /// Checks whether a run of a case is expired at `now`, returns why it is.
+    ///
+    /// Cases closed before their closing time was recorded only expire by age.
+    pub fn expired(&self, run: &Run, case: &Case, now: DateTime<Utc>) -> Option<String> {
+        if let Some(days) = self.max_age_days {
+            if now >= run.started_at + Duration::days(days.into()) {
+                return Some(format!("older than {} days", days));
+            }
+        }
+        if let (Some(days), Some(closed_at)) = (self.closed_case_days, case.closed_at) {
+            if case.status != CaseStatus::Open && now >= closed_at + Duration::days(days.into()) {
+                return Some(format!("case closed for {} days", days));
+            }
+        }
+        None
+    }
+}
+
+/// ## Description
+/// What a purge deleted, or would delete for a dry run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PurgeReport {
+    pub dry_run: bool,
+    /// When the purge was made.
+    pub at: DateTime<Utc>,
+    pub runs: Vec<PurgedRun>,
+}
+
+/// ## Description
+/// An expired run and the records purged with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PurgedRun {
+    pub run_id: Uuid,
+    pub case_id: Uuid,
+    pub query_id: Uuid,
+    pub engine: String,
+    pub started_at: DateTime<Utc>,
+    /// Why the run expired, i.e: `older than 30 days`.
+    pub reason: String,
+    /// The ids of the run's results.
+    pub results: Vec<Uuid>,
+    /// The ids of the entities extracted from the run's output.
+    pub entities: Vec<Uuid>,
+    /// The files of the run, the ones another run shares stay in the blob store.
+    pub artifacts: Vec<Artifact>,
+    /// Whether the query goes too, no other run uses it.
+    pub query_deleted: bool,
+}
+
+/// ## Description
+/// Replaces the content of a file atomically, then zeroes the old content
+/// so it doesn't linger in the freed blocks.
+///
+/// The new content is written to `<name>.tmp` and renamed over the file, a crash leaves
+/// either the old or the new content. The old file is zeroed through a handle kept open.
+pub(crate) fn overwrite_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut old = fs::OpenOptions::new().write(true).open(path)?;
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temporary = path.with_file_name(name);
+    let mut file = fs::File::create(&temporary)?;
+    fs::set_permissions(&temporary, old.metadata()?.permissions())?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        //the rename is only durable once the directory is synced
+        fs::File::open(parent)?.sync_all()?;
+    }
+    zero_file(&mut old)
+}
+
+/// ## Description
+/// Zeroes a file then removes it, a missing file is ignored.
+pub(crate) fn wipe_file(path: &Path) -> std::io::Result<()> {
+    let zeroed = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut file| zero_file(&mut file));
+    match zeroed {
+        Ok(()) => fs::remove_file(path),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+// overwrite the whole content of a file with zeroes, its length stays
+fn zero_file(file: &mut fs::File) -> std::io::Result<()> {
+    let len = file.metadata()?.len() as usize;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&vec![0; len])?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Query;
+
+    #[test]
+    fn policies() {
+        let mut case = Case::new("case");
+        let mut run = Run::start(&Query::new(case.id, "user123"), "facebook", "user");
+        let now = Utc::now();
+        run.started_at = now - Duration::days(10);
+
+        let by_age = RetentionPolicy {
+            max_age_days: Some(10),
+            closed_case_days: None,
+        };
+        assert_eq!(
+            by_age.expired(&run, &case, now).as_deref(),
+            Some("older than 10 days")
+        );
+        assert_eq!(by_age.expired(&run, &case, now - Duration::hours(1)), None);
+        assert_eq!(RetentionPolicy::default().expired(&run, &case, now), None);
+
+        //closed cases count from their closing, open ones never expire that way
+        let by_closing = RetentionPolicy {
+            max_age_days: None,
+            closed_case_days: Some(0),
+        };
+        assert_eq!(by_closing.expired(&run, &case, now), None);
+        case.set_status(CaseStatus::Closed);
+        assert!(by_closing.expired(&run, &case, Utc::now()).is_some());
+        case.set_status(CaseStatus::Open);
+        assert_eq!(case.closed_at, None);
+        assert_eq!(by_closing.expired(&run, &case, Utc::now()), None);
+    }
+}
@@ -0,0 +1,929 @@
+/*
+Search:
+A full-text index over the stored engine outputs, notes and entities, independent of the
+backend. Between the times the storage is opened it's kept in the blob store, encrypted like
+the blobs.
+
+Texts are split into lowercase alphanumeric terms, i.e: `+1 (555) 010-0199` is the terms
+`1 555 010 0199`. The phone numbers (as `extract_entities` finds them) are indexed by their
+digits too, without leading zeros and reversed: a phone in a query matches the phones that end
+with it, i.e: `555-010-0199` finds `+1 (555) 010-0199`, the `+15550100199` entity and
+`(555) 010 0199`.
+
+Query syntax, every clause must match:
+    jane                    a term
+    jane*                   a prefix
+    "jane doe"              a phrase, the terms next to each other
+    555-010-0199            a phone, or a phrase if it isn't shaped like one
+    "jane d*"               a phrase ending with a prefix
+
+The index is taken from the blob store when the storage is opened, or built from the storage
+on the first search. `IndexedStorage` then updates it with every record saved or deleted
+through it and, when the storage is synced, with the records the journal gained since (i.e:
+pulled from the server).
+ */
+use crate::entities::find_phones;
+use crate::model::Record;
+use crate::{
+    BlobStore, Case, Change, Entity, Error, EvidenceEntry, JournaledStorage, Note, Query, Run,
+    RunFilter, RunResult, Storage,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// The terms shown before the first match of a snippet.
+const SNIPPET_LEAD: usize = 8;
+/// The terms of a snippet.
+const SNIPPET_TERMS: usize = 32;
+/// What the terms of phones start with, the other terms are alphanumeric.
+const PHONE_PREFIX: &str = "tel:";
+
+/// ## Description
+/// The kinds of indexed records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum HitKind {
+    Result,
+    Note,
+    Entity,
+}
+
+/// ## Description
+/// The filters of a search, records must match every given filter.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(default)]
+pub struct SearchFilter {
+    pub case_id: Option<Uuid>,
+    /// The engine of the run, notes don't match an engine.
+    pub engine: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// ## Description
+/// A record that matched a search.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SearchHit {
+    pub kind: HitKind,
+    /// The id of the result, note or entity.
+    pub id: Uuid,
+    pub case_id: Uuid,
+    pub run_id: Option<Uuid>,
+    pub engine: Option<String>,
+    pub at: DateTime<Utc>,
+    /// The part of the text around the first match.
+    pub snippet: String,
+    /// The byte ranges of the matches in the snippet.
+    pub highlights: Vec<(usize, usize)>,
+    /// The number of matches, hits are sorted by it.
+    pub score: usize,
+}
+
+// a term of a text and its byte range
+struct Term {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+// a record's text, only the text is kept with the index, the terms are split again
+#[derive(Serialize, Deserialize)]
+struct Document {
+    kind: HitKind,
+    case_id: Uuid,
+    run_id: Option<Uuid>,
+    engine: Option<String>,
+    at: DateTime<Utc>,
+    text: String,
+    #[serde(skip)]
+    terms: Vec<Term>,
+    #[serde(skip)]
+    phones: Vec<Term>,
+}
+
+impl Document {
+    fn new(
+        kind: HitKind,
+        case_id: Uuid,
+        run_id: Option<Uuid>,
+        at: DateTime<Utc>,
+        text: &str,
+    ) -> Document {
+        Document {
+            kind,
+            case_id,
+            run_id,
+            engine: None,
+            at,
+            text: text.to_owned(),
+            terms: vec![],
+            phones: vec![],
+        }
+        .split()
+    }
+
+    // split the text into its terms and phones
+    fn split(mut self) -> Document {
+        self.terms = split(&self.text);
+        self.phones = find_phones(&self.text)
+            .into_iter()
+            .map(|(range, value)| Term {
+                text: phone_term(&value),
+                start: range.start,
+                end: range.end,
+            })
+            .collect();
+        self
+    }
+
+    fn with_engine(mut self, engine: Option<String>) -> Document {
+        self.engine = engine;
+        self
+    }
+}
+
+// the terms of a clause of a query, the last one may be a prefix
+struct Clause {
+    terms: Vec<String>,
+    prefix: bool,
+    // a phone, its only term is matched against the phones of the documents
+    phone: bool,
+}
+
+impl Clause {
+    // where the clause matches in a document, as the position of the first term and their number
+    fn find(&self, document: &Document) -> Vec<(usize, usize)> {
+        let terms = &document.terms;
+        if self.phone {
+            return document
+                .phones
+                .iter()
+                .filter(|phone| phone.text.starts_with(self.terms[0].as_str()))
+                .filter_map(|phone| {
+                    let start = terms.iter().position(|term| term.start >= phone.start)?;
+                    let length = terms[start..]
+                        .iter()
+                        .take_while(|term| term.end <= phone.end)
+                        .count();
+                    (length > 0).then_some((start, length))
+                })
+                .collect();
+        }
+        let length = self.terms.len();
+        if terms.len() < length {
+            return vec![];
+        }
+        (0..=terms.len() - length)
+            .filter(|start| {
+                self.terms.iter().enumerate().all(|(i, term)| {
+                    let found = &terms[start + i].text;
+                    match self.prefix && i == length - 1 {
+                        true => found.starts_with(term.as_str()),
+                        false => found == term,
+                    }
+                })
+            })
+            .map(|start| (start, length))
+            .collect()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct SearchIndex {
+    documents: HashMap<Uuid, Document>,
+    #[serde(skip)]
+    postings: BTreeMap<String, HashSet<Uuid>>,
+    // the last change of the journal that was indexed
+    journal_seq: u64,
+}
+
+impl SearchIndex {
+    // take the index kept in a blob store, a damaged one is left to be built again
+    pub(crate) fn take(blobs: &BlobStore) -> Option<SearchIndex> {
+        let content = blobs.take_index().ok()??;
+        let kept: SearchIndex = serde_json::from_slice(&content).ok()?;
+        let mut index = SearchIndex {
+            journal_seq: kept.journal_seq,
+            ..Default::default()
+        };
+        for (id, document) in kept.documents {
+            index.insert(id, document.split());
+        }
+        Some(index)
+    }
+
+    // keep the index in a blob store until the storage is opened again
+    pub(crate) fn keep(&self, blobs: &BlobStore) -> Result<(), Error> {
+        let content =
+            serde_json::to_vec(self).map_err(|error| Error::Serialization(error.to_string()))?;
+        blobs.keep_index(&content)
+    }
+
+    // index every result, note and entity of a storage
+    pub(crate) fn build(storage: &dyn Storage, journal_seq: u64) -> Result<SearchIndex, Error> {
+        let mut index = SearchIndex {
+            journal_seq,
+            ..Default::default()
+        };
+        for case in storage.list_cases()? {
+            let mut engines = HashMap::new();
+            for query in storage.list_queries(case.id)? {
+                for run in storage.list_runs(query.id)? {
+                    for result in storage.list_results(run.id)? {
+                        index.add_result(&result, &run, case.id);
+                    }
+                    engines.insert(run.id, run.engine);
+                }
+            }
+            for entity in storage.list_entities(case.id)? {
+                let engine = entity.run_id.and_then(|id| engines.get(&id).cloned());
+                index.add_entity(&entity, engine);
+            }
+            for note in storage.list_notes(case.id)? {
+                index.add_note(&note);
+            }
+        }
+        Ok(index)
+    }
+
+    pub(crate) fn add_result(&mut self, result: &RunResult, run: &Run, case_id: Uuid) {
+        self.insert(
+            result.id,
+            Document::new(
+                HitKind::Result,
+                case_id,
+                Some(run.id),
+                result.created_at,
+                &result.output,
+            )
+            .with_engine(Some(run.engine.clone())),
+        );
+    }
+
+    pub(crate) fn add_note(&mut self, note: &Note) {
+        self.insert(
+            note.id,
+            Document::new(
+                HitKind::Note,
+                note.case_id,
+                None,
+                note.updated_at,
+                &note.text,
+            ),
+        );
+    }
+
+    pub(crate) fn add_entity(&mut self, entity: &Entity, engine: Option<String>) {
+        let document = Document::new(
+            HitKind::Entity,
+            entity.case_id,
+            entity.run_id,
+            entity.created_at,
+            &entity.value,
+        );
+        self.insert(entity.id, document.with_engine(engine));
+    }
+
+    // index the records of the changes the journal gained since the last refresh
+    pub(crate) fn refresh(&mut self, journal: &JournaledStorage) -> Result<(), Error> {
+        let mut changed = HashSet::new();
+        loop {
+            let changes = journal.list_changes(self.journal_seq, crate::api::MAX_PAGE_SIZE)?;
+            let Some(last) = changes.last() else {
+                break;
+            };
+            self.journal_seq = last.seq;
+            changed.extend(
+                changes
+                    .into_iter()
+                    .map(|change| (change.kind, change.record_id)),
+            );
+        }
+        for (kind, id) in changed {
+            match kind.as_str() {
+                //records that are gone were deleted (i.e: purged)
+                RunResult::KIND => match journal.get_result(id)? {
+                    Some(result) => {
+                        if let Some((run, case_id)) = run_of(journal, result.run_id)? {
+                            self.add_result(&result, &run, case_id);
+                        }
+                    }
+                    None => self.remove(id),
+                },
+                Entity::KIND => match journal.get_entity(id)? {
+                    Some(entity) => {
+                        let engine = match entity.run_id {
+                            Some(run_id) => run_of(journal, run_id)?.map(|(run, _)| run.engine),
+                            None => None,
+                        };
+                        self.add_entity(&entity, engine);
+                    }
+                    None => self.remove(id),
+                },
+                Note::KIND => {
+                    if let Some(note) = journal.get_note(id)? {
+                        self.add_note(&note);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // index a record, replacing its previous text
+    fn insert(&mut self, id: Uuid, document: Document) {
+        self.remove(id);
+        for term in document.terms.iter().chain(&document.phones) {
+            self.postings
+                .entry(term.text.clone())
+                .or_default()
+                .insert(id);
+        }
+        self.documents.insert(id, document);
+    }
+
+    pub(crate) fn remove(&mut self, id: Uuid) {
+        if let Some(document) = self.documents.remove(&id) {
+            for term in document.terms.into_iter().chain(document.phones) {
+                if let Some(ids) = self.postings.get_mut(&term.text) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.postings.remove(&term.text);
+                    }
+                }
+            }
+        }
+    }
+
+    /// ## Description
+    /// Searches the index, the best hits first then the newest ones.
+    pub(crate) fn search(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let clauses = parse_query(query)?;
+        //the records that have the first term of every clause
+        let mut candidates: Option<HashSet<Uuid>> = None;
+        for clause in &clauses {
+            let ids = self.candidates(clause);
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+
+        let mut hits = vec![];
+        for id in candidates.unwrap_or_default() {
+            let document = &self.documents[&id];
+            if !matches_filter(document, filter) {
+                continue;
+            }
+            let mut matches = vec![];
+            for clause in &clauses {
+                let found = clause.find(document);
+                if found.is_empty() {
+                    matches.clear();
+                    break;
+                }
+                matches.extend(found);
+            }
+            if matches.is_empty() {
+                continue;
+            }
+            matches.sort_unstable();
+            let (snippet, highlights) = snippet(document, &matches);
+            hits.push(SearchHit {
+                kind: document.kind,
+                id,
+                case_id: document.case_id,
+                run_id: document.run_id,
+                engine: document.engine.clone(),
+                at: document.at,
+                snippet,
+                highlights,
+                score: matches.len(),
+            });
+        }
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then(b.at.cmp(&a.at)));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // the records with the first term of a clause
+    fn candidates(&self, clause: &Clause) -> HashSet<Uuid> {
+        let first = &clause.terms[0];
+        match clause.prefix && clause.terms.len() == 1 {
+            true => self
+                .postings
+                .range(first.clone()..)
+                .take_while(|(term, _)| term.starts_with(first.as_str()))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect(),
+            false => self.postings.get(first).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// ## Description
+/// A storage that updates its search index with the records saved and deleted through it,
+/// the other operations go to the wrapped storage as they are.
+pub(crate) struct IndexedStorage {
+    inner: Arc<dyn Storage>,
+    // `None` until the first search, unless it was kept
+    index: Mutex<Option<SearchIndex>>,
+}
+
+impl IndexedStorage {
+    pub(crate) fn new(inner: Arc<dyn Storage>, index: Option<SearchIndex>) -> IndexedStorage {
+        IndexedStorage {
+            inner,
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Gets the wrapped storage.
+    pub(crate) fn get_inner(&self) -> &Arc<dyn Storage> {
+        &self.inner
+    }
+
+    // take the index out, i.e: to keep it once the storage is closed
+    pub(crate) fn take_index(&self) -> Option<SearchIndex> {
+        self.index.lock().unwrap().take()
+    }
+
+    /// ## Description
+    /// Searches the index, building it on the first search.
+    /// `journal` is the journal of a synced storage, the records it gained are indexed first.
+    pub(crate) fn search(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+        journal: Option<&JournaledStorage>,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let mut index = self.index.lock().unwrap();
+        let index = match index.as_mut() {
+            Some(index) => index,
+            None => {
+                let journal_seq = journal.map_or(0, |journal| journal.get_last_seq());
+                index.insert(SearchIndex::build(self.inner.as_ref(), journal_seq)?)
+            }
+        };
+        //records merged by a sync only show up in the journal
+        if let Some(journal) = journal {
+            index.refresh(journal)?;
+        }
+        index.search(query, filter, limit)
+    }
+
+    // update the index if it's built already
+    fn update<F>(&self, update: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut SearchIndex, &dyn Storage) -> Result<(), Error>,
+    {
+        match self.index.lock().unwrap().as_mut() {
+            Some(index) => update(index, self.inner.as_ref()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Storage for IndexedStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        self.inner.save_case(case)
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        self.inner.get_case(id)
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        self.inner.list_cases()
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        self.inner.save_query(query)
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        self.inner.get_query(id)
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        self.inner.list_queries(case_id)
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        self.inner.save_run(run)
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        self.inner.get_run(id)
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        self.inner.list_runs(query_id)
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        self.inner.save_result(result)?;
+        self.update(|index, storage| {
+            if let Some((run, case_id)) = run_of(storage, result.run_id)? {
+                index.add_result(result, &run, case_id);
+            }
+            Ok(())
+        })
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        self.inner.get_result(id)
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        self.inner.list_results(run_id)
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        self.inner.save_entity(entity)?;
+        self.update(|index, storage| {
+            let engine = match entity.run_id {
+                Some(run_id) => storage.get_run(run_id)?.map(|run| run.engine),
+                None => None,
+            };
+            index.add_entity(entity, engine);
+            Ok(())
+        })
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        self.inner.get_entity(id)
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        self.inner.list_entities(case_id)
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        self.inner.save_note(note)?;
+        self.update(|index, _| {
+            index.add_note(note);
+            Ok(())
+        })
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        self.inner.get_note(id)
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        self.inner.list_notes(case_id)
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        self.inner.find_runs(filter)
+    }
+
+    fn save_run_with_results(&self, run: &Run, results: &[RunResult]) -> Result<(), Error> {
+        self.inner.save_run_with_results(run, results)?;
+        self.update(|index, storage| {
+            if let Some(query) = storage.get_query(run.query_id)? {
+                for result in results {
+                    index.add_result(result, run, query.case_id);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        let results = self.inner.list_results(id)?;
+        self.inner.delete_run(id)?;
+        self.update(|index, _| {
+            for result in &results {
+                index.remove(result.id);
+            }
+            Ok(())
+        })
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.inner.delete_query(id)
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.inner.delete_entity(id)?;
+        self.update(|index, _| {
+            index.remove(id);
+            Ok(())
+        })
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        self.inner.append_changes(changes)
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        self.inner.list_changes(after, limit)
+    }
+
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.inner.redact_changes(record_ids)
+    }
+
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        self.inner.append_evidence(entry)
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        self.inner.list_evidence(after, limit)
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.inner.redact_evidence(record_ids)
+    }
+}
+
+// the run of a result or an entity and the case it was run in
+fn run_of(storage: &dyn Storage, run_id: Uuid) -> Result<Option<(Run, Uuid)>, Error> {
+    let Some(run) = storage.get_run(run_id)? else {
+        return Ok(None);
+    };
+    Ok(storage
+        .get_query(run.query_id)?
+        .map(|query| (run, query.case_id)))
+}
+
+fn matches_filter(document: &Document, filter: &SearchFilter) -> bool {
+    filter.case_id.is_none_or(|id| document.case_id == id)
+        && filter
+            .engine
+            .as_ref()
+            .is_none_or(|engine| document.engine.as_ref() == Some(engine))
+        && filter.from.is_none_or(|from| document.at >= from)
+        && filter.to.is_none_or(|to| document.at <= to)
+}
+
+// the lowercase alphanumeric terms of a text
+fn split(text: &str) -> Vec<Term> {
+    let mut terms = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                terms.push(Term {
+                    text: text[from..i].to_lowercase(),
+                    start: from,
+                    end: i,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    terms
+}
+
+// the term of a phone, its digits without leading zeros (a trunk or international prefix)
+// reversed, so the terms starting with a phone's term are the phones ending with it
+fn phone_term(phone: &str) -> String {
+    let digits = phone
+        .trim_start_matches('+')
+        .trim_start_matches('0')
+        .chars()
+        .rev();
+    PHONE_PREFIX.chars().chain(digits).collect()
+}
+
+fn parse_query(query: &str) -> Result<Vec<Clause>, Error> {
+    let mut clauses = vec![];
+    let mut rest = query.trim();
+    while !rest.is_empty() {
+        let (clause, tail) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| Error::InvalidQuery(format!("unclosed quote: {}", rest)))?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        let clause = clause.trim();
+        //a clause that's a whole phone matches the phones that end with it
+        if let [(range, value)] = find_phones(clause).as_slice() {
+            if *range == (0..clause.len()) {
+                clauses.push(Clause {
+                    terms: vec![phone_term(value)],
+                    prefix: true,
+                    phone: true,
+                });
+                rest = tail.trim_start();
+                continue;
+            }
+        }
+        let terms: Vec<String> = split(clause).into_iter().map(|term| term.text).collect();
+        if !terms.is_empty() {
+            clauses.push(Clause {
+                terms,
+                prefix: clause.ends_with('*'),
+                phone: false,
+            });
+        }
+        rest = tail.trim_start();
+    }
+    if clauses.is_empty() {
+        return Err(Error::InvalidQuery(format!("nothing to search: {}", query)));
+    }
+    Ok(clauses)
+}
+
+// the terms around the first match, with the byte ranges of the matches in it
+fn snippet(document: &Document, matches: &[(usize, usize)]) -> (String, Vec<(usize, usize)>) {
+    let terms = &document.terms;
+    let first = matches[0].0.saturating_sub(SNIPPET_LEAD);
+    let last = (first + SNIPPET_TERMS).min(terms.len());
+    let from = if first == 0 { 0 } else { terms[first].start };
+    let to = match last == terms.len() {
+        true => document.text.len(),
+        false => terms[last - 1].end,
+    };
+
+    let mut snippet = String::new();
+    if first > 0 {
+        snippet.push('…');
+    }
+    let offset = snippet.len();
+    //the snippet is a single line, the replaced whitespace keeps the byte offsets
+    snippet.extend(document.text[from..to].chars().map(|c| {
+        if c == '\n' || c == '\r' || c == '\t' {
+            ' '
+        } else {
+            c
+        }
+    }));
+    if last < terms.len() {
+        snippet.push('…');
+    }
+
+    let mut highlights: Vec<(usize, usize)> = vec![];
+    for &(start, length) in matches {
+        if start < first || start + length > last {
+            continue;
+        }
+        let range = (
+            terms[start].start - from + offset,
+            terms[start + length - 1].end - from + offset,
+        );
+        match highlights.last_mut() {
+            //overlapping matches are one highlight
+            Some(previous) if previous.1 >= range.0 => previous.1 = previous.1.max(range.1),
+            _ => highlights.push(range),
+        }
+    }
+    (snippet, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityKind, MemoryStorage};
+
+    #[test]
+    fn queries() {
+        let storage = MemoryStorage::new();
+        let case = Case::new("case");
+        storage.save_case(&case).unwrap();
+        let query = Query::new(case.id, "jane doe");
+        storage.save_query(&query).unwrap();
+        let run = Run::start(&query, "facebook", "user");
+        let result = RunResult::new(
+            run.id,
+            "[+] Jane Doe\nlives in Springfield\nphone: +1 (555) 010-0199",
+        );
+        storage
+            .save_run_with_results(&run, std::slice::from_ref(&result))
+            .unwrap();
+        let note = Note::new(case.id, None, "Jane's second account is on twitter");
+        storage.save_note(&note).unwrap();
+        let entity = Entity::new(case.id, Some(run.id), EntityKind::Phone, "+15550100199");
+        storage.save_entity(&entity).unwrap();
+        let mut index = SearchIndex::build(&storage, 0).unwrap();
+        let search = |index: &SearchIndex, query: &str, filter: &SearchFilter| {
+            let hits = index.search(query, filter, 10).unwrap();
+            hits.into_iter().map(|hit| hit.id).collect::<Vec<_>>()
+        };
+        let all = SearchFilter::default();
+
+        //phrases match the terms next to each other, whatever separates them
+        assert_eq!(search(&index, "\"doe jane\"", &all), Vec::<Uuid>::new());
+        assert_eq!(search(&index, "\"jane d*\"", &all), vec![result.id]);
+        assert_eq!(search(&index, "15550100199", &all), vec![entity.id]);
+
+        //the best hits come first, every clause must match
+        let hits = index.search("jane spring*", &all, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].engine.as_deref(), Some("facebook"));
+        let highlighted: Vec<&str> = hits[0]
+            .highlights
+            .iter()
+            .map(|&(start, end)| &hits[0].snippet[start..end])
+            .collect();
+        assert_eq!(highlighted, vec!["Jane", "Springfield"]);
+        assert_eq!(search(&index, "jane", &all).len(), 2);
+
+        //filters
+        let twitter = SearchFilter {
+            engine: Some("twitter".into()),
+            ..Default::default()
+        };
+        assert!(search(&index, "jane", &twitter).is_empty());
+        let later = SearchFilter {
+            from: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert!(search(&index, "jane", &later).is_empty());
+
+        //edited records replace their previous text
+        let mut note = note;
+        note.edit("nothing to see");
+        index.add_note(&note);
+        assert_eq!(search(&index, "jane", &all), vec![result.id]);
+        assert_eq!(search(&index, "see", &all), vec![note.id]);
+
+        assert!(matches!(
+            index.search(" \"* ", &all, 10),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn phones() {
+        let mut index = SearchIndex::default();
+        let case_id = Uuid::new_v4();
+        let output = Note::new(
+            case_id,
+            None,
+            "[+] phone: +1 (555) 010-0199 since 2024-01-15",
+        );
+        let entity = Entity::new(case_id, None, EntityKind::Phone, "+15550100199");
+        let national = Note::new(case_id, None, "call 00 1 555 010 0199");
+        index.add_note(&output);
+        index.add_entity(&entity, None);
+        index.add_note(&national);
+        let search = |query: &str| {
+            let mut ids: Vec<Uuid> = index
+                .search(query, &SearchFilter::default(), 10)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        let mut all = vec![output.id, entity.id, national.id];
+        all.sort();
+
+        //however it's written, with or without the country code
+        for query in [
+            "555-010-0199",
+            "\"(555) 010 0199\"",
+            "\"+1 555 010 0199\"",
+            "+15550100199",
+            "001-555-010-0199",
+        ] {
+            assert_eq!(search(query), all, "{}", query);
+        }
+        assert!(search("+44 20 7946 0958").is_empty());
+        //dates aren't phones
+        assert_eq!(search("2024-01-15"), vec![output.id]);
+
+        let hits = index
+            .search("555.010.0199", &SearchFilter::default(), 10)
+            .unwrap();
+        let hit = hits.iter().find(|hit| hit.id == output.id).unwrap();
+        let (start, end) = hit.highlights[0];
+        assert_eq!(&hit.snippet[start..end], "1 (555) 010-0199");
+    }
+
+    #[test]
+    fn long_snippets() {
+        let mut index = SearchIndex::default();
+        let text = (0..100).map(|i| format!("w{}", i)).collect::<Vec<_>>();
+        let note = Note::new(Uuid::new_v4(), None, &text.join(" "));
+        index.add_note(&note);
+        let hit = &index.search("w50", &SearchFilter::default(), 1).unwrap()[0];
+        assert!(hit.snippet.starts_with("…w42 ") && hit.snippet.ends_with(" w73…"));
+        let (start, end) = hit.highlights[0];
+        assert_eq!(&hit.snippet[start..end], "w50");
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{Case, Change, Entity, Error, EvidenceEntry, Note, Query, Run, RunResult};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// ## Description
+/// The operations every storage backend provides.
+///
+/// The records are created by the caller (with their ids), the `save_*` methods insert a record
+/// or replace the record with the same id. Lists are ordered by creation time.
+pub trait Storage: Send + Sync {
+    /// Inserts or replaces a case.
+    fn save_case(&self, case: &Case) -> Result<(), Error>;
+    /// Gets a case by id.
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error>;
+    /// Lists all the cases.
+    fn list_cases(&self) -> Result<Vec<Case>, Error>;
+
+    /// Inserts or replaces a query.
+    fn save_query(&self, query: &Query) -> Result<(), Error>;
+    /// Gets a query by id.
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error>;
+    /// Lists the queries of a case.
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error>;
+
+    /// Inserts or replaces an engine run.
+    fn save_run(&self, run: &Run) -> Result<(), Error>;
+    /// Gets an engine run by id.
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error>;
+    /// Lists the engine runs of a query.
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error>;
+
+    /// Inserts or replaces the result of a run.
+    fn save_result(&self, result: &RunResult) -> Result<(), Error>;
+    /// Gets the result of a run by id.
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error>;
+    /// Lists the results of an engine run.
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error>;
+
+    /// Inserts or replaces an entity.
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error>;
+    /// Gets an entity by id.
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error>;
+    /// Lists the entities of a case.
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error>;
+
+    /// Inserts or replaces a note.
+    fn save_note(&self, note: &Note) -> Result<(), Error>;
+    /// Gets a note by id.
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error>;
+    /// Lists the notes of a case.
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error>;
+
+    /// Finds the engine runs that match every set field of the filter, ordered by start time.
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error>;
+
+    /// ## Description
+    /// Deletes an engine run and its results, deleting a missing run does nothing.
+    ///
+    /// Deletes are secure: the backends overwrite the deleted content where they keep it.
+    fn delete_run(&self, id: Uuid) -> Result<(), Error>;
+    /// Deletes a query, its runs are deleted with `delete_run`.
+    fn delete_query(&self, id: Uuid) -> Result<(), Error>;
+    /// Deletes an entity.
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error>;
+
+    /// ## Description
+    /// Appends changes to the journal, their sequence numbers must follow the last change's.
+    ///
+    /// The backends only keep the changes, recording and merging them is up to
+    /// `JournaledStorage`.
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error>;
+    /// Lists the changes of the journal that come after the `after` sequence number, in order.
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error>;
+    /// ## Description
+    /// Erases the values of the journal's changes of deleted records, they become `null`.
+    ///
+    /// The changes keep their sequence numbers, so the cursors of the journal stay valid.
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error>;
+
+    /// ## Description
+    /// Appends an entry to the evidence log, its sequence number must follow the last entry's.
+    ///
+    /// The log is append-only: appending an entry that's already there does nothing, another
+    /// entry with a taken (or a skipped) sequence number fails with `Error::Conflict`.
+    /// Chaining the entries is up to `StorageManager`.
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error>;
+    /// Lists the entries of the evidence log that come after the `after` sequence number, in order.
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error>;
+    /// ## Description
+    /// Redacts the evidence log's entries of records, see `EvidenceEntry::redact`.
+    ///
+    /// The hashes of the entries stay valid, so the chain is still intact.
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error>;
+
+    /// ## Description
+    /// Saves a run together with its results.
+    ///
+    /// Backends that support transactions save all or nothing, the default saves one by one.
+    fn save_run_with_results(&self, run: &Run, results: &[RunResult]) -> Result<(), Error> {
+        for result in results {
+            self.save_result(result)?;
+        }
+        self.save_run(run)
+    }
+}
+
+/// ## Description
+/// The fields to look engine runs up by, unset fields match every run.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use storage_manager::RunFilter;
+/// let filter = RunFilter {
+///     value: Some("user123".into()),
+///     engine: Some("facebook".into()),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunFilter {
+    /// The case the runs belong to.
+    pub case_id: Option<Uuid>,
+    /// The searched entity value, the text of the run's query.
+    pub value: Option<String>,
+    /// The engine that was executed.
+    pub engine: Option<String>,
+    /// Runs that started at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Runs that started before this time.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl RunFilter {
+    /// ## Description
+    /// Checks a run against the filter, `query` is the run's query.
+    pub fn matches(&self, run: &Run, query: Option<&Query>) -> bool {
+        self.case_id.is_none_or(|case_id| run.case_id == case_id)
+            && self
+                .engine
+                .as_ref()
+                .is_none_or(|engine| &run.engine == engine)
+            && self.from.is_none_or(|from| run.started_at >= from)
+            && self.to.is_none_or(|to| run.started_at < to)
+            && self
+                .value
+                .as_ref()
+                .is_none_or(|value| query.is_some_and(|query| &query.text == value))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{CaseStatus, ChangeOrigin, EntityKind, Provenance, RunStatus};
+
+    /// Checks the behaviour every backend must have.
+    pub(crate) fn check_backend(storage: &dyn Storage) {
+        //cases
+        let case = Case::new("first case");
+        let other_case = Case::new("second case");
+        storage.save_case(&case).unwrap();
+        storage.save_case(&other_case).unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap(), Some(case.clone()));
+        assert_eq!(storage.get_case(Uuid::new_v4()).unwrap(), None);
+        assert_eq!(
+            storage.list_cases().unwrap(),
+            vec![case.clone(), other_case.clone()]
+        );
+
+        //replacing a case keeps a single record
+        let mut renamed = case.clone();
+        renamed.title = "renamed".into();
+        storage.save_case(&renamed).unwrap();
+        assert_eq!(storage.list_cases().unwrap().len(), 2);
+        assert_eq!(storage.get_case(case.id).unwrap().unwrap().title, "renamed");
+
+        //queries are listed by case
+        let query = Query::new(case.id, "user123");
+        storage.save_query(&query).unwrap();
+        storage
+            .save_query(&Query::new(other_case.id, "other"))
+            .unwrap();
+        assert_eq!(storage.get_query(query.id).unwrap(), Some(query.clone()));
+        assert_eq!(storage.list_queries(case.id).unwrap(), vec![query.clone()]);
+
+        //runs are updated when they finish
+        let mut run = Run::start(&query, "facebook", "user");
+        storage.save_run(&run).unwrap();
+        run.finish(Err("timeout".into()));
+        run.artifacts = vec![crate::Artifact {
+            name: "pages/index.html".into(),
+            digest: "0".repeat(64),
+            size: 6,
+        }];
+        storage.save_run(&run).unwrap();
+        let runs = storage.list_runs(query.id).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].status, RunStatus::Failed("timeout".into()));
+        assert_eq!(storage.get_run(run.id).unwrap(), Some(run.clone()));
+
+        //results
+        let result = RunResult::new(run.id, "found user123");
+        storage.save_result(&result).unwrap();
+        assert_eq!(storage.get_result(result.id).unwrap(), Some(result.clone()));
+        assert_eq!(storage.list_results(run.id).unwrap(), vec![result]);
+        assert!(storage.list_results(Uuid::new_v4()).unwrap().is_empty());
+
+        //runs are found by case, entity value, engine and time range
+        let other_query = Query::new(other_case.id, "other");
+        storage.save_query(&other_query).unwrap();
+        let mut other_run = Run::start(&other_query, "twitter", "user");
+        other_run.finish(Ok(()));
+        let other_result = RunResult::new(other_run.id, "found other");
+        storage
+            .save_run_with_results(&other_run, std::slice::from_ref(&other_result))
+            .unwrap();
+        assert_eq!(
+            storage.list_results(other_run.id).unwrap(),
+            vec![other_result]
+        );
+
+        let find = |filter: RunFilter| storage.find_runs(&filter).unwrap();
+        assert_eq!(
+            find(RunFilter::default()),
+            vec![run.clone(), other_run.clone()]
+        );
+        let by_case = RunFilter {
+            case_id: Some(case.id),
+            ..Default::default()
+        };
+        assert_eq!(find(by_case), vec![run.clone()]);
+        let by_value = RunFilter {
+            value: Some("other".into()),
+            ..Default::default()
+        };
+        assert_eq!(find(by_value), vec![other_run.clone()]);
+        let by_engine = RunFilter {
+            engine: Some("facebook".into()),
+            ..Default::default()
+        };
+        assert_eq!(find(by_engine), vec![run.clone()]);
+        let by_time = RunFilter {
+            from: Some(other_run.started_at),
+            ..Default::default()
+        };
+        assert_eq!(find(by_time), vec![other_run.clone()]);
+        let before = RunFilter {
+            to: Some(run.started_at),
+            ..Default::default()
+        };
+        assert!(find(before).is_empty());
+
+        //entities and notes are listed by case
+        let entity = Entity::new(case.id, Some(run.id), EntityKind::Email, "jane@example.com");
+        storage.save_entity(&entity).unwrap();
+        storage
+            .save_entity(&Entity::new(
+                other_case.id,
+                None,
+                EntityKind::Other,
+                "other",
+            ))
+            .unwrap();
+        assert_eq!(storage.get_entity(entity.id).unwrap(), Some(entity.clone()));
+        assert_eq!(storage.list_entities(case.id).unwrap(), vec![entity]);
+
+        let mut note = Note::new(case.id, Some("analyst"), "check the alias");
+        storage.save_note(&note).unwrap();
+        note.edit("the alias is a dead end");
+        storage.save_note(&note).unwrap();
+        assert_eq!(storage.get_note(note.id).unwrap(), Some(note.clone()));
+        assert_eq!(storage.list_notes(case.id).unwrap(), vec![note]);
+        assert!(storage.list_notes(other_case.id).unwrap().is_empty());
+
+        //cases keep their status, owner, tags and retention policy
+        let mut closed = storage.get_case(case.id).unwrap().unwrap();
+        closed.set_status(CaseStatus::Closed);
+        closed.retention = Some(crate::RetentionPolicy {
+            max_age_days: Some(30),
+            closed_case_days: Some(7),
+        });
+        closed.owner = Some("analyst".into());
+        closed.tags = vec!["fraud".into(), "priority".into()];
+        storage.save_case(&closed).unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap(), Some(closed));
+
+        //the journal keeps the changes in order
+        assert!(storage.list_changes(0, 10).unwrap().is_empty());
+        let changes: Vec<Change> = (1..=3)
+            .map(|seq| Change {
+                seq,
+                kind: "cases".into(),
+                record_id: case.id,
+                field: "title".into(),
+                value: serde_json::json!(format!("title {}", seq)),
+                at: chrono::Utc::now(),
+                origin: ChangeOrigin::Local,
+            })
+            .collect();
+        storage.append_changes(&changes[..2]).unwrap();
+        storage.append_changes(&changes[2..]).unwrap();
+        assert_eq!(storage.list_changes(0, 10).unwrap(), changes);
+        assert_eq!(storage.list_changes(1, 1).unwrap(), changes[1..2]);
+        assert!(storage.list_changes(3, 10).unwrap().is_empty());
+
+        //the evidence log is append-only
+        assert!(storage.list_evidence(0, 10).unwrap().is_empty());
+        let mut entry = EvidenceEntry::for_run(&run, &Provenance::default());
+        entry.link(None);
+        storage.append_evidence(&entry).unwrap();
+        //appending the same entry again (i.e: a retried request) does nothing
+        storage.append_evidence(&entry).unwrap();
+        let mut next = EvidenceEntry::for_run(&other_run, &Provenance::default());
+        next.link(Some(&(entry.seq, entry.hash.clone())));
+        storage.append_evidence(&next).unwrap();
+        assert_eq!(
+            storage.list_evidence(0, 10).unwrap(),
+            vec![entry.clone(), next.clone()]
+        );
+        assert_eq!(storage.list_evidence(1, 10).unwrap(), vec![next.clone()]);
+
+        //entries can't be replaced or skip sequence numbers
+        let mut forged = entry.clone();
+        forged.argv = vec!["forged".into()];
+        assert!(matches!(
+            storage.append_evidence(&forged),
+            Err(Error::Conflict(_))
+        ));
+        forged.seq = 4;
+        assert!(matches!(
+            storage.append_evidence(&forged),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(
+            storage.list_evidence(0, 10).unwrap(),
+            vec![entry.clone(), next.clone()]
+        );
+
+        //redacted entries keep their hash, appending them again still does nothing
+        storage.redact_evidence(&[run.id]).unwrap();
+        storage.redact_evidence(&[run.id]).unwrap();
+        let redacted = storage.list_evidence(0, 10).unwrap();
+        assert!(redacted[0].redacted && !redacted[1].redacted);
+        assert_eq!(redacted[0].compute_hash(), entry.hash);
+        assert_eq!(redacted[1], next);
+        storage.append_evidence(&entry).unwrap();
+
+        //deleting a run deletes its results, deleting it again does nothing
+        let results = storage.list_results(run.id).unwrap();
+        storage.delete_run(run.id).unwrap();
+        storage.delete_run(run.id).unwrap();
+        assert_eq!(storage.get_run(run.id).unwrap(), None);
+        assert_eq!(storage.get_result(results[0].id).unwrap(), None);
+        assert!(storage.list_results(run.id).unwrap().is_empty());
+        assert_eq!(find(RunFilter::default()), vec![other_run]);
+        storage.delete_query(query.id).unwrap();
+        assert_eq!(storage.get_query(query.id).unwrap(), None);
+        assert!(storage.list_queries(case.id).unwrap().is_empty());
+        let entity = storage.list_entities(case.id).unwrap().remove(0);
+        storage.delete_entity(entity.id).unwrap();
+        assert!(storage.list_entities(case.id).unwrap().is_empty());
+
+        //redacted changes keep their place in the journal
+        storage.redact_changes(&[case.id]).unwrap();
+        let redacted = storage.list_changes(0, 10).unwrap();
+        assert_eq!(redacted.len(), 3);
+        assert!(redacted.iter().all(|change| change.value.is_null()));
+        assert_eq!(redacted[2].seq, 3);
+    }
+}
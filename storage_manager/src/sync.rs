@@ -0,0 +1,1230 @@
+/*
+Sync:
+Every change of a record is kept in a journal, one entry per changed field (`Change`), so two
+storages are synced by exchanging the entries their journals gained since the last sync.
+
+`JournaledStorage` records the changes of the storage it wraps and merges the changes made in
+other storages: a field keeps the value of its latest change (last writer wins). Changes are
+ordered by their time, then by the digest of their value, so both sides of a sync pick the
+same winner without talking to each other and end up with the same records.
+
+`SyncEngine` syncs a local journaled storage with a remote one (the reference server journals
+its storage too). It pulls the remote changes since the last sync and merges them, then pushes
+the local changes since the last sync. A field that changed on both sides since the last sync
+is a conflict: the losing change stays in the journal and the conflict is kept for the UI.
+The local storage is always usable, a sync that can't reach the remote leaves the changes in
+the journal for the next one.
+
+Deletes are journaled as a change of the `$deleted` field (a tombstone) and the values of the
+deleted records are erased from the journal. A tombstone wins over every change of its record:
+the merged changes of a deleted record are dropped, and so are its erased changes when they're
+pushed.
+ */
+use crate::model::Record;
+use crate::{
+    api, Case, Change, ChangeOrigin, Entity, Error, EvidenceEntry, Note, Query, Run, RunFilter,
+    RunResult, Storage,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use uuid::Uuid;
+
+/// The kinds of records, parents first.
+const KINDS: [&str; 6] = [
+    Case::KIND,
+    Query::KIND,
+    Run::KIND,
+    RunResult::KIND,
+    Entity::KIND,
+    Note::KIND,
+];
+
+/// The field of the changes that delete a record.
+const DELETED: &str = "$deleted";
+
+/// A field of a record: its kind, its record id and its name.
+type FieldKey = (String, Uuid, String);
+
+/// ## Description
+/// A field that was changed on both sides of a sync, resolved by keeping the latest change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Conflict {
+    /// The kind of the record, i.e: `cases`.
+    pub kind: String,
+    pub record_id: Uuid,
+    pub field: String,
+    /// When the field was changed locally.
+    pub local_at: DateTime<Utc>,
+    /// When the field was changed remotely.
+    pub remote_at: DateTime<Utc>,
+    /// The side whose value was kept.
+    pub winner: ChangeOrigin,
+}
+
+// the latest change of a field
+#[derive(Clone)]
+struct Version {
+    seq: u64,
+    at: DateTime<Utc>,
+    digest: String,
+    origin: ChangeOrigin,
+}
+
+impl Version {
+    // the order of the changes of a field, the greatest one wins
+    fn wins_over(&self, other: &Version) -> bool {
+        (self.at, &self.digest) > (other.at, &other.digest)
+    }
+}
+
+#[derive(Default)]
+struct Journal {
+    last_seq: u64,
+    fields: HashMap<FieldKey, Version>,
+}
+
+impl Journal {
+    // the local changes from `old` to `new`, numbered after the journal's last change
+    fn diff(
+        &self,
+        changes: &mut Vec<Change>,
+        kind: &str,
+        id: Uuid,
+        old: Option<&Map<String, Value>>,
+        new: Map<String, Value>,
+        at: DateTime<Utc>,
+    ) {
+        for (field, value) in new {
+            if old.and_then(|old| old.get(&field)) != Some(&value) {
+                changes.push(Change {
+                    seq: self.last_seq + changes.len() as u64 + 1,
+                    kind: kind.to_owned(),
+                    record_id: id,
+                    field,
+                    value,
+                    at,
+                    origin: ChangeOrigin::Local,
+                });
+            }
+        }
+    }
+
+    // the changes of the fields whose value isn't the latest one the journal has, i.e: the
+    // record was saved but its changes weren't appended
+    fn unjournaled(
+        &self,
+        changes: &mut Vec<Change>,
+        kind: &str,
+        id: Uuid,
+        fields: Map<String, Value>,
+        at: DateTime<Utc>,
+    ) {
+        for (field, value) in fields {
+            let key = (kind.to_owned(), id, field);
+            let digest = api::revision(&value);
+            if self
+                .fields
+                .get(&key)
+                .is_none_or(|version| version.digest != digest)
+            {
+                changes.push(Change {
+                    seq: self.last_seq + changes.len() as u64 + 1,
+                    kind: key.0,
+                    record_id: id,
+                    field: key.2,
+                    value,
+                    at,
+                    origin: ChangeOrigin::Local,
+                });
+            }
+        }
+    }
+
+    // index changes that were appended to the journal
+    fn commit(&mut self, changes: &[Change]) {
+        for change in changes {
+            self.last_seq = self.last_seq.max(change.seq);
+            self.fields.insert(key(change), version(change));
+        }
+    }
+}
+
+fn key(change: &Change) -> FieldKey {
+    (change.kind.clone(), change.record_id, change.field.clone())
+}
+
+fn version(change: &Change) -> Version {
+    Version {
+        seq: change.seq,
+        at: change.at,
+        digest: api::revision(&change.value),
+        origin: change.origin,
+    }
+}
+
+fn fields<T: Record>(record: &T) -> Result<Map<String, Value>, Error> {
+    match serde_json::to_value(record) {
+        Ok(Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err(Error::Serialization(format!(
+            "{} records aren't objects",
+            T::KIND
+        ))),
+        Err(error) => Err(Error::Serialization(error.to_string())),
+    }
+}
+
+fn decode<T: Record>(fields: Map<String, Value>) -> Result<T, Error> {
+    serde_json::from_value(Value::Object(fields)).map_err(|error| {
+        Error::Serialization(format!("incomplete record of {}: {}", T::KIND, error))
+    })
+}
+
+/// ## Description
+/// A storage that records every change of the storage it wraps in the storage's journal.
+///
+/// Changes appended to it were made in another storage, they are merged into the records
+/// (the latest change of a field wins) and journaled with new sequence numbers.
+/// Records that were saved before the storage was journaled are journaled when it's opened,
+/// and so are the changes of a save whose journal entries weren't appended (i.e: a crash
+/// between the two), a record and its changes aren't written at once.
+/// ## Example
+/// **Basic usage:**
+/// ```
+/// # use std::sync::Arc;
+/// # use storage_manager::{Case, JournaledStorage, MemoryStorage, Storage};
+/// let storage = JournaledStorage::new(Arc::new(MemoryStorage::new())).unwrap();
+/// storage.save_case(&Case::new("case")).unwrap();
+/// let changes = storage.list_changes(0, 10).unwrap();
+/// assert!(changes.iter().any(|change| change.field == "title"));
+/// ```
+pub struct JournaledStorage {
+    inner: Arc<dyn Storage>,
+    // held while a write saves and journals, so the journal follows the writes' order
+    journal: Mutex<Journal>,
+}
+
+impl JournaledStorage {
+    /// ## Description
+    /// Wraps a storage, reading its journal.
+    pub fn new(inner: Arc<dyn Storage>) -> Result<JournaledStorage, Error> {
+        let mut journal = Journal::default();
+        loop {
+            let changes = inner.list_changes(journal.last_seq, api::MAX_PAGE_SIZE)?;
+            if changes.is_empty() {
+                break;
+            }
+            journal.commit(&changes);
+        }
+        let storage = JournaledStorage {
+            inner,
+            journal: Mutex::new(journal),
+        };
+        storage.journal_unjournaled()?;
+        Ok(storage)
+    }
+
+    // journal what the stored records hold that the journal doesn't: the records saved before
+    // the storage was journaled and the saves that didn't get to append their changes
+    fn journal_unjournaled(&self) -> Result<(), Error> {
+        let mut journal = self.journal.lock().unwrap();
+        let mut changes = vec![];
+        let mut add = |kind: &str, id: Uuid, fields: Map<String, Value>, at: DateTime<Utc>| {
+            journal.unjournaled(&mut changes, kind, id, fields, at)
+        };
+        for case in self.inner.list_cases()? {
+            add(Case::KIND, case.id, fields(&case)?, case.created_at);
+            for query in self.inner.list_queries(case.id)? {
+                add(Query::KIND, query.id, fields(&query)?, query.created_at);
+                for run in self.inner.list_runs(query.id)? {
+                    add(Run::KIND, run.id, fields(&run)?, run.started_at);
+                    for result in self.inner.list_results(run.id)? {
+                        add(
+                            RunResult::KIND,
+                            result.id,
+                            fields(&result)?,
+                            result.created_at,
+                        );
+                    }
+                }
+            }
+            for entity in self.inner.list_entities(case.id)? {
+                add(Entity::KIND, entity.id, fields(&entity)?, entity.created_at);
+            }
+            for note in self.inner.list_notes(case.id)? {
+                add(Note::KIND, note.id, fields(&note)?, note.updated_at);
+            }
+        }
+        if !changes.is_empty() {
+            self.inner.append_changes(&changes)?;
+            journal.commit(&changes);
+        }
+        Ok(())
+    }
+
+    // the fields of a record, `None` if there's no such record
+    fn load(&self, kind: &str, id: Uuid) -> Result<Option<Map<String, Value>>, Error> {
+        match kind {
+            Case::KIND => self.inner.get_case(id)?.as_ref().map(fields).transpose(),
+            Query::KIND => self.inner.get_query(id)?.as_ref().map(fields).transpose(),
+            Run::KIND => self.inner.get_run(id)?.as_ref().map(fields).transpose(),
+            RunResult::KIND => self.inner.get_result(id)?.as_ref().map(fields).transpose(),
+            Entity::KIND => self.inner.get_entity(id)?.as_ref().map(fields).transpose(),
+            Note::KIND => self.inner.get_note(id)?.as_ref().map(fields).transpose(),
+            _ => Err(Error::Serialization(format!(
+                "unknown kind of record: {}",
+                kind
+            ))),
+        }
+    }
+
+    fn store(&self, kind: &str, fields: Map<String, Value>) -> Result<(), Error> {
+        match kind {
+            Case::KIND => self.inner.save_case(&decode(fields)?),
+            Query::KIND => self.inner.save_query(&decode(fields)?),
+            Run::KIND => self.inner.save_run(&decode(fields)?),
+            RunResult::KIND => self.inner.save_result(&decode(fields)?),
+            Entity::KIND => self.inner.save_entity(&decode(fields)?),
+            Note::KIND => self.inner.save_note(&decode(fields)?),
+            _ => Err(Error::Serialization(format!(
+                "unknown kind of record: {}",
+                kind
+            ))),
+        }
+    }
+
+    // save records through `save` and journal what changed
+    fn save<F>(&self, records: &[(&str, Uuid, Map<String, Value>)], save: F) -> Result<(), Error>
+    where
+        F: FnOnce(&dyn Storage) -> Result<(), Error>,
+    {
+        let mut journal = self.journal.lock().unwrap();
+        let mut old = vec![];
+        for (kind, id, _) in records {
+            old.push(self.load(kind, *id)?);
+        }
+        save(self.inner.as_ref())?;
+
+        let now = Utc::now();
+        let mut changes = vec![];
+        for ((kind, id, new), old) in records.iter().zip(&old) {
+            journal.diff(&mut changes, kind, *id, old.as_ref(), new.clone(), now);
+        }
+        if !changes.is_empty() {
+            self.inner.append_changes(&changes)?;
+            journal.commit(&changes);
+        }
+        Ok(())
+    }
+
+    // delete records through `delete`, erase their values from the journal and journal the
+    // deletion of the ones that existed
+    fn delete<F>(&self, records: &[(&str, Uuid)], delete: F) -> Result<(), Error>
+    where
+        F: FnOnce(&dyn Storage) -> Result<(), Error>,
+    {
+        let mut journal = self.journal.lock().unwrap();
+        let mut existing = vec![];
+        for (kind, id) in records {
+            if self.load(kind, *id)?.is_some() {
+                existing.push((*kind, *id));
+            }
+        }
+        delete(self.inner.as_ref())?;
+        if existing.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<Uuid> = existing.iter().map(|(_, id)| *id).collect();
+        self.inner.redact_changes(&ids)?;
+
+        let now = Utc::now();
+        let changes: Vec<Change> = existing
+            .iter()
+            .enumerate()
+            .map(|(i, (kind, id))| Change {
+                seq: journal.last_seq + i as u64 + 1,
+                kind: (*kind).to_owned(),
+                record_id: *id,
+                field: DELETED.to_owned(),
+                value: Value::Bool(true),
+                at: now,
+                origin: ChangeOrigin::Local,
+            })
+            .collect();
+        self.inner.append_changes(&changes)?;
+        journal.commit(&changes);
+        Ok(())
+    }
+
+    // delete a record a tombstone was merged for
+    fn erase(&self, kind: &str, id: Uuid) -> Result<(), Error> {
+        match kind {
+            Run::KIND => self.inner.delete_run(id),
+            Query::KIND => self.inner.delete_query(id),
+            Entity::KIND => self.inner.delete_entity(id),
+            //the results are deleted with their run
+            RunResult::KIND => Ok(()),
+            _ => Err(Error::Serialization(format!(
+                "records of {} can't be deleted",
+                kind
+            ))),
+        }
+    }
+
+    /// Checks whether a record was deleted, a tombstone of it is in the journal.
+    pub(crate) fn is_deleted(&self, kind: &str, id: Uuid) -> bool {
+        let key = (kind.to_owned(), id, DELETED.to_owned());
+        self.journal.lock().unwrap().fields.contains_key(&key)
+    }
+
+    /// ## Description
+    /// Merges changes made in another storage, the latest change of every field wins.
+    ///
+    /// With `pending_after`, the local changes after that sequence number haven't been synced
+    /// yet: remote changes of the same fields are conflicts and are returned.
+    pub fn merge(
+        &self,
+        incoming: &[Change],
+        pending_after: Option<u64>,
+    ) -> Result<Vec<Conflict>, Error> {
+        let mut journal = self.journal.lock().unwrap();
+        let mut latest: HashMap<FieldKey, Version> = HashMap::new();
+        let mut accepted: Vec<Change> = vec![];
+        let mut conflicts = vec![];
+        //the records deleted here or by the incoming changes
+        let deleted: HashSet<(&str, Uuid)> = incoming
+            .iter()
+            .filter(|change| change.field == DELETED)
+            .map(|change| (change.kind.as_str(), change.record_id))
+            .collect();
+        let is_deleted = |journal: &Journal, change: &Change| {
+            deleted.contains(&(change.kind.as_str(), change.record_id))
+                || journal.fields.contains_key(&(
+                    change.kind.clone(),
+                    change.record_id,
+                    DELETED.to_owned(),
+                ))
+        };
+        for change in incoming {
+            if !KINDS.contains(&change.kind.as_str()) {
+                return Err(Error::Serialization(format!(
+                    "unknown kind of record: {}",
+                    change.kind
+                )));
+            }
+            if change.field != DELETED && is_deleted(&journal, change) {
+                continue;
+            }
+            let key = key(change);
+            let theirs = version(change);
+            let ours = latest.get(&key).or_else(|| journal.fields.get(&key));
+            //the same value, i.e: a change that was synced already
+            if ours.is_some_and(|ours| ours.digest == theirs.digest) {
+                continue;
+            }
+            let wins = ours.is_none_or(|ours| theirs.wins_over(ours));
+            if let (Some(ours), Some(pending_after)) = (ours, pending_after) {
+                if ours.origin == ChangeOrigin::Local && ours.seq > pending_after {
+                    conflicts.push(Conflict {
+                        kind: change.kind.clone(),
+                        record_id: change.record_id,
+                        field: change.field.clone(),
+                        local_at: ours.at,
+                        remote_at: change.at,
+                        winner: if wins {
+                            ChangeOrigin::Remote
+                        } else {
+                            ChangeOrigin::Local
+                        },
+                    });
+                }
+            }
+            if wins {
+                let change = Change {
+                    seq: journal.last_seq + accepted.len() as u64 + 1,
+                    origin: ChangeOrigin::Remote,
+                    ..change.clone()
+                };
+                latest.insert(key, version(&change));
+                accepted.push(change);
+            }
+        }
+        if let Some(pending_after) = pending_after {
+            self.drop_echoes(&mut conflicts, pending_after)?;
+        }
+
+        //apply the winning values, parents first, then the deletes
+        let mut records: HashMap<(&str, Uuid), Map<String, Value>> = HashMap::new();
+        let mut erased = vec![];
+        for change in &accepted {
+            if change.field == DELETED {
+                erased.push((change.kind.as_str(), change.record_id));
+                continue;
+            }
+            records
+                .entry((change.kind.as_str(), change.record_id))
+                .or_default()
+                .insert(change.field.clone(), change.value.clone());
+        }
+        let mut records: Vec<_> = records.into_iter().collect();
+        records.sort_by_key(|((kind, _), _)| KINDS.iter().position(|k| k == kind));
+        for ((kind, id), changed) in records {
+            let mut fields = self.load(kind, id)?.unwrap_or_default();
+            fields.extend(changed);
+            self.store(kind, fields)?;
+        }
+        for (kind, id) in &erased {
+            self.erase(kind, *id)?;
+        }
+        if !erased.is_empty() {
+            let ids: Vec<Uuid> = erased.iter().map(|(_, id)| *id).collect();
+            self.inner.redact_changes(&ids)?;
+        }
+        if !accepted.is_empty() {
+            self.inner.append_changes(&accepted)?;
+            journal.commit(&accepted);
+        }
+        Ok(conflicts)
+    }
+
+    // a remote change that lost to a pending local change may be one of our own older changes
+    // coming back from the remote, those aren't conflicts
+    fn drop_echoes(&self, conflicts: &mut Vec<Conflict>, synced: u64) -> Result<(), Error> {
+        let candidates: HashSet<FieldKey> = conflicts
+            .iter()
+            .filter(|conflict| conflict.winner == ChangeOrigin::Local)
+            .map(|conflict| {
+                (
+                    conflict.kind.clone(),
+                    conflict.record_id,
+                    conflict.field.clone(),
+                )
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let mut synced_changes: HashSet<(FieldKey, DateTime<Utc>)> = HashSet::new();
+        let mut after = 0;
+        while after < synced {
+            let changes = self.inner.list_changes(after, api::MAX_PAGE_SIZE)?;
+            let Some(last) = changes.last() else {
+                break;
+            };
+            after = last.seq;
+            for change in changes {
+                let key = key(&change);
+                if change.seq <= synced
+                    && change.origin == ChangeOrigin::Local
+                    && candidates.contains(&key)
+                {
+                    synced_changes.insert((key, change.at));
+                }
+            }
+        }
+        conflicts.retain(|conflict| {
+            let key = (
+                conflict.kind.clone(),
+                conflict.record_id,
+                conflict.field.clone(),
+            );
+            !synced_changes.contains(&(key, conflict.remote_at))
+        });
+        Ok(())
+    }
+
+    /// Gets the sequence number of the journal's last change.
+    pub fn get_last_seq(&self) -> u64 {
+        self.journal.lock().unwrap().last_seq
+    }
+
+    /// Counts the local changes after a sequence number, i.e: the ones that weren't synced.
+    pub fn count_local_changes(&self, after: u64) -> Result<usize, Error> {
+        let mut count = 0;
+        let mut after = after;
+        loop {
+            let changes = self.inner.list_changes(after, api::MAX_PAGE_SIZE)?;
+            let Some(last) = changes.last() else {
+                return Ok(count);
+            };
+            after = last.seq;
+            count += changes
+                .iter()
+                .filter(|change| change.origin == ChangeOrigin::Local)
+                .count();
+        }
+    }
+}
+
+impl Storage for JournaledStorage {
+    fn save_case(&self, case: &Case) -> Result<(), Error> {
+        self.save(&[(Case::KIND, case.id, fields(case)?)], |inner| {
+            inner.save_case(case)
+        })
+    }
+
+    fn get_case(&self, id: Uuid) -> Result<Option<Case>, Error> {
+        self.inner.get_case(id)
+    }
+
+    fn list_cases(&self) -> Result<Vec<Case>, Error> {
+        self.inner.list_cases()
+    }
+
+    fn save_query(&self, query: &Query) -> Result<(), Error> {
+        self.save(&[(Query::KIND, query.id, fields(query)?)], |inner| {
+            inner.save_query(query)
+        })
+    }
+
+    fn get_query(&self, id: Uuid) -> Result<Option<Query>, Error> {
+        self.inner.get_query(id)
+    }
+
+    fn list_queries(&self, case_id: Uuid) -> Result<Vec<Query>, Error> {
+        self.inner.list_queries(case_id)
+    }
+
+    fn save_run(&self, run: &Run) -> Result<(), Error> {
+        self.save(&[(Run::KIND, run.id, fields(run)?)], |inner| {
+            inner.save_run(run)
+        })
+    }
+
+    fn get_run(&self, id: Uuid) -> Result<Option<Run>, Error> {
+        self.inner.get_run(id)
+    }
+
+    fn list_runs(&self, query_id: Uuid) -> Result<Vec<Run>, Error> {
+        self.inner.list_runs(query_id)
+    }
+
+    fn save_result(&self, result: &RunResult) -> Result<(), Error> {
+        self.save(&[(RunResult::KIND, result.id, fields(result)?)], |inner| {
+            inner.save_result(result)
+        })
+    }
+
+    fn get_result(&self, id: Uuid) -> Result<Option<RunResult>, Error> {
+        self.inner.get_result(id)
+    }
+
+    fn list_results(&self, run_id: Uuid) -> Result<Vec<RunResult>, Error> {
+        self.inner.list_results(run_id)
+    }
+
+    fn save_entity(&self, entity: &Entity) -> Result<(), Error> {
+        self.save(&[(Entity::KIND, entity.id, fields(entity)?)], |inner| {
+            inner.save_entity(entity)
+        })
+    }
+
+    fn get_entity(&self, id: Uuid) -> Result<Option<Entity>, Error> {
+        self.inner.get_entity(id)
+    }
+
+    fn list_entities(&self, case_id: Uuid) -> Result<Vec<Entity>, Error> {
+        self.inner.list_entities(case_id)
+    }
+
+    fn save_note(&self, note: &Note) -> Result<(), Error> {
+        self.save(&[(Note::KIND, note.id, fields(note)?)], |inner| {
+            inner.save_note(note)
+        })
+    }
+
+    fn get_note(&self, id: Uuid) -> Result<Option<Note>, Error> {
+        self.inner.get_note(id)
+    }
+
+    fn list_notes(&self, case_id: Uuid) -> Result<Vec<Note>, Error> {
+        self.inner.list_notes(case_id)
+    }
+
+    fn find_runs(&self, filter: &RunFilter) -> Result<Vec<Run>, Error> {
+        self.inner.find_runs(filter)
+    }
+
+    fn save_run_with_results(&self, run: &Run, results: &[RunResult]) -> Result<(), Error> {
+        let mut records = vec![];
+        for result in results {
+            records.push((RunResult::KIND, result.id, fields(result)?));
+        }
+        records.push((Run::KIND, run.id, fields(run)?));
+        self.save(&records, |inner| inner.save_run_with_results(run, results))
+    }
+
+    fn delete_run(&self, id: Uuid) -> Result<(), Error> {
+        let mut records = vec![(Run::KIND, id)];
+        for result in self.inner.list_results(id)? {
+            records.push((RunResult::KIND, result.id));
+        }
+        self.delete(&records, |inner| inner.delete_run(id))
+    }
+
+    fn delete_query(&self, id: Uuid) -> Result<(), Error> {
+        self.delete(&[(Query::KIND, id)], |inner| inner.delete_query(id))
+    }
+
+    fn delete_entity(&self, id: Uuid) -> Result<(), Error> {
+        self.delete(&[(Entity::KIND, id)], |inner| inner.delete_entity(id))
+    }
+
+    fn append_changes(&self, changes: &[Change]) -> Result<(), Error> {
+        self.merge(changes, None).map(|_| ())
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<Change>, Error> {
+        self.inner.list_changes(after, limit)
+    }
+
+    fn redact_changes(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.inner.redact_changes(record_ids)
+    }
+
+    //the evidence log isn't synced, it's kept by the storage that collected the runs
+    fn append_evidence(&self, entry: &EvidenceEntry) -> Result<(), Error> {
+        self.inner.append_evidence(entry)
+    }
+
+    fn list_evidence(&self, after: u64, limit: usize) -> Result<Vec<EvidenceEntry>, Error> {
+        self.inner.list_evidence(after, limit)
+    }
+
+    fn redact_evidence(&self, record_ids: &[Uuid]) -> Result<(), Error> {
+        self.inner.redact_evidence(record_ids)
+    }
+}
+
+/// ## Description
+/// What a sync engine is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum SyncPhase {
+    Idle,
+    Syncing,
+    /// The last sync couldn't reach the remote storage.
+    Offline,
+    /// The last sync failed for another reason.
+    Failed,
+}
+
+/// ## Description
+/// The sync status shown to the user.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SyncStatus {
+    pub phase: SyncPhase,
+    /// When the last successful sync finished.
+    pub last_sync: Option<DateTime<Utc>>,
+    /// Why the last sync failed.
+    pub error: Option<String>,
+    /// The number of local changes that weren't pushed yet.
+    pub pending: usize,
+    /// The conflicts that were resolved since they were last cleared, oldest first.
+    pub conflicts: Vec<Conflict>,
+}
+
+// what's kept between syncs, the cursors are sequence numbers of the journals
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SyncState {
+    pulled: u64,
+    pushed: u64,
+    last_sync: Option<DateTime<Utc>>,
+    conflicts: Vec<Conflict>,
+}
+
+/// Connects to the remote storage of a sync engine.
+pub type Connect = Box<dyn Fn() -> Result<Arc<dyn Storage>, Error> + Send + Sync>;
+
+/// ## Description
+/// Syncs a local journaled storage with a remote storage.
+///
+/// The engine connects to the remote when it syncs, so it can be created offline.
+/// The sync cursors and the conflicts are kept in a json file when a state path is given.
+/// ## Example
+/// **Basic usage:**
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use storage_manager::{JournaledStorage, RemoteStorage, SqliteStorage, Storage, SyncEngine};
+/// let local = Arc::new(JournaledStorage::new(Arc::new(SqliteStorage::open("./store.db").unwrap())).unwrap());
+/// let engine = SyncEngine::new(
+///     local,
+///     Box::new(|| {
+///         let remote = RemoteStorage::connect("https://sherlock.example.com/api", None, true, None)?;
+///         Ok(Arc::new(remote) as Arc<dyn Storage>)
+///     }),
+///     Some("./store.sync.json".into()),
+/// )
+/// .unwrap();
+/// let status = engine.sync().unwrap();
+/// println!("{} changes left to push", status.pending);
+/// ```
+pub struct SyncEngine {
+    local: Arc<JournaledStorage>,
+    connect: Connect,
+    remote: Mutex<Option<Arc<dyn Storage>>>,
+    state_path: Option<PathBuf>,
+    // held for the whole sync, one sync at a time
+    state: Mutex<SyncState>,
+    // mirrors of the state that can be read during a sync
+    pushed: AtomicU64,
+    status: Mutex<SyncStatus>,
+}
+
+impl SyncEngine {
+    /// ## Description
+    /// Creates a sync engine, reading the state of the previous syncs from `state_path`.
+    pub fn new(
+        local: Arc<JournaledStorage>,
+        connect: Connect,
+        state_path: Option<PathBuf>,
+    ) -> Result<SyncEngine, Error> {
+        let state: SyncState = match &state_path {
+            Some(path) => match fs::read(path) {
+                Ok(json) => serde_json::from_slice(&json)
+                    .map_err(|error| Error::Serialization(error.to_string()))?,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => SyncState::default(),
+                Err(error) => return Err(Error::Io(error.to_string())),
+            },
+            None => SyncState::default(),
+        };
+        let status = SyncStatus {
+            phase: SyncPhase::Idle,
+            last_sync: state.last_sync,
+            error: None,
+            pending: 0,
+            conflicts: state.conflicts.clone(),
+        };
+        Ok(SyncEngine {
+            local,
+            connect,
+            remote: Mutex::new(None),
+            state_path,
+            pushed: AtomicU64::new(state.pushed),
+            state: Mutex::new(state),
+            status: Mutex::new(status),
+        })
+    }
+
+    /// Gets the local storage that is synced.
+    pub fn get_local(&self) -> &Arc<JournaledStorage> {
+        &self.local
+    }
+
+    /// ## Description
+    /// Gets the sync status, the pending changes are counted on every call.
+    pub fn get_status(&self) -> Result<SyncStatus, Error> {
+        let mut status = self.status.lock().unwrap().clone();
+        status.pending = self
+            .local
+            .count_local_changes(self.pushed.load(Ordering::SeqCst))?;
+        Ok(status)
+    }
+
+    /// ## Description
+    /// Pulls the remote changes, then pushes the local ones.
+    ///
+    /// Fails with `Error::Remote` when the remote can't be reached, the status is `Offline`
+    /// until a sync succeeds.
+    pub fn sync(&self) -> Result<SyncStatus, Error> {
+        let mut state = self.state.lock().unwrap();
+        {
+            let mut status = self.status.lock().unwrap();
+            status.phase = SyncPhase::Syncing;
+            status.error = None;
+        }
+        let outcome = self.sync_state(&mut state);
+        {
+            let mut status = self.status.lock().unwrap();
+            status.last_sync = state.last_sync;
+            status.conflicts = state.conflicts.clone();
+            match &outcome {
+                Ok(()) => status.phase = SyncPhase::Idle,
+                Err(error) => {
+                    status.phase = match error {
+                        Error::Remote(_) => SyncPhase::Offline,
+                        _ => SyncPhase::Failed,
+                    };
+                    status.error = Some(error.to_string());
+                }
+            }
+        }
+        if let Err(Error::Remote(_)) = &outcome {
+            //connect again on the next sync
+            *self.remote.lock().unwrap() = None;
+        }
+        drop(state);
+        outcome.and_then(|_| self.get_status())
+    }
+
+    /// ## Description
+    /// Forgets the conflicts, i.e: once the user has reviewed them.
+    pub fn clear_conflicts(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.conflicts.clear();
+        self.save_state(&state)?;
+        self.status.lock().unwrap().conflicts.clear();
+        Ok(())
+    }
+
+    fn remote(&self) -> Result<Arc<dyn Storage>, Error> {
+        let mut remote = self.remote.lock().unwrap();
+        match remote.as_ref() {
+            Some(remote) => Ok(remote.clone()),
+            None => {
+                let connected = (self.connect)()?;
+                *remote = Some(connected.clone());
+                Ok(connected)
+            }
+        }
+    }
+
+    fn sync_state(&self, state: &mut SyncState) -> Result<(), Error> {
+        let remote = self.remote()?;
+
+        //pull everything before merging, a record's fields may be split between pages
+        let mut pulled = vec![];
+        let mut cursor = state.pulled;
+        loop {
+            let changes = remote.list_changes(cursor, api::MAX_PAGE_SIZE)?;
+            let Some(last) = changes.last() else {
+                break;
+            };
+            cursor = last.seq;
+            pulled.extend(changes);
+        }
+        let conflicts = self.local.merge(&pulled, Some(state.pushed))?;
+        state.pulled = cursor;
+        state.conflicts.extend(conflicts);
+        self.save_state(state)?;
+
+        //push the local changes, the merged ones came from the remote
+        loop {
+            let mut changes = self.local.list_changes(state.pushed, api::MAX_PAGE_SIZE)?;
+            //a new record must be pushed whole, leave the record cut by the page for the next one
+            if changes.len() == api::MAX_PAGE_SIZE {
+                let cut = changes.last().map(|last| last.record_id);
+                let whole = changes
+                    .iter()
+                    .rposition(|change| Some(change.record_id) != cut);
+                if let Some(whole) = whole {
+                    changes.truncate(whole + 1);
+                }
+            }
+            let Some(last) = changes.last() else {
+                break;
+            };
+            let last = last.seq;
+            //the erased changes of deleted records would be merged as values
+            let local: Vec<Change> = changes
+                .into_iter()
+                .filter(|change| change.origin == ChangeOrigin::Local)
+                .filter(|change| {
+                    change.field == DELETED
+                        || !self.local.is_deleted(&change.kind, change.record_id)
+                })
+                .collect();
+            if !local.is_empty() {
+                remote.append_changes(&local)?;
+            }
+            state.pushed = last;
+            self.pushed.store(last, Ordering::SeqCst);
+            self.save_state(state)?;
+        }
+
+        state.last_sync = Some(Utc::now());
+        self.save_state(state)
+    }
+
+    // write to a temporary file first so a crash can't leave a half written state
+    fn save_state(&self, state: &SyncState) -> Result<(), Error> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(state)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, json).map_err(|error| Error::Io(error.to_string()))?;
+        fs::rename(&temp, path).map_err(|error| Error::Io(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStorage;
+    use chrono::Duration;
+
+    fn journaled() -> Arc<JournaledStorage> {
+        Arc::new(JournaledStorage::new(Arc::new(MemoryStorage::new())).unwrap())
+    }
+
+    // a sync engine between `local` and a remote that is another journaled storage
+    fn engine(local: &Arc<JournaledStorage>, remote: &Arc<JournaledStorage>) -> SyncEngine {
+        let remote = remote.clone();
+        SyncEngine::new(
+            local.clone(),
+            Box::new(move || Ok(remote.clone() as Arc<dyn Storage>)),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn journal_records_changed_fields() {
+        let storage = journaled();
+        let mut case = Case::new("case");
+        storage.save_case(&case).unwrap();
+        assert_eq!(storage.list_changes(0, 10).unwrap().len(), 8);
+
+        //only the changed fields are journaled, saving the same record again journals nothing
+        case.title = "renamed".into();
+        storage.save_case(&case).unwrap();
+        storage.save_case(&case).unwrap();
+        let changes = storage.list_changes(8, 10).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "title");
+        assert_eq!(changes[0].value, "renamed");
+        assert_eq!(changes[0].seq, 9);
+
+        //records saved before the storage was journaled are journaled when it's opened
+        let inner = Arc::new(MemoryStorage::new());
+        let query = Query::new(case.id, "user123");
+        inner.save_case(&case).unwrap();
+        inner.save_query(&query).unwrap();
+        let storage = JournaledStorage::new(inner).unwrap();
+        assert_eq!(storage.list_changes(0, 20).unwrap().len(), 12);
+        assert_eq!(storage.count_local_changes(0).unwrap(), 12);
+
+        //and so are the saves that didn't get to append their changes
+        let inner = Arc::new(MemoryStorage::new());
+        let storage = JournaledStorage::new(inner.clone()).unwrap();
+        let mut case = Case::new("case");
+        storage.save_case(&case).unwrap();
+        case.title = "saved before a crash".into();
+        inner.save_case(&case).unwrap();
+        let storage = JournaledStorage::new(inner.clone()).unwrap();
+        let changes = storage.list_changes(8, 10).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].value, "saved before a crash");
+        assert_eq!(JournaledStorage::new(inner).unwrap().get_last_seq(), 9);
+    }
+
+    #[test]
+    fn merges_keep_the_latest_change() {
+        let storage = journaled();
+        let case = Case::new("case");
+        storage.save_case(&case).unwrap();
+        let change = |title: &str, at: DateTime<Utc>| Change {
+            seq: 1,
+            kind: Case::KIND.into(),
+            record_id: case.id,
+            field: "title".into(),
+            value: title.into(),
+            at,
+            origin: ChangeOrigin::Local,
+        };
+
+        //an older change loses, a newer one wins
+        let before = change("older", Utc::now() - Duration::hours(1));
+        let after = change("newer", Utc::now() + Duration::hours(1));
+        storage.append_changes(&[before]).unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap().unwrap().title, "case");
+        storage
+            .append_changes(std::slice::from_ref(&after))
+            .unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap().unwrap().title, "newer");
+
+        //merged changes are journaled as remote ones, merging them again changes nothing
+        let changes = storage.list_changes(0, 10).unwrap();
+        assert_eq!(changes.last().unwrap().origin, ChangeOrigin::Remote);
+        storage.append_changes(&[after]).unwrap();
+        assert_eq!(storage.list_changes(0, 10).unwrap(), changes);
+
+        //changes of records that don't exist yet create them
+        let new_case = Case::new("new");
+        let created: Vec<Change> = fields(&new_case)
+            .unwrap()
+            .into_iter()
+            .map(|(field, value)| Change {
+                seq: 1,
+                kind: Case::KIND.into(),
+                record_id: new_case.id,
+                field,
+                value,
+                at: Utc::now(),
+                origin: ChangeOrigin::Local,
+            })
+            .collect();
+        storage.append_changes(&created).unwrap();
+        assert_eq!(storage.get_case(new_case.id).unwrap(), Some(new_case));
+    }
+
+    #[test]
+    fn sync_two_storages() {
+        let server = journaled();
+        let laptop = journaled();
+        let desktop = journaled();
+        let laptop_sync = engine(&laptop, &server);
+        let desktop_sync = engine(&desktop, &server);
+
+        //records made on one side reach the other one
+        let case = Case::new("case");
+        laptop.save_case(&case).unwrap();
+        assert_eq!(laptop_sync.get_status().unwrap().pending, 8);
+        let status = laptop_sync.sync().unwrap();
+        assert_eq!(status.phase, SyncPhase::Idle);
+        assert_eq!(status.pending, 0);
+        assert!(status.last_sync.is_some());
+        desktop_sync.sync().unwrap();
+        assert_eq!(desktop.get_case(case.id).unwrap(), Some(case.clone()));
+
+        //both sides rename the case while offline, the latest rename wins on both sides
+        let mut first = case.clone();
+        first.title = "first".into();
+        desktop.save_case(&first).unwrap();
+        let mut second = case.clone();
+        second.title = "second".into();
+        laptop.save_case(&second).unwrap();
+        desktop_sync.sync().unwrap();
+        let status = laptop_sync.sync().unwrap();
+        desktop_sync.sync().unwrap();
+        for storage in [&server, &laptop, &desktop] {
+            assert_eq!(storage.get_case(case.id).unwrap(), Some(second.clone()));
+        }
+
+        //the conflict is recorded on the side that saw both changes
+        assert_eq!(status.conflicts.len(), 1);
+        assert_eq!(status.conflicts[0].field, "title");
+        assert_eq!(status.conflicts[0].winner, ChangeOrigin::Local);
+        assert!(desktop_sync.get_status().unwrap().conflicts.is_empty());
+        laptop_sync.clear_conflicts().unwrap();
+        assert!(laptop_sync.get_status().unwrap().conflicts.is_empty());
+
+        //a side's own changes coming back aren't conflicts
+        let mut third = second.clone();
+        third.title = "third".into();
+        laptop.save_case(&third).unwrap();
+        let status = laptop_sync.sync().unwrap();
+        assert!(status.conflicts.is_empty());
+        assert_eq!(server.get_case(case.id).unwrap(), Some(third));
+    }
+
+    #[test]
+    fn deletes_are_synced() {
+        let server = journaled();
+        let laptop = journaled();
+        let desktop = journaled();
+        let laptop_sync = engine(&laptop, &server);
+        let desktop_sync = engine(&desktop, &server);
+
+        let case = Case::new("case");
+        let query = Query::new(case.id, "user123");
+        let mut run = Run::start(&query, "facebook", "user");
+        run.finish(Ok(()));
+        let result = RunResult::new(run.id, "jane.doe lives at 221B Baker Street");
+        laptop.save_case(&case).unwrap();
+        laptop.save_query(&query).unwrap();
+        laptop
+            .save_run_with_results(&run, std::slice::from_ref(&result))
+            .unwrap();
+        laptop_sync.sync().unwrap();
+        desktop_sync.sync().unwrap();
+        assert!(desktop.get_result(result.id).unwrap().is_some());
+
+        //a deleted run is deleted everywhere, and its values are erased from every journal
+        laptop.delete_run(run.id).unwrap();
+        laptop_sync.sync().unwrap();
+        desktop_sync.sync().unwrap();
+        for storage in [&server, &laptop, &desktop] {
+            assert_eq!(storage.get_run(run.id).unwrap(), None);
+            assert_eq!(storage.get_result(result.id).unwrap(), None);
+            let changes = storage.list_changes(0, 100).unwrap();
+            assert!(changes
+                .iter()
+                .filter(|change| change.record_id == result.id && change.field == "output")
+                .all(|change| change.value.is_null()));
+        }
+
+        //a change of a deleted record made elsewhere doesn't bring it back
+        let change = Change {
+            seq: 1,
+            kind: Run::KIND.into(),
+            record_id: run.id,
+            field: "engine".into(),
+            value: "twitter".into(),
+            at: Utc::now() + Duration::hours(1),
+            origin: ChangeOrigin::Local,
+        };
+        desktop.append_changes(&[change]).unwrap();
+        assert_eq!(desktop.get_run(run.id).unwrap(), None);
+
+        //a record that's deleted before it was synced never leaves
+        let mut other = Run::start(&query, "facebook", "user");
+        other.finish(Ok(()));
+        laptop.save_run(&other).unwrap();
+        laptop.delete_run(other.id).unwrap();
+        laptop_sync.sync().unwrap();
+        assert_eq!(server.get_run(other.id).unwrap(), None);
+        assert!(server
+            .list_changes(0, 100)
+            .unwrap()
+            .iter()
+            .filter(|change| change.record_id == other.id)
+            .all(|change| change.field == DELETED));
+    }
+
+    #[test]
+    fn offline_syncs_keep_the_changes() {
+        let local = journaled();
+        let engine = SyncEngine::new(
+            local.clone(),
+            Box::new(|| Err(Error::Remote("unreachable".into()))),
+            None,
+        )
+        .unwrap();
+        local.save_case(&Case::new("case")).unwrap();
+        assert!(matches!(engine.sync(), Err(Error::Remote(_))));
+        let status = engine.get_status().unwrap();
+        assert_eq!(status.phase, SyncPhase::Offline);
+        assert!(status.error.is_some());
+        assert_eq!(status.pending, 8);
+    }
+
+    #[test]
+    fn state_is_kept_between_engines() {
+        let path = std::env::temp_dir().join(format!(
+            "sherlock_sync_state_test-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let server = journaled();
+        let local = journaled();
+        local.save_case(&Case::new("case")).unwrap();
+        let remote = server.clone();
+        let connect: Connect = Box::new(move || Ok(remote.clone() as Arc<dyn Storage>));
+        let engine = SyncEngine::new(local.clone(), connect, Some(path.clone())).unwrap();
+        engine.sync().unwrap();
+        drop(engine);
+
+        //a new engine starts from the previous sync, nothing is pending
+        let remote = server.clone();
+        let connect: Connect = Box::new(move || Ok(remote.clone() as Arc<dyn Storage>));
+        let engine = SyncEngine::new(local, connect, Some(path.clone())).unwrap();
+        let status = engine.get_status().unwrap();
+        assert_eq!(status.pending, 0);
+        assert!(status.last_sync.is_some());
+        fs::remove_file(&path).unwrap();
+    }
+}
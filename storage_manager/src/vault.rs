@@ -0,0 +1,188 @@
+/*
+Vault:
+A small encrypted file of named secrets, i.e: the storage key or the auth token of a remote
+storage server, unlocked with a passphrase.
+
+The file is `MAGIC | salt | sealed json map of the secrets`, it's rewritten on every change.
+ */
+use crate::crypto::{self, Cipher, KeySource, KEY_SIZE, SALT_SIZE};
+use crate::Error;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8] = b"SHERLOCK-VAULT-1";
+/// The authenticated context of the sealed secrets.
+const CONTEXT: &[u8] = b"vault";
+/// The name of the secret that holds the key of an encrypted storage.
+pub const STORAGE_KEY_SECRET: &str = "storage_key";
+
+/// ## Description
+/// An unlocked vault of secrets.
+pub struct Vault {
+    path: PathBuf,
+    salt: Vec<u8>,
+    cipher: Cipher,
+    secrets: BTreeMap<String, Zeroizing<String>>,
+}
+
+impl Vault {
+    /// ## Description
+    /// Unlocks the vault at `path`, an empty vault is created if the file doesn't exist.
+    ///
+    /// Fails with `Error::WrongKey` if the passphrase doesn't unlock the vault.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::Vault;
+    /// let mut vault = Vault::open("./vault", "correct horse battery staple").unwrap();
+    /// vault.set("remote_token", "abc").unwrap();
+    /// assert_eq!(vault.get("remote_token"), Some("abc"));
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Vault, Error> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let salt = crypto::random_bytes(SALT_SIZE);
+                let cipher = Cipher::new(&KeySource::Passphrase(passphrase.into()), Some(&salt))?;
+                let vault = Vault {
+                    path,
+                    salt,
+                    cipher,
+                    secrets: BTreeMap::new(),
+                };
+                vault.save()?;
+                return Ok(vault);
+            }
+            Err(error) => return Err(Error::Io(error.to_string())),
+        };
+
+        if !content.starts_with(MAGIC) || content.len() < MAGIC.len() + SALT_SIZE {
+            return Err(Error::Crypto(format!("{} isn't a vault", path.display())));
+        }
+        let (salt, sealed) = content[MAGIC.len()..].split_at(SALT_SIZE);
+        let cipher = Cipher::new(&KeySource::Passphrase(passphrase.into()), Some(salt))?;
+        //a vault that can't be opened was locked with another passphrase
+        let json = Zeroizing::new(cipher.open(sealed, CONTEXT).map_err(|_| Error::WrongKey)?);
+        let secrets: BTreeMap<String, String> = serde_json::from_slice(&json)
+            .map_err(|error| Error::Serialization(error.to_string()))?;
+        Ok(Vault {
+            path,
+            salt: salt.to_vec(),
+            cipher,
+            secrets: secrets
+                .into_iter()
+                .map(|(name, value)| (name, Zeroizing::new(value)))
+                .collect(),
+        })
+    }
+
+    /// Gets the file the vault is kept in.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets a secret by name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(|value| value.as_str())
+    }
+
+    /// Lists the names of the secrets.
+    pub fn list(&self) -> Vec<&str> {
+        self.secrets.keys().map(String::as_str).collect()
+    }
+
+    /// Adds or replaces a secret and saves the vault.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.secrets
+            .insert(name.to_owned(), Zeroizing::new(value.to_owned()));
+        self.save()
+    }
+
+    /// Removes a secret and saves the vault, returns whether the secret existed.
+    pub fn remove(&mut self, name: &str) -> Result<bool, Error> {
+        let removed = self.secrets.remove(name).is_some();
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// ## Description
+    /// Gets the key of the encrypted storage, a random key is generated on first use.
+    pub fn storage_key(&mut self) -> Result<KeySource, Error> {
+        if self.get(STORAGE_KEY_SECRET).is_none() {
+            let key = crypto::to_hex(&crypto::random_bytes(KEY_SIZE));
+            self.set(STORAGE_KEY_SECRET, &key)?;
+        }
+        crypto::from_hex(self.get(STORAGE_KEY_SECRET).unwrap_or_default())
+            .map(KeySource::Key)
+            .ok_or_else(|| Error::Crypto(format!("`{}` isn't a valid key", STORAGE_KEY_SECRET)))
+    }
+
+    /// Locks the vault with a new passphrase.
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<(), Error> {
+        self.salt = crypto::random_bytes(SALT_SIZE);
+        self.cipher = Cipher::new(&KeySource::Passphrase(passphrase.into()), Some(&self.salt))?;
+        self.save()
+    }
+
+    // rewrite the whole file through a temporary file
+    fn save(&self) -> Result<(), Error> {
+        let secrets: BTreeMap<&str, &str> = self
+            .secrets
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let json = Zeroizing::new(
+            serde_json::to_vec(&secrets)
+                .map_err(|error| Error::Serialization(error.to_string()))?,
+        );
+        let content = [MAGIC, &self.salt, &self.cipher.seal(&json, CONTEXT)].concat();
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|error| Error::Io(error.to_string()))?;
+        }
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, content).map_err(|error| Error::Io(error.to_string()))?;
+        fs::rename(&temp, &self.path).map_err(|error| Error::Io(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_and_unlock() {
+        let path = std::env::temp_dir().join(format!("sherlock_vault_test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut vault = Vault::open(&path, "secret").unwrap();
+        vault.set("remote_token", "abc123").unwrap();
+        let key = match vault.storage_key().unwrap() {
+            KeySource::Key(key) => key,
+            KeySource::Passphrase(_) => panic!("expected a raw key"),
+        };
+        assert_eq!(key.len(), KEY_SIZE);
+
+        //the secrets are encrypted on disk
+        let content = fs::read(&path).unwrap();
+        assert!(!content.windows(6).any(|window| window == b"abc123"));
+
+        assert!(matches!(Vault::open(&path, "wrong"), Err(Error::WrongKey)));
+        let mut vault = Vault::open(&path, "secret").unwrap();
+        assert_eq!(vault.get("remote_token"), Some("abc123"));
+        assert!(matches!(vault.storage_key().unwrap(), KeySource::Key(k) if k == key));
+
+        vault.change_passphrase("new secret").unwrap();
+        assert!(Vault::open(&path, "secret").is_err());
+        assert_eq!(vault.list(), vec!["remote_token", STORAGE_KEY_SECRET]);
+        assert!(vault.remove("remote_token").unwrap());
+        let vault = Vault::open(&path, "new secret").unwrap();
+        assert_eq!(vault.get("remote_token"), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1012 @@
+/*
+Storage Server:
+The reference server of the remote storage, it serves any storage backend over a REST/JSON API.
+It speaks plain HTTP, put it behind a TLS reverse proxy when it's reachable from the network.
+
+API (version 1):
+    Every request must carry `Authorization: Bearer <token>` when the server has a token.
+    Records are the json forms of `Case`, `Query`, `Run`, `RunResult`, `Entity` and `Note`,
+    errors are answered as `{"error": "<message>"}`.
+
+    GET /v1/status                      {"api_version": 1}
+
+    GET /v1/cases                       a page of cases
+    GET /v1/queries?case_id=<id>        a page of the queries of a case
+    GET /v1/runs?query_id=<id>          a page of the runs of a query
+    GET /v1/runs?case_id=&value=&engine=&from=&to=
+                                        a page of the runs that match every given filter,
+                                        `value` is the query's text, `from`/`to` are RFC 3339
+    GET /v1/results?run_id=<id>         a page of the results of a run
+    GET /v1/entities?case_id=<id>       a page of the entities extracted in a case
+    GET /v1/notes?case_id=<id>          a page of the notes of a case
+
+    GET /v1/{kind}/<id>                 a record, `404` if there's none
+    PUT /v1/{kind}/<id>                 inserts or replaces a record
+    PUT /v1/runs/<id>/outcome           saves `{"run": .., "results": [..]}` all at once
+    DELETE /v1/{kind}/<id>              deletes a run (with its results), a query or an entity,
+                                        its values are erased from the journal
+
+    GET /v1/changes?cursor=<seq>        a page of the journal's changes after a sequence number
+    PUT /v1/changes                     merges `[change, ..]` made in a client's storage
+
+    GET /v1/evidence?cursor=<seq>       a page of the evidence log's entries after a sequence number
+    PUT /v1/evidence                    appends an entry to the evidence log, `409` if its
+                                        sequence number doesn't follow the last entry's
+    PUT /v1/evidence/redacted           redacts the entries of `[id, ..]`, i.e: of a purged run
+
+Pagination:
+    Lists take `limit` (100 by default, 500 at most) and `cursor`, and answer
+    `{"items": [..], "next": <cursor>}`. Pass `next` as the `cursor` of the following request,
+    it's `null` on the last page. The cursor of the changes (and of the evidence log's entries)
+    is the last sequence number of the page, following pages may show up later as the log grows.
+    The other lists are read once, on their first page: their cursor keeps the rest of the list
+    on the server and is answered `410 Gone` once it went unused for a few minutes.
+
+Sync:
+    The served storage is journaled, every write (through the records' endpoints or merged
+    changes) is recorded in its journal. Clients sync by pulling the changes after the last
+    change they've seen and pushing their own, see `storage_manager::SyncEngine`.
+
+Conflicts:
+    Records are answered with an `ETag`, the revision of their content. A `PUT` with
+    `If-Match: "<revision>"` is refused with `409 Conflict` if the stored record has another
+    revision, i.e: someone else changed it since it was read.
+ */
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use storage_manager::{
+    api::{self, ErrorBody, Page, RunOutcome, Status},
+    Case, Change, Entity, EvidenceEntry, JournaledStorage, Note, Query, Run, RunFilter, RunResult,
+    Storage,
+};
+use tiny_http::{Header, Method, Request, Response};
+use uuid::Uuid;
+
+/// The environment variable with the token the clients must send.
+pub const TOKEN_ENV_VAR: &str = "SHERLOCK_STORAGE_TOKEN";
+/// The largest request body the server reads.
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+/// The lists being paged through that the server keeps at most, the oldest is dropped first.
+const MAX_LISTINGS: usize = 64;
+/// How long the rest of a list is kept after its last page was read.
+const LISTING_TTL: Duration = Duration::from_secs(300);
+
+/// ## Description
+/// A storage server, created with `bind` and run with `serve`.
+pub struct Server {
+    http: tiny_http::Server,
+    storage: JournaledStorage,
+    token: Option<String>,
+    // the revision checks and the writes they guard happen one at a time
+    writes: Mutex<()>,
+    // the rest of the lists being paged through, by cursor
+    listings: Mutex<HashMap<String, Listing>>,
+    stopped: AtomicBool,
+}
+
+// the records of a list that weren't answered yet
+struct Listing {
+    records: VecDeque<serde_json::Value>,
+    touched: Instant,
+}
+
+// an answer to a request
+struct Reply {
+    status: u16,
+    body: Vec<u8>,
+    etag: Option<String>,
+}
+
+// a refused request
+struct ApiError(u16, String);
+
+type Answer = Result<Reply, ApiError>;
+
+impl Server {
+    /// ## Description
+    /// Listens on `address`, i.e: `127.0.0.1:8700` (port `0` picks a free port).
+    ///
+    /// Without a token every client is accepted. The storage is journaled so clients can sync
+    /// with it, records it had before are journaled first.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```no_run
+    /// # use storage_manager::MemoryStorage;
+    /// # use storage_server::Server;
+    /// let server = Server::bind("127.0.0.1:8700", Box::new(MemoryStorage::new()), None).unwrap();
+    /// server.serve(4);
+    /// ```
+    pub fn bind(
+        address: &str,
+        storage: Box<dyn Storage>,
+        token: Option<String>,
+    ) -> Result<Server, String> {
+        let listener = TcpListener::bind(address).map_err(|error| error.to_string())?;
+        Server::from_listener(listener, storage, token)
+    }
+
+    /// ## Description
+    /// Serves on a listener that is already bound, i.e: one handed over by the service manager.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::MemoryStorage;
+    /// # use storage_server::Server;
+    /// let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let server = Server::from_listener(listener, Box::new(MemoryStorage::new()), None).unwrap();
+    /// ```
+    pub fn from_listener(
+        listener: TcpListener,
+        storage: Box<dyn Storage>,
+        token: Option<String>,
+    ) -> Result<Server, String> {
+        let storage =
+            JournaledStorage::new(Arc::from(storage)).map_err(|error| error.to_string())?;
+        let http =
+            tiny_http::Server::from_listener(listener, None).map_err(|error| error.to_string())?;
+        Ok(Server {
+            http,
+            storage,
+            token,
+            writes: Mutex::new(()),
+            listings: Mutex::new(HashMap::new()),
+            stopped: AtomicBool::new(false),
+        })
+    }
+
+    /// Gets the address the server listens on.
+    pub fn get_address(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// ## Description
+    /// Answers requests with `workers` threads until `stop` is called.
+    pub fn serve(&self, workers: usize) {
+        thread::scope(|scope| {
+            for _ in 0..workers.max(1) {
+                scope.spawn(|| loop {
+                    match self.http.recv() {
+                        Ok(request) => self.answer(request),
+                        Err(_) if self.stopped.load(Ordering::SeqCst) => break,
+                        Err(error) => eprintln!("storage server: {}", error),
+                    }
+                });
+            }
+        });
+    }
+
+    /// Stops the workers of `serve`.
+    pub fn stop(&self, workers: usize) {
+        self.stopped.store(true, Ordering::SeqCst);
+        for _ in 0..workers.max(1) {
+            self.http.unblock();
+        }
+    }
+
+    fn answer(&self, mut request: Request) {
+        let reply = self
+            .handle(&mut request)
+            .unwrap_or_else(|ApiError(status, error)| Reply {
+                status,
+                body: to_json(&ErrorBody { error }),
+                etag: None,
+            });
+        let mut response = Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header("Content-Type", "application/json"));
+        if let Some(etag) = reply.etag {
+            response.add_header(header("ETag", &format!("\"{}\"", etag)));
+        }
+        //the client went away, nothing left to do
+        let _ = request.respond(response);
+    }
+
+    fn handle(&self, request: &mut Request) -> Answer {
+        self.authorize(request)?;
+
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let query: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let if_match =
+            find_header(request, "If-Match").map(|value| value.trim_matches('"').to_owned());
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Get, ["v1", "status"]) => ok(&Status {
+                api_version: api::API_VERSION,
+            }),
+            (Method::Get, ["v1", "changes"]) => self.changes(&query),
+            (Method::Put, ["v1", "changes"]) => {
+                let changes: Vec<Change> = parse_body(&read_body(request)?)?;
+                let _writes = self.writes.lock().unwrap();
+                self.storage.append_changes(&changes).map_err(internal)?;
+                ok(&serde_json::json!({}))
+            }
+            (Method::Get, ["v1", "evidence"]) => self.evidence(&query),
+            (Method::Put, ["v1", "evidence"]) => {
+                let entry: EvidenceEntry = parse_body(&read_body(request)?)?;
+                let _writes = self.writes.lock().unwrap();
+                self.storage
+                    .append_evidence(&entry)
+                    .map_err(|error| match error {
+                        storage_manager::Error::Conflict(error) => ApiError(409, error),
+                        error => internal(error),
+                    })?;
+                ok(&serde_json::json!({}))
+            }
+            (Method::Put, ["v1", "evidence", "redacted"]) => {
+                let ids: Vec<Uuid> = parse_body(&read_body(request)?)?;
+                let _writes = self.writes.lock().unwrap();
+                self.storage.redact_evidence(&ids).map_err(internal)?;
+                ok(&serde_json::json!({}))
+            }
+            (Method::Get, ["v1", kind]) => self.list(kind, &query),
+            (Method::Get, ["v1", kind, id]) => self.get(kind, parse_id(id)?),
+            (Method::Delete, ["v1", kind, id]) => self.delete(kind, parse_id(id)?),
+            (Method::Put, ["v1", kind, id]) => {
+                let body = read_body(request)?;
+                self.put(kind, parse_id(id)?, &body, if_match)
+            }
+            (Method::Put, ["v1", "runs", id, "outcome"]) => {
+                let outcome: RunOutcome = parse_body(&read_body(request)?)?;
+                let _writes = self.writes.lock().unwrap();
+                check_id(parse_id(id)?, outcome.run.id)?;
+                check_revision(
+                    if_match,
+                    self.storage.get_run(outcome.run.id).map_err(internal)?,
+                )?;
+                self.storage
+                    .save_run_with_results(&outcome.run, &outcome.results)
+                    .map_err(internal)?;
+                saved(&outcome.run)
+            }
+            _ => Err(ApiError(
+                404,
+                format!("no such endpoint: {} {}", request.method(), path),
+            )),
+        }
+    }
+
+    fn authorize(&self, request: &Request) -> Result<(), ApiError> {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        let sent = find_header(request, "Authorization").unwrap_or_default();
+        match sent.strip_prefix("Bearer ") {
+            Some(sent) if constant_time_eq(sent.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(ApiError(401, "missing or invalid token".into())),
+        }
+    }
+
+    fn list(&self, kind: &str, query: &HashMap<String, String>) -> Answer {
+        //the following pages come from the list read for the first one
+        if let Some(cursor) = query.get("cursor") {
+            return self.next_page(cursor, parse_limit(query)?);
+        }
+        let id = |name: &str| -> Result<Uuid, ApiError> {
+            let value = query
+                .get(name)
+                .ok_or_else(|| ApiError(400, format!("`{}` is required", name)))?;
+            parse_id(value)
+        };
+        match kind {
+            "cases" => self.first_page(self.storage.list_cases().map_err(internal)?, query),
+            "queries" => self.first_page(
+                self.storage
+                    .list_queries(id("case_id")?)
+                    .map_err(internal)?,
+                query,
+            ),
+            "runs" if query.contains_key("query_id") => self.first_page(
+                self.storage.list_runs(id("query_id")?).map_err(internal)?,
+                query,
+            ),
+            "runs" => {
+                let time = |name: &str| -> Result<_, ApiError> {
+                    query
+                        .get(name)
+                        .map(|time| {
+                            chrono::DateTime::parse_from_rfc3339(time)
+                                .map(|time| time.with_timezone(&chrono::Utc))
+                                .map_err(|error| ApiError(400, format!("`{}`: {}", name, error)))
+                        })
+                        .transpose()
+                };
+                let filter = RunFilter {
+                    case_id: query.get("case_id").map(|id| parse_id(id)).transpose()?,
+                    value: query.get("value").cloned(),
+                    engine: query.get("engine").cloned(),
+                    from: time("from")?,
+                    to: time("to")?,
+                };
+                self.first_page(self.storage.find_runs(&filter).map_err(internal)?, query)
+            }
+            "results" => self.first_page(
+                self.storage.list_results(id("run_id")?).map_err(internal)?,
+                query,
+            ),
+            "entities" => self.first_page(
+                self.storage
+                    .list_entities(id("case_id")?)
+                    .map_err(internal)?,
+                query,
+            ),
+            "notes" => self.first_page(
+                self.storage.list_notes(id("case_id")?).map_err(internal)?,
+                query,
+            ),
+            _ => Err(ApiError(404, format!("no such collection: {}", kind))),
+        }
+    }
+
+    // answer the first page of a list, the rest is kept for the following pages
+    fn first_page<T: Serialize>(&self, records: Vec<T>, query: &HashMap<String, String>) -> Answer {
+        let limit = parse_limit(query)?;
+        let records = records
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<VecDeque<_>, _>>()
+            .map_err(|error| ApiError(500, error.to_string()))?;
+        self.page(records, limit)
+    }
+
+    // answer the following page of a list
+    fn next_page(&self, cursor: &str, limit: usize) -> Answer {
+        let listing = self.listings.lock().unwrap().remove(cursor);
+        match listing {
+            Some(listing) => self.page(listing.records, limit),
+            None => Err(ApiError(
+                410,
+                format!("the cursor expired, list again from the start: {}", cursor),
+            )),
+        }
+    }
+
+    // cut a page out of the records left, the others are kept under a new cursor
+    fn page(&self, mut records: VecDeque<serde_json::Value>, limit: usize) -> Answer {
+        let items: Vec<serde_json::Value> = records.drain(..limit.min(records.len())).collect();
+        if records.is_empty() {
+            return ok(&Page { items, next: None });
+        }
+        let cursor = Uuid::new_v4().to_string();
+        let mut listings = self.listings.lock().unwrap();
+        //the abandoned lists are forgotten
+        listings.retain(|_, listing| listing.touched.elapsed() < LISTING_TTL);
+        if listings.len() >= MAX_LISTINGS {
+            let oldest = listings
+                .iter()
+                .min_by_key(|(_, listing)| listing.touched)
+                .map(|(cursor, _)| cursor.clone());
+            if let Some(oldest) = oldest {
+                listings.remove(&oldest);
+            }
+        }
+        listings.insert(
+            cursor.clone(),
+            Listing {
+                records,
+                touched: Instant::now(),
+            },
+        );
+        ok(&Page {
+            items,
+            next: Some(cursor),
+        })
+    }
+
+    // a page of the journal, the cursor is the sequence number of the last change seen
+    fn changes(&self, query: &HashMap<String, String>) -> Answer {
+        let limit = parse_limit(query)?;
+        let after = parse_cursor(query)?;
+        let items = self.storage.list_changes(after, limit).map_err(internal)?;
+        let next = items
+            .last()
+            .filter(|_| items.len() == limit)
+            .map(|last| last.seq.to_string());
+        ok(&Page { items, next })
+    }
+
+    // a page of the evidence log, the cursor is the sequence number of the last entry seen
+    fn evidence(&self, query: &HashMap<String, String>) -> Answer {
+        let limit = parse_limit(query)?;
+        let after = parse_cursor(query)?;
+        let items = self.storage.list_evidence(after, limit).map_err(internal)?;
+        let next = items
+            .last()
+            .filter(|_| items.len() == limit)
+            .map(|last| last.seq.to_string());
+        ok(&Page { items, next })
+    }
+
+    fn get(&self, kind: &str, id: Uuid) -> Answer {
+        match kind {
+            "cases" => found(self.storage.get_case(id).map_err(internal)?),
+            "queries" => found(self.storage.get_query(id).map_err(internal)?),
+            "runs" => found(self.storage.get_run(id).map_err(internal)?),
+            "results" => found(self.storage.get_result(id).map_err(internal)?),
+            "entities" => found(self.storage.get_entity(id).map_err(internal)?),
+            "notes" => found(self.storage.get_note(id).map_err(internal)?),
+            _ => Err(ApiError(404, format!("no such collection: {}", kind))),
+        }
+    }
+
+    fn put(&self, kind: &str, id: Uuid, body: &[u8], if_match: Option<String>) -> Answer {
+        let storage = &self.storage;
+        let _writes = self.writes.lock().unwrap();
+        match kind {
+            "cases" => {
+                let case: Case = parse_body(body)?;
+                check_id(id, case.id)?;
+                check_revision(if_match, storage.get_case(id).map_err(internal)?)?;
+                storage.save_case(&case).map_err(internal)?;
+                saved(&case)
+            }
+            "queries" => {
+                let query: Query = parse_body(body)?;
+                check_id(id, query.id)?;
+                check_revision(if_match, storage.get_query(id).map_err(internal)?)?;
+                storage.save_query(&query).map_err(internal)?;
+                saved(&query)
+            }
+            "runs" => {
+                let run: Run = parse_body(body)?;
+                check_id(id, run.id)?;
+                check_revision(if_match, storage.get_run(id).map_err(internal)?)?;
+                storage.save_run(&run).map_err(internal)?;
+                saved(&run)
+            }
+            "results" => {
+                let result: RunResult = parse_body(body)?;
+                check_id(id, result.id)?;
+                check_revision(if_match, storage.get_result(id).map_err(internal)?)?;
+                storage.save_result(&result).map_err(internal)?;
+                saved(&result)
+            }
+            "entities" => {
+                let entity: Entity = parse_body(body)?;
+                check_id(id, entity.id)?;
+                check_revision(if_match, storage.get_entity(id).map_err(internal)?)?;
+                storage.save_entity(&entity).map_err(internal)?;
+                saved(&entity)
+            }
+            "notes" => {
+                let note: Note = parse_body(body)?;
+                check_id(id, note.id)?;
+                check_revision(if_match, storage.get_note(id).map_err(internal)?)?;
+                storage.save_note(&note).map_err(internal)?;
+                saved(&note)
+            }
+            _ => Err(ApiError(404, format!("no such collection: {}", kind))),
+        }
+    }
+
+    fn delete(&self, kind: &str, id: Uuid) -> Answer {
+        let storage = &self.storage;
+        let _writes = self.writes.lock().unwrap();
+        match kind {
+            "runs" => storage.delete_run(id).map_err(internal)?,
+            "queries" => storage.delete_query(id).map_err(internal)?,
+            "entities" => storage.delete_entity(id).map_err(internal)?,
+            _ => {
+                return Err(ApiError(
+                    405,
+                    format!("records of {} can't be deleted", kind),
+                ))
+            }
+        }
+        ok(&serde_json::json!({}))
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn find_header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_owned())
+}
+
+// compare without leaking how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn to_json<T: Serialize>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).unwrap_or_default()
+}
+
+fn internal(error: storage_manager::Error) -> ApiError {
+    ApiError(500, error.to_string())
+}
+
+// the sequence number cursor of the logs' pages, 0 for the first page
+fn parse_cursor(query: &HashMap<String, String>) -> Result<u64, ApiError> {
+    match query.get("cursor") {
+        Some(cursor) => cursor
+            .parse::<u64>()
+            .map_err(|_| ApiError(400, format!("invalid cursor: {}", cursor))),
+        None => Ok(0),
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, ApiError> {
+    Uuid::parse_str(id).map_err(|_| ApiError(400, format!("invalid id: {}", id)))
+}
+
+fn check_id(path: Uuid, record: Uuid) -> Result<(), ApiError> {
+    if path != record {
+        return Err(ApiError(
+            400,
+            "the record's id doesn't match the url".into(),
+        ));
+    }
+    Ok(())
+}
+
+// refuse the write if the record changed since the revision the client has read
+fn check_revision<T: Serialize>(
+    if_match: Option<String>,
+    current: Option<T>,
+) -> Result<(), ApiError> {
+    match (if_match, current) {
+        (None, _) => Ok(()),
+        (Some(expected), Some(current))
+            if expected == "*" || api::revision(&current) == expected =>
+        {
+            Ok(())
+        }
+        (Some(_), Some(current)) => Err(ApiError(
+            409,
+            format!(
+                "the record was changed, its revision is now {}",
+                api::revision(&current)
+            ),
+        )),
+        (Some(_), None) => Err(ApiError(409, "the record was removed".into())),
+    }
+}
+
+fn read_body(request: &mut Request) -> Result<Vec<u8>, ApiError> {
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|error| ApiError(400, error.to_string()))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(ApiError(413, "the request body is too large".into()));
+    }
+    Ok(body)
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|error| ApiError(400, error.to_string()))
+}
+
+fn ok<T: Serialize>(value: &T) -> Answer {
+    Ok(Reply {
+        status: 200,
+        body: to_json(value),
+        etag: None,
+    })
+}
+
+fn found<T: Serialize>(record: Option<T>) -> Answer {
+    match record {
+        Some(record) => Ok(Reply {
+            status: 200,
+            body: to_json(&record),
+            etag: Some(api::revision(&record)),
+        }),
+        None => Err(ApiError(404, "no such record".into())),
+    }
+}
+
+fn saved<T: Serialize>(record: &T) -> Answer {
+    Ok(Reply {
+        status: 200,
+        body: b"{}".to_vec(),
+        etag: Some(api::revision(record)),
+    })
+}
+
+fn parse_limit(query: &HashMap<String, String>) -> Result<usize, ApiError> {
+    let limit = match query.get("limit") {
+        Some(limit) => limit
+            .parse::<usize>()
+            .map_err(|_| ApiError(400, format!("invalid limit: {}", limit)))?,
+        None => api::DEFAULT_PAGE_SIZE,
+    };
+    Ok(limit.clamp(1, api::MAX_PAGE_SIZE))
+}
+
+/// ## Description
+/// Runs a server on a background thread, for tests and for embedding the server.
+pub struct BackgroundServer {
+    server: Arc<Server>,
+    workers: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl BackgroundServer {
+    /// ## Description
+    /// Starts serving on a background thread.
+    /// ## Example
+    /// **Basic usage:**
+    /// ```
+    /// # use storage_manager::MemoryStorage;
+    /// # use storage_server::{BackgroundServer, Server};
+    /// let server = Server::bind("127.0.0.1:0", Box::new(MemoryStorage::new()), None).unwrap();
+    /// let server = BackgroundServer::start(server, 2);
+    /// let url = server.get_url();
+    /// ```
+    pub fn start(server: Server, workers: usize) -> BackgroundServer {
+        let server = Arc::new(server);
+        let serving = server.clone();
+        BackgroundServer {
+            server,
+            workers,
+            thread: Some(thread::spawn(move || serving.serve(workers))),
+        }
+    }
+
+    /// Gets the base url of the server's API.
+    pub fn get_url(&self) -> String {
+        match self.server.get_address() {
+            Some(address) => format!("http://{}", address),
+            None => String::new(),
+        }
+    }
+}
+
+impl Drop for BackgroundServer {
+    fn drop(&mut self) {
+        self.server.stop(self.workers);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage_manager::{
+        ChangeOrigin, EntityKind, Error, JournaledStorage, MemoryStorage, RemoteStorage, SyncEngine,
+    };
+
+    fn start(token: Option<&str>) -> BackgroundServer {
+        let server = Server::bind(
+            "127.0.0.1:0",
+            Box::new(MemoryStorage::new()),
+            token.map(ToOwned::to_owned),
+        )
+        .unwrap();
+        BackgroundServer::start(server, 2)
+    }
+
+    fn connect(server: &BackgroundServer, token: Option<&str>) -> Result<RemoteStorage, Error> {
+        RemoteStorage::connect(&server.get_url(), token.map(ToOwned::to_owned), true, None)
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let server = start(Some("secret"));
+        let storage = connect(&server, Some("secret")).unwrap();
+
+        let case = Case::new("case");
+        storage.save_case(&case).unwrap();
+        assert_eq!(storage.get_case(case.id).unwrap(), Some(case.clone()));
+        assert_eq!(storage.get_case(Uuid::new_v4()).unwrap(), None);
+        assert_eq!(storage.list_cases().unwrap(), vec![case.clone()]);
+
+        let query = Query::new(case.id, "jane doe");
+        storage.save_query(&query).unwrap();
+        assert_eq!(storage.list_queries(case.id).unwrap(), vec![query.clone()]);
+
+        let mut run = Run::start(&query, "facebook", "user");
+        storage.save_run(&run).unwrap();
+        run.finish(Ok(()));
+        let result = RunResult::new(run.id, "found");
+        storage
+            .save_run_with_results(&run, std::slice::from_ref(&result))
+            .unwrap();
+        assert_eq!(storage.list_runs(query.id).unwrap(), vec![run.clone()]);
+        assert_eq!(storage.list_results(run.id).unwrap(), vec![result]);
+
+        //the filters are sent as url parameters
+        let filter = RunFilter {
+            value: Some("jane doe".into()),
+            from: Some(run.started_at),
+            ..Default::default()
+        };
+        assert_eq!(storage.find_runs(&filter).unwrap(), vec![run.clone()]);
+        let filter = RunFilter {
+            engine: Some("twitter".into()),
+            ..Default::default()
+        };
+        assert!(storage.find_runs(&filter).unwrap().is_empty());
+
+        let entity = Entity::new(case.id, Some(run.id), EntityKind::Email, "jane@example.com");
+        storage.save_entity(&entity).unwrap();
+        assert_eq!(storage.list_entities(case.id).unwrap(), vec![entity]);
+        let note = Note::new(case.id, Some("analyst"), "same avatar on both");
+        storage.save_note(&note).unwrap();
+        assert_eq!(storage.get_note(note.id).unwrap(), Some(note.clone()));
+        assert_eq!(storage.list_notes(case.id).unwrap(), vec![note]);
+
+        //runs, queries and entities can be deleted
+        storage.delete_run(run.id).unwrap();
+        storage.delete_query(query.id).unwrap();
+        assert_eq!(storage.get_run(run.id).unwrap(), None);
+        assert!(storage.list_results(run.id).unwrap().is_empty());
+        assert!(storage.list_queries(case.id).unwrap().is_empty());
+        //deleting a missing record does nothing
+        storage.delete_entity(Uuid::new_v4()).unwrap();
+        let changes = storage.list_changes(0, 100).unwrap();
+        assert!(changes
+            .iter()
+            .any(|change| change.record_id == run.id && change.field == "$deleted"));
+    }
+
+    #[test]
+    fn authentication() {
+        let server = start(Some("secret"));
+        assert!(matches!(connect(&server, None), Err(Error::Remote(_))));
+        assert!(matches!(
+            connect(&server, Some("wrong")),
+            Err(Error::Remote(_))
+        ));
+        assert!(connect(&server, Some("secret")).is_ok());
+    }
+
+    #[test]
+    fn pagination() {
+        let server = start(None);
+        let storage = connect(&server, None).unwrap();
+        let query = Query::new(Uuid::new_v4(), "user123");
+        let run = Run::start(&query, "facebook", "user");
+        let results: Vec<RunResult> = (0..250)
+            .map(|i| RunResult::new(run.id, &i.to_string()))
+            .collect();
+        for result in &results {
+            storage.save_result(result).unwrap();
+        }
+        assert_eq!(storage.list_results(run.id).unwrap(), results);
+    }
+
+    #[test]
+    fn conflicting_writes() {
+        let server = start(None);
+        let first = connect(&server, None).unwrap();
+        let second = connect(&server, None).unwrap();
+
+        let case = Case::new("case");
+        first.save_case(&case).unwrap();
+
+        //the second client changes the case the first one has written
+        let mut renamed = second.get_case(case.id).unwrap().unwrap();
+        renamed.title = "renamed".into();
+        second.save_case(&renamed).unwrap();
+
+        let mut stale = case.clone();
+        stale.title = "stale".into();
+        assert!(matches!(first.save_case(&stale), Err(Error::Conflict(_))));
+
+        //reading it again resolves the conflict
+        let mut fresh = first.get_case(case.id).unwrap().unwrap();
+        assert_eq!(fresh.title, "renamed");
+        fresh.title = "fresh".into();
+        first.save_case(&fresh).unwrap();
+        assert_eq!(second.get_case(case.id).unwrap().unwrap().title, "fresh");
+    }
+
+    #[test]
+    fn shared_evidence_log() {
+        let server = start(None);
+        let manager = || {
+            let backend = storage_manager::Backend::Remote {
+                url: server.get_url(),
+                token: None,
+                verify_tls: true,
+                ca_cert: None,
+            };
+            storage_manager::StorageManager::with_storage(
+                backend,
+                Box::new(connect(&server, None).unwrap()),
+            )
+        };
+        let (first, second) = (manager(), manager());
+        let case = first.default_case().unwrap();
+
+        //both clients chain their entries to the server's log
+        for (i, manager) in [&first, &second, &first].into_iter().enumerate() {
+            let (_, output) = manager
+                .record_run(case.id, "user123", "facebook", "user", || {
+                    Ok(format!("found {}", i))
+                })
+                .unwrap();
+            assert!(output.is_ok());
+        }
+        let export = second.export_evidence().unwrap();
+        assert_eq!(export.head_seq, 6);
+        assert!(export.verify().is_empty());
+        assert!(first.verify_evidence().unwrap().is_intact());
+
+        //redacted entries keep the chain intact
+        let client = connect(&server, None).unwrap();
+        client
+            .redact_evidence(&[export.entries[0].record_id])
+            .unwrap();
+        let export = second.export_evidence().unwrap();
+        assert!(export.entries[0].redacted);
+        assert!(export.verify().is_empty());
+
+        //an entry that doesn't follow the log is refused
+        let mut stale = export.entries[5].clone();
+        stale.seq = 3;
+        assert!(matches!(
+            connect(&server, None).unwrap().append_evidence(&stale),
+            Err(Error::Conflict(_))
+        ));
+    }
+
+    // a local storage that syncs with the server
+    fn synced(server: &BackgroundServer) -> (Arc<JournaledStorage>, SyncEngine) {
+        let local = Arc::new(JournaledStorage::new(Arc::new(MemoryStorage::new())).unwrap());
+        let url = server.get_url();
+        let engine = SyncEngine::new(
+            local.clone(),
+            Box::new(move || {
+                let remote = RemoteStorage::connect(&url, Some("secret".into()), true, None)?;
+                Ok(Arc::new(remote) as Arc<dyn Storage>)
+            }),
+            None,
+        )
+        .unwrap();
+        (local, engine)
+    }
+
+    #[test]
+    fn sync_through_the_server() {
+        let server = start(Some("secret"));
+        let (laptop, laptop_sync) = synced(&server);
+        let (desktop, desktop_sync) = synced(&server);
+
+        //a case investigated on the laptop shows up on the desktop
+        let case = Case::new("case");
+        laptop.save_case(&case).unwrap();
+        let query = Query::new(case.id, "jane doe");
+        laptop.save_query(&query).unwrap();
+        let mut run = Run::start(&query, "facebook", "user");
+        run.finish(Ok(()));
+        let results: Vec<RunResult> = (0..300)
+            .map(|i| RunResult::new(run.id, &i.to_string()))
+            .collect();
+        laptop.save_run_with_results(&run, &results).unwrap();
+        assert_eq!(laptop_sync.sync().unwrap().pending, 0);
+        desktop_sync.sync().unwrap();
+        assert_eq!(desktop.get_case(case.id).unwrap(), Some(case.clone()));
+        assert_eq!(desktop.list_runs(query.id).unwrap(), vec![run.clone()]);
+        assert_eq!(desktop.list_results(run.id).unwrap().len(), 300);
+
+        //records written to the server directly are synced too
+        let remote = connect(&server, Some("secret")).unwrap();
+        let other = Case::new("other");
+        remote.save_case(&other).unwrap();
+        laptop_sync.sync().unwrap();
+        assert_eq!(laptop.get_case(other.id).unwrap(), Some(other));
+
+        //both rename the case while offline, every side keeps the latest rename
+        let mut first = case.clone();
+        first.title = "first".into();
+        laptop.save_case(&first).unwrap();
+        let mut second = case.clone();
+        second.title = "second".into();
+        desktop.save_case(&second).unwrap();
+        laptop_sync.sync().unwrap();
+        let status = desktop_sync.sync().unwrap();
+        laptop_sync.sync().unwrap();
+        for storage in [&*laptop as &dyn Storage, &*desktop, &remote] {
+            assert_eq!(storage.get_case(case.id).unwrap(), Some(second.clone()));
+        }
+        assert_eq!(status.conflicts.len(), 1);
+        assert_eq!(status.conflicts[0].winner, ChangeOrigin::Local);
+    }
+
+    #[test]
+    fn retries_until_the_server_is_up() {
+        //the port stays bound, the first requests find a server that is still starting
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let busy = |listener: &std::net::TcpListener| {
+            let (mut connection, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = connection.read(&mut request);
+            std::io::Write::write_all(
+                &mut connection,
+                b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            )
+            .unwrap();
+        };
+
+        //a client that only finds a busy server gives up with an error
+        let starting = thread::spawn(move || {
+            for _ in 0..4 {
+                busy(&listener);
+            }
+            listener
+        });
+        assert!(matches!(
+            RemoteStorage::connect(&url, None, true, None),
+            Err(Error::Remote(_))
+        ));
+        let listener = starting.join().unwrap();
+
+        //and retries while the server is starting
+        let starting = thread::spawn(move || {
+            busy(&listener);
+            let server =
+                Server::from_listener(listener, Box::new(MemoryStorage::new()), None).unwrap();
+            BackgroundServer::start(server, 1)
+        });
+        let storage = RemoteStorage::connect(&url, None, true, None);
+        let server = starting.join().unwrap();
+        assert!(storage.is_ok());
+        drop(server);
+    }
+
+    #[test]
+    fn write_that_is_already_saved() {
+        let server = start(None);
+        let first = connect(&server, None).unwrap();
+        let second = connect(&server, None).unwrap();
+        let case = Case::new("case");
+        first.save_case(&case).unwrap();
+
+        //the first client's revision is stale, the record holds what it writes though
+        let mut renamed = second.get_case(case.id).unwrap().unwrap();
+        renamed.title = "renamed".into();
+        second.save_case(&renamed).unwrap();
+        first.save_case(&renamed).unwrap();
+
+        //and its next write is checked against the latest revision
+        let mut stale = renamed.clone();
+        stale.title = "stale".into();
+        second.save_case(&stale).unwrap();
+        renamed.title = "again".into();
+        assert!(matches!(first.save_case(&renamed), Err(Error::Conflict(_))));
+    }
+
+    #[test]
+    fn expired_cursor() {
+        let server = start(None);
+        let address = server.get_url().replace("http://", "");
+        let mut connection = std::net::TcpStream::connect(address).unwrap();
+        std::io::Write::write_all(
+            &mut connection,
+            b"GET /v1/cases?cursor=unknown HTTP/1.1\r\nhost: sherlock\r\nconnection: close\r\n\r\n",
+        )
+        .unwrap();
+        let mut answer = String::new();
+        connection.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 410"), "{}", answer);
+    }
+}
@@ -0,0 +1,68 @@
+/*
+Storage Server:
+    storage_server [--listen <address>] [--db <path> | --memory] [--workers <count>]
+
+The token the clients must send is read from `SHERLOCK_STORAGE_TOKEN`,
+an encrypted database is opened with the passphrase of `SHERLOCK_PASSPHRASE`.
+ */
+use std::env;
+use storage_manager::{KeySource, MemoryStorage, SqliteStorage, Storage};
+use storage_server::{Server, TOKEN_ENV_VAR};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8700";
+const DEFAULT_DB: &str = "./sherlock-storage.db";
+const PASSPHRASE_ENV_VAR: &str = "SHERLOCK_PASSPHRASE";
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("storage_server: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut address = DEFAULT_ADDRESS.to_owned();
+    let mut db = Some(DEFAULT_DB.to_owned());
+    let mut workers = 4;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => address = value()?,
+            "--db" => db = Some(value()?),
+            "--memory" => db = None,
+            "--workers" => {
+                workers = value()?
+                    .parse()
+                    .map_err(|_| "--workers must be a number".to_owned())?
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let storage: Box<dyn Storage> = match (db, env::var(PASSPHRASE_ENV_VAR)) {
+        (Some(db), Ok(passphrase)) => Box::new(
+            SqliteStorage::open_encrypted(&db, &KeySource::Passphrase(passphrase))
+                .map_err(|error| error.to_string())?,
+        ),
+        (Some(db), Err(_)) => {
+            Box::new(SqliteStorage::open(&db).map_err(|error| error.to_string())?)
+        }
+        (None, _) => Box::new(MemoryStorage::new()),
+    };
+    let token = env::var(TOKEN_ENV_VAR)
+        .ok()
+        .filter(|token| !token.is_empty());
+    if token.is_none() {
+        eprintln!(
+            "storage_server: {} isn't set, every client is accepted",
+            TOKEN_ENV_VAR
+        );
+    }
+
+    let server = Server::bind(&address, storage, token)?;
+    println!("storage_server: listening on {}", address);
+    server.serve(workers);
+    Ok(())
+}